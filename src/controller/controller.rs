@@ -1,268 +1,712 @@
-use rusqlite::{Connection, Result};
-use std::error::Error;
+use rusqlite::{Connection, OptionalExtension, Result};
 use std::fs;
-use std::io::{self, Write};
+use std::io;
 use std::collections::HashMap;
-use std::path::Path; 
-use gtk::prelude::*;
-use gtk::ListStore;
-use gtk::{Window, MessageDialog, MessageType, ButtonsType};
+use std::path::{Path, PathBuf};
 use crate::model::music_miner::miner; 
-use crate::database_tables::create_all_tables;
-use crate::populate_db::populate_database;
-use crate::config::{create_database_file, create_config_dir};
+use crate::config::{get_quarantine_dir, update_settings};
+use crate::controller::library::{self, song_from_row, Library, SongDetails, SONG_QUERY};
+use crate::model::music_miner::loudness::{self, ReplayGain};
+use crate::model::music_miner::rating::MAX_RATING;
+use crate::model::music_miner::duplicates::{self, DuplicateCandidate};
+use crate::model::music_miner::fingerprint;
+use crate::model::music_miner::tag_writer::WRITABLE_FIELDS;
+use crate::model::music_miner::musicbrainz::{self, LocalAlbum, LocalTrack, Release, TrackProposal};
+use crate::model::error::{LibraryError, LibraryResult};
 
-/// Represents a song with its title, artist, and album.
-pub struct SongDetails {
-    pub title: String,
+/// Fetches the song stored with the given path from the library.
+///
+/// # Returns
+/// * `Ok(SongDetails)` - The song.
+/// * `Err(LibraryError)` - If the library cannot be read or no song has this path.
+pub fn get_song_details(library: &Library, path: &str) -> LibraryResult<SongDetails> {
+    library.song_by_path(path)
+        .map_err(|e| e.with_path(Path::new(path)))?
+        .ok_or_else(|| LibraryError::validation(Some(Path::new(path)), "No song of the library has this path"))
+}
+
+/// Represents an album with its name, album artist, year, and cover thumbnail.
+pub struct AlbumDetails {
+    pub id: i64,
+    pub name: String,
     pub artist: String,
-    pub album: String,
-    pub path: String,
-    pub track_number: i32,
     pub year: i32,
-    pub genre: String,
+    pub cover: Option<String>,
 }
 
-/// Fetches a list of songs from the database with their details.
-///
-/// Queries the database for song details (title, artist, album, path, track number, year, and genre), 
-/// handling potential `NULL` values for artist and album by using "Unknown".
+/// Fetches every album from the database, ordered by name and year.
+/// Albums without an album artist use the performer of one of their songs.
 ///
 /// # Arguments
 /// * `connection` - Reference to a SQLite `Connection` for database access.
 ///
 /// # Returns
-/// * `Ok(Vec<SongDetails>)` - A vector of `SongDetails` structs.
+/// * `Ok(Vec<AlbumDetails>)` - A vector of `AlbumDetails` structs.
 /// * `Err(rusqlite::Error)` - If the query or mapping fails.
-pub fn get_songs_from_database(connection: &Connection) -> Result<Vec<SongDetails>> {
+pub fn get_albums_from_database(connection: &Connection) -> Result<Vec<AlbumDetails>> {
     let mut stmt = connection.prepare(
-        "SELECT rolas.title, performers.name, albums.name, rolas.path, rolas.track, rolas.year, rolas.genre
-        FROM rolas
-        LEFT JOIN performers ON rolas.id_performer = performers.id_performer
-        LEFT JOIN albums ON rolas.id_album = albums.id_album"
+        "SELECT albums.id_album, albums.name, albums.year, albums.cover,
+            COALESCE(album_artists.name, (
+                SELECT performers.name FROM rolas
+                JOIN performers ON rolas.id_performer = performers.id_performer
+                WHERE rolas.id_album = albums.id_album
+                LIMIT 1
+            ))
+        FROM albums
+        LEFT JOIN performers AS album_artists ON albums.id_album_artist = album_artists.id_performer
+        ORDER BY albums.name COLLATE NOCASE, albums.year"
     )?;
 
     let rows = stmt.query_map([], |row| {
-        Ok(SongDetails {
-            title: row.get(0)?,
-            artist: row.get(1).unwrap_or_else(|_| String::from("Unknown")),
-            album: row.get(2).unwrap_or_else(|_| String::from("Unknown")),
-            path: row.get(3)?,
-            track_number: row.get(4)?,
-            year: row.get(5)?,
-            genre: row.get(6)?,
+        Ok(AlbumDetails {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            artist: row.get(4).unwrap_or_else(|_| String::from("Unknown")),
+            year: row.get(2)?,
+            cover: row.get(3)?,
         })
     })?;
 
-    let mut songs = Vec::new();
-    for song in rows {
-        songs.push(song?);
-    }
-    Ok(songs)
+    rows.collect()
 }
 
+/// A performer in the library browser, with the number of songs credited to it.
+pub struct PerformerSummary {
+    pub id: i64,
+    pub name: String,
+    pub song_count: i64,
+}
 
-pub fn get_song_details(title: &str) -> Result<SongDetails, Box<dyn Error>> {
-    let db_path = create_database_file()?;
-    let conn = Connection::open(db_path)?;
+/// An album of a performer in the library browser, with the number of songs it contains.
+pub struct AlbumSummary {
+    pub id: i64,
+    pub name: String,
+    pub year: i32,
+    pub song_count: i64,
+}
 
-    let mut stmt = conn.prepare("SELECT title, path, track, year, genre FROM rolas WHERE title = ?1")?;
-    let mut rows = stmt.query([title])?;
+/// Fetches every performer that has at least one song, ordered by name.
+/// Songs are credited to the album artist when their album has one, so compilations are
+/// grouped under "Various Artists" instead of under each track artist.
+///
+/// # Arguments
+/// * `connection` - Reference to a SQLite `Connection` for database access.
+///
+/// # Returns
+/// * `Ok(Vec<PerformerSummary>)` - The performers and their song counts.
+/// * `Err(rusqlite::Error)` - If the query or mapping fails.
+pub fn get_performer_summaries(connection: &Connection) -> Result<Vec<PerformerSummary>> {
+    let mut stmt = connection.prepare(
+        "SELECT performers.id_performer, performers.name, COUNT(rolas.id_rola)
+        FROM rolas
+        LEFT JOIN albums ON rolas.id_album = albums.id_album
+        JOIN performers ON COALESCE(albums.id_album_artist, rolas.id_performer) = performers.id_performer
+        GROUP BY performers.id_performer
+        ORDER BY performers.name COLLATE NOCASE"
+    )?;
 
-    if let Some(row) = rows.next()? {
-        Ok(SongDetails {
-            title: row.get::<_, String>(0)?,
-            artist: String::from("Unknown"),
-            album: String::from("Unknown"),
-            path: row.get::<_, String>(1)?,
-            track_number: row.get::<_, i32>(2)?,
-            year: row.get::<_, i32>(3)?,
-            genre: row.get::<_, String>(4)?,
+    let rows = stmt.query_map([], |row| {
+        Ok(PerformerSummary {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            song_count: row.get(2)?,
         })
-    } else {
-        Err("No song found with the given title".into())
-    }
+    })?;
+
+    rows.collect()
 }
 
-/// Populates the given `ListStore` with songs from the database.
-///
-/// Retrieves a list of songs from the database and populates the `ListStore` with
-/// song details (title, artist, album). Clears the existing contents of the list before adding the new entries.
+/// Fetches the albums containing songs credited to a performer, ordered by year and name.
 ///
 /// # Arguments
-/// * `list_store` - A reference to the `ListStore` where the song data will be inserted.
+/// * `connection` - Reference to a SQLite `Connection` for database access.
+/// * `performer_id` - The `id_performer` of the performer.
 ///
-/// # Errors
-/// Prints an error to the console if the database connection fails or if songs cannot be retrieved.
-pub fn populate_song_list(list_store: &ListStore) {
-    let connection = match create_database_connection() {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Failed to connect to the database: {}", err);
-            return;
-        }
-    };
+/// # Returns
+/// * `Ok(Vec<AlbumSummary>)` - The albums and the number of songs of the performer in each.
+/// * `Err(rusqlite::Error)` - If the query or mapping fails.
+pub fn get_performer_album_summaries(connection: &Connection, performer_id: i64) -> Result<Vec<AlbumSummary>> {
+    let mut stmt = connection.prepare(
+        "SELECT albums.id_album, albums.name, albums.year, COUNT(rolas.id_rola)
+        FROM rolas
+        JOIN albums ON rolas.id_album = albums.id_album
+        WHERE COALESCE(albums.id_album_artist, rolas.id_performer) = ?1
+        GROUP BY albums.id_album
+        ORDER BY albums.year, albums.name COLLATE NOCASE"
+    )?;
 
-    match get_songs_from_database(&connection) {
-        Ok(songs) => {
-            list_store.clear();
-            
-            for song in songs {
-                let iter = list_store.append();
-                list_store.set(&iter, &[
-                    (0, &song.title), 
-                    (1, &song.artist), 
-                    (2, &song.album)
-                ]);
-            }
-        },
-        Err(err) => {
-            eprintln!("Failed to retrieve songs from the database: {}", err);
-        }
-    };
+    let rows = stmt.query_map([performer_id], |row| {
+        Ok(AlbumSummary {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            year: row.get(2)?,
+            song_count: row.get(3)?,
+        })
+    })?;
+
+    rows.collect()
 }
 
-/// Creates a connection to the SQLite database located at `~/.config/musicmanager/database.db`.
+/// Fetches the songs of a performer in one album, ordered by track number.
 ///
-/// This function first ensures that the `database.db` file exists by calling `create_database_file()`.
-/// Then, it attempts to create an SQLite connection to this database file. If successful,
-/// the connection object is returned for further database operations.
+/// # Arguments
+/// * `connection` - Reference to a SQLite `Connection` for database access.
+/// * `performer_id` - The `id_performer` of the performer.
+/// * `album_id` - The `id_album` of the album.
 ///
 /// # Returns
+/// * `Ok(Vec<SongDetails>)` - The songs of the performer in the album.
+/// * `Err(rusqlite::Error)` - If the query or mapping fails.
+pub fn get_performer_album_songs(connection: &Connection, performer_id: i64, album_id: i64) -> Result<Vec<SongDetails>> {
+    let mut stmt = connection.prepare(&format!(
+        "{} WHERE COALESCE(albums.id_album_artist, rolas.id_performer) = ?1 AND rolas.id_album = ?2 ORDER BY rolas.track", SONG_QUERY
+    ))?;
+
+    let rows = stmt.query_map([performer_id, album_id], song_from_row)?;
+
+    rows.collect()
+}
+
+/// Number of songs listed by the "Most played" and "Recently played" views.
+const VIEW_LIMIT: usize = 100;
+
+/// The lists of songs that can be shown instead of the whole library.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SongView {
+    All,
+    /// The songs played the most, most played first.
+    MostPlayed,
+    /// The songs played most recently, last played first.
+    RecentlyPlayed,
+    /// The songs that have never been played.
+    NeverPlayed,
+    /// The loved songs: those rated five stars.
+    Loved,
+}
+
+/// Fetches the songs of a view of the library.
+///
+/// # Arguments
+/// * `connection` - Reference to a SQLite `Connection` for database access.
+/// * `view` - The view to list.
 ///
-/// - `Ok(Connection)` if the connection to the database was established successfully.
-/// - `Err(rusqlite::Error)` if there was an error creating the database file or opening the connection.
-pub fn create_database_connection() -> Result<Connection> {
-    let file_path = match create_database_file() {
-        Ok(path) => path,
-        Err(e) => return Err(rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+/// # Returns
+/// * `Ok(Vec<SongDetails>)` - The songs of the view, in its order.
+/// * `Err(rusqlite::Error)` - If the query or mapping fails.
+pub fn get_songs_in_view(connection: &Connection, view: SongView) -> Result<Vec<SongDetails>> {
+    let clause = match view {
+        SongView::All => String::new(),
+        SongView::MostPlayed => format!(
+            "WHERE rolas.play_count > 0 ORDER BY rolas.play_count DESC, rolas.last_played DESC LIMIT {}", VIEW_LIMIT
+        ),
+        SongView::RecentlyPlayed => format!(
+            "WHERE rolas.last_played IS NOT NULL ORDER BY rolas.last_played DESC LIMIT {}", VIEW_LIMIT
+        ),
+        SongView::NeverPlayed => String::from("WHERE COALESCE(rolas.play_count, 0) = 0"),
+        SongView::Loved => format!("WHERE rolas.rating = {}", MAX_RATING),
     };
+    let mut stmt = connection.prepare(&format!("{} {}", SONG_QUERY, clause))?;
+
+    let rows = stmt.query_map([], song_from_row)?;
 
-    let connection = Connection::open(file_path)?;
-    Ok(connection)
+    rows.collect()
 }
 
-pub fn remove_database_file_if_exists() -> Result<()> {
-    let file_path = match create_database_file() {
-        Ok(path) => path,
-        Err(e) => return Err(rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+/// What a set of labels is attached to.
+pub enum LabelTarget {
+    /// A song, identified by its path.
+    Song(String),
+    /// An album, identified by the path of its directory.
+    Album(String),
+}
+
+/// Fetches every label given to a song or album, sorted by name.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` - The names of the labels.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_all_labels(connection: &Connection) -> Result<Vec<String>> {
+    let mut stmt = connection.prepare("SELECT name FROM labels ORDER BY name")?;
+    let labels = stmt.query_map([], |row| row.get(0))?;
+    labels.collect()
+}
+
+/// Fetches the labels of a song or album.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` - The names of the labels, sorted by name.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_labels(connection: &Connection, target: &LabelTarget) -> Result<Vec<String>> {
+    let (query, key) = match target {
+        LabelTarget::Song(path) => (
+            "SELECT labels.name FROM rola_labels JOIN labels ON rola_labels.id_label = labels.id_label
+             WHERE rola_labels.path = ?1 ORDER BY labels.name",
+            path,
+        ),
+        LabelTarget::Album(album_path) => (
+            "SELECT labels.name FROM album_labels JOIN labels ON album_labels.id_label = labels.id_label
+             WHERE album_labels.album_path = ?1 ORDER BY labels.name",
+            album_path,
+        ),
     };
+    let mut stmt = connection.prepare(query)?;
+    let labels = stmt.query_map([key], |row| row.get(0))?;
+    labels.collect()
+}
+
+/// Fetches the paths of the songs of the album stored in a directory.
+pub fn get_album_song_paths(connection: &Connection, album_path: &str) -> Result<Vec<String>> {
+    let mut stmt = connection.prepare(
+        "SELECT rolas.path FROM rolas JOIN albums ON rolas.id_album = albums.id_album WHERE albums.path = ?1"
+    )?;
+    let paths = stmt.query_map([album_path], |row| row.get(0))?;
+    paths.collect()
+}
+
+/// Returns the path of the album directory of a song, used to label its album.
+///
+/// # Returns
+/// * `Ok(Some(String))` - The path of the album.
+/// * `Ok(None)` - If the song is not in the database or has no album.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_album_path(connection: &Connection, song_path: &str) -> Result<Option<String>> {
+    connection.query_row(
+        "SELECT albums.path FROM rolas JOIN albums ON rolas.id_album = albums.id_album WHERE rolas.path = ?1",
+        [song_path],
+        |row| row.get(0),
+    ).optional()
+}
+
+/// Where the synchronised lyrics edited in the lyrics editor are saved.
+pub enum LyricsTarget {
+    /// An `.lrc` file next to the song.
+    Sidecar,
+    /// A SYLT frame in the song's ID3 tag.
+    Tag,
+}
 
-    if Path::new(&file_path).exists() {
-        if let Err(e) = fs::remove_file(&file_path) {
-            eprintln!("Error al eliminar el archivo: {}", e);
+/// Fetches the albums whose loudness has not been analysed yet.
+///
+/// # Returns
+/// * `Ok(Vec<(i64, String)>)` - The `id_album` and name of each album without ReplayGain values, sorted by name.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_albums_missing_loudness(connection: &Connection) -> Result<Vec<(i64, String)>> {
+    let mut stmt = connection.prepare(
+        "SELECT id_album, name FROM albums
+         WHERE album_gain IS NULL OR album_peak IS NULL
+         ORDER BY name COLLATE NOCASE"
+    )?;
+
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+/// Analyses the loudness of the songs of an album and writes the ReplayGain values to the
+/// tags of its MP3 and FLAC files. Only files are read and written, so it can run on a
+/// worker thread; the values are then stored with `Library::store_loudness()`.
+///
+/// # Arguments
+/// * `paths` - The paths of the songs of the album.
+///
+/// # Returns
+/// The values of every song that could be decoded, and the problems met with individual
+/// songs (decoding failures, or tags that could not be written). Values of songs whose tag
+/// could not be written are still returned.
+pub fn analyse_loudness(paths: &[String]) -> (Vec<(String, ReplayGain)>, Vec<String>) {
+    let mut gains = Vec::new();
+    let mut problems = Vec::new();
+    for (path, result) in loudness::analyse_album(paths) {
+        match result {
+            Ok(gain) => {
+                if let Err(e) = loudness::write_replaygain(Path::new(&path), &gain) {
+                    problems.push(format!("Could not write the ReplayGain frames of {}: {}", path, e));
+                }
+                gains.push((path, gain));
+            }
+            Err(e) => problems.push(format!("Could not decode {}: {}", path, e)),
         }
     }
-
-    Ok(())
+    (gains, problems)
 }
 
-/// Saves the provided music directory path to a configuration file.
+/// Fetches the songs of the database as candidates for `find_duplicates()`. Songs whose
+/// title is the default one given to untagged songs are passed without a title, so they are
+/// only grouped by their hash.
 ///
-/// Writes the music directory path to a `Config.TOML` file in the configuration directory.
-/// If the file or directory does not exist, it is created.
+/// # Returns
+/// * `Ok(Vec<DuplicateCandidate>)` - The songs, without their hash, sorted by path.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_duplicate_candidates(connection: &Connection) -> Result<Vec<DuplicateCandidate>> {
+    let mut stmt = connection.prepare(
+        "SELECT rolas.path, COALESCE(rola_credits.credited, performers.name, ''),
+            CASE WHEN EXISTS(SELECT 1 FROM field_sources WHERE field_sources.id_rola = rolas.id_rola
+                AND field = 'Title' AND source = 'Default') THEN '' ELSE COALESCE(rolas.title, '') END,
+            COALESCE(rolas.duration_ms, 0), COALESCE(rolas.bitrate, 0)
+         FROM rolas
+         LEFT JOIN performers ON rolas.id_performer = performers.id_performer
+         LEFT JOIN rola_credits ON rolas.id_rola = rola_credits.id_rola
+         ORDER BY rolas.path"
+    )?;
+    let candidates = stmt.query_map([], |row| Ok(DuplicateCandidate {
+        path: row.get(0)?,
+        artist: row.get(1)?,
+        title: row.get(2)?,
+        duration_ms: row.get(3)?,
+        bitrate: row.get(4)?,
+        content_hash: None,
+    }))?;
+    candidates.collect()
+}
+
+/// Finds the candidates that are copies of each other (see `duplicates::group_duplicates()`).
+/// Only the songs whose audio has the same length as another song's are hashed, since only
+/// those can have identical audio. Only files are read, so it can run on a worker thread.
 ///
 /// # Arguments
-/// * `directory` - The path to the music directory to save.
+/// * `candidates` - The songs to compare, from `Library::duplicate_candidates()`.
+/// * `progress` - Called with the number of songs hashed so far and the number to hash.
 ///
 /// # Returns
-/// * `Ok(())` - On success.
-/// * `Err(io::Error)` - If there is an issue creating the config directory or writing to the file.
-pub fn save_directory_to_config(directory: &str) -> io::Result<()> {
-    let config_dir = create_config_dir()?;
-    let file_path = config_dir.join("Config.TOML");
+/// The groups of duplicates. Songs that could not be read are only grouped by their artist,
+/// title and duration.
+pub fn find_duplicates(mut candidates: Vec<DuplicateCandidate>, mut progress: impl FnMut(usize, usize)) -> Vec<Vec<DuplicateCandidate>> {
+    let ranges: Vec<Option<(u64, u64)>> = candidates.iter()
+        .map(|candidate| match duplicates::audio_range(Path::new(&candidate.path)) {
+            Ok(range) => Some(range),
+            Err(e) => {
+                log::warn!(path = candidate.path.as_str(); "Could not read the song: {}", e);
+                None
+            }
+        })
+        .collect();
+
+    let mut lengths: HashMap<u64, usize> = HashMap::new();
+    for (start, end) in ranges.iter().flatten() {
+        *lengths.entry(end - start).or_default() += 1;
+    }
+    let to_hash: Vec<usize> = (0..candidates.len())
+        .filter(|&index| matches!(ranges[index], Some((start, end)) if lengths[&(end - start)] > 1))
+        .collect();
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&file_path)?;
+    for (hashed, &index) in to_hash.iter().enumerate() {
+        progress(hashed, to_hash.len());
+        let path = Path::new(&candidates[index].path);
+        match ranges[index].map(|range| duplicates::audio_content_hash(path, range)) {
+            Some(Ok(hash)) => candidates[index].content_hash = Some(hash),
+            Some(Err(e)) => log::warn!(path = candidates[index].path.as_str(); "Could not hash the song: {}", e),
+            None => {}
+        }
+    }
+    progress(to_hash.len(), to_hash.len());
 
-    writeln!(file, "music_directory = \"{}\"", directory)?;
+    duplicates::group_duplicates(&candidates)
+        .into_iter()
+        .map(|group| group.into_iter().map(|index| candidates[index].clone()).collect())
+        .collect()
+}
 
-    Ok(())
+/// Where the duplicate finder moves the copies that are not kept.
+#[derive(Clone, Copy)]
+pub enum DuplicateAction {
+    /// The trash of the desktop, from where the file manager can restore them.
+    Trash,
+    /// The quarantine folder (see `get_quarantine_dir()`).
+    Quarantine,
 }
 
-/// Displays an error dialog with a specific message.
-///
-/// # Parameters
-/// * `window`: The main window on which the dialog is shown.
-/// * `message`: The message that will be displayed in the dialog.
+/// Moves an unwanted copy of a song to the trash or the quarantine folder and removes it
+/// from the library.
 ///
-/// # Description
-/// Creates a modal pop-up window (`MessageDialog`) that displays an error message
-/// and an "OK" button to close the dialog. This dialog blocks interaction
-/// with the main window until it is closed.
+/// # Returns
+/// * `Ok(PathBuf)` - The new path of the file.
+/// * `Err(LibraryError)` - If the file could not be moved, the quarantine folder could not be
+///   read from the configuration, or the database could not be updated.
+pub fn remove_duplicate(library: &Library, path: &str, action: DuplicateAction) -> LibraryResult<PathBuf> {
+    let file = Path::new(path);
+    let destination = match action {
+        DuplicateAction::Trash => duplicates::move_to_trash(file),
+        DuplicateAction::Quarantine => {
+            let quarantine_dir = get_quarantine_dir().map_err(|e| LibraryError::config(None, e))?;
+            duplicates::move_to_quarantine(file, &quarantine_dir)
+        }
+    }.map_err(|e| LibraryError::io(file, e))?;
+
+    library.remove_song(path)?;
+    Ok(destination)
+}
+
+/// Returns the size of a file and its modification time, in seconds since the epoch.
+fn file_stamp(path: &Path) -> io::Result<(i64, i64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0);
+    Ok((metadata.len() as i64, modified))
+}
+
+/// A song with the size and modification time of the file its cached fingerprint was
+/// computed from, if it has one.
+pub struct FingerprintStamp {
+    pub path: String,
+    pub cached: Option<(i64, i64)>,
+}
+
+/// Fetches every song of the database with the stamp of its cached fingerprint.
+pub fn get_fingerprint_stamps(connection: &Connection) -> Result<Vec<FingerprintStamp>> {
+    let mut stmt = connection.prepare(
+        "SELECT rolas.path, fingerprints.file_size, fingerprints.modified
+         FROM rolas LEFT JOIN fingerprints ON rolas.path = fingerprints.path
+         ORDER BY rolas.path"
+    )?;
+    let stamps = stmt.query_map([], |row| {
+        let cached = match (row.get::<_, Option<i64>>(1)?, row.get::<_, Option<i64>>(2)?) {
+            (Some(file_size), Some(modified)) => Some((file_size, modified)),
+            _ => None,
+        };
+        Ok(FingerprintStamp { path: row.get(0)?, cached })
+    })?;
+    stamps.collect()
+}
+
+/// An acoustic fingerprint computed by `compute_fingerprints()`, with the size and
+/// modification time of its file.
+pub struct ComputedFingerprint {
+    pub path: String,
+    pub file_size: i64,
+    pub modified: i64,
+    pub values: Vec<u32>,
+}
+
+/// Computes the acoustic fingerprint of every song that has none yet, or whose file changed
+/// since it was computed. Only files are read, so it can run on a worker thread; the
+/// fingerprints are then cached with `Library::store_fingerprints()`.
 ///
-/// # Example
-/// ```rust
-/// show_error_dialog(&window, "No directory provided.");
-/// ```
-pub fn show_error_dialog(window: &Window, message: &str) {
-    let dialog = MessageDialog::new(
-        Some(window),
-        gtk::DialogFlags::MODAL,
-        MessageType::Error,
-        ButtonsType::Ok,
-        message,
-    );
-    dialog.run();
-    dialog.close();
-}
-
-/// Ensures that the required database tables exist.
-/// 
-/// This function checks if all necessary tables in the database exist, and if not, it creates them.
-/// It calls the internal function `create_all_tables` to handle the table creation process.
-/// 
 /// # Arguments
-/// 
-/// * `connection` - A reference to the active database connection (`&Connection`).
-/// 
+/// * `songs` - The songs with the stamps of their cached fingerprints, from `Library::fingerprint_stamps()`.
+/// * `progress` - Called with the number of songs checked so far and the number of songs.
+///
 /// # Returns
-/// 
-/// * `Result<(), Box<dyn std::error::Error>>` - Returns `Ok(())` if the operation is successful,
-///   or an error wrapped in `Box<dyn std::error::Error>` if the table creation fails.
-pub fn create_tables_if_not_exist(connection: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    create_all_tables(connection)?;
-    Ok(())
-}
-
-/// Inserts song data into the database.
-/// 
-/// This function takes a connection to the database and a `HashMap` containing song data, 
-/// and inserts that data into the corresponding table in the database.
-/// It relies on the `populate_database` function to handle the actual data insertion.
-/// 
+/// The new fingerprints, and the problems met with individual songs (files that could not
+/// be read or decoded).
+pub fn compute_fingerprints(songs: &[FingerprintStamp], mut progress: impl FnMut(usize, usize)) -> (Vec<ComputedFingerprint>, Vec<String>) {
+    let mut fingerprints = Vec::new();
+    let mut problems = Vec::new();
+    for (index, song) in songs.iter().enumerate() {
+        progress(index, songs.len());
+        let (file_size, modified) = match file_stamp(Path::new(&song.path)) {
+            Ok(stamp) => stamp,
+            Err(e) => {
+                problems.push(format!("Could not read {}: {}", song.path, e));
+                continue;
+            }
+        };
+        if song.cached == Some((file_size, modified)) {
+            continue;
+        }
+
+        match fingerprint::compute_fingerprint(Path::new(&song.path)) {
+            Ok(values) => fingerprints.push(ComputedFingerprint { path: song.path.clone(), file_size, modified, values }),
+            Err(e) => problems.push(format!("Could not decode {}: {}", song.path, e)),
+        }
+    }
+    progress(songs.len(), songs.len());
+    (fingerprints, problems)
+}
+
+/// A song found to be the same recording as other songs of the library.
+#[derive(Clone)]
+pub struct RecordingCopy {
+    pub path: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Number of fields of the song read from its tag rather than inferred or defaulted,
+    /// used to find the best tagged copy.
+    pub tagged_fields: i64,
+}
+
+/// Groups the songs of the database that are the same recording, judging by their cached
+/// fingerprints (see `compute_fingerprints()`), whatever their tags say.
+///
+/// # Returns
+/// * `Ok(Vec<Vec<RecordingCopy>>)` - The groups, each sorted from the best tagged copy down.
+/// * `Err(rusqlite::Error)` - If the query fails.
+pub fn get_same_recordings(connection: &Connection) -> Result<Vec<Vec<RecordingCopy>>> {
+    let mut stmt = connection.prepare(
+        "SELECT rolas.path, COALESCE(rolas.title, ''), COALESCE(rola_credits.credited, performers.name, ''),
+            COALESCE(albums.name, ''),
+            (SELECT COUNT(*) FROM field_sources WHERE field_sources.id_rola = rolas.id_rola AND source = 'Tag'),
+            COALESCE(rolas.duration_ms, 0), fingerprints.fingerprint
+         FROM rolas
+         JOIN fingerprints ON rolas.path = fingerprints.path
+         LEFT JOIN performers ON rolas.id_performer = performers.id_performer
+         LEFT JOIN rola_credits ON rolas.id_rola = rola_credits.id_rola
+         LEFT JOIN albums ON rolas.id_album = albums.id_album"
+    )?;
+    let rows = stmt.query_map([], |row| Ok((
+        RecordingCopy {
+            path: row.get(0)?,
+            title: row.get(1)?,
+            artist: row.get(2)?,
+            album: row.get(3)?,
+            tagged_fields: row.get(4)?,
+        },
+        (row.get::<_, i64>(5)?, fingerprint::from_bytes(&row.get::<_, Vec<u8>>(6)?)),
+    )))?.collect::<Result<Vec<_>>>()?;
+
+    let (copies, songs): (Vec<RecordingCopy>, Vec<(i64, Vec<u32>)>) = rows.into_iter().unzip();
+    let groups = fingerprint::group_recordings(&songs)
+        .into_iter()
+        .map(|group| {
+            let mut group: Vec<RecordingCopy> = group.into_iter().map(|index| copies[index].clone()).collect();
+            group.sort_by_key(|copy| std::cmp::Reverse(copy.tagged_fields));
+            group
+        })
+        .collect();
+    Ok(groups)
+}
+
+/// Copies the metadata of a well-tagged copy of a recording to another copy: every field of
+/// `WRITABLE_FIELDS` read from the source's tag is written to the target's tag, and the
+/// target is read again into the library.
+///
 /// # Arguments
-/// 
-/// * `connection` - A reference to the active database connection (`&Connection`).
-/// * `song_data` - A `HashMap` where the keys are `String` values representing song attributes
-///   (e.g., title, artist, album) and the values are their corresponding data.
-/// 
+/// * `library` - The library the target belongs to.
+/// * `source` - The path of the copy whose tags are copied.
+/// * `target` - The path of the MP3 file to tag.
+///
 /// # Returns
-/// 
-/// * `Result<(), rusqlite::Error>` - Returns `Ok(())` if the insertion is successful, or a 
-///   `rusqlite::Error` if the operation fails.
-pub fn insert_song_into_database(connection: &Connection, song_data: HashMap<String, String>) -> Result<(), rusqlite::Error> {
-    populate_database(connection, song_data)
-}
-
-/// Extracts song metadata from a directory of music files.
-/// 
-/// This function scans the specified directory for music files and extracts metadata (such as title,
-/// artist, and album) for each song. It returns a vector of `HashMap` objects, where each map
-/// represents the metadata for a single song.
-/// 
+/// * `Ok(())` - If the tag was written and the database updated.
+/// * `Err(LibraryError)` - If the source cannot be read, the target cannot be written
+///   (only MP3 and FLAC files can), or the database cannot be updated.
+pub fn copy_song_metadata(library: &Library, source: &str, target: &str) -> LibraryResult<()> {
+    let source_path = Path::new(source);
+    let source_map = miner::extract_song(source_path, &library::library_root_of(source_path))?;
+
+    let fields: HashMap<String, String> = WRITABLE_FIELDS.iter()
+        .filter(|key| !matches!(source_map.get(&format!("{} Source", key)), Some(source) if source != "Tag"))
+        .filter_map(|key| source_map.get(*key).map(|value| (key.to_string(), value.clone())))
+        .collect();
+    library.set_tags(target, &fields)
+}
+
+/// Reads the albums of the database with their songs, as compared to MusicBrainz releases.
+pub fn get_albums_to_match(connection: &Connection) -> Result<Vec<LocalAlbum>> {
+    let mut stmt = connection.prepare(
+        "SELECT albums.id_album, COALESCE(albums.name, ''), COALESCE(performers.name, ''), albums.year,
+            albums.release_date, albums.mbid
+         FROM albums
+         LEFT JOIN performers ON albums.id_album_artist = performers.id_performer
+         ORDER BY albums.name"
+    )?;
+    let mut albums = stmt.query_map([], |row| Ok(LocalAlbum {
+        id_album: row.get(0)?,
+        name: row.get(1)?,
+        artist: row.get(2)?,
+        year: row.get(3)?,
+        release_date: row.get(4)?,
+        mbid: row.get(5)?,
+        tracks: Vec::new(),
+    }))?.collect::<Result<Vec<_>>>()?;
+
+    let mut stmt = connection.prepare(
+        "SELECT path, COALESCE(title, ''), disc, disc_total, COALESCE(track, 0), track_total, duration_ms, mbid
+         FROM rolas WHERE id_album = ?1 ORDER BY disc, track, path"
+    )?;
+    for album in &mut albums {
+        album.tracks = stmt.query_map([album.id_album], |row| Ok(LocalTrack {
+            path: row.get(0)?,
+            title: row.get(1)?,
+            disc: row.get(2)?,
+            disc_total: row.get(3)?,
+            track: row.get(4)?,
+            track_total: row.get(5)?,
+            duration_ms: row.get(6)?,
+            mbid: row.get(7)?,
+        }))?.collect::<Result<Vec<_>>>()?;
+    }
+    Ok(albums)
+}
+
+/// An album of the library matched to a MusicBrainz release, with the changes proposed
+/// for its songs.
+pub struct AlbumMatch {
+    pub album: LocalAlbum,
+    pub release: Release,
+    pub score: f64,
+    pub proposals: Vec<TrackProposal>,
+}
+
+/// # Match Albums function
+///
+/// Matches albums of the library to the releases of a local MusicBrainz dump (see
+/// `musicbrainz::load_releases()`) by track count, durations and names, and proposes the
+/// corrections for the albums that do not agree with their release yet. Only the releases
+/// with as many tracks as some album are kept in memory. The dump path is saved in
+/// `Config.TOML` as `musicbrainz_dump`. The database is not used, so it can run on a worker
+/// thread.
+///
 /// # Arguments
-/// 
-/// * `directory` - A string slice (`&str`) representing the path to the directory containing music files.
-/// 
+/// * `albums` - The albums to match, from `Library::albums_to_match()`.
+/// * `dump_path` - The path of the dump.
+/// * `progress` - Called with the number of albums compared so far and the number of albums.
+///
 /// # Returns
-/// 
-/// * `Vec<HashMap<String, String>>` - A vector of hash maps, each representing the metadata for an extracted song.
-pub fn extract_songs_from_directory(directory: &str) -> Vec<HashMap<String, String>> {
-    miner::extract(directory)
+/// * `Ok(Vec<AlbumMatch>)` - The matched albums with at least one proposed change.
+/// * `Err(LibraryError)` - If the dump could not be read.
+pub fn match_albums(albums: Vec<LocalAlbum>, dump_path: &Path, mut progress: impl FnMut(usize, usize)) -> LibraryResult<Vec<AlbumMatch>> {
+    let track_counts: std::collections::HashSet<usize> = albums.iter().map(|album| album.tracks.len()).collect();
+    let releases = musicbrainz::load_releases(dump_path, |release| track_counts.contains(&release.tracks.len()))
+        .map_err(|e| LibraryError::io(dump_path, e))?;
+    if let Err(e) = update_settings(|settings| settings.musicbrainz_dump = Some(dump_path.to_string_lossy().into_owned())) {
+        eprintln!("Failed to save the MusicBrainz dump path: {}", e);
+    }
+
+    let total = albums.len();
+    let mut matches = Vec::new();
+    for (index, album) in albums.into_iter().enumerate() {
+        progress(index, total);
+        let Some((release, score)) = musicbrainz::best_release(&album, &releases) else {
+            continue;
+        };
+        let proposals = musicbrainz::propose_corrections(&album, release);
+        if !proposals.is_empty() {
+            matches.push(AlbumMatch { release: release.clone(), album, score, proposals });
+        }
+    }
+    progress(total, total);
+    Ok(matches)
 }
 
+/// # Apply Album Match function
+///
+/// Writes the proposed changes of a matched album into the tags of its songs and reads the
+/// songs again into the library.
+///
+/// # Returns
+/// The problems met with individual songs (e.g. files of other formats than MP3 and FLAC,
+/// which cannot be written, or songs that could not be read again).
+pub fn apply_album_match(library: &Library, album_match: &AlbumMatch) -> Vec<String> {
+    let mut problems = Vec::new();
+    for proposal in &album_match.proposals {
+        let fields: HashMap<String, String> = proposal.changes.iter()
+            .map(|change| (change.field.clone(), change.new.clone()))
+            .collect();
+        if let Err(e) = library.set_tags(&proposal.path, &fields) {
+            problems.push(format!("Could not tag {}: {}", proposal.path, e));
+        }
+    }
+    problems
+}
 
+/// Removes library roots together with their songs, e.g. after they were deleted from the
+/// preferences, and returns the number of songs removed.
+pub fn remove_library_roots(library: &Library, paths: &[String]) -> LibraryResult<usize> {
+    let mut removed = 0;
+    for path in paths {
+        removed += library.remove_root(path)?;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,941 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::config::{create_database_file, create_history_file, load_settings, update_settings, LibraryRoot};
+use crate::controller::controller::{self, AlbumDetails, ComputedFingerprint, FingerprintStamp, LabelTarget, LyricsTarget,
+    RecordingCopy, SongView};
+use crate::controller::search::{build_search_clause, register_search_functions};
+use crate::database_tables::create_all_tables;
+use crate::model::error::{LibraryError, LibraryResult};
+use crate::model::music_miner::duplicates::DuplicateCandidate;
+use crate::model::music_miner::fingerprint;
+use crate::model::music_miner::loudness::ReplayGain;
+use crate::model::music_miner::musicbrainz::LocalAlbum;
+use crate::model::music_miner::rating::{self, MAX_RATING};
+use crate::model::music_miner::synced_lyrics::{self, LyricLine};
+use crate::model::music_miner::{miner, tag_writer};
+use crate::populate_db::{self, populate_database};
+
+/// Represents a song with its title, artist, and album.
+/// `artist` lists every main and featured performer credited on the song.
+#[derive(Serialize)]
+pub struct SongDetails {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub path: String,
+    pub track_number: i32,
+    pub year: i32,
+    pub genre: String,
+    pub cover: Option<String>,
+    pub album_artist: String,
+    pub remixers: Option<String>,
+    pub composers: Option<String>,
+    pub disc: Option<i32>,
+    pub disc_total: Option<i32>,
+    pub track_total: Option<i32>,
+    pub composer: Option<String>,
+    pub conductor: Option<String>,
+    pub bpm: Option<i32>,
+    pub key: Option<String>,
+    pub comment: Option<String>,
+    pub lyrics: Option<String>,
+    pub synced_lyrics: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub bitrate: Option<i32>,
+    pub sample_rate: Option<i32>,
+    pub channel_mode: Option<String>,
+    pub encoder: Option<String>,
+    pub play_count: i64,
+    pub last_played: Option<String>,
+    pub rating: i32,
+    /// Labels given to the song itself.
+    pub labels: Vec<String>,
+    /// Labels given to the album of the song.
+    pub album_labels: Vec<String>,
+    /// The library root the song was found in.
+    pub root: Option<String>,
+}
+
+/// Selects every column needed to build a `SongDetails`, joining performers and albums.
+/// Callers append their own `WHERE` and `ORDER BY` clauses.
+pub const SONG_QUERY: &str =
+    "SELECT rolas.title, COALESCE(rola_credits.credited, performers.name), albums.name, rolas.path, rolas.track,
+        rolas.year, rolas.genre, albums.cover, COALESCE(album_artists.name, performers.name),
+        rola_credits.remixers, rola_credits.composers, rolas.disc, rolas.disc_total, rolas.track_total,
+        rolas.composer, rolas.conductor, rolas.bpm, rolas.musical_key, rolas.comment, rolas.lyrics,
+        rolas.synced_lyrics, rolas.duration_ms, rolas.bitrate, rolas.sample_rate,
+        rolas.channel_mode, rolas.encoder, COALESCE(rolas.play_count, 0), rolas.last_played,
+        COALESCE(rolas.rating, 0),
+        (SELECT GROUP_CONCAT(labels.name, ',') FROM rola_labels JOIN labels ON rola_labels.id_label = labels.id_label
+            WHERE rola_labels.path = rolas.path),
+        (SELECT GROUP_CONCAT(labels.name, ',') FROM album_labels JOIN labels ON album_labels.id_label = labels.id_label
+            WHERE album_labels.album_path = albums.path),
+        library_roots.path
+    FROM rolas
+    LEFT JOIN performers ON rolas.id_performer = performers.id_performer
+    LEFT JOIN rola_credits ON rolas.id_rola = rola_credits.id_rola
+    LEFT JOIN albums ON rolas.id_album = albums.id_album
+    LEFT JOIN performers AS album_artists ON albums.id_album_artist = album_artists.id_performer
+    LEFT JOIN library_roots ON rolas.id_root = library_roots.id_root";
+
+/// Builds a `SongDetails` from a row selected with `SONG_QUERY`,
+/// using "Unknown" for a missing artist or album.
+pub fn song_from_row(row: &rusqlite::Row) -> Result<SongDetails> {
+    Ok(SongDetails {
+        title: row.get(0)?,
+        artist: row.get(1).unwrap_or_else(|_| String::from("Unknown")),
+        album: row.get(2).unwrap_or_else(|_| String::from("Unknown")),
+        path: row.get(3)?,
+        track_number: row.get(4)?,
+        year: row.get(5)?,
+        genre: row.get(6)?,
+        cover: row.get(7)?,
+        album_artist: row.get(8).unwrap_or_else(|_| String::from("Unknown")),
+        remixers: row.get(9)?,
+        composers: row.get(10)?,
+        disc: row.get(11)?,
+        disc_total: row.get(12)?,
+        track_total: row.get(13)?,
+        composer: row.get(14)?,
+        conductor: row.get(15)?,
+        bpm: row.get(16)?,
+        key: row.get(17)?,
+        comment: row.get(18)?,
+        lyrics: row.get(19)?,
+        synced_lyrics: row.get(20)?,
+        duration_ms: row.get(21)?,
+        bitrate: row.get(22)?,
+        sample_rate: row.get(23)?,
+        channel_mode: row.get(24)?,
+        encoder: row.get(25)?,
+        play_count: row.get(26)?,
+        last_played: row.get(27)?,
+        rating: row.get(28)?,
+        labels: parse_labels(&row.get::<_, Option<String>>(29)?.unwrap_or_default()),
+        album_labels: parse_labels(&row.get::<_, Option<String>>(30)?.unwrap_or_default()),
+        root: row.get(31)?,
+    })
+}
+
+/// # Parse Labels function
+///
+/// Splits comma-separated labels, trimming them and dropping empty and repeated ones.
+///
+/// # Arguments
+/// * `text` - The labels, e.g. `workout, vinyl-rip`.
+///
+/// # Returns
+/// The labels, in the order they were written.
+pub fn parse_labels(text: &str) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for label in text.split(',').map(str::trim).filter(|label| !label.is_empty()) {
+        if !labels.iter().any(|existing| existing == label) {
+            labels.push(label.to_string());
+        }
+    }
+    labels
+}
+
+/// Overall figures of the library.
+#[derive(Serialize)]
+pub struct LibraryStats {
+    pub songs: i64,
+    pub performers: i64,
+    pub albums: i64,
+    pub total_duration_ms: i64,
+    pub total_plays: i64,
+    pub rated_songs: i64,
+    /// The genres with their number of songs, most common first.
+    pub genres: Vec<(String, i64)>,
+}
+
+/// The outcome of `Library::scan()`.
+pub struct ScanReport {
+    /// The number of songs added.
+    pub added: usize,
+    /// The files and directories that could not be read or added, with the reason.
+    pub failures: Vec<LibraryError>,
+}
+
+/// A library root with its number of songs, as listed by `Library::roots()`.
+#[derive(Serialize)]
+pub struct RootSummary {
+    pub path: String,
+    pub enabled: bool,
+    pub excludes: Vec<String>,
+    pub songs: i64,
+}
+
+/// A change of the library, sent to the listeners registered with `Library::subscribe()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LibraryEvent {
+    /// Every song was removed, before a scan.
+    Cleared,
+    /// A song was added, by its path.
+    SongAdded(String),
+    /// A song was read again after its tags changed, by its path.
+    SongChanged(String),
+    /// A song was removed, by its path.
+    SongRemoved(String),
+}
+
+/// Returns the scanned directory containing a song: the library root of `Config.TOML` the
+/// song is inside, otherwise the song's own directory.
+pub fn library_root_of(path: &Path) -> PathBuf {
+    let roots = load_settings().map(|settings| settings.roots).unwrap_or_default();
+    roots.into_iter()
+        .map(|root| PathBuf::from(root.path))
+        .find(|directory| path.starts_with(directory))
+        .unwrap_or_else(|| path.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// Creates a connection to the SQLite database located at `~/.config/musicmanager/database.db`.
+///
+/// This function first ensures that the `database.db` file exists by calling `create_database_file()`.
+/// Then, it attempts to create an SQLite connection to this database file and attaches the
+/// listening history (`history.db`) as the `listening` schema. If successful,
+/// the connection object is returned for further database operations.
+///
+/// # Returns
+///
+/// - `Ok(Connection)` if the connection to the database was established successfully.
+/// - `Err(LibraryError)` - A configuration error if the database files cannot be created, or a
+///   database error carrying the file's path if it cannot be opened.
+pub fn create_database_connection() -> LibraryResult<Connection> {
+    let file_path = create_database_file().map_err(|e| LibraryError::config(None, e))?;
+    let history_path = create_history_file().map_err(|e| LibraryError::config(None, e))?;
+
+    let connection = Connection::open(&file_path).map_err(|e| LibraryError::from(e).with_path(&file_path))?;
+    connection.execute("ATTACH DATABASE ?1 AS listening", [history_path.to_string_lossy()])
+        .map_err(|e| LibraryError::from(e).with_path(&history_path))?;
+    Ok(connection)
+}
+
+/// # Reset Library function
+///
+/// Prepares a rescan of the library roots, as the Refresh button does: the directory given,
+/// if any, becomes the first root of `Config.TOML`, and the library is reset (so its tables
+/// are recreated with the current columns). The listening history, ratings and labels are
+/// kept in `history.db`.
+///
+/// # Arguments
+/// * `library` - The library to reset.
+/// * `directory` - A music directory to add to the roots, or `None` to scan the roots as they are.
+///
+/// # Returns
+/// * `Ok(Vec<LibraryRoot>)` - The roots of `Config.TOML`, to be scanned with `Library::scan()`.
+/// * `Err(LibraryError)` - If the directory does not exist, no root is enabled, or the
+///   database or the configuration could not be read or written.
+pub fn reset_library(library: &Library, directory: Option<&str>) -> LibraryResult<Vec<LibraryRoot>> {
+    if let Some(directory) = directory {
+        let path = Path::new(directory);
+        if !path.is_dir() {
+            return Err(LibraryError::validation(Some(path), "Not a directory"));
+        }
+        save_directory_to_config(directory).map_err(|e| LibraryError::config(None, e))?;
+    }
+    let settings = load_settings().map_err(|e| LibraryError::config(None, e))?;
+    if settings.enabled_roots().is_empty() {
+        return Err(LibraryError::validation(None, "No music directory is enabled"));
+    }
+    library.reset()?;
+    Ok(settings.roots)
+}
+
+/// Saves the provided music directory path to a configuration file.
+///
+/// Makes the directory the first of the `roots` of `Config.TOML`, enabled, keeping the
+/// other settings of the file. If the file or directory does not exist, it is created.
+///
+/// # Arguments
+/// * `directory` - The path to the music directory to save.
+///
+/// # Returns
+/// * `Ok(())` - On success.
+/// * `Err(io::Error)` - If there is an issue creating the config directory or writing to the file.
+pub fn save_directory_to_config(directory: &str) -> io::Result<()> {
+    update_settings(|settings| settings.set_music_directory(directory))
+}
+
+/// A function called with every change of the library.
+type Listener = Box<dyn Fn(&LibraryEvent)>;
+
+/// # Library struct
+///
+/// The music library, independent of the interface: it owns a connection to the database
+/// (with the listening history attached as the `listening` schema) and offers the scanning,
+/// querying and editing used by the window, the command line and the tests. Every change is
+/// sent to the listeners registered with `subscribe()`.
+pub struct Library {
+    connection: Connection,
+    listeners: Vec<Listener>,
+}
+
+impl Library {
+    /// Opens the library stored in `~/.config/musicmanager`, creating its files and tables
+    /// if needed.
+    pub fn open() -> LibraryResult<Library> {
+        Library::with_connection(create_database_connection()?)
+    }
+
+    /// Opens an empty library held in memory, for tests.
+    #[cfg(test)]
+    pub fn open_in_memory() -> LibraryResult<Library> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute("ATTACH DATABASE ':memory:' AS listening", [])?;
+        Library::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> LibraryResult<Library> {
+        create_all_tables(&connection)?;
+        register_search_functions(&connection)?;
+        Ok(Library { connection, listeners: Vec::new() })
+    }
+
+    /// The connection to the database, for the queries the library does not offer.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Registers a function called with every change of the library.
+    pub fn subscribe(&mut self, listener: impl Fn(&LibraryEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    fn notify(&self, event: LibraryEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+
+    /// Runs `SONG_QUERY` with a clause appended.
+    fn query_songs(&self, clause: &str, params: impl rusqlite::Params) -> LibraryResult<Vec<SongDetails>> {
+        let mut stmt = self.connection.prepare(&format!("{} {}", SONG_QUERY, clause))?;
+        let rows = stmt.query_map(params, song_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>>>()?)
+    }
+
+    /// Returns every song of the library.
+    pub fn songs(&self) -> LibraryResult<Vec<SongDetails>> {
+        self.query_songs("", [])
+    }
+
+    /// Returns the songs matching a search query, written in the language of the search entry
+    /// (see `build_search_clause()`).
+    pub fn search(&self, query: &str) -> LibraryResult<Vec<SongDetails>> {
+        let (clause, params) = build_search_clause(query);
+        self.query_songs(&clause, rusqlite::params_from_iter(params))
+    }
+
+    /// Returns the song stored with the given path, if any.
+    pub fn song_by_path(&self, path: &str) -> LibraryResult<Option<SongDetails>> {
+        Ok(self.connection.query_row(&format!("{} WHERE rolas.path = ?1", SONG_QUERY), [path], song_from_row).optional()?)
+    }
+
+    /// Returns the songs of an album, ordered by track number.
+    pub fn album_songs(&self, album_id: i64) -> LibraryResult<Vec<SongDetails>> {
+        self.query_songs("WHERE rolas.id_album = ?1 ORDER BY rolas.track", [album_id])
+    }
+
+    /// Returns the songs of a view of the library (see `controller::get_songs_in_view()`).
+    pub fn songs_in_view(&self, view: SongView) -> LibraryResult<Vec<SongDetails>> {
+        Ok(controller::get_songs_in_view(&self.connection, view)?)
+    }
+
+    /// Returns every album, ordered by name and year.
+    pub fn albums(&self) -> LibraryResult<Vec<AlbumDetails>> {
+        Ok(controller::get_albums_from_database(&self.connection)?)
+    }
+
+    /// Counts the songs, performers and albums of the library, their total duration and
+    /// plays, and the songs of every genre.
+    pub fn stats(&self) -> LibraryResult<LibraryStats> {
+        let (songs, total_duration_ms, total_plays, rated_songs) = self.connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(duration_ms), 0), COALESCE(SUM(play_count), 0),
+                COUNT(CASE WHEN rating > 0 THEN 1 END)
+             FROM rolas",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let performers = self.connection.query_row(
+            "SELECT COUNT(DISTINCT id_performer) FROM rolas WHERE id_performer IS NOT NULL", [], |row| row.get(0))?;
+        let albums = self.connection.query_row("SELECT COUNT(*) FROM albums", [], |row| row.get(0))?;
+
+        let mut stmt = self.connection.prepare(
+            "SELECT COALESCE(genre, 'Unknown'), COUNT(*) FROM rolas GROUP BY 1 ORDER BY 2 DESC, 1"
+        )?;
+        let genres = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<Vec<_>>>()?;
+
+        Ok(LibraryStats { songs, performers, albums, total_duration_ms, total_plays, rated_songs, genres })
+    }
+
+    /// Adds a song from the tag map built by the miner (see `miner::extract()`).
+    pub fn add_song(&self, tag_map: HashMap<String, String>) -> LibraryResult<()> {
+        let path = tag_map.get("Path").cloned().unwrap_or_default();
+        populate_database(&self.connection, tag_map)?;
+        self.notify(LibraryEvent::SongAdded(path));
+        Ok(())
+    }
+
+    /// Removes a song from the library. Its file, history, rating and labels are kept.
+    pub fn remove_song(&self, path: &str) -> LibraryResult<()> {
+        populate_db::remove_rola(&self.connection, path).map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+        self.notify(LibraryEvent::SongRemoved(path.to_string()));
+        Ok(())
+    }
+
+    /// Removes every song, performer, album and root from the library, keeping the listening
+    /// history, ratings, labels and fingerprints stored in the `listening` schema.
+    pub fn clear(&self) -> LibraryResult<()> {
+        self.connection.execute_batch(
+            "DELETE FROM rola_performers;
+             DELETE FROM field_sources;
+             DELETE FROM rolas;
+             DELETE FROM albums;
+             DELETE FROM performers;
+             DELETE FROM library_roots;"
+        )?;
+        self.notify(LibraryEvent::Cleared);
+        Ok(())
+    }
+
+    /// Removes every song, performer, album and root from the library and recreates their
+    /// tables with the current columns, before a rescan. The listening history, ratings,
+    /// labels and fingerprints of the `listening` schema are kept.
+    pub fn reset(&self) -> LibraryResult<()> {
+        self.connection.execute_batch(
+            "DROP VIEW IF EXISTS main.rola_credits;
+             DROP TABLE IF EXISTS main.rola_performers;
+             DROP TABLE IF EXISTS main.field_sources;
+             DROP TABLE IF EXISTS main.rolas;
+             DROP TABLE IF EXISTS main.in_group;
+             DROP TABLE IF EXISTS main.groups;
+             DROP TABLE IF EXISTS main.persons;
+             DROP TABLE IF EXISTS main.albums;
+             DROP TABLE IF EXISTS main.performers;
+             DROP TABLE IF EXISTS main.library_roots;
+             DROP TABLE IF EXISTS main.types;"
+        )?;
+        create_all_tables(&self.connection)?;
+        self.notify(LibraryEvent::Cleared);
+        Ok(())
+    }
+
+    /// # Scan method
+    ///
+    /// Replaces the songs of the library with the songs found in its roots. Every root is
+    /// stored in the `library_roots` table, but only the enabled ones are scanned, leaving out
+    /// the files matching their exclude patterns (see `miner::extract()`).
+    ///
+    /// # Arguments
+    /// * `roots` - The library roots, usually the `roots` of `Config.TOML`.
+    /// * `progress` - Called with the number of songs added so far and the number of songs found.
+    ///
+    /// # Returns
+    /// * `Ok(ScanReport)` - The number of songs added, and the files that could not be read or added.
+    /// * `Err(LibraryError)` - If the library could not be cleared or a root could not be stored.
+    pub fn scan(&self, roots: &[LibraryRoot], mut progress: impl FnMut(usize, usize)) -> LibraryResult<ScanReport> {
+        self.clear()?;
+        let mut extracted_data = Vec::new();
+        let mut failures = Vec::new();
+        for root in roots {
+            populate_db::insert_or_update_root(&self.connection, root)
+                .map_err(|e| LibraryError::from(e).with_path(Path::new(&root.path)))?;
+            if !root.enabled {
+                continue;
+            }
+            log::info!(path = root.path.as_str(); "Scanning the library root");
+            let (root_data, root_failures) = miner::extract(root);
+            extracted_data.extend(root_data);
+            failures.extend(root_failures);
+        }
+
+        let total = extracted_data.len();
+        let mut added = 0;
+        for (index, tag_map) in extracted_data.into_iter().enumerate() {
+            progress(index, total);
+            match self.add_song(tag_map) {
+                Ok(()) => added += 1,
+                Err(e) => failures.push(e),
+            }
+        }
+        progress(total, total);
+        for failure in &failures {
+            let path = failure.path().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default();
+            log::warn!(path = path.as_str(); "{} error: {}", failure.kind(), failure.message());
+        }
+        log::info!("Scan finished: {} song(s) added, {} failure(s)", added, failures.len());
+        Ok(ScanReport { added, failures })
+    }
+
+    /// Returns the roots the library was scanned from, with their number of songs.
+    pub fn roots(&self) -> LibraryResult<Vec<RootSummary>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT library_roots.path, library_roots.enabled, library_roots.excludes, COUNT(rolas.id_rola)
+             FROM library_roots LEFT JOIN rolas ON rolas.id_root = library_roots.id_root
+             GROUP BY library_roots.id_root ORDER BY library_roots.id_root"
+        )?;
+        let roots = stmt.query_map([], |row| {
+            let excludes: Option<String> = row.get(2)?;
+            Ok(RootSummary {
+                path: row.get(0)?,
+                enabled: row.get(1)?,
+                excludes: excludes.unwrap_or_default().lines().map(str::to_string).collect(),
+                songs: row.get(3)?,
+            })
+        })?;
+        Ok(roots.collect::<Result<Vec<_>>>()?)
+    }
+
+    /// Removes a library root together with its songs, keeping their files, history,
+    /// ratings and labels.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of songs removed.
+    /// * `Err(LibraryError)` - If the database could not be changed.
+    pub fn remove_root(&self, path: &str) -> LibraryResult<usize> {
+        let removed = populate_db::remove_root(&self.connection, path)
+            .map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+        for song_path in &removed {
+            self.notify(LibraryEvent::SongRemoved(song_path.clone()));
+        }
+        log::info!(path = path; "Removed the library root and its {} song(s)", removed.len());
+        Ok(removed.len())
+    }
+
+    /// Reads a song again and replaces its row, e.g. after its tags were written.
+    pub fn rescan(&self, path: &str) -> LibraryResult<()> {
+        let song_path = Path::new(path);
+        let tag_map = miner::extract_song(song_path, &library_root_of(song_path))?;
+        populate_db::remove_rola(&self.connection, path).map_err(|e| LibraryError::from(e).with_path(song_path))?;
+        populate_database(&self.connection, tag_map)?;
+        self.notify(LibraryEvent::SongChanged(path.to_string()));
+        Ok(())
+    }
+
+    /// # Set Tags method
+    ///
+    /// Writes fields into the tag of a song with `tag_writer::write_fields()` and reads the
+    /// song again.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the MP3 file.
+    /// * `fields` - The values to write, by tag map key (`Title`, `Track Number`…).
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the tag was written and the song read again.
+    /// * `Err(LibraryError)` - If the tag cannot be written (only MP3 and FLAC files can) or read again.
+    pub fn set_tags(&self, path: &str, fields: &HashMap<String, String>) -> LibraryResult<()> {
+        tag_writer::write_fields(Path::new(path), fields).map_err(|e| LibraryError::tag(Path::new(path), e))?;
+        self.rescan(path)
+    }
+
+    /// Records a playback of a song in the listening history.
+    ///
+    /// Used by the player when a song ends or is skipped, and available to external tools that
+    /// report songs played elsewhere.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the song.
+    /// * `fraction` - The fraction of the song listened to, between 0 and 1.
+    /// * `source` - What reported the playback, e.g. `player`.
+    pub fn record_play(&self, path: &str, fraction: f64, source: &str) -> LibraryResult<()> {
+        populate_db::record_play(&self.connection, path, fraction, source)
+            .map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+        self.notify(LibraryEvent::SongChanged(path.to_string()));
+        Ok(())
+    }
+
+    /// # Rate Song method
+    ///
+    /// Rates a song, and optionally writes the rating to its tag.
+    ///
+    /// The rating is always stored in the database first, so it is kept even if the tag cannot
+    /// be written (e.g. for formats other than MP3 and FLAC, or read-only files).
+    ///
+    /// # Arguments
+    /// * `path` - The path of the song.
+    /// * `rating` - The number of stars, from 0 (unrated) to 5.
+    /// * `write_tag` - Whether to also write the POPM and FMPS_Rating frames (or FMPS_RATING comment) of the song.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the rating was stored (and written, when asked).
+    /// * `Err(LibraryError)` - If the database could not be updated or the tag could not be written.
+    pub fn rate_song(&self, path: &str, rating: i32, write_tag: bool) -> LibraryResult<()> {
+        let rating = rating.clamp(0, MAX_RATING);
+        populate_db::set_rating(&self.connection, path, rating).map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+        self.notify(LibraryEvent::SongChanged(path.to_string()));
+
+        if write_tag {
+            rating::write_rating(Path::new(path), rating).map_err(|e| LibraryError::tag(Path::new(path), e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns every label given to a song or album, sorted by name.
+    pub fn all_labels(&self) -> LibraryResult<Vec<String>> {
+        Ok(controller::get_all_labels(&self.connection)?)
+    }
+
+    /// Returns the labels of a song or album, sorted by name.
+    pub fn labels(&self, target: &LabelTarget) -> LibraryResult<Vec<String>> {
+        Ok(controller::get_labels(&self.connection, target)?)
+    }
+
+    /// Replaces the labels of a song or album. Every song concerned is sent as changed.
+    pub fn save_labels(&self, target: &LabelTarget, labels: &[String]) -> LibraryResult<()> {
+        let changed = match target {
+            LabelTarget::Song(path) => {
+                populate_db::set_song_labels(&self.connection, path, labels)
+                    .map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+                vec![path.clone()]
+            }
+            LabelTarget::Album(album_path) => {
+                let with_path = |e| LibraryError::from(e).with_path(Path::new(album_path));
+                populate_db::set_album_labels(&self.connection, album_path, labels).map_err(with_path)?;
+                controller::get_album_song_paths(&self.connection, album_path).map_err(with_path)?
+            }
+        };
+        for path in changed {
+            self.notify(LibraryEvent::SongChanged(path));
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the album directory of a song, used to label its album, or `None`
+    /// if the song is not in the library or has no album.
+    pub fn album_path(&self, song_path: &str) -> LibraryResult<Option<String>> {
+        Ok(controller::get_album_path(&self.connection, song_path)?)
+    }
+
+    /// # Save Synced Lyrics method
+    ///
+    /// Saves synchronised lyrics for a song and stores them in the database.
+    ///
+    /// # Arguments
+    /// * `song_path` - The path of the song file.
+    /// * `lines` - The time-coded lines to save.
+    /// * `target` - Whether to write an `.lrc` sidecar file or a SYLT frame.
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the lyrics were written and the database was updated.
+    /// * `Err(LibraryError)` - If the file or tag could not be written, or the database update failed.
+    pub fn save_synced_lyrics(&self, song_path: &str, lines: &[LyricLine], target: LyricsTarget) -> LibraryResult<()> {
+        let path = Path::new(song_path);
+        match target {
+            LyricsTarget::Sidecar => {
+                synced_lyrics::save_lrc_sidecar(path, lines).map_err(|e| LibraryError::io(&synced_lyrics::sidecar_path(path), e))?;
+            }
+            LyricsTarget::Tag => {
+                synced_lyrics::save_sylt(path, lines).map_err(|e| LibraryError::tag(path, e))?;
+            }
+        }
+
+        self.connection.execute(
+            "UPDATE rolas SET synced_lyrics = ?1 WHERE path = ?2",
+            rusqlite::params![synced_lyrics::to_lrc(lines), song_path],
+        ).map_err(|e| LibraryError::from(e).with_path(path))?;
+        self.notify(LibraryEvent::SongChanged(song_path.to_string()));
+        Ok(())
+    }
+
+    /// Returns the `id_album` and name of the albums whose loudness has not been analysed yet.
+    pub fn albums_missing_loudness(&self) -> LibraryResult<Vec<(i64, String)>> {
+        Ok(controller::get_albums_missing_loudness(&self.connection)?)
+    }
+
+    /// Stores the ReplayGain values of the songs of an album, as measured by
+    /// `controller::analyse_loudness()`, and the values of the album.
+    pub fn store_loudness(&self, album_id: i64, gains: &[(String, ReplayGain)]) -> LibraryResult<()> {
+        for (path, gain) in gains {
+            self.connection.execute(
+                "UPDATE rolas SET track_gain = ?1, track_peak = ?2 WHERE path = ?3",
+                rusqlite::params![gain.track_gain, gain.track_peak, path],
+            ).map_err(|e| LibraryError::from(e).with_path(Path::new(path)))?;
+            self.notify(LibraryEvent::SongChanged(path.clone()));
+        }
+
+        if let Some((_, gain)) = gains.last() {
+            populate_db::set_album_loudness(&self.connection, album_id, gain.album_gain, gain.album_peak)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the songs to compare with `controller::find_duplicates()`.
+    pub fn duplicate_candidates(&self) -> LibraryResult<Vec<DuplicateCandidate>> {
+        Ok(controller::get_duplicate_candidates(&self.connection)?)
+    }
+
+    /// Returns every song with the stamp of its cached fingerprint, to be checked with
+    /// `controller::compute_fingerprints()`.
+    pub fn fingerprint_stamps(&self) -> LibraryResult<Vec<FingerprintStamp>> {
+        Ok(controller::get_fingerprint_stamps(&self.connection)?)
+    }
+
+    /// Caches fingerprints computed by `controller::compute_fingerprints()` in `history.db`.
+    pub fn store_fingerprints(&self, fingerprints: &[ComputedFingerprint]) -> LibraryResult<()> {
+        for computed in fingerprints {
+            populate_db::set_fingerprint(&self.connection, &computed.path, computed.file_size, computed.modified,
+                &fingerprint::to_bytes(&computed.values))
+                .map_err(|e| LibraryError::from(e).with_path(Path::new(&computed.path)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the groups of songs that are the same recording, judging by their cached
+    /// fingerprints (see `controller::get_same_recordings()`).
+    pub fn same_recordings(&self) -> LibraryResult<Vec<Vec<RecordingCopy>>> {
+        Ok(controller::get_same_recordings(&self.connection)?)
+    }
+
+    /// Returns the albums with their songs, to be matched to MusicBrainz releases with
+    /// `controller::match_albums()`.
+    pub fn albums_to_match(&self) -> LibraryResult<Vec<LocalAlbum>> {
+        Ok(controller::get_albums_to_match(&self.connection)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Builds the tag map of a song as the miner would, with the fields `populate_database()` needs.
+    fn tag_map(path: &str, title: &str, artist: &str, album: &str, year: i32, track: i32) -> HashMap<String, String> {
+        let album_path = Path::new(path).parent().unwrap().to_string_lossy().into_owned();
+        HashMap::from([
+            ("Path".to_string(), path.to_string()),
+            ("AlbumPath".to_string(), album_path),
+            ("Title".to_string(), title.to_string()),
+            ("Artist".to_string(), artist.to_string()),
+            ("Album".to_string(), album.to_string()),
+            ("Year".to_string(), year.to_string()),
+            ("Genre".to_string(), "Rock".to_string()),
+            ("Track Number".to_string(), track.to_string()),
+        ])
+    }
+
+    /// A library with two songs of one album and one song of another.
+    fn sample_library() -> Library {
+        let library = Library::open_in_memory().unwrap();
+        library.add_song(tag_map("/music/Queen/Opera/01.mp3", "Death on Two Legs", "Queen", "A Night at the Opera", 1975, 1)).unwrap();
+        library.add_song(tag_map("/music/Queen/Opera/11.mp3", "Bohemian Rhapsody", "Queen", "A Night at the Opera", 1975, 11)).unwrap();
+        library.add_song(tag_map("/music/Bowie/Low/01.mp3", "Speed of Life", "David Bowie", "Low", 1977, 1)).unwrap();
+        library
+    }
+
+    #[test]
+    fn added_songs_are_listed_with_their_fields() {
+        let library = sample_library();
+        assert_eq!(library.songs().unwrap().len(), 3);
+
+        let song = library.song_by_path("/music/Queen/Opera/11.mp3").unwrap().unwrap();
+        assert_eq!(song.title, "Bohemian Rhapsody");
+        assert_eq!(song.artist, "Queen");
+        assert_eq!(song.album, "A Night at the Opera");
+        assert_eq!(song.track_number, 11);
+        assert_eq!(song.year, 1975);
+
+        assert!(library.song_by_path("/music/missing.mp3").unwrap().is_none());
+        assert_eq!(library.song_by_path("/music/Bowie/Low/01.mp3").unwrap().unwrap().title, "Speed of Life");
+    }
+
+    #[test]
+    fn search_uses_the_query_language() {
+        let library = sample_library();
+        let titles = |query: &str| {
+            let mut titles: Vec<String> = library.search(query).unwrap().into_iter().map(|song| song.title).collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(titles("n:queen"), ["Bohemian Rhapsody", "Death on Two Legs"]);
+        assert_eq!(titles("y:>1976"), ["Speed of Life"]);
+        assert_eq!(titles("n:queen && tr:11"), ["Bohemian Rhapsody"]);
+        assert!(titles("n:abba").is_empty());
+    }
+
+    #[test]
+    fn search_ignores_case_in_every_alphabet() {
+        let library = sample_library();
+        library.add_song(tag_map("/music/Émile/Valses/01.mp3", "Les Patineurs", "Émile Waldteufel", "Valses", 1882, 1)).unwrap();
+        library.add_song(tag_map("/music/Ωmega/Ένα/01.mp3", "ΆΛΦΑ", "Ωmega", "Ένα", 1990, 1)).unwrap();
+        let titles = |query: &str| library.search(query).unwrap().into_iter().map(|song| song.title).collect::<Vec<_>>();
+
+        assert_eq!(titles("n:émile"), ["Les Patineurs"]);
+        assert_eq!(titles("N:ÉMILE && T:patineurs"), ["Les Patineurs"]);
+        assert_eq!(titles("άλφα"), ["ΆΛΦΑ"]);
+        assert_eq!(titles("A:ένα"), ["ΆΛΦΑ"]);
+        assert_eq!(titles("Y:1882"), ["Les Patineurs"]);
+        assert!(titles("100%").is_empty());
+    }
+
+    #[test]
+    fn songs_without_a_title_are_reported_with_their_path() {
+        let library = sample_library();
+        let mut untitled = tag_map("/music/Bowie/Low/02.mp3", "", "David Bowie", "Low", 1977, 2);
+        untitled.remove("Title");
+
+        let error = library.add_song(untitled).unwrap_err();
+        assert!(matches!(error, LibraryError::Validation { .. }));
+        assert_eq!(error.path(), Some(Path::new("/music/Bowie/Low/02.mp3")));
+        assert_eq!(library.songs().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn stats_count_songs_albums_and_genres() {
+        let stats = sample_library().stats().unwrap();
+        assert_eq!(stats.songs, 3);
+        assert_eq!(stats.performers, 2);
+        assert_eq!(stats.albums, 2);
+        assert_eq!(stats.genres, [("Rock".to_string(), 3)]);
+    }
+
+    #[test]
+    fn credits_are_listed_in_credit_order() {
+        let library = Library::open_in_memory().unwrap();
+        let mut song = tag_map("/music/z/1.mp3", "Song", "Zed; Abe feat. Cy", "First", 2000, 1);
+        song.insert("Credits".to_string(), "main=Zed\nmain=Abe\nfeatured=Cy\ncomposer=Yo\ncomposer=Al".to_string());
+        library.add_song(song).unwrap();
+
+        let song = library.song_by_path("/music/z/1.mp3").unwrap().unwrap();
+        assert_eq!(song.artist, "Zed, Abe feat. Cy");
+        assert_eq!(song.composers.as_deref(), Some("Yo, Al"));
+    }
+
+    #[test]
+    fn compilations_are_kept_apart_by_directory_but_not_by_disc() {
+        let library = Library::open_in_memory().unwrap();
+        for (path, artist) in [("/music/Hits A/01.mp3", "Queen"), ("/music/Hits B/01.mp3", "ABBA"),
+            ("/music/Now/CD1/01.mp3", "Blur"), ("/music/Now/Disc 2/01.mp3", "Oasis")] {
+            let album = if path.starts_with("/music/Now") { "Now" } else { "Greatest Hits" };
+            let mut song = tag_map(path, "Song", artist, album, 2000, 1);
+            song.insert("Compilation".to_string(), "1".to_string());
+            library.add_song(song).unwrap();
+        }
+
+        assert_eq!(library.stats().unwrap().albums, 3);
+        let album_path = |song: &str| library.connection().query_row(
+            "SELECT albums.path FROM rolas JOIN albums ON rolas.id_album = albums.id_album WHERE rolas.path = ?1",
+            [song], |row| row.get::<_, String>(0)
+        ).unwrap();
+        assert_eq!(album_path("/music/Now/Disc 2/01.mp3"), "/music/Now");
+        assert_eq!(album_path("/music/Hits B/01.mp3"), "/music/Hits B");
+    }
+
+    #[test]
+    fn listeners_receive_every_change() {
+        let mut library = Library::open_in_memory().unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        {
+            let events = Rc::clone(&events);
+            library.subscribe(move |event| events.borrow_mut().push(event.clone()));
+        }
+
+        library.add_song(tag_map("/music/a/1.mp3", "One", "A", "First", 2000, 1)).unwrap();
+        library.remove_song("/music/a/1.mp3").unwrap();
+        library.clear().unwrap();
+
+        assert_eq!(*events.borrow(), [
+            LibraryEvent::SongAdded("/music/a/1.mp3".to_string()),
+            LibraryEvent::SongRemoved("/music/a/1.mp3".to_string()),
+            LibraryEvent::Cleared,
+        ]);
+        assert!(library.songs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn clearing_keeps_ratings_and_labels() {
+        let library = sample_library();
+        let path = "/music/Queen/Opera/11.mp3";
+        populate_db::set_rating(library.connection(), path, 5).unwrap();
+        populate_db::set_song_labels(library.connection(), path, &["epic".to_string()]).unwrap();
+
+        library.clear().unwrap();
+        assert!(library.songs().unwrap().is_empty());
+        assert_eq!(library.stats().unwrap().albums, 0);
+
+        library.add_song(tag_map(path, "Bohemian Rhapsody", "Queen", "A Night at the Opera", 1975, 11)).unwrap();
+        let song = library.song_by_path(path).unwrap().unwrap();
+        assert_eq!(song.rating, 5);
+        assert_eq!(song.labels, ["epic"]);
+    }
+
+    #[test]
+    fn resetting_recreates_the_tables_and_keeps_ratings() {
+        let library = sample_library();
+        let path = "/music/Bowie/Low/01.mp3";
+        library.rate_song(path, 4, false).unwrap();
+
+        library.reset().unwrap();
+        assert!(library.songs().unwrap().is_empty());
+        assert!(library.roots().unwrap().is_empty());
+
+        library.add_song(tag_map(path, "Speed of Life", "David Bowie", "Low", 1977, 1)).unwrap();
+        assert_eq!(library.song_by_path(path).unwrap().unwrap().rating, 4);
+    }
+
+    #[test]
+    fn edits_are_sent_as_changes() {
+        let mut library = sample_library();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        {
+            let events = Rc::clone(&events);
+            library.subscribe(move |event| events.borrow_mut().push(event.clone()));
+        }
+        let path = "/music/Queen/Opera/01.mp3";
+
+        library.rate_song(path, 9, false).unwrap();
+        library.record_play(path, 1.0, "player").unwrap();
+        let album_path = library.album_path(path).unwrap().unwrap();
+        library.save_labels(&LabelTarget::Album(album_path.clone()), &["opera".to_string()]).unwrap();
+
+        let song = library.song_by_path(path).unwrap().unwrap();
+        assert_eq!((song.rating, song.play_count), (MAX_RATING, 1));
+        assert_eq!(library.labels(&LabelTarget::Album(album_path)).unwrap(), ["opera"]);
+        assert_eq!(library.all_labels().unwrap(), ["opera"]);
+        assert_eq!(events.borrow().len(), 4);
+        assert!(events.borrow().iter().all(|event| matches!(event, LibraryEvent::SongChanged(_))));
+    }
+
+    #[test]
+    fn removing_a_root_removes_its_songs() {
+        let mut library = Library::open_in_memory().unwrap();
+        let mut nas = LibraryRoot::new("/mnt/nas");
+        nas.excludes = vec!["**/Samples/**".to_string()];
+        populate_db::insert_or_update_root(library.connection(), &LibraryRoot::new("/music")).unwrap();
+        populate_db::insert_or_update_root(library.connection(), &nas).unwrap();
+        for (root, path, title) in [("/music", "/music/a/1.mp3", "One"), ("/mnt/nas", "/mnt/nas/b/2.mp3", "Two"),
+            ("/mnt/nas", "/mnt/nas/b/3.mp3", "Three")] {
+            let mut song = tag_map(path, title, "A", "First", 2000, 1);
+            song.insert("Root".to_string(), root.to_string());
+            library.add_song(song).unwrap();
+        }
+        assert_eq!(library.song_by_path("/mnt/nas/b/2.mp3").unwrap().unwrap().root.as_deref(), Some("/mnt/nas"));
+
+        let roots = library.roots().unwrap();
+        assert_eq!(roots.len(), 2);
+        assert_eq!((roots[1].path.as_str(), roots[1].songs), ("/mnt/nas", 2));
+        assert_eq!(roots[1].excludes, ["**/Samples/**"]);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        {
+            let events = Rc::clone(&events);
+            library.subscribe(move |event| events.borrow_mut().push(event.clone()));
+        }
+        assert_eq!(library.remove_root("/mnt/nas").unwrap(), 2);
+        assert_eq!(events.borrow().len(), 2);
+        assert_eq!(library.songs().unwrap().len(), 1);
+        assert_eq!(library.roots().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_labels_trims_and_drops_repeats() {
+        assert_eq!(parse_labels(" workout, vinyl-rip,, workout "), ["workout", "vinyl-rip"]);
+        assert!(parse_labels(" , ").is_empty());
+    }
+}
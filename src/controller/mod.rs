@@ -1,2 +1,4 @@
 pub mod controller; 
 pub mod extra_tags; 
+pub mod search;
+pub mod library;
@@ -0,0 +1,158 @@
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+/// How the value after a search prefix is compared with its column.
+#[derive(Clone, Copy, PartialEq)]
+enum FieldKind {
+    /// Case-insensitive substring match, for every alphabet (see `register_search_functions()`).
+    Text,
+    /// Exact number, comparison (`>2000`, `<=120`) or inclusive range (`1990-1999`).
+    Number,
+}
+
+/// The prefixes of the query language, with the SQL expression each one filters on.
+/// The expressions refer to the tables and aliases joined by `SONG_QUERY`.
+const SEARCH_FIELDS: [(&str, &str, FieldKind); 20] = [
+    ("n:", "COALESCE(rola_credits.credited, performers.name)", FieldKind::Text),
+    ("t:", "rolas.title", FieldKind::Text),
+    ("a:", "albums.name", FieldKind::Text),
+    ("g:", "rolas.genre", FieldKind::Text),
+    ("y:", "rolas.year", FieldKind::Number),
+    ("tr:", "rolas.track", FieldKind::Number),
+    ("d:", "rolas.disc", FieldKind::Number),
+    ("c:", "rolas.composer", FieldKind::Text),
+    ("cd:", "rolas.conductor", FieldKind::Text),
+    ("bpm:", "rolas.bpm", FieldKind::Number),
+    ("k:", "rolas.musical_key", FieldKind::Text),
+    ("cm:", "rolas.comment", FieldKind::Text),
+    ("ly:", "rolas.lyrics", FieldKind::Text),
+    ("aa:", "COALESCE(album_artists.name, performers.name)", FieldKind::Text),
+    ("len:", "rolas.duration_ms / 1000", FieldKind::Number),
+    ("br:", "rolas.bitrate", FieldKind::Number),
+    ("pc:", "COALESCE(rolas.play_count, 0)", FieldKind::Number),
+    ("lp:", "CAST(julianday('now') - julianday(rolas.last_played) AS INTEGER)", FieldKind::Number),
+    ("r:", "COALESCE(rolas.rating, 0)", FieldKind::Number),
+    ("l:", "(SELECT GROUP_CONCAT(labels.name, ',') FROM labels WHERE labels.id_label IN (
+        SELECT id_label FROM rola_labels WHERE rola_labels.path = rolas.path
+        UNION SELECT id_label FROM album_labels WHERE album_labels.album_path = albums.path))", FieldKind::Text),
+];
+
+/// Name of the SQL function lower-casing text with the Unicode rules of Rust, since the
+/// `lower()` and `LIKE` of SQLite only fold ASCII letters.
+const FOLD_CASE_FUNCTION: &str = "fold_case";
+
+/// # Register Search Functions function
+///
+/// Adds to a connection the `fold_case(text)` function used by the clauses of
+/// `build_search_clause()`, so `émile` finds `Émile`.
+///
+/// # Arguments
+/// * `connection` - The connection the searches run on.
+///
+/// # Returns
+/// * `Ok(())` - If the function was registered.
+/// * `Err(rusqlite::Error)` - If SQLite refused it.
+pub fn register_search_functions(connection: &Connection) -> rusqlite::Result<()> {
+    connection.create_scalar_function(
+        FOLD_CASE_FUNCTION,
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |context| Ok(context.get::<Option<String>>(0)?.map(|text| text.to_lowercase())),
+    )
+}
+
+/// Builds the condition matching the rows whose `column` contains `value`, ignoring case.
+fn text_condition(column: &str, value: &str, params: &mut Vec<Value>) -> String {
+    params.push(Value::Text(value.to_lowercase()));
+    format!("instr({}({}), ?) > 0", FOLD_CASE_FUNCTION, column)
+}
+
+/// Splits a term such as `cm:live` into its field and value, ignoring the case of the prefix.
+/// Longer prefixes are tried first so `cd:` is not mistaken for `c:`.
+fn split_prefix(term: &str) -> Option<(&'static str, FieldKind, &str)> {
+    let mut fields = SEARCH_FIELDS;
+    fields.sort_by_key(|(prefix, _, _)| std::cmp::Reverse(prefix.len()));
+
+    fields.iter()
+        .find(|(prefix, _, _)| term.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix)))
+        .map(|(prefix, column, kind)| (*column, *kind, &term[prefix.len()..]))
+}
+
+/// Builds the SQL condition for a numeric field, or `None` if the value is not a number,
+/// comparison or range.
+fn number_condition(column: &str, value: &str, params: &mut Vec<Value>) -> Option<String> {
+    for operator in [">=", "<=", ">", "<", "="] {
+        if let Some(number) = value.strip_prefix(operator) {
+            let number: i64 = number.trim().parse().ok()?;
+            params.push(Value::Integer(number));
+            return Some(format!("{} {} ?", column, operator));
+        }
+    }
+
+    if let Some((low, high)) = value.split_once('-') {
+        let low: i64 = low.trim().parse().ok()?;
+        let high: i64 = high.trim().parse().ok()?;
+        params.push(Value::Integer(low));
+        params.push(Value::Integer(high));
+        return Some(format!("{} BETWEEN ? AND ?", column));
+    }
+
+    let number: i64 = value.parse().ok()?;
+    params.push(Value::Integer(number));
+    Some(format!("{} = ?", column))
+}
+
+/// # Build Search Clause function
+///
+/// Translates a search query into an SQL `WHERE` clause for `SONG_QUERY`, to be run on a
+/// connection set up with `register_search_functions()`.
+///
+/// Terms are separated by `&&` and must all match. A term starts with a prefix naming the
+/// field it searches: `n:` artist, `t:` title, `a:` album, `g:` genre, `y:` year, `tr:` track,
+/// `d:` disc, `c:` composer, `cd:` conductor, `bpm:` BPM, `k:` key, `cm:` comment, `ly:` lyrics
+/// `aa:` album artist, `len:` duration in seconds, `br:` bitrate in kbps, `pc:` play count,
+/// `lp:` days since last played, `r:` rating in stars and `l:` label of the song or its album. Text fields match case-insensitive substrings, numeric fields accept
+/// `2000`, `>2000`, `<=120` or `1990-1999`. A term without a prefix matches the title, artist
+/// or album. Terms that cannot be parsed are ignored.
+///
+/// # Arguments
+/// * `query` - The text typed in the search entry.
+///
+/// # Returns
+/// A tuple with the clause (empty if there are no usable terms, otherwise starting with
+/// `WHERE`) and its positional parameters.
+pub fn build_search_clause(query: &str) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    for term in query.split("&&").map(str::trim).filter(|term| !term.is_empty()) {
+        match split_prefix(term) {
+            Some((column, FieldKind::Text, value)) => {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                conditions.push(text_condition(column, value, &mut params));
+            }
+            Some((column, FieldKind::Number, value)) => {
+                if let Some(condition) = number_condition(column, value.trim(), &mut params) {
+                    conditions.push(condition);
+                }
+            }
+            None => {
+                let alternatives: Vec<String> = ["rolas.title", "COALESCE(rola_credits.credited, performers.name)", "albums.name"]
+                    .iter()
+                    .map(|column| text_condition(column, term, &mut params))
+                    .collect();
+                conditions.push(format!("({})", alternatives.join(" OR ")));
+            }
+        }
+    }
+
+    if conditions.is_empty() {
+        (String::new(), params)
+    } else {
+        (format!("WHERE {}", conditions.join(" AND ")), params)
+    }
+}
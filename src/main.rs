@@ -5,6 +5,13 @@ mod view;
 use model::database_config::{config, database_tables, populate_db};
 
 fn main() {
+    if let Err(e) = model::logging::init() {
+        eprintln!("Could not open the log file: {}", e);
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--cli") {
+        std::process::exit(view::cli::run(&args[1..]));
+    }
     initialize_ui();
 }
 
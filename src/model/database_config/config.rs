@@ -1,7 +1,12 @@
 extern crate dirs;
 use std::fs;
 use std::io;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::model::music_miner::path_tags::DEFAULT_PATTERNS;
+use crate::model::music_miner::artist_credits::ArtistSeparators;
+use crate::model::playback::player::PlaybackSettings;
 
 /// Creates the `/home/user/.config/musicmanager/` directory if it doesn't exist.
 ///
@@ -51,6 +56,27 @@ pub fn create_database_file() -> io::Result<PathBuf> {
     Ok(file_path)
 }
 
+/// Creates the `history.db` file inside the `~/.local/share/musicmanager/` directory if it doesn't exist.
+///
+/// The listening history, the ratings and the labels are kept apart from `database.db`, which is rebuilt
+/// from the music files on every refresh, so they survive rescans. The file is attached to every
+/// database connection under the `listening` schema.
+///
+/// # Returns
+///
+/// - `Ok(PathBuf)` containing the path to the `history.db` file.
+/// - `Err(io::Error)` if there was an error creating the directory or the file.
+pub fn create_history_file() -> io::Result<PathBuf> {
+    let file_path = get_local_dir()?.join("history.db");
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file_path)?;
+
+    Ok(file_path)
+}
+
 /// Retrieves or creates the `~/.local/share/musicmanager/` directory for the user's application data.
 ///
 /// This function ensures that a `musicmanager` directory is created inside the user's local
@@ -71,3 +97,411 @@ pub fn get_local_dir() -> io::Result<PathBuf> {
         Err(io::Error::new(io::ErrorKind::NotFound, "Could not find configuration directory."))
     }
 }
+
+/// Loads the user-defined path patterns used to infer missing tags.
+///
+/// The patterns are read from `PathPatterns.txt` in the configuration directory, one pattern
+/// per line (e.g. `%artist%/%album%/%track% - %title%`). Empty lines and lines starting with
+/// `#` are ignored. If the file does not exist, it is created with the default patterns so
+/// the user has an example to edit.
+///
+/// # Returns
+///
+/// - `Ok(Vec<String>)` containing the patterns in order of preference.
+/// - `Err(io::Error)` if the configuration directory or the file could not be read or created.
+pub fn load_path_patterns() -> io::Result<Vec<String>> {
+    let file_path = create_config_dir()?.join("PathPatterns.txt");
+
+    if !file_path.exists() {
+        fs::write(&file_path, DEFAULT_PATTERNS.join("\n") + "\n")?;
+    }
+
+    let patterns = fs::read_to_string(&file_path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+
+    Ok(patterns)
+}
+
+/// Loads the separators used to split artist frames into several performers.
+///
+/// The separators are read from `ArtistSeparators.txt` in the configuration directory, one
+/// per line in the form `kind = separator`, where `kind` is `featured`, `split` or
+/// `featured_split` (see `ArtistSeparators`). Empty lines and lines starting with `#` are
+/// ignored. If the file does not exist, it is created with the default separators.
+///
+/// # Returns
+///
+/// - `Ok(ArtistSeparators)` containing the configured separators.
+/// - `Err(io::Error)` if the file could not be read or created, or if a line has an unknown kind.
+pub fn load_artist_separators() -> io::Result<ArtistSeparators> {
+    let file_path = create_config_dir()?.join("ArtistSeparators.txt");
+
+    if !file_path.exists() {
+        let defaults = ArtistSeparators::default();
+        let mut contents = String::new();
+        for (kind, values) in [("featured", &defaults.featured), ("split", &defaults.split), ("featured_split", &defaults.featured_split)] {
+            for value in values {
+                contents.push_str(&format!("{} = {}\n", kind, value));
+            }
+        }
+        fs::write(&file_path, contents)?;
+    }
+
+    let mut separators = ArtistSeparators { featured: Vec::new(), split: Vec::new(), featured_split: Vec::new() };
+    for line in fs::read_to_string(&file_path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (kind, value) = line.split_once('=').unwrap_or((line, ""));
+        let value = value.trim().to_string();
+        match kind.trim() {
+            "featured" => separators.featured.push(value),
+            "split" => separators.split.push(value),
+            "featured_split" => separators.featured_split.push(value),
+            other => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown separator kind '{}' in {:?}", other, file_path),
+            )),
+        }
+    }
+
+    Ok(separators)
+}
+
+/// Name of the settings file in the configuration directory.
+pub const CONFIG_FILE_NAME: &str = "Config.TOML";
+
+/// The colour scheme of the interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+/// # ScanSettings struct
+///
+/// The options of a library scan, the `[scan]` table of `Config.TOML`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    /// Fills the fields missing from a tag using the patterns of `PathPatterns.txt`.
+    pub infer_tags_from_paths: bool,
+    /// Skips the files and directories whose name starts with a dot.
+    pub skip_hidden: bool,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        ScanSettings { infer_tags_from_paths: true, skip_hidden: false }
+    }
+}
+
+/// # ColumnSettings struct
+///
+/// The layout of the song list, the `[columns]` table of `Config.TOML`. Columns are named by
+/// their title.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnSettings {
+    /// The titles of the optional columns shown, e.g. `Duration` or `Plays`.
+    pub visible: Vec<String>,
+    /// The titles of the columns in the order they are shown; empty for the default order.
+    pub order: Vec<String>,
+    /// The column the songs are sorted by, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_column: Option<String>,
+    pub sort_descending: bool,
+    /// The widths in pixels of the columns resized by the user.
+    pub widths: BTreeMap<String, i32>,
+}
+
+/// # LibraryRoot struct
+///
+/// A music directory of the library, one of the `[[roots]]` tables of `Config.TOML`, e.g. a
+/// folder on the local disk and another on a NAS mount. Disabled roots are kept in the list
+/// but not scanned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryRoot {
+    pub path: String,
+    pub enabled: bool,
+    /// Glob patterns of the files and directories left out of the scan, matched against the
+    /// path relative to the root, e.g. `**/Samples/**` or `*.tmp.mp3`.
+    pub excludes: Vec<String>,
+}
+
+impl Default for LibraryRoot {
+    fn default() -> Self {
+        LibraryRoot { path: String::new(), enabled: true, excludes: Vec::new() }
+    }
+}
+
+impl LibraryRoot {
+    /// An enabled root without exclude patterns.
+    pub fn new(path: &str) -> LibraryRoot {
+        LibraryRoot { path: path.to_string(), ..LibraryRoot::default() }
+    }
+}
+
+/// # WindowSettings struct
+///
+/// The size and position of the main window and of its panes, the `[window]` table of
+/// `Config.TOML`, saved when the application is closed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: i32,
+    pub height: i32,
+    /// The position of the window; left to the window manager if unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    pub maximized: bool,
+    /// The width of the library browser pane.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser_width: Option<i32>,
+    /// The position of the divider between the song list and the song details.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details_position: Option<i32>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: 900,
+            height: 600,
+            x: None,
+            y: None,
+            maximized: false,
+            browser_width: None,
+            details_position: None,
+        }
+    }
+}
+
+/// # Settings struct
+///
+/// Every setting of `Config.TOML`. Missing keys take their default value, so files written by
+/// older versions keep working.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The music directories of the library; the first one is shown in the directory entry.
+    pub roots: Vec<LibraryRoot>,
+    pub theme: Theme,
+    pub gapless: bool,
+    pub crossfade_ms: u32,
+    /// Writes the ratings into the POPM frames of MP3 files and the FMPS_RATING comments of FLAC files.
+    pub sync_ratings: bool,
+    /// Where the duplicate finder moves unwanted copies (see `get_quarantine_dir()`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantine_dir: Option<String>,
+    /// The MusicBrainz dump last used to match releases.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub musicbrainz_dump: Option<String>,
+    pub scan: ScanSettings,
+    pub columns: ColumnSettings,
+    pub window: WindowSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let playback = PlaybackSettings::default();
+        Settings {
+            roots: Vec::new(),
+            theme: Theme::Dark,
+            gapless: playback.gapless,
+            crossfade_ms: playback.crossfade_ms,
+            sync_ratings: false,
+            quarantine_dir: None,
+            musicbrainz_dump: None,
+            scan: ScanSettings::default(),
+            columns: ColumnSettings::default(),
+            window: WindowSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// The first music directory, shown in the directory entry, if one was chosen.
+    pub fn music_directory(&self) -> Option<&str> {
+        self.roots.first().map(|root| root.path.as_str())
+    }
+
+    /// Makes `directory` the first music directory and enables it, keeping its exclude
+    /// patterns if it was already a root and the other roots after it.
+    pub fn set_music_directory(&mut self, directory: &str) {
+        let mut root = self.root(directory).cloned().unwrap_or_else(|| LibraryRoot::new(directory));
+        root.enabled = true;
+        self.roots.retain(|existing| existing.path != directory);
+        self.roots.insert(0, root);
+    }
+
+    /// The root with the given path, if any.
+    pub fn root(&self, path: &str) -> Option<&LibraryRoot> {
+        self.roots.iter().find(|root| root.path == path)
+    }
+
+    /// The roots scanned by the Refresh button.
+    pub fn enabled_roots(&self) -> Vec<LibraryRoot> {
+        self.roots.iter().filter(|root| root.enabled).cloned().collect()
+    }
+
+    /// The playback options of the player.
+    pub fn playback(&self) -> PlaybackSettings {
+        PlaybackSettings { gapless: self.gapless, crossfade_ms: self.crossfade_ms }
+    }
+
+    /// Stores the playback options of the player.
+    pub fn set_playback(&mut self, playback: &PlaybackSettings) {
+        self.gapless = playback.gapless;
+        self.crossfade_ms = playback.crossfade_ms;
+    }
+}
+
+/// Reads the `key = value` lines of a file that is not valid TOML, as written by older
+/// versions without escaping (e.g. a Windows path in quotes). Values that are not valid TOML
+/// are kept as strings, without their quotes.
+fn parse_legacy_lines(contents: &str) -> toml::Table {
+    let mut table = toml::Table::new();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_string();
+        let value = value.trim();
+        let parsed = format!("value = {}", value).parse::<toml::Table>().ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.trim_matches('"').to_string()));
+        table.insert(key, parsed);
+    }
+    table
+}
+
+/// # Parse Settings function
+///
+/// Reads the contents of `Config.TOML`. Files that are not valid TOML are read line by line,
+/// and the `music_directory` and `music_directories` of older versions become enabled roots.
+///
+/// # Arguments
+/// * `contents` - The contents of the file.
+///
+/// # Returns
+/// * `Ok(Settings)` - The settings, with defaults for the missing keys.
+/// * `Err(io::Error)` - If a setting has a value of the wrong type.
+pub fn parse_settings(contents: &str) -> io::Result<Settings> {
+    let mut table = contents.parse::<toml::Table>().unwrap_or_else(|e| {
+        log::warn!("{} is not valid TOML, reading it line by line: {}", CONFIG_FILE_NAME, e);
+        parse_legacy_lines(contents)
+    });
+
+    let mut directories = Vec::new();
+    if let Some(toml::Value::String(directory)) = table.remove("music_directory") {
+        directories.push(toml::Value::String(directory));
+    }
+    if let Some(toml::Value::Array(older)) = table.remove("music_directories") {
+        directories = older;
+    }
+    if !directories.is_empty() && !table.contains_key("roots") {
+        let roots = directories.into_iter()
+            .map(|path| toml::Value::Table(toml::Table::from_iter([("path".to_string(), path)])))
+            .collect();
+        table.insert("roots".to_string(), toml::Value::Array(roots));
+    }
+
+    table.try_into().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Loads the settings from `Config.TOML`, or the defaults if the file does not exist.
+///
+/// # Returns
+///
+/// - `Ok(Settings)` containing the settings.
+/// - `Err(io::Error)` if the file cannot be read or a setting has a value of the wrong type.
+pub fn load_settings() -> io::Result<Settings> {
+    let file_path = create_config_dir()?.join(CONFIG_FILE_NAME);
+    if !file_path.exists() {
+        return Ok(Settings::default());
+    }
+    parse_settings(&fs::read_to_string(&file_path)?)
+}
+
+/// Writes the settings to `Config.TOML`, replacing the file.
+pub fn save_settings(settings: &Settings) -> io::Result<()> {
+    let file_path = create_config_dir()?.join(CONFIG_FILE_NAME);
+    let contents = toml::to_string(settings).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(&file_path, contents)
+}
+
+/// Loads the settings, changes them with `change` and saves them. Nothing is written if the
+/// file cannot be read, so a file with an invalid value is not replaced by the defaults.
+pub fn update_settings(change: impl FnOnce(&mut Settings)) -> io::Result<()> {
+    let mut settings = load_settings()?;
+    change(&mut settings);
+    save_settings(&settings)
+}
+
+/// Returns the folder where the duplicate finder quarantines unwanted copies: the
+/// `quarantine_dir` setting of `Config.TOML` if there is one, otherwise `Quarantine` in the
+/// local data directory. The folder is not created.
+pub fn get_quarantine_dir() -> io::Result<PathBuf> {
+    match load_settings()?.quarantine_dir {
+        Some(directory) => Ok(PathBuf::from(directory)),
+        None => Ok(get_local_dir()?.join("Quarantine")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_through_toml() {
+        let mut settings = Settings::default();
+        settings.set_music_directory("/music/ssd");
+        settings.set_music_directory("/music/nas \"shared\"");
+        settings.roots[1].enabled = false;
+        settings.roots[1].excludes = vec!["**/Samples/**".to_string(), "*.tmp.mp3".to_string()];
+        settings.theme = Theme::Light;
+        settings.crossfade_ms = 2500;
+        settings.scan.skip_hidden = true;
+        settings.columns.visible = vec!["Duration".to_string(), "Plays".to_string()];
+        settings.columns.order = vec!["Album".to_string(), "Title".to_string()];
+        settings.columns.sort_column = Some("Album".to_string());
+        settings.columns.widths.insert("Title".to_string(), 240);
+        settings.window.maximized = true;
+        settings.window.browser_width = Some(180);
+
+        let contents = toml::to_string(&settings).unwrap();
+        assert!(contents.contains("[[roots]]"));
+        assert_eq!(parse_settings(&contents).unwrap(), settings);
+        assert_eq!(settings.music_directory(), Some("/music/nas \"shared\""));
+        assert_eq!(settings.enabled_roots(), [LibraryRoot::new("/music/nas \"shared\"")]);
+
+        settings.set_music_directory("/music/ssd");
+        assert_eq!(settings.roots.len(), 2);
+        assert!(settings.roots[0].enabled);
+        assert_eq!(settings.roots[0].excludes.len(), 2);
+    }
+
+    #[test]
+    fn files_of_older_versions_are_migrated() {
+        let contents = "music_directory = \"C:\\Users\\me\\Music\"\ngapless = false\ncrossfade_ms = 3000\n";
+        let settings = parse_settings(contents).unwrap();
+        assert_eq!(settings.music_directory(), Some("C:\\Users\\me\\Music"));
+        assert!(!settings.gapless);
+        assert_eq!(settings.crossfade_ms, 3000);
+        assert_eq!(settings.theme, Theme::Dark);
+
+        let settings = parse_settings("music_directories = [\"/music/ssd\", \"/mnt/nas\"]").unwrap();
+        assert_eq!(settings.roots, [LibraryRoot::new("/music/ssd"), LibraryRoot::new("/mnt/nas")]);
+
+        assert!(parse_settings("crossfade_ms = \"long\"").is_err());
+    }
+}
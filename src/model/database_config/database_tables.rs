@@ -72,14 +72,41 @@ fn create_table_in_group(connection: &Connection) -> Result<()> {
 }
 
 /// Creates the `albums` table if it doesn't exist.
-/// Stores album details like path, name, and year.
+/// Stores album details like path, name, year, and the path of the cached cover thumbnail.
+/// `id_album_artist` links to the album-level performer (TPE2 or "Various Artists" for
+/// compilations) and is `NULL` when the album artist is unknown.
+/// `album_gain` (dB) and `album_peak` hold the ReplayGain values of the album, and are `NULL`
+/// until its loudness has been analysed.
 fn create_table_albums(connection: &Connection) -> Result<()> {
     connection.execute(
         "CREATE TABLE IF NOT EXISTS albums (
-            id_album        INTEGER PRIMARY KEY,
-            path            TEXT,
-            name            TEXT,
-            year            INTEGER
+            id_album            INTEGER PRIMARY KEY,
+            id_album_artist     INTEGER,
+            path                TEXT,
+            name                TEXT,
+            year                INTEGER,
+            cover               TEXT,
+            compilation         INTEGER DEFAULT 0,
+            album_gain          REAL,
+            album_peak          REAL,
+            mbid                TEXT,
+            release_date        TEXT,
+            FOREIGN KEY (id_album_artist) REFERENCES performers(id_performer)
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `library_roots` table if it doesn't exist.
+/// Stores the music directories of `Config.TOML` the library was scanned from, whether they
+/// are enabled, and their exclude patterns, one per line.
+fn create_table_library_roots(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS library_roots (
+            id_root     INTEGER PRIMARY KEY,
+            path        TEXT NOT NULL UNIQUE,
+            enabled     INTEGER NOT NULL DEFAULT 1,
+            excludes    TEXT
         )", ())?;
 
     Ok(())
@@ -87,24 +114,195 @@ fn create_table_albums(connection: &Connection) -> Result<()> {
 
 /// Creates the `rolas` table if it doesn't exist.
 /// Stores song details, linking to `performers` and `albums`.
+/// Optional fields (disc, composer, lyrics, ...) are `NULL` when the tag does not have them.
+/// `synced_lyrics` holds time-coded lyrics in LRC format.
+/// The audio properties are stored as `duration_ms`, `bitrate` (kbps), `sample_rate` (Hz),
+/// `channels`, `channel_mode` and `encoder`, and the ReplayGain values as `track_gain` (dB)
+/// and `track_peak`. `play_count` and `last_played` summarise the `history` of the song, and
+/// `rating` holds its stars (0 to 5, 0 meaning unrated). `id_root` links to the library root
+/// the song was found in, and is `NULL` for songs outside every root.
 fn create_table_rolas(connection: &Connection) -> Result<()> {
     connection.execute(
         "CREATE TABLE IF NOT EXISTS rolas (
             id_rola         INTEGER PRIMARY KEY,
             id_performer    INTEGER,
             id_album        INTEGER,
+            id_root         INTEGER,
             path            TEXT,
             title           TEXT,
             track           INTEGER,
             year            INTEGER,
             genre           TEXT,
+            disc            INTEGER,
+            disc_total      INTEGER,
+            track_total     INTEGER,
+            composer        TEXT,
+            conductor       TEXT,
+            bpm             INTEGER,
+            musical_key     TEXT,
+            comment         TEXT,
+            lyrics          TEXT,
+            synced_lyrics   TEXT,
+            duration_ms     INTEGER,
+            bitrate         INTEGER,
+            sample_rate     INTEGER,
+            channels        INTEGER,
+            channel_mode    TEXT,
+            encoder         TEXT,
+            track_gain      REAL,
+            track_peak      REAL,
+            play_count      INTEGER DEFAULT 0,
+            last_played     TEXT,
+            rating          INTEGER DEFAULT 0,
+            mbid            TEXT,
             FOREIGN KEY (id_performer) REFERENCES performers(id_performer),
-            FOREIGN KEY (id_album) REFERENCES albums(id_album)
+            FOREIGN KEY (id_album) REFERENCES albums(id_album),
+            FOREIGN KEY (id_root) REFERENCES library_roots(id_root)
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `history` table in the attached `listening` database if it doesn't exist.
+/// Stores one row per playback of a song, identified by its path since `rolas` is rebuilt on
+/// every refresh: when it was played (UTC, `YYYY-MM-DD HH:MM:SS`), the fraction of the song
+/// listened to, and what reported it (`player`, or the name of an external tool).
+fn create_table_history(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.history (
+            id_history      INTEGER PRIMARY KEY,
+            path            TEXT,
+            played_at       TEXT,
+            fraction        REAL,
+            source          TEXT
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `ratings` table in the attached `listening` database if it doesn't exist.
+/// Keeps the star rating given to each song, by path, so ratings survive refreshes even when
+/// they are not written to the tags.
+fn create_table_ratings(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.ratings (
+            path            TEXT PRIMARY KEY,
+            rating          INTEGER
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `labels` table in the attached `listening` database if it doesn't exist.
+/// Stores the free-form labels (e.g. "workout", "needs-retag") given to songs and albums.
+fn create_table_labels(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.labels (
+            id_label        INTEGER PRIMARY KEY,
+            name            TEXT UNIQUE
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `rola_labels` table in the attached `listening` database if it doesn't exist.
+/// Links songs, by path, to their labels.
+fn create_table_rola_labels(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.rola_labels (
+            path            TEXT,
+            id_label        INTEGER,
+            PRIMARY KEY (path, id_label),
+            FOREIGN KEY (id_label) REFERENCES labels(id_label)
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `album_labels` table in the attached `listening` database if it doesn't exist.
+/// Links albums, by the path of their directory, to their labels.
+fn create_table_album_labels(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.album_labels (
+            album_path      TEXT,
+            id_label        INTEGER,
+            PRIMARY KEY (album_path, id_label),
+            FOREIGN KEY (id_label) REFERENCES labels(id_label)
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `fingerprints` table in the attached `listening` database if it doesn't exist.
+/// Caches the acoustic fingerprint of each song, by path, with the size and modification
+/// time (seconds since the epoch) of the file it was computed from, so it is only computed
+/// again when the file changes.
+fn create_table_fingerprints(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS listening.fingerprints (
+            path            TEXT PRIMARY KEY,
+            file_size       INTEGER,
+            modified        INTEGER,
+            fingerprint     BLOB
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `field_sources` table if it doesn't exist.
+/// Records, for each song field, whether its value came from the tag, the path or a default.
+fn create_table_field_sources(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS field_sources (
+            id_rola         INTEGER,
+            field           TEXT,
+            source          TEXT,
+            PRIMARY KEY (id_rola, field),
+            FOREIGN KEY (id_rola) REFERENCES rolas(id_rola)
+        )", ())?;
+
+    Ok(())
+}
+
+/// Creates the `rola_performers` table if it doesn't exist.
+/// Links songs to every credited performer with a role (main, featured, remixer, composer).
+/// `position` keeps the order in which the performers are credited.
+fn create_table_rola_performers(connection: &Connection) -> Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS rola_performers (
+            id_rola         INTEGER,
+            id_performer    INTEGER,
+            role            TEXT,
+            position        INTEGER,
+            PRIMARY KEY (id_rola, id_performer, role),
+            FOREIGN KEY (id_rola) REFERENCES rolas(id_rola),
+            FOREIGN KEY (id_performer) REFERENCES performers(id_performer)
         )", ())?;
 
     Ok(())
 }
 
+/// Creates the `rola_credits` view, replacing the one of an older version if needed.
+/// Summarises `rola_performers` per song: `credited` reads like `A, B feat. C`, while
+/// `remixers` and `composers` list the performers with those roles, in credit order.
+fn create_view_rola_credits(connection: &Connection) -> Result<()> {
+    connection.execute("DROP VIEW IF EXISTS rola_credits", ())?;
+    connection.execute(
+        "CREATE VIEW rola_credits AS
+        SELECT
+            rola_performers.id_rola,
+            GROUP_CONCAT(CASE WHEN role = 'main' THEN name END, ', ' ORDER BY position)
+                || COALESCE(' feat. ' || GROUP_CONCAT(CASE WHEN role = 'featured' THEN name END, ', ' ORDER BY position), '')
+                AS credited,
+            GROUP_CONCAT(CASE WHEN role = 'remixer' THEN name END, ', ' ORDER BY position) AS remixers,
+            GROUP_CONCAT(CASE WHEN role = 'composer' THEN name END, ', ' ORDER BY position) AS composers
+        FROM rola_performers
+        JOIN performers ON rola_performers.id_performer = performers.id_performer
+        GROUP BY rola_performers.id_rola", ())?;
+
+    Ok(())
+}
+
 /// Creates all necessary tables in the database.
 pub fn create_all_tables(connection: &Connection) -> Result<()> {
     create_table_types(connection)?;
@@ -113,7 +311,17 @@ pub fn create_all_tables(connection: &Connection) -> Result<()> {
     create_table_groups(connection)?;
     create_table_in_group(connection)?;
     create_table_albums(connection)?;
+    create_table_library_roots(connection)?;
     create_table_rolas(connection)?;
+    create_table_field_sources(connection)?;
+    create_table_rola_performers(connection)?;
+    create_view_rola_credits(connection)?;
+    create_table_history(connection)?;
+    create_table_ratings(connection)?;
+    create_table_labels(connection)?;
+    create_table_rola_labels(connection)?;
+    create_table_album_labels(connection)?;
+    create_table_fingerprints(connection)?;
 
     Ok(())
 }
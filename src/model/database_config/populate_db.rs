@@ -3,6 +3,13 @@ use chrono::Datelike;
 use rusqlite::{params, Connection, Result};
 use std::collections::HashMap;
 use rusqlite::OptionalExtension;
+use crate::model::database_config::config::LibraryRoot;
+use crate::model::music_miner::artist_credits::{decode_credits, ROLE_MAIN};
+use crate::model::error::{LibraryError, LibraryResult};
+use std::path::Path;
+
+/// A playback counts towards `play_count` and `last_played` when at least this fraction of the song was heard.
+pub const PLAYED_FRACTION: f64 = 0.5;
 
 /// Inserts default types into the "types" table.
 /// These types represent different kinds of entities, such as "Person", "Group", or "Unknown".
@@ -41,7 +48,7 @@ fn insert_or_update_performer(connection: &Connection, artist: &str) -> Result<i
                     "UPDATE performers SET name = ?1, id_type = 2 WHERE id_performer = ?2",
                     params![artist, id]
                 )?;
-                println!("Updated performer: {}", artist);
+                log::debug!("Updated performer: {}", artist);
             }
             Ok(id)
         },
@@ -51,7 +58,7 @@ fn insert_or_update_performer(connection: &Connection, artist: &str) -> Result<i
                 params![artist]
             )?;
             let new_id = connection.last_insert_rowid();
-            println!("Inserted new performer: {}", artist);
+            log::debug!("Inserted new performer: {}", artist);
             Ok(new_id)
         }
     }
@@ -72,31 +79,77 @@ fn album_needs_update(connection: &Connection, album_id: i64, album: &str, year:
     }
 }
 
+/// Name of the performer used as album artist for compilations without a TPE2 frame.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// Checks whether a directory name is a disc subfolder of an album, such as `CD1`, `CD 2` or `Disc 01`.
+fn is_disc_folder(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["cd", "disc", "disk"].iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .map(|number| number.trim_start_matches([' ', '_', '-']))
+            .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// Returns the directory of an album: the directory of its songs, or its parent when the songs
+/// are in a disc subfolder, so the discs of an album stay together.
+fn album_directory(album_path: &str) -> &str {
+    let path = Path::new(album_path);
+    match (path.file_name().and_then(|name| name.to_str()), path.parent().and_then(Path::to_str)) {
+        (Some(name), Some(parent)) if is_disc_folder(name) && !parent.is_empty() => parent,
+        _ => album_path,
+    }
+}
+
+/// Looks up the ID of an album in the "albums" table.
+/// Albums without an album artist are identified by their path, name, and year, as before.
+/// Albums with an album artist are identified by their path, name and album artist, so that
+/// tracks with different years or artists stay together instead of becoming one album each.
+/// The path of a compilation is its album directory (see `album_directory()`), so unrelated
+/// compilations with the same title stay apart.
+fn find_album(connection: &Connection, album: &str, year: i32, path: &str, album_artist_id: Option<i64>, compilation: bool) -> Result<Option<i64>> {
+    match album_artist_id {
+        Some(artist_id) if compilation => connection.query_row(
+            "SELECT id_album FROM albums WHERE path = ?1 AND name = ?2 AND id_album_artist = ?3 AND compilation = 1",
+            params![album_directory(path), album, artist_id], |row| row.get(0)
+        ).optional(),
+        Some(artist_id) => connection.query_row(
+            "SELECT id_album FROM albums WHERE path = ?1 AND name = ?2 AND id_album_artist = ?3",
+            params![path, album, artist_id], |row| row.get(0)
+        ).optional(),
+        None => connection.query_row(
+            "SELECT id_album FROM albums WHERE path = ?1 AND name = ?2 AND year = ?3 AND id_album_artist IS NULL",
+            params![path, album, year], |row| row.get(0)
+        ).optional(),
+    }
+}
+
 /// Finds or inserts an album in the "albums" table.
-/// It checks if the album already exists by matching its name and release year.
+/// It checks if the album already exists with `find_album`.
 /// If found, it returns the album's ID. If not, it inserts a new album and returns the new ID.
-fn insert_or_update_album(connection: &Connection, album: &str, year: i32, path: &str) -> Result<i64> {
-    let mut stmt = connection.prepare("SELECT id_album FROM albums WHERE path = ?1 AND name = ?2 AND year = ?3")?;
-    let album_id: Option<i64> = stmt.query_row(params![path, album, year], |row| row.get(0)).optional()?;
+fn insert_or_update_album(connection: &Connection, album: &str, year: i32, path: &str, album_artist_id: Option<i64>, compilation: bool) -> Result<i64> {
+    let album_id = find_album(connection, album, year, path, album_artist_id, compilation)?;
 
     match album_id {
         Some(id) => {
-            if album_needs_update(connection, id, album, year, path)? {
+            if album_artist_id.is_none() && album_needs_update(connection, id, album, year, path)? {
                 connection.execute(
                     "UPDATE albums SET name = ?1, year = ?2, path = ?3 WHERE id_album = ?4",
                     params![album, year, path, id]
                 )?;
-                println!("Updated album: {}", album);
+                log::debug!(path = path; "Updated album: {}", album);
             }
             Ok(id)
         },
         None => {
+            let path = if compilation { album_directory(path) } else { path };
             connection.execute(
-                "INSERT INTO albums (name, year, path) VALUES (?1, ?2, ?3)",
-                params![album, year, path]
+                "INSERT INTO albums (name, year, path, id_album_artist, compilation) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![album, year, path, album_artist_id, compilation]
             )?;
             let new_id = connection.last_insert_rowid();
-            println!("Inserted new album: {}", album);
+            log::debug!(path = path; "Inserted new album: {}", album);
             Ok(new_id)
         }
     }
@@ -136,6 +189,7 @@ fn song_needs_update(
 
 /// Inserts a new track (song) into the "rolas" table only if it doesn't already exist.
 /// This function adds a new track with the associated performer and album IDs, title, track number, year, and genre.
+/// Returns the ID of the inserted or updated track.
 fn insert_or_update_rola(
     connection: &Connection,
     performer_id: i64,
@@ -145,7 +199,7 @@ fn insert_or_update_rola(
     year: i32,
     genre: &str,
     path: &str
-) -> Result<()> {
+) -> Result<i64> {
     // Ahora verificamos la existencia de la canción usando también el path
     if !rola_exists(connection, performer_id, album_id, title, path)? {
         connection.execute(
@@ -153,38 +207,359 @@ fn insert_or_update_rola(
             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![performer_id, album_id, title, track, year, genre, path]
         )?;
-        println!("Inserted new song: {} with path: {}", title, path);
+        log::debug!(path = path; "Inserted new song: {}", title);
     } else if song_needs_update(connection, performer_id, album_id, title, track, year, genre, path)? {
         connection.execute(
             "UPDATE rolas SET track = ?1, year = ?2, genre = ?3, path = ?4
              WHERE id_performer = ?5 AND id_album = ?6 AND title = ?7",
             params![track, year, genre, path, performer_id, album_id, title]
         )?;
-        println!("Updated existing song: {} with new path: {}", title, path);
+        log::debug!(path = path; "Updated existing song: {}", title);
+    }
+
+    let mut stmt = connection.prepare("SELECT id_rola FROM rolas WHERE id_performer = ?1 AND id_album = ?2 AND title = ?3 AND path = ?4")?;
+    let rola_id: i64 = stmt.query_row(params![performer_id, album_id, title, path], |row| row.get(0))?;
+    Ok(rola_id)
+}
+
+/// Stores the optional fields of a song (disc, composer, BPM, lyrics, audio properties, ...) in the "rolas" table.
+/// Fields missing from the tag map, or numbers that cannot be parsed, are stored as `NULL`.
+fn update_rola_optional_fields(connection: &Connection, rola_id: i64, tag_map: &HashMap<String, String>) -> Result<()> {
+    let text = |key: &str| tag_map.get(key).map(|value| value.trim()).filter(|value| !value.is_empty());
+    let number = |key: &str| text(key).and_then(|value| value.split('.').next()?.parse::<i32>().ok());
+    let real = |key: &str| text(key).and_then(|value| value.parse::<f64>().ok());
+
+    connection.execute(
+        "UPDATE rolas SET disc = ?1, disc_total = ?2, track_total = ?3, composer = ?4, conductor = ?5,
+            bpm = ?6, musical_key = ?7, comment = ?8, lyrics = ?9, synced_lyrics = ?10,
+            duration_ms = ?11, bitrate = ?12, sample_rate = ?13, channels = ?14, channel_mode = ?15,
+            encoder = ?16, track_gain = ?17, track_peak = ?18, mbid = ?19
+         WHERE id_rola = ?20",
+        params![
+            number("Disc Number"), number("Disc Total"), number("Track Total"),
+            text("Composer"), text("Conductor"), number("BPM"), text("Key"),
+            text("Comment"), text("Lyrics"), text("Synced Lyrics"),
+            number("Duration"), number("Bitrate"), number("Sample Rate"), number("Channels"),
+            text("Channel Mode"), text("Encoder"), real("Track Gain"), real("Track Peak"),
+            text("MusicBrainz Recording Id"), rola_id
+        ]
+    )?;
+    Ok(())
+}
+
+/// Records where each field of a song came from (`Tag`, `Path` or `Default`) in the "field_sources" table.
+/// The source of every field is read from the `<Field> Source` keys of the tag map.
+fn insert_or_update_field_sources(connection: &Connection, rola_id: i64, tag_map: &HashMap<String, String>) -> Result<()> {
+    for (key, source) in tag_map.iter() {
+        if let Some(field) = key.strip_suffix(" Source") {
+            connection.execute(
+                "INSERT OR REPLACE INTO field_sources (id_rola, field, source) VALUES (?1, ?2, ?3)",
+                params![rola_id, field, source]
+            )?;
+        }
     }
     Ok(())
 }
 
+/// Replaces the performers credited on a song in the "rola_performers" table.
+/// Each `(role, name)` pair is linked to a performer, inserting the performer if needed.
+fn insert_rola_performers(connection: &Connection, rola_id: i64, credits: &[(String, String)]) -> Result<()> {
+    connection.execute("DELETE FROM rola_performers WHERE id_rola = ?1", params![rola_id])?;
+
+    for (position, (role, name)) in credits.iter().enumerate() {
+        let performer_id = insert_or_update_performer(connection, name)?;
+        connection.execute(
+            "INSERT OR IGNORE INTO rola_performers (id_rola, id_performer, role, position) VALUES (?1, ?2, ?3, ?4)",
+            params![rola_id, performer_id, role, position as i64]
+        )?;
+    }
+    Ok(())
+}
+
+/// Links a cover thumbnail to an album in the "albums" table.
+/// The first cover found for an album is kept, so later songs of the same album do not replace it.
+fn set_album_cover(connection: &Connection, album_id: i64, cover: &str) -> Result<()> {
+    connection.execute(
+        "UPDATE albums SET cover = ?1 WHERE id_album = ?2 AND cover IS NULL",
+        params![cover, album_id]
+    )?;
+    Ok(())
+}
+
+/// Copies the play count and last playback of a song from the "history" table into "rolas".
+/// Only playbacks of at least `PLAYED_FRACTION` of the song count.
+fn update_rola_play_statistics(connection: &Connection, rola_id: i64) -> Result<()> {
+    connection.execute(
+        "UPDATE rolas SET
+            play_count = (SELECT COUNT(*) FROM history WHERE history.path = rolas.path AND history.fraction >= ?1),
+            last_played = (SELECT MAX(played_at) FROM history WHERE history.path = rolas.path AND history.fraction >= ?1)
+         WHERE id_rola = ?2",
+        params![PLAYED_FRACTION, rola_id]
+    )?;
+    Ok(())
+}
+
+/// Records a playback of a song in the "history" table, and updates `play_count` and
+/// `last_played` in "rolas" when enough of the song was heard.
+///
+/// # Arguments
+/// * `path` - The path of the song.
+/// * `fraction` - The fraction of the song listened to, between 0 and 1.
+/// * `source` - What reported the playback, e.g. `player`.
+pub fn record_play(connection: &Connection, path: &str, fraction: f64, source: &str) -> Result<()> {
+    let played_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let fraction = fraction.clamp(0.0, 1.0);
+
+    connection.execute(
+        "INSERT INTO history (path, played_at, fraction, source) VALUES (?1, ?2, ?3, ?4)",
+        params![path, played_at, fraction, source]
+    )?;
+    if fraction >= PLAYED_FRACTION {
+        connection.execute(
+            "UPDATE rolas SET play_count = COALESCE(play_count, 0) + 1, last_played = ?1 WHERE path = ?2",
+            params![played_at, path]
+        )?;
+    }
+    Ok(())
+}
+
+/// Sets the rating of a song in "rolas": the rating given in the application, kept in the
+/// "ratings" table, or else the rating read from the tag.
+fn update_rola_rating(connection: &Connection, rola_id: i64, tag_rating: Option<i32>) -> Result<()> {
+    connection.execute(
+        "UPDATE rolas SET rating = COALESCE((SELECT rating FROM ratings WHERE ratings.path = rolas.path), ?1, 0)
+         WHERE id_rola = ?2",
+        params![tag_rating, rola_id]
+    )?;
+    Ok(())
+}
+
+/// Rates a song: stores the rating in the "ratings" table, so it survives refreshes, and in "rolas".
+///
+/// # Arguments
+/// * `path` - The path of the song.
+/// * `rating` - The number of stars, from 0 (unrated) to 5.
+pub fn set_rating(connection: &Connection, path: &str, rating: i32) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO ratings (path, rating) VALUES (?1, ?2)",
+        params![path, rating]
+    )?;
+    connection.execute("UPDATE rolas SET rating = ?1 WHERE path = ?2", params![rating, path])?;
+    Ok(())
+}
+
+/// Replaces the labels of a song ("rola_labels") or of an album ("album_labels"), adding new
+/// labels to the "labels" table and removing the labels no longer used by anything.
+///
+/// # Arguments
+/// * `table` - Either `rola_labels` or `album_labels`.
+/// * `column` - The column identifying the song or album: `path` or `album_path`.
+/// * `key` - The path of the song, or of the album directory.
+/// * `labels` - The new labels.
+fn replace_labels(connection: &Connection, table: &str, column: &str, key: &str, labels: &[String]) -> Result<()> {
+    connection.execute(&format!("DELETE FROM {} WHERE {} = ?1", table, column), params![key])?;
+
+    for label in labels {
+        connection.execute("INSERT OR IGNORE INTO labels (name) VALUES (?1)", params![label])?;
+        connection.execute(
+            &format!("INSERT OR IGNORE INTO {} ({}, id_label) SELECT ?1, id_label FROM labels WHERE name = ?2", table, column),
+            params![key, label]
+        )?;
+    }
+
+    connection.execute(
+        "DELETE FROM labels WHERE id_label NOT IN (SELECT id_label FROM rola_labels UNION SELECT id_label FROM album_labels)",
+        ()
+    )?;
+    Ok(())
+}
+
+/// Replaces the labels of a song, identified by its path.
+pub fn set_song_labels(connection: &Connection, path: &str, labels: &[String]) -> Result<()> {
+    replace_labels(connection, "rola_labels", "path", path, labels)
+}
+
+/// Replaces the labels of an album, identified by the path of its directory.
+pub fn set_album_labels(connection: &Connection, album_path: &str, labels: &[String]) -> Result<()> {
+    replace_labels(connection, "album_labels", "album_path", album_path, labels)
+}
+
+/// Removes a song, identified by its path, from "rolas" together with its performer credits
+/// and field sources. Its listening history, rating and labels are kept in case the file
+/// comes back.
+pub fn remove_rola(connection: &Connection, path: &str) -> Result<()> {
+    let rola_ids: Vec<i64> = connection
+        .prepare("SELECT id_rola FROM rolas WHERE path = ?1")?
+        .query_map(params![path], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    for rola_id in rola_ids {
+        connection.execute("DELETE FROM rola_performers WHERE id_rola = ?1", params![rola_id])?;
+        connection.execute("DELETE FROM field_sources WHERE id_rola = ?1", params![rola_id])?;
+        connection.execute("DELETE FROM rolas WHERE id_rola = ?1", params![rola_id])?;
+    }
+    Ok(())
+}
+
+/// Stores a library root in the "library_roots" table, updating whether it is enabled and
+/// its exclude patterns if it is already there, and returns its id.
+pub fn insert_or_update_root(connection: &Connection, root: &LibraryRoot) -> Result<i64> {
+    connection.execute(
+        "INSERT INTO library_roots (path, enabled, excludes) VALUES (?1, ?2, ?3)
+         ON CONFLICT(path) DO UPDATE SET enabled = excluded.enabled, excludes = excluded.excludes",
+        params![root.path, root.enabled, root.excludes.join("\n")]
+    )?;
+    connection.query_row("SELECT id_root FROM library_roots WHERE path = ?1", params![root.path], |row| row.get(0))
+}
+
+/// Links a song to the library root it was found in, given by the `Root` key of its tag map.
+/// The link is removed if the root is not in the "library_roots" table.
+fn set_rola_root(connection: &Connection, rola_id: i64, root: Option<&str>) -> Result<()> {
+    connection.execute(
+        "UPDATE rolas SET id_root = (SELECT id_root FROM library_roots WHERE path = ?1) WHERE id_rola = ?2",
+        params![root, rola_id]
+    )?;
+    Ok(())
+}
+
+/// Removes a library root from the "library_roots" table together with its songs (see
+/// `remove_rola()`), and returns the paths of the songs removed.
+pub fn remove_root(connection: &Connection, root_path: &str) -> Result<Vec<String>> {
+    let paths: Vec<String> = connection
+        .prepare("SELECT rolas.path FROM rolas JOIN library_roots ON rolas.id_root = library_roots.id_root
+                  WHERE library_roots.path = ?1")?
+        .query_map(params![root_path], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    for path in &paths {
+        remove_rola(connection, path)?;
+    }
+    connection.execute("DELETE FROM library_roots WHERE path = ?1", params![root_path])?;
+    Ok(paths)
+}
+
+/// Stores the acoustic fingerprint of a song in the "fingerprints" table, with the size and
+/// modification time of the file it was computed from.
+pub fn set_fingerprint(connection: &Connection, path: &str, file_size: i64, modified: i64, fingerprint: &[u8]) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO fingerprints (path, file_size, modified, fingerprint) VALUES (?1, ?2, ?3, ?4)",
+        params![path, file_size, modified, fingerprint]
+    )?;
+    Ok(())
+}
+
+/// Stores the ReplayGain values of an album in the "albums" table.
+pub fn set_album_loudness(connection: &Connection, album_id: i64, gain: f64, peak: f64) -> Result<()> {
+    connection.execute(
+        "UPDATE albums SET album_gain = ?1, album_peak = ?2 WHERE id_album = ?3",
+        params![gain, peak, album_id]
+    )?;
+    Ok(())
+}
+
+/// Stores the MusicBrainz release ID and the full release date of an album in the "albums"
+/// table, keeping the values already stored when a song lacks them.
+fn set_album_release(connection: &Connection, album_id: i64, mbid: Option<&str>, release_date: Option<&str>) -> Result<()> {
+    connection.execute(
+        "UPDATE albums SET mbid = COALESCE(?1, mbid), release_date = COALESCE(?2, release_date) WHERE id_album = ?3",
+        params![mbid, release_date, album_id]
+    )?;
+    Ok(())
+}
+
+/// Returns a field every tag map built by the miner has, or a validation error naming it.
+fn required_field<'a>(tag_map: &'a HashMap<String, String>, key: &str) -> LibraryResult<&'a String> {
+    tag_map.get(key).ok_or_else(|| {
+        let path = tag_map.get("Path").map(Path::new);
+        LibraryError::validation(path, format!("The song has no \"{}\" field", key))
+    })
+}
+
 /// Populates the database with a new song (track) based on the provided tag map.
 /// The tag map contains metadata such as the artist's name, album, title, track number, year, and genre.
 /// It first finds or inserts the performer and album, then inserts the new track (song).
-pub fn populate_database(connection: &Connection, tag_map: HashMap<String, String>) -> Result<()> {
+/// Compilations (TCMP) without an album artist are grouped under "Various Artists".
+/// The song is linked to its first main performer, and every credited performer is stored
+/// in "rola_performers".
+///
+/// # Returns
+/// * `Ok(())` - If the song was stored.
+/// * `Err(LibraryError)` - A validation error if the tag map lacks `Path`, `AlbumPath`,
+///   `Title`, `Artist`, `Album`, `Year`, `Genre` or `Track Number`, or a database error
+///   carrying the song's path.
+pub fn populate_database(connection: &Connection, tag_map: HashMap<String, String>) -> LibraryResult<()> {
+    let path = required_field(&tag_map, "Path")?;
+    store_song(connection, &tag_map).map_err(|e| e.with_path(Path::new(path)))
+}
+
+/// Stores a song in the database (see `populate_database()`).
+///
+/// The statements run in one transaction, committed once the song is fully stored, so a song
+/// that fails halfway leaves no performer, album or credit behind.
+fn store_song(connection: &Connection, tag_map: &HashMap<String, String>) -> LibraryResult<()> {
+    let transaction = connection.unchecked_transaction()?;
+    let connection: &Connection = &transaction;
     insert_types(connection)?;    
 
-    let artist = tag_map.get("Artist").unwrap();
-    let title = tag_map.get("Title").unwrap();
-    let album = tag_map.get("Album").unwrap();
-    let year = tag_map.get("Year").unwrap().parse::<i32>().unwrap_or(chrono::Utc::now().year());
-    let genre = tag_map.get("Genre").unwrap();
-    let track_number = tag_map.get("Track Number").unwrap().parse::<i32>().unwrap_or(0);
-    let path = tag_map.get("Path").unwrap();
-    let album_path = tag_map.get("AlbumPath").unwrap(); 
+    let artist = required_field(tag_map, "Artist")?;
+    let title = required_field(tag_map, "Title")?;
+    let album = required_field(tag_map, "Album")?;
+    let year = required_field(tag_map, "Year")?.parse::<i32>().unwrap_or(chrono::Utc::now().year());
+    let genre = required_field(tag_map, "Genre")?;
+    let track_number = required_field(tag_map, "Track Number")?.parse::<i32>().unwrap_or(0);
+    let path = required_field(tag_map, "Path")?;
+    let album_path = required_field(tag_map, "AlbumPath")?;
+
+    let compilation = tag_map.get("Compilation").map(String::as_str) == Some("1");
+    let album_artist = tag_map.get("Album Artist").map(String::as_str)
+        .or(if compilation { Some(VARIOUS_ARTISTS) } else { None });
+
+    let credits = tag_map.get("Credits").map(|encoded| decode_credits(encoded)).unwrap_or_default();
+    let main_artist = credits.iter()
+        .find(|(role, _)| role == ROLE_MAIN)
+        .map(|(_, name)| name.as_str())
+        .unwrap_or(artist);
+
+    let performer_id = insert_or_update_performer(connection, main_artist)?;
+
+    let album_artist_id = match album_artist {
+        Some(album_artist) => Some(insert_or_update_performer(connection, album_artist)?),
+        None => None,
+    };
+
+    let album_id = insert_or_update_album(connection, album, year, album_path, album_artist_id, compilation)?;
+
+    if let Some(cover) = tag_map.get("Cover") {
+        set_album_cover(connection, album_id, cover)?;
+    }
+
+    let album_mbid = tag_map.get("MusicBrainz Album Id").map(String::as_str);
+    let release_date = tag_map.get("Release Date").map(String::as_str);
+    if album_mbid.is_some() || release_date.is_some() {
+        set_album_release(connection, album_id, album_mbid, release_date)?;
+    }
+
+    let album_gain = tag_map.get("Album Gain").and_then(|gain| gain.parse::<f64>().ok());
+    let album_peak = tag_map.get("Album Peak").and_then(|peak| peak.parse::<f64>().ok());
+    if let (Some(gain), Some(peak)) = (album_gain, album_peak) {
+        set_album_loudness(connection, album_id, gain, peak)?;
+    }
+
+    let rola_id = insert_or_update_rola(connection, performer_id, album_id, title, track_number, year, genre, path)?;
+
+    update_rola_optional_fields(connection, rola_id, tag_map)?;
+
+    set_rola_root(connection, rola_id, tag_map.get("Root").map(String::as_str))?;
+
+    update_rola_play_statistics(connection, rola_id)?;
 
-    let performer_id = insert_or_update_performer(connection, artist)?;
+    let tag_rating = tag_map.get("Rating").and_then(|rating| rating.parse::<i32>().ok());
+    update_rola_rating(connection, rola_id, tag_rating)?;
 
-    let album_id = insert_or_update_album(connection, album, year, album_path)?;
+    insert_or_update_field_sources(connection, rola_id, tag_map)?;
 
-    insert_or_update_rola(connection, performer_id, album_id, title, track_number, year, genre, path)?;
+    insert_rola_performers(connection, rola_id, &credits)?;
 
+    transaction.commit()?;
     Ok(())
 }
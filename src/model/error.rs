@@ -0,0 +1,117 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// # LibraryError enum
+///
+/// The errors of the library, shared by the model and the controller. Each one carries the
+/// file it concerns when there is one, so scans can report which songs failed and why.
+#[derive(Debug)]
+pub enum LibraryError {
+    /// A file or directory could not be read, written or moved.
+    Io { path: PathBuf, source: io::Error },
+    /// The tags or metadata blocks of a song could not be parsed.
+    TagParse { path: PathBuf, message: String },
+    /// A database query failed, while storing `path` if known.
+    Database { path: Option<PathBuf>, source: rusqlite::Error },
+    /// The configuration or data directories could not be read or written.
+    Config { path: Option<PathBuf>, message: String },
+    /// Some data is missing or invalid, e.g. a song without a title or a path that is not a directory.
+    Validation { path: Option<PathBuf>, message: String },
+}
+
+/// The result of the library's operations.
+pub type LibraryResult<T> = Result<T, LibraryError>;
+
+impl LibraryError {
+    /// Builds an I/O error about `path`.
+    pub fn io(path: &Path, source: io::Error) -> LibraryError {
+        LibraryError::Io { path: path.to_path_buf(), source }
+    }
+
+    /// Builds a configuration error from the I/O error met while reading or writing it.
+    pub fn config(path: Option<&Path>, source: io::Error) -> LibraryError {
+        LibraryError::Config { path: path.map(Path::to_path_buf), message: source.to_string() }
+    }
+
+    /// Builds the error of reading or writing the ID3 tag of `path`: I/O failures are kept as
+    /// such, unsupported formats and invalid values are validation errors, and the rest are
+    /// parse errors.
+    pub fn tag(path: &Path, error: id3::Error) -> LibraryError {
+        match error.kind {
+            id3::ErrorKind::Io(source) => LibraryError::io(path, source),
+            id3::ErrorKind::UnsupportedFeature | id3::ErrorKind::InvalidInput => {
+                LibraryError::validation(Some(path), error.description)
+            }
+            _ => LibraryError::TagParse { path: path.to_path_buf(), message: error.to_string() },
+        }
+    }
+
+    /// Builds a validation error, about `path` if known.
+    pub fn validation(path: Option<&Path>, message: impl Into<String>) -> LibraryError {
+        LibraryError::Validation { path: path.map(Path::to_path_buf), message: message.into() }
+    }
+
+    /// Sets the path of a database, configuration or validation error that has none yet.
+    pub fn with_path(mut self, new_path: &Path) -> LibraryError {
+        if let LibraryError::Database { path, .. } | LibraryError::Config { path, .. } | LibraryError::Validation { path, .. } = &mut self {
+            path.get_or_insert_with(|| new_path.to_path_buf());
+        }
+        self
+    }
+
+    /// The file the error concerns, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            LibraryError::Io { path, .. } | LibraryError::TagParse { path, .. } => Some(path),
+            LibraryError::Database { path, .. } | LibraryError::Config { path, .. } | LibraryError::Validation { path, .. } => path.as_deref(),
+        }
+    }
+
+    /// A short name of the kind of error, as shown in the scan report.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LibraryError::Io { .. } => "I/O",
+            LibraryError::TagParse { .. } => "Tag",
+            LibraryError::Database { .. } => "Database",
+            LibraryError::Config { .. } => "Configuration",
+            LibraryError::Validation { .. } => "Validation",
+        }
+    }
+
+    /// The description of the error without its path.
+    pub fn message(&self) -> String {
+        match self {
+            LibraryError::Io { source, .. } => source.to_string(),
+            LibraryError::Database { source, .. } => source.to_string(),
+            LibraryError::TagParse { message, .. } | LibraryError::Config { message, .. }
+                | LibraryError::Validation { message, .. } => message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{} error in {}: {}", self.kind(), path.display(), self.message()),
+            None => write!(f, "{} error: {}", self.kind(), self.message()),
+        }
+    }
+}
+
+impl Error for LibraryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LibraryError::Io { source, .. } => Some(source),
+            LibraryError::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LibraryError {
+    fn from(source: rusqlite::Error) -> LibraryError {
+        LibraryError::Database { path: None, source }
+    }
+}
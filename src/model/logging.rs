@@ -0,0 +1,283 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::kv::Key;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use crate::model::database_config::config::get_local_dir;
+
+/// Name of the current log file in the local data directory.
+pub const LOG_FILE_NAME: &str = "musicmanager.log";
+
+/// Size from which the log file is rotated.
+const MAX_LOG_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Number of rotated files kept (`musicmanager.log.1` being the newest).
+const MAX_ROTATED_FILES: usize = 3;
+
+/// Environment variable choosing the lowest level written: `error`, `warn`, `info`, `debug` or `trace`.
+pub const LOG_LEVEL_VARIABLE: &str = "MUSICMANAGER_LOG";
+
+/// # LogEntry struct
+///
+/// One line of the log file, written as JSON. `path` is the file the record concerns, given
+/// with the `path` key of the logging macros, e.g. `log::debug!(path = path_str; "Inserted")`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: String,
+    pub target: String,
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// Returns the level of the entry, or `None` if it is not a known level.
+    pub fn level(&self) -> Option<Level> {
+        self.level.parse().ok()
+    }
+
+    /// Whether the entry is at least as severe as `min_level` and its path contains
+    /// `path_filter`, ignoring case. An empty filter matches every entry.
+    pub fn matches(&self, min_level: LevelFilter, path_filter: &str) -> bool {
+        let severe_enough = self.level().is_none_or(|level| level <= min_level);
+        let path_filter = path_filter.trim().to_lowercase();
+        severe_enough && (path_filter.is_empty()
+            || self.path.as_deref().is_some_and(|path| path.to_lowercase().contains(&path_filter)))
+    }
+}
+
+/// The open log file with its size, counted as lines are written so the file is only
+/// read from the disk when it is opened.
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    /// Opens `musicmanager.log` for appending, rotating it first if it is already full.
+    fn open(directory: &Path) -> io::Result<LogFile> {
+        let current = directory.join(LOG_FILE_NAME);
+        let open = || OpenOptions::new().create(true).append(true).open(&current);
+        let file = open()?;
+        let size = file.metadata()?.len();
+        if size < MAX_LOG_SIZE {
+            return Ok(LogFile { file, size });
+        }
+        drop(file);
+        rotate(directory)?;
+        Ok(LogFile { file: open()?, size: 0 })
+    }
+}
+
+/// # FileLogger struct
+///
+/// Writes the records to `musicmanager.log` as JSON lines, rotating the file when it reaches
+/// `MAX_LOG_SIZE`. Warnings and errors are printed to the standard error too.
+pub struct FileLogger {
+    directory: PathBuf,
+    level: LevelFilter,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl FileLogger {
+    /// Creates a logger writing in `directory` the records of `level` and above.
+    pub fn new(directory: &Path, level: LevelFilter) -> FileLogger {
+        FileLogger { directory: directory.to_path_buf(), level, file: Mutex::new(None) }
+    }
+
+    /// Writes one line, opening the file or rotating it first if needed.
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if file.as_ref().is_some_and(|open| open.size >= MAX_LOG_SIZE) {
+            *file = None;
+            rotate(&self.directory)?;
+        }
+        let open = match file.as_mut() {
+            Some(open) => open,
+            None => file.insert(LogFile::open(&self.directory)?),
+        };
+        writeln!(open.file, "{}", line)?;
+        open.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = LogEntry {
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            path: record.key_values().get(Key::from("path")).map(|path| path.to_string()),
+            message: record.args().to_string(),
+        };
+        if record.level() <= Level::Warn {
+            match &entry.path {
+                Some(path) => eprintln!("{}: {} ({})", entry.level, entry.message, path),
+                None => eprintln!("{}: {}", entry.level, entry.message),
+            }
+        }
+        let written = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| self.write_line(&line));
+        if let Err(e) = written {
+            eprintln!("Could not write the log: {}", e);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(open) = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).as_mut() {
+            let _ = open.file.flush();
+        }
+    }
+}
+
+/// Shifts `musicmanager.log.N` to `.N+1`, dropping the oldest, and the current file to `.1`.
+fn rotate(directory: &Path) -> io::Result<()> {
+    let rotated = |index: usize| directory.join(format!("{}.{}", LOG_FILE_NAME, index));
+    for index in (1..MAX_ROTATED_FILES).rev() {
+        if rotated(index).exists() {
+            fs::rename(rotated(index), rotated(index + 1))?;
+        }
+    }
+    fs::rename(directory.join(LOG_FILE_NAME), rotated(1))
+}
+
+/// Reads the level of `MUSICMANAGER_LOG`, `Info` if it is unset or invalid.
+fn level_from_environment() -> LevelFilter {
+    std::env::var(LOG_LEVEL_VARIABLE).ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+/// # Init function
+///
+/// Installs the `FileLogger` writing to the local data directory, at the level chosen by
+/// `MUSICMANAGER_LOG`. It is called once at startup, before the interface or the command
+/// line runs.
+///
+/// # Returns
+/// * `Ok(())` - If the logger was installed.
+/// * `Err(io::Error)` - If the local data directory cannot be created, or a logger is
+///   already installed.
+pub fn init() -> io::Result<()> {
+    let level = level_from_environment();
+    let logger = FileLogger::new(&get_local_dir()?, level);
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Returns the log files of `directory` that exist, oldest first.
+pub fn log_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = (1..=MAX_ROTATED_FILES).rev()
+        .map(|index| directory.join(format!("{}.{}", LOG_FILE_NAME, index)))
+        .collect();
+    files.push(directory.join(LOG_FILE_NAME));
+    files.retain(|file| file.exists());
+    files
+}
+
+/// # Read Entries function
+///
+/// Reads the entries of the log files of `directory`, oldest first. Lines that are not
+/// valid entries, such as a line cut by a crash, are skipped.
+///
+/// # Arguments
+/// * `directory` - The directory of the log files, the local data directory for the application.
+///
+/// # Returns
+/// * `Ok(Vec<LogEntry>)` - The entries of every log file.
+/// * `Err(io::Error)` - If a log file cannot be read.
+pub fn read_entries(directory: &Path) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    for file in log_files(directory) {
+        for line in BufReader::new(File::open(file)?).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    fn record(logger: &FileLogger, level: Level, path: Option<&str>, message: &str) {
+        match path {
+            Some(path) => logger.log(&Record::builder()
+                .level(level)
+                .key_values(&[("path", path)])
+                .args(format_args!("{}", message))
+                .build()),
+            None => logger.log(&Record::builder().level(level).args(format_args!("{}", message)).build()),
+        }
+    }
+
+    #[test]
+    fn entries_are_read_back_and_filtered() {
+        let directory = TempFile::dir("log");
+        let logger = FileLogger::new(directory.path(), LevelFilter::Debug);
+        record(&logger, Level::Debug, Some("/music/Queen/01.mp3"), "Processing file");
+        record(&logger, Level::Warn, Some("/music/Bowie/02.flac"), "Not a FLAC file");
+        record(&logger, Level::Trace, None, "Too verbose");
+        logger.flush();
+
+        let entries = read_entries(directory.path()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path.as_deref(), Some("/music/Queen/01.mp3"));
+        assert_eq!(entries[1].level(), Some(Level::Warn));
+
+        let matching = |level, filter| entries.iter().filter(|entry| entry.matches(level, filter)).count();
+        assert_eq!(matching(LevelFilter::Trace, ""), 2);
+        assert_eq!(matching(LevelFilter::Warn, ""), 1);
+        assert_eq!(matching(LevelFilter::Trace, "queen"), 1);
+        assert_eq!(matching(LevelFilter::Warn, "queen"), 0);
+    }
+
+    #[test]
+    fn full_files_are_rotated() {
+        let directory = TempFile::dir("rotation");
+        fs::write(directory.path().join(LOG_FILE_NAME), vec![b'\n'; MAX_LOG_SIZE as usize]).unwrap();
+        fs::write(directory.path().join(format!("{}.1", LOG_FILE_NAME)), "").unwrap();
+
+        let logger = FileLogger::new(directory.path(), LevelFilter::Info);
+        record(&logger, Level::Info, None, "After the rotation");
+        logger.flush();
+
+        let files = log_files(directory.path());
+        let entries = read_entries(directory.path()).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "After the rotation");
+    }
+
+    #[test]
+    fn files_filled_by_the_logger_are_rotated() {
+        let directory = TempFile::dir("filled");
+        fs::write(directory.path().join(LOG_FILE_NAME), vec![b'\n'; MAX_LOG_SIZE as usize - 10]).unwrap();
+
+        let logger = FileLogger::new(directory.path(), LevelFilter::Info);
+        record(&logger, Level::Info, None, "Fills the file");
+        record(&logger, Level::Info, None, "After the rotation");
+        logger.flush();
+
+        let entries = read_entries(directory.path()).unwrap();
+        assert_eq!(log_files(directory.path()).len(), 2);
+        assert_eq!(fs::read_to_string(directory.path().join(LOG_FILE_NAME)).unwrap().lines().count(), 1);
+        assert_eq!(entries.last().unwrap().message, "After the rotation");
+    }
+}
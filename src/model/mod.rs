@@ -8,6 +8,15 @@
 ///
 /// - `music_miner`: Handles the processing of music files and extracting metadata.
 /// - `database`: Manages configuration and interaction with configuration files.
+/// - `playback`: Plays songs through the default audio output.
+/// - `error`: Defines the errors of the library, with the file they concern.
+/// - `logging`: Writes the leveled log to a rotating file and reads it back for the scan log.
+/// - `test_support`: Temporary files for the tests, removed when dropped.
 
 pub mod music_miner;
 pub mod database_config;
+pub mod playback;
+pub mod error;
+pub mod logging;
+#[cfg(test)]
+pub mod test_support;
@@ -0,0 +1,224 @@
+/// Role of the performers the song is credited to.
+pub const ROLE_MAIN: &str = "main";
+/// Role of guest performers (e.g. the `B` in `A feat. B`).
+pub const ROLE_FEATURED: &str = "featured";
+/// Role of the performers who remixed the song (TPE4).
+pub const ROLE_REMIXER: &str = "remixer";
+/// Role of the composers of the song (TCOM).
+pub const ROLE_COMPOSER: &str = "composer";
+
+/// The separators used to split an artist frame into several performers.
+///
+/// - `featured`: markers such as `feat.` that introduce guest performers.
+/// - `split`: separators between performers of the same role, such as `;`. `/` is not split
+///   on by default, as it is part of names like `AC/DC`.
+/// - `featured_split`: extra separators only used between guest performers, such as `&`,
+///   so that `A feat. B & C` credits both `B` and `C` while `Simon & Garfunkel` stays intact.
+///
+/// Values of multi-valued ID3v2.4 frames (separated by null bytes) are always split.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtistSeparators {
+    pub featured: Vec<String>,
+    pub split: Vec<String>,
+    pub featured_split: Vec<String>,
+}
+
+impl Default for ArtistSeparators {
+    fn default() -> Self {
+        ArtistSeparators {
+            featured: vec!["feat.".to_string(), "ft.".to_string(), "featuring".to_string()],
+            split: vec![";".to_string()],
+            featured_split: vec!["&".to_string(), ",".to_string()],
+        }
+    }
+}
+
+/// # Find Marker function
+///
+/// Finds the first featuring marker in `text` that stands as a word of its own, i.e. is
+/// preceded by whitespace or an opening bracket and followed by whitespace.
+/// The comparison ignores ASCII case.
+///
+/// # Returns
+/// * `Some((start, end))` - The byte range of the marker, including a preceding bracket.
+/// * `None` - If no marker is found.
+fn find_marker(text: &str, markers: &[String]) -> Option<(usize, usize)> {
+    let lowercase = text.to_ascii_lowercase();
+
+    markers.iter()
+        .filter(|marker| !marker.is_empty())
+        .filter_map(|marker| {
+            let marker = marker.to_ascii_lowercase();
+            lowercase.match_indices(&marker).find_map(|(start, _)| {
+                let end = start + marker.len();
+                let before = lowercase[..start].chars().last();
+                let after = lowercase[end..].chars().next();
+                let starts_word = matches!(before, Some(c) if c.is_whitespace() || c == '(' || c == '[');
+                let ends_word = matches!(after, Some(c) if c.is_whitespace());
+                if !(starts_word && ends_word) {
+                    return None;
+                }
+                match before {
+                    Some('(') | Some('[') => Some((start - 1, end)),
+                    _ => Some((start, end)),
+                }
+            })
+        })
+        .min()
+}
+
+/// Splits `text` on every separator, trimming whitespace and brackets and dropping empty names.
+fn split_names(text: &str, separators: &[&String]) -> Vec<String> {
+    let mut parts = vec![text.to_string()];
+    for separator in separators.iter().filter(|separator| !separator.is_empty()) {
+        parts = parts.iter()
+            .flat_map(|part| part.split(separator.as_str()).map(str::to_string).collect::<Vec<_>>())
+            .collect();
+    }
+
+    parts.iter()
+        .map(|part| part.trim_matches(|c: char| c.is_whitespace() || "()[]".contains(c)).to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// # Parse Credits function
+///
+/// Splits the artist, remixer and composer frames of a song into individual performers with
+/// their roles. Guest performers are taken from featuring markers in the artist frame and in
+/// the title (e.g. `Song (feat. B)`).
+///
+/// # Arguments
+/// * `artist` - The artist (TPE1) frame, possibly multi-valued.
+/// * `title` - The title of the song.
+/// * `remixer` - The remixer (TPE4) frame, if any.
+/// * `composer` - The composer (TCOM) frame, if any.
+/// * `separators` - The separators used to split the frames.
+///
+/// # Returns
+/// A vector of `(role, name)` pairs without duplicates, main performers first.
+pub fn parse_credits(
+    artist: &str,
+    title: &str,
+    remixer: Option<&str>,
+    composer: Option<&str>,
+    separators: &ArtistSeparators,
+) -> Vec<(&'static str, String)> {
+    let split: Vec<&String> = separators.split.iter().collect();
+    let featured_split: Vec<&String> = separators.split.iter().chain(&separators.featured_split).collect();
+    let mut credits: Vec<(&'static str, String)> = Vec::new();
+    let mut featured = Vec::new();
+
+    for value in artist.split('\0') {
+        match find_marker(value, &separators.featured) {
+            Some((start, end)) => {
+                credits.extend(split_names(&value[..start], &split).into_iter().map(|name| (ROLE_MAIN, name)));
+                featured.extend(split_names(&value[end..], &featured_split));
+            }
+            None => {
+                credits.extend(split_names(value, &split).into_iter().map(|name| (ROLE_MAIN, name)));
+            }
+        }
+    }
+
+    if let Some((start, end)) = find_marker(title, &separators.featured) {
+        if title[start..].starts_with('(') || title[start..].starts_with('[') {
+            let rest = &title[end..];
+            let guests = rest.find([')', ']']).map_or(rest, |close| &rest[..close]);
+            featured.extend(split_names(guests, &featured_split));
+        }
+    }
+
+    credits.extend(featured.into_iter().map(|name| (ROLE_FEATURED, name)));
+
+    for (role, frame) in [(ROLE_REMIXER, remixer), (ROLE_COMPOSER, composer)] {
+        if let Some(frame) = frame {
+            for value in frame.split('\0') {
+                credits.extend(split_names(value, &split).into_iter().map(|name| (role, name)));
+            }
+        }
+    }
+
+    let mut unique = Vec::new();
+    for credit in credits {
+        if !unique.contains(&credit) {
+            unique.push(credit);
+        }
+    }
+    unique
+}
+
+/// Encodes credits as `role=name` lines so they can be stored in the tag map under `Credits`.
+pub fn encode_credits(credits: &[(&'static str, String)]) -> String {
+    credits.iter()
+        .map(|(role, name)| format!("{}={}", role, name))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Decodes the `Credits` entry of a tag map back into `(role, name)` pairs.
+pub fn decode_credits(encoded: &str) -> Vec<(String, String)> {
+    encoded.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(role, name)| (role.to_string(), name.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credits(artist: &str, title: &str) -> Vec<(&'static str, String)> {
+        parse_credits(artist, title, None, None, &ArtistSeparators::default())
+    }
+
+    fn credit(role: &'static str, name: &str) -> (&'static str, String) {
+        (role, name.to_string())
+    }
+
+    #[test]
+    fn featured_performers_are_split_on_their_own_separators() {
+        assert_eq!(credits("A feat. B & C", "Song"), [credit(ROLE_MAIN, "A"), credit(ROLE_FEATURED, "B"), credit(ROLE_FEATURED, "C")]);
+        assert_eq!(credits("Simon & Garfunkel", "The Boxer"), [credit(ROLE_MAIN, "Simon & Garfunkel")]);
+        assert_eq!(credits("AC/DC", "Thunderstruck"), [credit(ROLE_MAIN, "AC/DC")]);
+        assert_eq!(credits("A; B FT. C, D", "Song"),
+            [credit(ROLE_MAIN, "A"), credit(ROLE_MAIN, "B"), credit(ROLE_FEATURED, "C"), credit(ROLE_FEATURED, "D")]);
+    }
+
+    #[test]
+    fn guests_are_read_from_the_title() {
+        assert_eq!(credits("A", "Song (feat. B)"), [credit(ROLE_MAIN, "A"), credit(ROLE_FEATURED, "B")]);
+        assert_eq!(credits("A feat. B", "Song [ft. B & C] (Remix)"),
+            [credit(ROLE_MAIN, "A"), credit(ROLE_FEATURED, "B"), credit(ROLE_FEATURED, "C")]);
+        // A marker outside brackets is part of the title.
+        assert_eq!(credits("A", "Song feat. B"), [credit(ROLE_MAIN, "A")]);
+    }
+
+    #[test]
+    fn null_separated_values_and_other_roles_are_split() {
+        let credits = parse_credits("A\0B feat. C", "Song", Some("D/E"), Some("F\0G; H"), &ArtistSeparators::default());
+        assert_eq!(credits, [
+            credit(ROLE_MAIN, "A"), credit(ROLE_MAIN, "B"), credit(ROLE_FEATURED, "C"),
+            credit(ROLE_REMIXER, "D/E"), credit(ROLE_COMPOSER, "F"), credit(ROLE_COMPOSER, "G"), credit(ROLE_COMPOSER, "H"),
+        ]);
+    }
+
+    #[test]
+    fn markers_must_stand_as_words() {
+        let markers = ArtistSeparators::default().featured;
+        assert_eq!(find_marker("A feat. B", &markers), Some((2, 7)));
+        assert_eq!(find_marker("Song (Featuring B)", &markers), Some((5, 15)));
+        assert_eq!(find_marker("Swift feat.", &markers), None);
+        assert_eq!(find_marker("Left.Feat. B", &markers), None);
+        assert_eq!(find_marker("Aloft. B", &markers), None);
+    }
+
+    #[test]
+    fn split_names_trims_and_drops_empty_names() {
+        let separators = [";".to_string(), ",".to_string()];
+        let separators: Vec<&String> = separators.iter().collect();
+        assert_eq!(split_names(" A ;; (B), [C] ", &separators), ["A", "B", "C"]);
+        assert!(split_names(" ; ", &separators).is_empty());
+        assert_eq!(split_names("A;B", &[&String::new()]), ["A;B"]);
+    }
+}
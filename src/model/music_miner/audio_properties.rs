@@ -0,0 +1,502 @@
+use crate::model::music_miner::flac;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Number of bytes read after the ID3v2 tag when looking for the first MPEG frame.
+const SCAN_WINDOW: usize = 64 * 1024;
+
+/// Bitrates in kbps, indexed by the bitrate index of the frame header.
+const BITRATES_V1_L1: [u32; 15] = [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448];
+const BITRATES_V1_L2: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+const BITRATES_V1_L3: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const BITRATES_V2_L1: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+const BITRATES_V2_L23: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// The properties of the audio stream of a song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioProperties {
+    pub duration_ms: u64,
+    /// Average bitrate in kbps.
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub channels: u8,
+    /// `Stereo`, `Joint stereo`, `Dual channel`, `Mono` or `<n> channels`.
+    pub channel_mode: String,
+    /// The encoder named by a LAME tag or the FLAC vendor string.
+    pub encoder: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MpegVersion {
+    V1,
+    V2,
+    V25,
+}
+
+/// The fields of an MPEG audio frame header.
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: MpegVersion,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    channel_mode: u8,
+}
+
+impl FrameHeader {
+    /// Parses the four bytes of a frame header, rejecting reserved and free-format values.
+    fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0x3 {
+            0 => MpegVersion::V25,
+            2 => MpegVersion::V2,
+            3 => MpegVersion::V1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0x3 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let bitrates = match (version, layer) {
+            (MpegVersion::V1, 1) => &BITRATES_V1_L1,
+            (MpegVersion::V1, 2) => &BITRATES_V1_L2,
+            (MpegVersion::V1, _) => &BITRATES_V1_L3,
+            (_, 1) => &BITRATES_V2_L1,
+            _ => &BITRATES_V2_L23,
+        };
+
+        let sample_rates = match version {
+            MpegVersion::V1 => [44_100, 48_000, 32_000],
+            MpegVersion::V2 => [22_050, 24_000, 16_000],
+            MpegVersion::V25 => [11_025, 12_000, 8_000],
+        };
+        let sample_rate = *sample_rates.get(((bytes[2] >> 2) & 0x3) as usize)?;
+
+        Some(FrameHeader {
+            version,
+            layer,
+            bitrate: bitrates[bitrate_index],
+            sample_rate,
+            padding: (bytes[2] >> 1) & 0x1 == 1,
+            channel_mode: bytes[3] >> 6,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, MpegVersion::V2) | (3, MpegVersion::V25) => 576,
+            _ => 1152,
+        }
+    }
+
+    /// Length of the whole frame in bytes, header included.
+    fn frame_length(&self) -> usize {
+        let padding = u32::from(self.padding);
+        let length = if self.layer == 1 {
+            (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4
+        } else {
+            self.samples_per_frame() / 8 * self.bitrate * 1000 / self.sample_rate + padding
+        };
+        length as usize
+    }
+
+    /// Offset of the Xing/Info header from the start of the frame (header plus side information).
+    fn xing_offset(&self) -> usize {
+        let mono = self.channel_mode == 3;
+        match (self.version, mono) {
+            (MpegVersion::V1, false) => 4 + 32,
+            (MpegVersion::V1, true) | (_, false) => 4 + 17,
+            (_, true) => 4 + 9,
+        }
+    }
+
+    fn channels(&self) -> u8 {
+        if self.channel_mode == 3 { 1 } else { 2 }
+    }
+
+    fn channel_mode_name(&self) -> &'static str {
+        match self.channel_mode {
+            0 => "Stereo",
+            1 => "Joint stereo",
+            2 => "Dual channel",
+            _ => "Mono",
+        }
+    }
+}
+
+/// The frame and byte counts of a VBR header, and the encoder named by a LAME tag.
+#[derive(Debug, Default)]
+struct VbrHeader {
+    frames: Option<u32>,
+    bytes: Option<u32>,
+    encoder: Option<String>,
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// # Parse Xing function
+///
+/// Parses a Xing (VBR) or Info (CBR) header and the LAME tag that follows it.
+///
+/// # Arguments
+/// * `data` - The bytes starting at the `Xing`/`Info` identifier.
+fn parse_xing(data: &[u8]) -> Option<VbrHeader> {
+    if !(data.starts_with(b"Xing") || data.starts_with(b"Info")) {
+        return None;
+    }
+
+    let flags = read_u32_be(data, 4)?;
+    let mut offset = 8;
+    let mut header = VbrHeader::default();
+    if flags & 0x1 != 0 {
+        header.frames = read_u32_be(data, offset);
+        offset += 4;
+    }
+    if flags & 0x2 != 0 {
+        header.bytes = read_u32_be(data, offset);
+        offset += 4;
+    }
+    if flags & 0x4 != 0 {
+        offset += 100;
+    }
+    if flags & 0x8 != 0 {
+        offset += 4;
+    }
+
+    // The LAME tag starts with a 9-byte encoder string such as `LAME3.100`.
+    header.encoder = data.get(offset..offset + 9)
+        .map(|encoder| String::from_utf8_lossy(encoder).trim_end_matches(['\0', ' ']).to_string())
+        .filter(|encoder| !encoder.is_empty() && encoder.chars().all(|c| c.is_ascii_graphic() || c == ' '));
+
+    Some(header)
+}
+
+/// Parses a VBRI header (written by Fraunhofer encoders), which sits 32 bytes after the frame header.
+fn parse_vbri(data: &[u8]) -> Option<VbrHeader> {
+    if !data.starts_with(b"VBRI") {
+        return None;
+    }
+
+    Some(VbrHeader {
+        bytes: read_u32_be(data, 10),
+        frames: read_u32_be(data, 14),
+        encoder: None,
+    })
+}
+
+/// Returns the size of the ID3v2 tag at the start of the file, footer included, or 0 if there is none.
+pub fn id3v2_size(file: &mut File) -> io::Result<u64> {
+    let mut header = [0u8; 10];
+    file.seek(SeekFrom::Start(0))?;
+    if file.read(&mut header)? < 10 || &header[..3] != b"ID3" {
+        return Ok(0);
+    }
+
+    let size = header[6..10].iter().fold(0u64, |size, byte| (size << 7) | u64::from(byte & 0x7F));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(10 + size + footer)
+}
+
+/// Returns the size of the ID3v1 tag at the end of the file, or 0 if there is none.
+pub fn id3v1_size(file: &mut File, file_size: u64) -> io::Result<u64> {
+    if file_size < 128 {
+        return Ok(0);
+    }
+
+    let mut marker = [0u8; 3];
+    file.seek(SeekFrom::Start(file_size - 128))?;
+    file.read_exact(&mut marker)?;
+    Ok(if &marker == b"TAG" { 128 } else { 0 })
+}
+
+/// Finds the first frame header in `data` that is followed by another valid frame header
+/// (or by the end of the window), so stray sync bytes in the audio are not mistaken for a frame.
+fn find_first_frame(data: &[u8]) -> Option<(usize, FrameHeader)> {
+    (0..data.len().saturating_sub(4)).find_map(|offset| {
+        let header = FrameHeader::parse(&data[offset..])?;
+        let next = offset + header.frame_length();
+        if next + 4 > data.len() || FrameHeader::parse(&data[next..]).is_some() {
+            Some((offset, header))
+        } else {
+            None
+        }
+    })
+}
+
+/// # Read MPEG Properties function
+///
+/// Reads the properties of an MPEG audio file from its first frame header. The duration of
+/// VBR files is taken from the frame count of a Xing or VBRI header; files without one are
+/// assumed to be CBR and their duration is computed from the size of the audio data.
+fn read_mpeg_properties(path: &Path) -> io::Result<AudioProperties> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let audio_start = id3v2_size(&mut file)?;
+    let audio_end = file_size - id3v1_size(&mut file, file_size)?;
+
+    let mut data = Vec::with_capacity(SCAN_WINDOW);
+    file.seek(SeekFrom::Start(audio_start))?;
+    file.take(SCAN_WINDOW as u64).read_to_end(&mut data)?;
+
+    let (offset, header) = find_first_frame(&data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No MPEG audio frame found"))?;
+
+    let vbr = data.get(offset + header.xing_offset()..).and_then(parse_xing)
+        .or_else(|| data.get(offset + 36..).and_then(parse_vbri))
+        .unwrap_or_default();
+
+    let audio_bytes = audio_end.saturating_sub(audio_start + offset as u64);
+    let (duration_ms, bitrate) = match vbr.frames {
+        Some(frames) if frames > 0 => {
+            let samples = u64::from(frames) * u64::from(header.samples_per_frame());
+            let duration_ms = samples * 1000 / u64::from(header.sample_rate);
+            let bytes = vbr.bytes.map_or(audio_bytes, u64::from);
+            let bitrate = (bytes * 8).checked_div(duration_ms).map_or(header.bitrate, |bitrate| bitrate as u32);
+            (duration_ms, bitrate)
+        }
+        _ => (audio_bytes * 8 / u64::from(header.bitrate), header.bitrate),
+    };
+
+    Ok(AudioProperties {
+        duration_ms,
+        bitrate,
+        sample_rate: header.sample_rate,
+        channels: header.channels(),
+        channel_mode: header.channel_mode_name().to_string(),
+        encoder: vbr.encoder,
+    })
+}
+
+/// Reads the properties of a FLAC file from its STREAMINFO block and vendor string.
+fn read_flac_properties(path: &Path) -> io::Result<AudioProperties> {
+    let metadata = flac::read_flac_metadata(path)?;
+    let stream_info = &metadata.stream_info;
+    if stream_info.sample_rate == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "FLAC file has an invalid sample rate"));
+    }
+
+    let duration_ms = stream_info.total_samples * 1000 / u64::from(stream_info.sample_rate);
+    let audio_bytes = fs::metadata(path)?.len().saturating_sub(metadata.audio_offset);
+    let bitrate = (audio_bytes * 8).checked_div(duration_ms).map_or(0, |bitrate| bitrate as u32);
+    let channel_mode = match stream_info.channels {
+        1 => "Mono".to_string(),
+        2 => "Stereo".to_string(),
+        channels => format!("{} channels", channels),
+    };
+
+    Ok(AudioProperties {
+        duration_ms,
+        bitrate,
+        sample_rate: stream_info.sample_rate,
+        channels: stream_info.channels,
+        channel_mode,
+        encoder: metadata.vendor.filter(|vendor| !vendor.is_empty()),
+    })
+}
+
+/// # Read Audio Properties function
+///
+/// Reads the duration, bitrate, sample rate, channels and encoder of a song. FLAC files are
+/// read from their STREAMINFO block; every other file is parsed as MPEG audio.
+///
+/// # Arguments
+/// * `path` - The path of the song.
+///
+/// # Returns
+/// * `Ok(AudioProperties)` - The properties of the audio stream.
+/// * `Err(io::Error)` - If the file cannot be read or has no recognisable audio stream.
+pub fn read_audio_properties(path: &Path) -> io::Result<AudioProperties> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("flac") => read_flac_properties(path),
+        _ => read_mpeg_properties(path),
+    }
+}
+
+/// Stores the properties in the tag map under `Duration` (milliseconds), `Bitrate` (kbps),
+/// `Sample Rate` (Hz), `Channels`, `Channel Mode` and `Encoder`.
+pub fn insert_properties(map: &mut HashMap<String, String>, properties: &AudioProperties) {
+    map.insert("Duration".to_string(), properties.duration_ms.to_string());
+    map.insert("Bitrate".to_string(), properties.bitrate.to_string());
+    map.insert("Sample Rate".to_string(), properties.sample_rate.to_string());
+    map.insert("Channels".to_string(), properties.channels.to_string());
+    map.insert("Channel Mode".to_string(), properties.channel_mode.clone());
+    if let Some(encoder) = &properties.encoder {
+        map.insert("Encoder".to_string(), encoder.clone());
+    }
+}
+
+/// Formats a duration in milliseconds as `m:ss`, or `h:mm:ss` for durations of an hour or more.
+pub fn format_duration(duration_ms: i64) -> String {
+    let seconds = duration_ms / 1000;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    fn read_fixture(name: &str, contents: &[u8]) -> AudioProperties {
+        let file = TempFile::new(name, contents);
+        let properties = read_audio_properties(file.path());
+        properties.unwrap()
+    }
+
+    /// Builds `count` MPEG frames starting with `header`, the first one holding `first_frame_data`
+    /// right after its header.
+    fn mpeg_frames(header: [u8; 4], count: usize, first_frame_data: &[(usize, &[u8])]) -> Vec<u8> {
+        let length = FrameHeader::parse(&header).unwrap().frame_length();
+        let mut frames = Vec::new();
+        for index in 0..count {
+            let mut frame = vec![0u8; length];
+            frame[..4].copy_from_slice(&header);
+            if index == 0 {
+                for (offset, data) in first_frame_data {
+                    frame[*offset..*offset + data.len()].copy_from_slice(data);
+                }
+            }
+            frames.extend(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn frame_headers_are_parsed() {
+        let header = FrameHeader::parse(&[0xFF, 0xFB, 0x90, 0x40]).unwrap();
+        assert_eq!((header.version, header.layer, header.bitrate, header.sample_rate), (MpegVersion::V1, 3, 128, 44_100));
+        assert_eq!(header.frame_length(), 417);
+        assert_eq!(header.samples_per_frame(), 1152);
+        assert_eq!(header.channel_mode_name(), "Joint stereo");
+
+        let padded = FrameHeader::parse(&[0xFF, 0xFB, 0x92, 0xC0]).unwrap();
+        assert_eq!(padded.frame_length(), 418);
+        assert_eq!((padded.channels(), padded.xing_offset()), (1, 21));
+
+        let mpeg2 = FrameHeader::parse(&[0xFF, 0xF3, 0x80, 0xC0]).unwrap();
+        assert_eq!((mpeg2.version, mpeg2.bitrate, mpeg2.sample_rate, mpeg2.samples_per_frame()), (MpegVersion::V2, 64, 22_050, 576));
+
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0x00, 0x00]).is_none(), "free format");
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x00]).is_none(), "bad bitrate");
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0x9C, 0x00]).is_none(), "reserved sample rate");
+        assert!(FrameHeader::parse(&[0xFF, 0xEB, 0x90, 0x00]).is_none(), "reserved version");
+        assert!(FrameHeader::parse(&[0x49, 0x44, 0x33, 0x04]).is_none());
+    }
+
+    #[test]
+    fn cbr_files_are_timed_from_their_size() {
+        let mut contents = b"ID3\x04\x00\x00\x00\x00\x00\x0A".to_vec();
+        contents.extend([0u8; 10]);
+        contents.extend(mpeg_frames([0xFF, 0xFB, 0x90, 0x00], 100, &[]));
+        contents.extend(b"TAG");
+        contents.extend([0u8; 125]);
+
+        let properties = read_fixture("cbr.mp3", &contents);
+        assert_eq!(properties, AudioProperties {
+            duration_ms: 417 * 100 * 8 / 128,
+            bitrate: 128,
+            sample_rate: 44_100,
+            channels: 2,
+            channel_mode: "Stereo".to_string(),
+            encoder: None,
+        });
+    }
+
+    #[test]
+    fn xing_headers_give_the_duration_of_vbr_files() {
+        let mut xing = b"Xing".to_vec();
+        xing.extend(3u32.to_be_bytes());
+        xing.extend(1_000u32.to_be_bytes());
+        xing.extend(400_000u32.to_be_bytes());
+        xing.extend(b"LAME3.100");
+        let contents = mpeg_frames([0xFF, 0xFB, 0x90, 0x40], 3, &[(36, &xing)]);
+
+        let properties = read_fixture("xing.mp3", &contents);
+        assert_eq!(properties.duration_ms, 1_000 * 1152 * 1000 / 44_100);
+        assert_eq!(properties.bitrate, 400_000 * 8 / 26_122);
+        assert_eq!(properties.encoder.as_deref(), Some("LAME3.100"));
+        assert_eq!(properties.channel_mode, "Joint stereo");
+
+        let info = parse_xing(b"Info\x00\x00\x00\x00LAME3.99r").unwrap();
+        assert_eq!((info.frames, info.bytes, info.encoder.as_deref()), (None, None, Some("LAME3.99r")));
+        assert!(parse_xing(b"Xing\x00\x00\x00\x01\x00\x00").unwrap().frames.is_none());
+    }
+
+    #[test]
+    fn vbri_headers_give_the_duration_of_vbr_files() {
+        let mut vbri = b"VBRI".to_vec();
+        vbri.extend([0u8; 6]);
+        vbri.extend(100_000u32.to_be_bytes());
+        vbri.extend(500u32.to_be_bytes());
+        let contents = mpeg_frames([0xFF, 0xF3, 0x80, 0xC0], 3, &[(36, &vbri)]);
+
+        let properties = read_fixture("vbri.mp3", &contents);
+        assert_eq!(properties.duration_ms, 500 * 576 * 1000 / 22_050);
+        assert_eq!(properties.bitrate, 100_000 * 8 / 13_061);
+        assert_eq!((properties.sample_rate, properties.channels), (22_050, 1));
+        assert_eq!(properties.channel_mode, "Mono");
+        assert_eq!(properties.encoder, None);
+    }
+
+    #[test]
+    fn stray_sync_bytes_are_not_taken_for_a_frame() {
+        let mut contents = vec![0xFF, 0xFB, 0x90, 0x00, 0x12, 0x34];
+        contents.extend(mpeg_frames([0xFF, 0xFB, 0x50, 0xC0], 4, &[]));
+        let (offset, header) = find_first_frame(&contents).unwrap();
+        assert_eq!((offset, header.bitrate), (6, 64));
+        assert!(find_first_frame(b"no audio here").is_none());
+    }
+
+    #[test]
+    fn streaminfo_gives_the_properties_of_flac_files() {
+        let mut contents = b"fLaC".to_vec();
+        let mut stream_info = [0u8; 34];
+        let packed: u64 = (44_100 << 44) | (1 << 41) | (15 << 36) | 441_000;
+        stream_info[10..18].copy_from_slice(&packed.to_be_bytes());
+        contents.extend([0x00, 0x00, 0x00, 34]);
+        contents.extend(stream_info);
+        let vendor = b"reference libFLAC 1.4.3";
+        let mut comment = (vendor.len() as u32).to_le_bytes().to_vec();
+        comment.extend(vendor);
+        comment.extend(0u32.to_le_bytes());
+        contents.extend([0x84, 0x00, 0x00, comment.len() as u8]);
+        contents.extend(comment);
+        contents.extend(vec![0u8; 44_100]);
+
+        let properties = read_fixture("streaminfo.flac", &contents);
+        assert_eq!(properties, AudioProperties {
+            duration_ms: 10_000,
+            bitrate: 44_100 * 8 / 10_000,
+            sample_rate: 44_100,
+            channels: 2,
+            channel_mode: "Stereo".to_string(),
+            encoder: Some("reference libFLAC 1.4.3".to_string()),
+        });
+    }
+
+    #[test]
+    fn durations_are_formatted() {
+        assert_eq!(format_duration(59_999), "0:59");
+        assert_eq!(format_duration(354_000), "5:54");
+        assert_eq!(format_duration(3_723_000), "1:02:03");
+    }
+}
@@ -0,0 +1,129 @@
+extern crate id3;
+use crate::model::database_config::config::get_local_dir;
+use id3::frame::PictureType;
+use id3::Tag;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File names (without extension) recognised as album covers inside an album directory.
+const FOLDER_COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+
+/// Image extensions recognised for album covers inside an album directory.
+const FOLDER_COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Width and height, in pixels, of the cached thumbnails.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// # Embedded Cover function
+///
+/// Returns the image data of the front cover stored in the APIC frames of an ID3 tag.
+/// If there is no front cover, the first picture in the tag is used instead.
+///
+/// # Arguments
+/// * `tag` - A reference to the tag of the song.
+///
+/// # Returns
+/// * `Some(Vec<u8>)` - The raw image data.
+/// * `None` - If the tag contains no pictures.
+pub fn embedded_cover(tag: &Tag) -> Option<Vec<u8>> {
+    tag.pictures()
+        .find(|picture| picture.picture_type == PictureType::CoverFront)
+        .or_else(|| tag.pictures().next())
+        .map(|picture| picture.data.clone())
+}
+
+/// # Folder Cover function
+///
+/// Looks for an image such as `cover.jpg` or `folder.png` in the album directory.
+/// File names are compared case-insensitively.
+///
+/// # Arguments
+/// * `album_dir` - The directory containing the song.
+///
+/// # Returns
+/// * `Some(Vec<u8>)` - The raw image data of the first cover found.
+/// * `None` - If the directory has no recognised cover image or it cannot be read.
+pub fn folder_cover(album_dir: &Path) -> Option<Vec<u8>> {
+    let entries = fs::read_dir(album_dir).ok()?;
+
+    let mut candidates: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_lowercase);
+            let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+            match (stem, extension) {
+                (Some(stem), Some(extension)) => {
+                    FOLDER_COVER_NAMES.contains(&stem.as_str())
+                        && FOLDER_COVER_EXTENSIONS.contains(&extension.as_str())
+                }
+                _ => false,
+            }
+        })
+        .collect();
+
+    candidates.sort_by_key(|path| {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("").to_lowercase();
+        FOLDER_COVER_NAMES.iter().position(|name| *name == stem)
+    });
+
+    candidates.iter().find_map(|path| fs::read(path).ok())
+}
+
+/// # Cache Cover function
+///
+/// Stores a thumbnail of the given image in the `covers` directory under `get_local_dir()`.
+/// Thumbnails are named after the SHA-256 hash of the original image, so an image shared by
+/// every song of an album (or by several albums) is decoded and stored only once.
+///
+/// # Arguments
+/// * `data` - The raw image data (JPEG or PNG).
+///
+/// # Returns
+/// * `Ok(PathBuf)` - The path of the cached thumbnail.
+/// * `Err(io::Error)` - If the image cannot be decoded or the thumbnail cannot be written.
+pub fn cache_cover(data: &[u8]) -> io::Result<PathBuf> {
+    let covers_dir = get_local_dir()?.join("covers");
+    fs::create_dir_all(&covers_dir)?;
+
+    let hash: String = Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect();
+    let thumbnail_path = covers_dir.join(format!("{}.png", hash));
+
+    if thumbnail_path.exists() {
+        return Ok(thumbnail_path);
+    }
+
+    let image = image::load_from_memory(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .save_with_format(&thumbnail_path, image::ImageFormat::Png)
+        .map_err(io::Error::other)?;
+
+    Ok(thumbnail_path)
+}
+
+/// # Find Cover function
+///
+/// Finds the cover of a song, preferring the picture embedded in its tag over an image in
+/// the album directory, and returns the path of its cached thumbnail.
+///
+/// # Arguments
+/// * `tag` - A reference to the tag of the song.
+/// * `song_path` - The path of the song file.
+///
+/// # Returns
+/// * `Some(String)` - The path of the cached thumbnail.
+/// * `None` - If no cover was found or it could not be cached.
+pub fn find_cover(tag: &Tag, song_path: &Path) -> Option<String> {
+    let data = embedded_cover(tag).or_else(|| song_path.parent().and_then(folder_cover))?;
+
+    match cache_cover(&data) {
+        Ok(thumbnail_path) => thumbnail_path.to_str().map(str::to_string),
+        Err(e) => {
+            log::warn!(path = song_path.to_string_lossy().as_ref(); "Could not cache the cover: {}", e);
+            None
+        }
+    }
+}
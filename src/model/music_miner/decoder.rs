@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::{Error, Result};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+/// Decodes the first audio track of an MP3 or FLAC file into interleaved `f32` samples.
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// Number of frames of the song, when the container declares it.
+    pub total_frames: Option<u64>,
+    /// Length of the song, when the container declares its number of frames.
+    pub duration_ms: Option<u64>,
+    /// Number of frames (samples per channel) before the next packet to be decoded.
+    position_frames: u64,
+}
+
+impl AudioDecoder {
+    /// # Open function
+    ///
+    /// Opens a song and prepares a decoder for its first audio track.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the MP3 or FLAC file.
+    /// * `gapless` - Whether to trim the encoder delay and padding declared by a LAME tag, so
+    ///   consecutive tracks of an album join without silence.
+    ///
+    /// # Returns
+    /// * `Ok(AudioDecoder)` - The decoder, positioned at the start of the song.
+    /// * `Err(symphonia::core::errors::Error)` - If the file cannot be read or its codec is not supported.
+    pub fn open(path: &Path, gapless: bool) -> Result<AudioDecoder> {
+        let file = File::open(path)?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions { enable_gapless: gapless, ..Default::default() }, &MetadataOptions::default())?;
+        let format = probed.format;
+
+        let track = format.tracks().iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(Error::Unsupported("no audio track"))?;
+        let track_id = track.id;
+        let sample_rate = track.codec_params.sample_rate.ok_or(Error::Unsupported("unknown sample rate"))?;
+        let channels = track.codec_params.channels.map_or(2, |channels| channels.count());
+        let total_frames = track.codec_params.n_frames;
+        let duration_ms = total_frames.map(|frames| frames * 1000 / u64::from(sample_rate));
+        let decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        Ok(AudioDecoder { format, decoder, track_id, sample_rate, channels, total_frames, duration_ms, position_frames: 0 })
+    }
+
+    /// Returns the position of the next packet to be decoded, in frames.
+    pub fn position_frames(&self) -> u64 {
+        self.position_frames
+    }
+
+    /// Returns the position of the next packet to be decoded, in milliseconds.
+    pub fn position_ms(&self) -> u64 {
+        self.position_frames * 1000 / u64::from(self.sample_rate)
+    }
+
+    /// # Seek function
+    ///
+    /// Moves to the given position of the song. The decoder lands on the packet containing the
+    /// position, so playback may resume slightly before it.
+    ///
+    /// # Arguments
+    /// * `position_ms` - The position to move to, in milliseconds from the start of the song.
+    pub fn seek(&mut self, position_ms: u64) -> Result<()> {
+        let time = Time::new(position_ms / 1000, (position_ms % 1000) as f64 / 1000.0);
+        let seeked = self.format.seek(SeekMode::Accurate, SeekTo::Time { time, track_id: Some(self.track_id) })?;
+        self.decoder.reset();
+        self.position_frames = seeked.actual_ts;
+        Ok(())
+    }
+
+    /// # Next Samples function
+    ///
+    /// Decodes the next packet of the song. Packets that fail to decode are skipped, as
+    /// players usually do with corrupted frames.
+    ///
+    /// # Returns
+    /// * `Some(Vec<f32>)` - The interleaved samples of the packet.
+    /// * `None` - At the end of the song, or if the file cannot be read any further.
+    pub fn next_samples(&mut self) -> Option<Vec<f32>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(_) => return None,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+                    buffer.copy_interleaved_ref(decoded);
+                    self.position_frames += (buffer.samples().len() / self.channels.max(1)) as u64;
+                    return Some(buffer.samples().to_vec());
+                }
+                Err(Error::DecodeError(e)) => {
+                    log::warn!("Skipping a corrupted packet: {}", e);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    /// Number of audio frames of the fixture, after its Info frame.
+    const MPEG_FRAMES: u64 = 20;
+    /// Frames (samples per channel) of an MPEG-1 Layer III frame.
+    const FRAME_SAMPLES: u64 = 1152;
+    /// Delay and padding declared in the LAME tag, which decoders extend by the 529 samples of
+    /// latency of the synthesis filter bank.
+    const DELAY: u64 = 576;
+    const PADDING: u64 = 1_000;
+    const DECODER_DELAY: u64 = 529;
+
+    /// Packs values of a given number of bits, most significant bit first.
+    fn pack_bits(fields: &[(u32, u32)], length: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; length];
+        let mut position = 0;
+        for (value, bits) in fields {
+            for bit in (0..*bits).rev() {
+                if value >> bit & 1 == 1 {
+                    bytes[position / 8] |= 0x80 >> (position % 8);
+                }
+                position += 1;
+            }
+        }
+        bytes
+    }
+
+    /// Writes a 128 kbit/s stereo MP3 starting with an Info frame carrying a LAME tag. Each
+    /// granule of the audio frames holds a single spectral line, coded in the count1 region, so
+    /// the song is a quiet but non-silent signal.
+    fn write_lame_mp3(name: &str) -> TempFile {
+        const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+        const FRAME_LENGTH: usize = 417;
+
+        let mut info = HEADER.to_vec();
+        info.extend([0; 32]);
+        info.extend(b"Info");
+        info.extend(1u32.to_be_bytes());
+        info.extend((MPEG_FRAMES as u32).to_be_bytes());
+        info.extend(b"Lavf58.76");
+        info.extend([0; 12]);
+        let trim = (DELAY << 12) | PADDING;
+        info.extend(&(trim as u32).to_be_bytes()[1..]);
+        info.resize(FRAME_LENGTH, 0);
+
+        // main_data_begin, private bits and scfsi, then for each granule and channel:
+        // part2_3_length, big_values, global_gain, scalefac_compress, window switching,
+        // table_select, region counts, preflag, scalefac_scale and count1table_select.
+        let mut side_info = vec![(0, 9), (0, 3), (0, 8)];
+        for _ in 0..4 {
+            side_info.extend([(5, 12), (0, 9), (210, 8), (0, 4), (0, 1), (0, 15), (0, 4), (0, 3), (0, 1), (0, 1), (0, 1)]);
+        }
+        // In count1 table A, `0101` codes the quadruple (0, 0, 0, 1), followed by its sign bit.
+        let main_data = vec![(0b01010, 5); 4];
+
+        let mut contents = info;
+        for _ in 0..MPEG_FRAMES {
+            contents.extend(HEADER);
+            contents.extend(pack_bits(&side_info, 32));
+            contents.extend(pack_bits(&main_data, FRAME_LENGTH - 36));
+        }
+
+        TempFile::new(&format!("{}.mp3", name), contents)
+    }
+
+    fn decode(path: &Path, gapless: bool) -> (AudioDecoder, Vec<f32>) {
+        let mut decoder = AudioDecoder::open(path, gapless).unwrap();
+        let mut samples = Vec::new();
+        while let Some(packet) = decoder.next_samples() {
+            samples.extend(packet);
+        }
+        (decoder, samples)
+    }
+
+    #[test]
+    fn lame_delay_and_padding_are_trimmed() {
+        let file = write_lame_mp3("lame");
+        let (untrimmed_decoder, untrimmed) = decode(file.path(), false);
+        let (trimmed_decoder, trimmed) = decode(file.path(), true);
+
+        let delay = (DELAY + DECODER_DELAY) as usize;
+        let frames = MPEG_FRAMES * FRAME_SAMPLES - (DELAY + DECODER_DELAY) - (PADDING - DECODER_DELAY);
+        assert_eq!(untrimmed_decoder.total_frames, Some(MPEG_FRAMES * FRAME_SAMPLES));
+        assert_eq!(trimmed_decoder.total_frames, Some(frames));
+        assert_eq!(trimmed_decoder.position_frames(), frames);
+        assert_eq!(untrimmed.len() as u64, MPEG_FRAMES * FRAME_SAMPLES * 2);
+        assert_eq!(trimmed.len() as u64, frames * 2);
+
+        // The trimmed song is the untrimmed one without its first and last samples, with no
+        // sample dropped or repeated at packet boundaries.
+        assert!(trimmed.iter().any(|sample| *sample != 0.0));
+        assert_eq!(trimmed[..], untrimmed[delay * 2..delay * 2 + trimmed.len()]);
+    }
+}
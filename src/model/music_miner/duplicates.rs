@@ -0,0 +1,415 @@
+use crate::model::music_miner::audio_properties::{id3v1_size, id3v2_size};
+use crate::model::music_miner::flac;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Songs whose normalised artist and title match are duplicates if their durations differ by
+/// at most this many milliseconds (different rips of a song rarely have the same length).
+const DURATION_TOLERANCE_MS: i64 = 2000;
+
+/// Size of the footer (and optional header) of an APEv2 tag.
+const APE_FOOTER_SIZE: u64 = 32;
+
+/// A song considered by the duplicate finder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateCandidate {
+    pub path: String,
+    pub artist: String,
+    pub title: String,
+    pub duration_ms: i64,
+    /// Average bitrate in kbps.
+    pub bitrate: u32,
+    /// SHA-256 of the audio frames, with the tags stripped. Only computed for songs whose
+    /// audio has the same length as another song's, since only those can be identical.
+    pub content_hash: Option<String>,
+}
+
+/// Which copy of a group of duplicates is kept by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeepPreference {
+    HighestBitrate,
+    /// FLAC over MP3, then the highest bitrate.
+    Lossless,
+    ShortestPath,
+}
+
+/// # Normalise function
+///
+/// Lowercases a text and keeps only its letters and digits, so `The Beatles` and
+/// `the beatles!` compare equal.
+pub fn normalise(text: &str) -> String {
+    text.chars()
+        .filter(|character| character.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns whether a song is a FLAC file, judging by its extension.
+pub fn is_lossless(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+}
+
+/// Returns the size of the APEv2 tag that ends at `end`, header included, or 0 if there is none.
+fn apev2_size(file: &mut File, end: u64) -> io::Result<u64> {
+    if end < APE_FOOTER_SIZE {
+        return Ok(0);
+    }
+
+    let mut footer = [0u8; APE_FOOTER_SIZE as usize];
+    file.seek(SeekFrom::Start(end - APE_FOOTER_SIZE))?;
+    file.read_exact(&mut footer)?;
+    if &footer[..8] != b"APETAGEX" {
+        return Ok(0);
+    }
+
+    let size = u64::from(u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]));
+    let has_header = footer[23] & 0x80 != 0;
+    Ok(size + if has_header { APE_FOOTER_SIZE } else { 0 })
+}
+
+/// # Audio Range function
+///
+/// Finds where the audio of a song starts and ends, leaving out its tags: the metadata
+/// blocks of a FLAC file, or the ID3v2 tag at the start and the APEv2 and ID3v1 tags at the
+/// end of an MP3 file.
+///
+/// # Returns
+/// The offset of the first audio byte and the offset just past the last one.
+pub fn audio_range(path: &Path) -> io::Result<(u64, u64)> {
+    let file_size = fs::metadata(path)?.len();
+
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("flac")) {
+        let metadata = flac::read_flac_metadata(path)?;
+        return Ok((metadata.audio_offset.min(file_size), file_size));
+    }
+
+    let mut file = File::open(path)?;
+    let start = id3v2_size(&mut file)?.min(file_size);
+    let mut end = file_size - id3v1_size(&mut file, file_size)?;
+    end = end.saturating_sub(apev2_size(&mut file, end)?);
+    Ok((start, end.max(start)))
+}
+
+/// # Audio Content Hash function
+///
+/// Hashes the audio of a song between the offsets returned by `audio_range()`, so two files
+/// with the same audio but different tags get the same hash.
+///
+/// # Returns
+/// The SHA-256 of the audio, in lowercase hexadecimal.
+pub fn audio_content_hash(path: &Path, (start, end): (u64, u64)) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut hasher = Sha256::new();
+    let mut remaining = end - start;
+    let mut buffer = vec![0u8; 64 * 1024];
+    while remaining > 0 {
+        let wanted = remaining.min(buffer.len() as u64) as usize;
+        let read = file.read(&mut buffer[..wanted])?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        remaining -= read as u64;
+    }
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Finds the representative of a candidate in the union-find forest, compressing the path.
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (root_a, root_b) = (find(parents, a), find(parents, b));
+    if root_a != root_b {
+        parents[root_b] = root_a;
+    }
+}
+
+/// # Group Duplicates function
+///
+/// Groups the candidates that are copies of the same song: those with the same audio content
+/// hash, and those with the same normalised artist and title whose durations differ by at
+/// most two seconds. Songs without a title are only grouped by their hash.
+///
+/// # Returns
+/// The groups of two or more candidates, as indices into `candidates`, in the order the
+/// candidates were given.
+pub fn group_duplicates(candidates: &[DuplicateCandidate]) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..candidates.len()).collect();
+
+    let mut by_hash: HashMap<&str, usize> = HashMap::new();
+    let mut by_name: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        if let Some(hash) = &candidate.content_hash {
+            match by_hash.get(hash.as_str()) {
+                Some(&first) => union(&mut parents, first, index),
+                None => {
+                    by_hash.insert(hash, index);
+                }
+            }
+        }
+
+        let title = normalise(&candidate.title);
+        if !title.is_empty() {
+            by_name.entry((normalise(&candidate.artist), title)).or_default().push(index);
+        }
+    }
+
+    for mut indices in by_name.into_values() {
+        indices.sort_by_key(|&index| candidates[index].duration_ms);
+        for pair in indices.windows(2) {
+            if candidates[pair[1]].duration_ms - candidates[pair[0]].duration_ms <= DURATION_TOLERANCE_MS {
+                union(&mut parents, pair[0], pair[1]);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for index in 0..candidates.len() {
+        let root = find(&mut parents, index);
+        match group_of_root.get(&root) {
+            Some(&group) => groups[group].push(index),
+            None => {
+                group_of_root.insert(root, groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// # Preferred Copy function
+///
+/// Picks the copy of a group of duplicates to keep. Ties are broken by the bitrate, then by
+/// the shortest path.
+///
+/// # Returns
+/// The position in `group` of the copy to keep (0 for an empty group).
+pub fn preferred_copy(group: &[&DuplicateCandidate], preference: KeepPreference) -> usize {
+    let path_length = |candidate: &DuplicateCandidate| candidate.path.chars().count();
+
+    (0..group.len())
+        .min_by(|&a, &b| {
+            let (a, b) = (group[a], group[b]);
+            let by_bitrate = b.bitrate.cmp(&a.bitrate);
+            let by_path = path_length(a).cmp(&path_length(b));
+            match preference {
+                KeepPreference::HighestBitrate => by_bitrate.then(by_path),
+                KeepPreference::Lossless => is_lossless(&b.path).cmp(&is_lossless(&a.path))
+                    .then(by_bitrate)
+                    .then(by_path),
+                KeepPreference::ShortestPath => by_path.then(by_bitrate),
+            }
+        })
+        .unwrap_or(0)
+}
+
+/// Returns `file_name` with ` (<number>)` added before its extension, or unchanged for 1.
+fn numbered_name(file_name: &str, number: u32) -> String {
+    if number == 1 {
+        return file_name.to_string();
+    }
+
+    let name = Path::new(file_name);
+    let stem = name.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = name.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    format!("{} ({}){}", stem, number, extension)
+}
+
+/// Returns a path in `directory` for a file named `file_name` that does not exist yet,
+/// adding ` (2)`, ` (3)`… before the extension if needed.
+fn unused_path(directory: &Path, file_name: &str) -> PathBuf {
+    (1..)
+        .map(|number| directory.join(numbered_name(file_name, number)))
+        .find(|path| !path.exists())
+        .expect("an unused file name")
+}
+
+/// Moves a file, copying and removing it when it has to cross filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// # Move To Quarantine function
+///
+/// Moves a file into a quarantine directory, keeping its name (or adding a number if a file
+/// with that name is already quarantined).
+///
+/// # Returns
+/// The new path of the file.
+pub fn move_to_quarantine(path: &Path, quarantine_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(quarantine_dir)?;
+    let file_name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The path has no file name"))?;
+
+    let destination = unused_path(quarantine_dir, &file_name.to_string_lossy());
+    move_file(path, &destination)?;
+    Ok(destination)
+}
+
+/// Percent-encodes a path for the `Path` key of a `.trashinfo` file, leaving `/` and the
+/// unreserved characters of RFC 3986 as they are.
+fn encode_trash_path(path: &Path) -> String {
+    path.to_string_lossy().bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// # Move To Trash function
+///
+/// Moves a file into the home trash (`$XDG_DATA_HOME/Trash`), following the FreeDesktop.org
+/// trash specification so the file manager can restore it: the file goes into `files/` and a
+/// `.trashinfo` file recording its original path and the deletion date into `info/`.
+///
+/// # Returns
+/// The path of the file in the trash.
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let trash_dir = dirs::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find the data directory"))?
+        .join("Trash");
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let original = fs::canonicalize(path)?;
+    let file_name = original.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The path has no file name"))?
+        .to_string_lossy()
+        .into_owned();
+
+    // The name of the trashed file is reserved by creating its info file exclusively.
+    let mut number = 1;
+    let (destination, info_path, mut info_file) = loop {
+        let trashed_name = numbered_name(&file_name, number);
+        number += 1;
+        let destination = files_dir.join(&trashed_name);
+        if destination.exists() {
+            continue;
+        }
+
+        let info_path = info_dir.join(format!("{}.trashinfo", trashed_name));
+        match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(info_file) => break (destination, info_path, info_file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let deletion_date = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
+    writeln!(info_file, "[Trash Info]\nPath={}\nDeletionDate={}", encode_trash_path(&original), deletion_date)?;
+
+    if let Err(e) = move_file(&original, &destination) {
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+    Ok(destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    fn candidate(path: &str, artist: &str, title: &str, duration_ms: i64, content_hash: Option<&str>) -> DuplicateCandidate {
+        DuplicateCandidate {
+            path: path.to_string(),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration_ms,
+            bitrate: 320,
+            content_hash: content_hash.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn songs_are_grouped_by_name_and_duration_or_by_hash() {
+        let candidates = [
+            candidate("/a/help.mp3", "The Beatles", "Help!", 138_000, None),
+            candidate("/b/help.flac", "the beatles", "HELP", 139_500, None),
+            candidate("/c/help (live).mp3", "The Beatles", "Help!", 160_000, None),
+            candidate("/d/untitled.mp3", "", "", 200_000, Some("1234")),
+            candidate("/e/track01.mp3", "Queen", "", 201_000, Some("1234")),
+            candidate("/f/track02.mp3", "Queen", "", 201_000, Some("5678")),
+        ];
+
+        assert_eq!(group_duplicates(&candidates), [vec![0, 1], vec![3, 4]]);
+    }
+
+    #[test]
+    fn durations_chain_within_a_name() {
+        let candidates = [
+            candidate("/a.mp3", "A", "Song", 100_000, None),
+            candidate("/b.mp3", "A", "Song", 103_000, None),
+            candidate("/c.mp3", "A", "Song", 101_500, None),
+            candidate("/d.mp3", "B", "Song", 100_000, None),
+        ];
+
+        assert_eq!(group_duplicates(&candidates), [vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn audio_range_leaves_out_the_tags() {
+        // An ID3v2.4 header declaring a 128-byte tag.
+        let mut contents = b"ID3\x04\x00\x00\x00\x00\x01\x00".to_vec();
+        contents.extend([0; 128]);
+        contents.extend([0xAA; 1000]);
+        let mut ape_footer = b"APETAGEX".to_vec();
+        ape_footer.extend(2000u32.to_le_bytes());
+        ape_footer.extend(64u32.to_le_bytes());
+        ape_footer.extend([0; 16]);
+        // An APEv2 tag without header: 32 bytes of items, then its footer, whose size counts both.
+        contents.extend([0; 32]);
+        contents.extend(&ape_footer);
+        contents.extend(b"TAG");
+        contents.extend([0; 125]);
+
+        let file = TempFile::new("duplicates.mp3", &contents);
+        let range = audio_range(file.path());
+
+        assert_eq!(range.unwrap(), (138, 1138));
+    }
+
+    #[test]
+    fn numbered_names_keep_the_extension() {
+        assert_eq!(numbered_name("song.mp3", 1), "song.mp3");
+        assert_eq!(numbered_name("song.mp3", 2), "song (2).mp3");
+        assert_eq!(numbered_name("archive.tar.gz", 3), "archive.tar (3).gz");
+        assert_eq!(numbered_name("README", 2), "README (2)");
+    }
+
+    #[test]
+    fn trash_paths_are_percent_encoded() {
+        assert_eq!(encode_trash_path(Path::new("/music/a-b_c.~1/song.mp3")), "/music/a-b_c.~1/song.mp3");
+        assert_eq!(encode_trash_path(Path::new("/music/Sigur Rós/100%.flac")), "/music/Sigur%20R%C3%B3s/100%25.flac");
+    }
+}
@@ -0,0 +1,454 @@
+use crate::model::music_miner::decoder::AudioDecoder;
+use std::f64::consts::PI;
+use std::path::Path;
+use symphonia::core::dsp::complex::Complex;
+use symphonia::core::dsp::fft::Fft;
+use symphonia::core::errors::Result;
+
+/// Sample rate the audio is resampled to before being analysed, as in Chromaprint.
+const SAMPLE_RATE: u32 = 11025;
+
+/// Length of the audio fingerprinted, from the start of the song (the default of `fpcalc`).
+const MAX_LENGTH_SECONDS: u32 = 120;
+
+/// Size of the FFT frames, and the distance between the starts of two frames (a third of a frame).
+const FRAME_SIZE: usize = 4096;
+const FRAME_STEP: usize = FRAME_SIZE / 3;
+
+/// Frequency range folded into the twelve chroma bands.
+const MIN_FREQUENCY: f64 = 28.0;
+const MAX_FREQUENCY: f64 = 3520.0;
+const CHROMA_BANDS: usize = 12;
+
+/// Frequency of the A of octave 0, from which the chroma bands are counted.
+const REFERENCE_FREQUENCY: f64 = 440.0 / 16.0;
+
+/// Weights of the filter smoothing each chroma vector with its two neighbours on each side.
+const CHROMA_FILTER: [f64; 5] = [0.25, 0.75, 1.0, 0.75, 0.25];
+
+/// Chroma vectors with a smaller euclidean norm are treated as silence.
+const CHROMA_NORM_THRESHOLD: f64 = 0.01;
+
+/// Fraction of the output Nyquist frequency kept by the resampler, and the number of zero
+/// crossings of its windowed-sinc kernel on each side.
+const RESAMPLE_CUTOFF: f64 = 0.8;
+const RESAMPLE_ZERO_CROSSINGS: f64 = 8.0;
+/// Number of fractional positions for which the resampling kernel is precomputed.
+const RESAMPLE_PHASES: usize = 256;
+
+/// Maps the four quantised levels of a classifier to two bits, so neighbouring levels differ
+/// by a single bit.
+const GRAY_CODE: [u32; 4] = [0, 1, 3, 2];
+
+/// Largest shift, in sub-fingerprints (about 0.12 s each), tried when aligning two
+/// fingerprints, and the smallest overlap compared.
+const MAX_ALIGNMENT_OFFSET: usize = 80;
+const MIN_OVERLAP: usize = 40;
+
+/// Smallest `similarity()` of two fingerprints of the same recording. Different encodings of
+/// a recording usually score above 0.9, unrelated recordings around 0.5.
+const SAME_RECORDING_SIMILARITY: f64 = 0.8;
+
+/// Songs whose durations differ by more than this are never the same recording.
+const DURATION_TOLERANCE_MS: i64 = 10_000;
+
+/// A Haar-like filter over a `width` × `height` rectangle of the chroma image (time ×
+/// bands, starting at band `y`), and the thresholds quantising its response into 4 levels.
+struct Classifier {
+    filter: u8,
+    y: usize,
+    height: usize,
+    width: usize,
+    thresholds: [f64; 3],
+}
+
+const fn classifier(filter: u8, y: usize, height: usize, width: usize, thresholds: [f64; 3]) -> Classifier {
+    Classifier { filter, y, height, width, thresholds }
+}
+
+/// The classifiers of Chromaprint's default algorithm (`TEST2`); each one gives two bits of a
+/// sub-fingerprint, from the most significant ones down.
+const CLASSIFIERS: [Classifier; 16] = [
+    classifier(0, 4, 3, 15, [1.98215, 2.35817, 2.63523]),
+    classifier(4, 4, 6, 15, [-1.03809, -0.651211, -0.282167]),
+    classifier(1, 0, 4, 16, [-0.298702, 0.119262, 0.558497]),
+    classifier(3, 8, 2, 12, [-0.105439, 0.0153946, 0.135898]),
+    classifier(3, 4, 4, 8, [-0.142891, 0.0258736, 0.200632]),
+    classifier(4, 0, 3, 5, [-0.826319, -0.590612, -0.368214]),
+    classifier(1, 2, 2, 9, [-0.557409, -0.233035, 0.0534525]),
+    classifier(2, 7, 3, 4, [-0.0646826, 0.00620476, 0.0784847]),
+    classifier(2, 6, 2, 16, [-0.192387, -0.029699, 0.215855]),
+    classifier(2, 1, 3, 2, [-0.0397818, -0.00568076, 0.0292026]),
+    classifier(5, 10, 1, 15, [-0.53823, -0.369934, -0.190235]),
+    classifier(3, 6, 2, 10, [-0.124877, 0.0296483, 0.139239]),
+    classifier(2, 1, 1, 14, [-0.101475, 0.0225617, 0.231971]),
+    classifier(3, 5, 6, 4, [-0.0799915, -0.00729616, 0.063262]),
+    classifier(1, 9, 2, 12, [-0.272556, 0.019424, 0.302559]),
+    classifier(3, 4, 2, 14, [-0.164292, -0.0321188, 0.0846339]),
+];
+
+/// The widest classifier: a sub-fingerprint needs this many chroma vectors.
+const MAX_CLASSIFIER_WIDTH: usize = 16;
+
+/// Decodes up to `MAX_LENGTH_SECONDS` of a song and mixes it down to mono.
+fn decode_mono(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let mut decoder = AudioDecoder::open(path, true)?;
+    let channels = decoder.channels.max(1);
+    let max_frames = (decoder.sample_rate * MAX_LENGTH_SECONDS) as usize;
+
+    let mut mono = Vec::new();
+    while mono.len() < max_frames {
+        let Some(samples) = decoder.next_samples() else { break };
+        mono.extend(samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32));
+    }
+    mono.truncate(max_frames);
+    Ok((mono, decoder.sample_rate))
+}
+
+/// Resamples mono audio to `SAMPLE_RATE` with a Hann-windowed sinc low-pass filter.
+fn resample(input: &[f32], from_rate: u32) -> Vec<f32> {
+    if from_rate == SAMPLE_RATE {
+        return input.to_vec();
+    }
+
+    let step = f64::from(from_rate) / f64::from(SAMPLE_RATE);
+    let bandwidth = RESAMPLE_CUTOFF * (1.0 / step).min(1.0);
+    let half = (RESAMPLE_ZERO_CROSSINGS / bandwidth).ceil() as usize;
+    let taps = 2 * half;
+
+    // kernels[phase][tap] weights the input sample at `tap - (half - 1)` from the integer
+    // part of the position, for a fractional part of `phase / RESAMPLE_PHASES`.
+    let kernels: Vec<Vec<f32>> = (0..RESAMPLE_PHASES)
+        .map(|phase| {
+            let fraction = phase as f64 / RESAMPLE_PHASES as f64;
+            let weights: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    let distance = tap as f64 - (half - 1) as f64 - fraction;
+                    let x = bandwidth * distance;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.5 + 0.5 * (PI * distance / half as f64).cos();
+                    if distance.abs() >= half as f64 { 0.0 } else { sinc * window }
+                })
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            weights.iter().map(|weight| (weight / sum) as f32).collect()
+        })
+        .collect();
+
+    let output_length = (input.len() as f64 / step) as usize;
+    (0..output_length)
+        .map(|index| {
+            let position = index as f64 * step;
+            let mut start = position.floor() as usize;
+            let mut phase = ((position - start as f64) * RESAMPLE_PHASES as f64).round() as usize;
+            if phase == RESAMPLE_PHASES {
+                start += 1;
+                phase = 0;
+            }
+
+            kernels[phase].iter().enumerate()
+                .filter_map(|(tap, weight)| {
+                    let sample = (start + tap).checked_sub(half - 1)?;
+                    input.get(sample).map(|value| value * weight)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Computes the chroma vector of every FFT frame: the energy of the power spectrum between
+/// `MIN_FREQUENCY` and `MAX_FREQUENCY`, folded into the twelve notes of the scale.
+fn chroma_vectors(samples: &[f32]) -> Vec<[f64; CHROMA_BANDS]> {
+    let fft = Fft::new(FRAME_SIZE);
+    let window: Vec<f32> = (0..FRAME_SIZE)
+        .map(|index| (0.54 - 0.46 * (2.0 * PI * index as f64 / (FRAME_SIZE - 1) as f64).cos()) as f32)
+        .collect();
+
+    let to_index = |frequency: f64| (FRAME_SIZE as f64 * frequency / f64::from(SAMPLE_RATE)).round() as usize;
+    let min_index = to_index(MIN_FREQUENCY).max(1);
+    let max_index = to_index(MAX_FREQUENCY).min(FRAME_SIZE / 2);
+    let bands: Vec<usize> = (min_index..max_index)
+        .map(|index| {
+            let frequency = index as f64 * f64::from(SAMPLE_RATE) / FRAME_SIZE as f64;
+            let octave = (frequency / REFERENCE_FREQUENCY).log2();
+            (CHROMA_BANDS as f64 * (octave - octave.floor())) as usize
+        })
+        .collect();
+
+    let mut frame = vec![Complex::default(); FRAME_SIZE];
+    (0..samples.len()).step_by(FRAME_STEP)
+        .take_while(|start| start + FRAME_SIZE <= samples.len())
+        .map(|start| {
+            for (value, (sample, weight)) in frame.iter_mut().zip(samples[start..].iter().zip(&window)) {
+                *value = Complex::new(sample * weight, 0.0);
+            }
+            fft.fft_inplace(&mut frame);
+
+            let mut chroma = [0.0; CHROMA_BANDS];
+            for (index, band) in (min_index..max_index).zip(&bands) {
+                let bin = frame[index];
+                chroma[*band] += f64::from(bin.re * bin.re + bin.im * bin.im);
+            }
+            chroma
+        })
+        .collect()
+}
+
+/// Smooths the chroma vectors over time with `CHROMA_FILTER` and scales each one to a unit
+/// euclidean norm.
+fn filter_chroma(vectors: &[[f64; CHROMA_BANDS]]) -> Vec<[f64; CHROMA_BANDS]> {
+    vectors.windows(CHROMA_FILTER.len())
+        .map(|window| {
+            let mut filtered = [0.0; CHROMA_BANDS];
+            for (vector, weight) in window.iter().zip(CHROMA_FILTER) {
+                for (value, band) in filtered.iter_mut().zip(vector) {
+                    *value += weight * band;
+                }
+            }
+
+            let norm = filtered.iter().map(|value| value * value).sum::<f64>().sqrt();
+            if norm < CHROMA_NORM_THRESHOLD {
+                [0.0; CHROMA_BANDS]
+            } else {
+                filtered.map(|value| value / norm)
+            }
+        })
+        .collect()
+}
+
+/// Sums of the chroma image, so the sum of any rectangle is found with four lookups.
+struct IntegralImage {
+    sums: Vec<[f64; CHROMA_BANDS + 1]>,
+}
+
+impl IntegralImage {
+    fn new(vectors: &[[f64; CHROMA_BANDS]]) -> Self {
+        let mut sums = vec![[0.0; CHROMA_BANDS + 1]; vectors.len() + 1];
+        for (row, vector) in vectors.iter().enumerate() {
+            for band in 0..CHROMA_BANDS {
+                sums[row + 1][band + 1] = vector[band] + sums[row][band + 1] + sums[row + 1][band] - sums[row][band];
+            }
+        }
+        IntegralImage { sums }
+    }
+
+    /// Sum of the vectors `x1..x2` over the bands `y1..y2`.
+    fn area(&self, x1: usize, y1: usize, x2: usize, y2: usize) -> f64 {
+        self.sums[x2][y2] - self.sums[x1][y2] - self.sums[x2][y1] + self.sums[x1][y1]
+    }
+}
+
+impl Classifier {
+    /// Applies the filter at time `x` and quantises its response into a level from 0 to 3.
+    fn classify(&self, image: &IntegralImage, x: usize) -> usize {
+        let (y, w, h) = (self.y, self.width, self.height);
+        let (a, b) = match self.filter {
+            0 => (image.area(x, y, x + w, y + h), 0.0),
+            1 => (image.area(x, y + h / 2, x + w, y + h), image.area(x, y, x + w, y + h / 2)),
+            2 => (image.area(x + w / 2, y, x + w, y + h), image.area(x, y, x + w / 2, y + h)),
+            3 => (
+                image.area(x, y + h / 2, x + w / 2, y + h) + image.area(x + w / 2, y, x + w, y + h / 2),
+                image.area(x, y, x + w / 2, y + h / 2) + image.area(x + w / 2, y + h / 2, x + w, y + h),
+            ),
+            4 => (
+                image.area(x, y + h / 3, x + w, y + 2 * h / 3),
+                image.area(x, y, x + w, y + h / 3) + image.area(x, y + 2 * h / 3, x + w, y + h),
+            ),
+            _ => (
+                image.area(x + w / 3, y, x + 2 * w / 3, y + h),
+                image.area(x, y, x + w / 3, y + h) + image.area(x + 2 * w / 3, y, x + w, y + h),
+            ),
+        };
+
+        let value = (1.0 + a).ln() - (1.0 + b).ln();
+        let [t0, t1, t2] = self.thresholds;
+        if value < t1 {
+            if value < t0 { 0 } else { 1 }
+        } else if value < t2 {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+/// # Fingerprint Samples function
+///
+/// Computes the fingerprint of mono audio sampled at `SAMPLE_RATE`, following Chromaprint's
+/// default algorithm: chroma features of overlapping FFT frames, smoothed and normalised,
+/// then 16 classifiers giving two bits each of one 32-bit sub-fingerprint per frame.
+fn fingerprint_samples(samples: &[f32]) -> Vec<u32> {
+    let vectors = filter_chroma(&chroma_vectors(samples));
+    let image = IntegralImage::new(&vectors);
+
+    (0..(vectors.len() + 1).saturating_sub(MAX_CLASSIFIER_WIDTH))
+        .map(|x| CLASSIFIERS.iter().fold(0u32, |bits, classifier| (bits << 2) | GRAY_CODE[classifier.classify(&image, x)]))
+        .collect()
+}
+
+/// # Compute Fingerprint function
+///
+/// Decodes the first two minutes of a song and computes its acoustic fingerprint with
+/// Chromaprint's default algorithm, so the raw values are comparable with those printed by
+/// `fpcalc -raw`. The fingerprint only depends on the audio, not on the tags or the format.
+///
+/// # Returns
+/// * `Ok(Vec<u32>)` - One sub-fingerprint for about every 0.12 seconds of audio (empty for
+///   songs shorter than about three seconds).
+/// * `Err(symphonia::core::errors::Error)` - If the song cannot be decoded.
+pub fn compute_fingerprint(path: &Path) -> Result<Vec<u32>> {
+    let (mono, sample_rate) = decode_mono(path)?;
+    Ok(fingerprint_samples(&resample(&mono, sample_rate)))
+}
+
+/// # Similarity function
+///
+/// Compares two fingerprints, shifting them by up to about ten seconds against each other
+/// to allow for different lead-in silences.
+///
+/// # Returns
+/// The fraction of identical bits at the best alignment, from about 0.5 for unrelated
+/// recordings to 1.0 for identical audio; 0.0 if the fingerprints are too short to compare.
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let compare = |a: &[u32], b: &[u32]| -> Option<f64> {
+        let overlap = a.len().min(b.len());
+        if overlap < MIN_OVERLAP {
+            return None;
+        }
+        let errors: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+        Some(1.0 - f64::from(errors) / (32 * overlap) as f64)
+    };
+
+    (0..=MAX_ALIGNMENT_OFFSET)
+        .flat_map(|offset| [
+            a.get(offset..).and_then(|a| compare(a, b)),
+            b.get(offset..).and_then(|b| compare(a, b)),
+        ])
+        .flatten()
+        .fold(0.0, f64::max)
+}
+
+/// # Group Recordings function
+///
+/// Groups songs that are the same recording: each song joins the first group whose first
+/// song has a similar duration and a fingerprint similar enough to its own.
+///
+/// # Arguments
+/// * `songs` - The duration in milliseconds and the fingerprint of each song.
+///
+/// # Returns
+/// The groups of two or more songs, as indices into `songs`.
+pub fn group_recordings(songs: &[(i64, Vec<u32>)]) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..songs.len()).collect();
+    order.sort_by_key(|&index| songs[index].0);
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for index in order {
+        let (duration, fingerprint) = &songs[index];
+        let group = groups.iter_mut().rev()
+            .take_while(|group| duration - songs[group[0]].0 <= DURATION_TOLERANCE_MS)
+            .find(|group| similarity(&songs[group[0]].1, fingerprint) >= SAME_RECORDING_SIMILARITY);
+        match group {
+            Some(group) => group.push(index),
+            None => groups.push(vec![index]),
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Converts a fingerprint into the bytes stored in the database (little-endian values).
+pub fn to_bytes(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// Converts the bytes stored in the database back into a fingerprint.
+pub fn from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks_exact(4).map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generates `seconds` of a sum of sines of the given frequencies, at `sample_rate`.
+    fn tones(frequencies: &[f64], seconds: u32, sample_rate: u32) -> Vec<f32> {
+        (0..seconds * sample_rate)
+            .map(|index| {
+                let time = f64::from(index) / f64::from(sample_rate);
+                let sum: f64 = frequencies.iter().map(|frequency| (2.0 * PI * frequency * time).sin()).sum();
+                (0.5 * sum / frequencies.len() as f64) as f32
+            })
+            .collect()
+    }
+
+    /// A pseudo-random fingerprint (xorshift), so unrelated fingerprints can be compared.
+    fn random_fingerprint(seed: u32, length: usize) -> Vec<u32> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fingerprints_round_trip_through_bytes() {
+        let fingerprint = vec![0, 1, 0xDEAD_BEEF, u32::MAX];
+        let bytes = to_bytes(&fingerprint);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[8..12], [0xEF, 0xBE, 0xAD, 0xDE]);
+        assert_eq!(from_bytes(&bytes), fingerprint);
+        assert!(from_bytes(&[1, 2, 3]).is_empty());
+    }
+
+    #[test]
+    fn similarity_allows_for_shifts() {
+        let a = random_fingerprint(1, 200);
+        assert_eq!(similarity(&a, &a), 1.0);
+        assert_eq!(similarity(&a[10..], &a), 1.0);
+        assert_eq!(similarity(&a, &a[MAX_ALIGNMENT_OFFSET..]), 1.0);
+
+        let unrelated = similarity(&a, &random_fingerprint(2, 200));
+        assert!(unrelated > 0.4 && unrelated < 0.6, "{}", unrelated);
+        assert!(similarity(&a, &a[MAX_ALIGNMENT_OFFSET + 1..]) < SAME_RECORDING_SIMILARITY);
+        assert_eq!(similarity(&a[..MIN_OVERLAP - 1], &a), 0.0);
+    }
+
+    #[test]
+    fn recordings_are_grouped_by_fingerprint_and_duration() {
+        let song = random_fingerprint(1, 200);
+        let mut noisy = song.clone();
+        for value in noisy.iter_mut().step_by(4) {
+            *value ^= 0x0000_00FF;
+        }
+        let songs = [
+            (180_000, song.clone()),
+            (240_000, random_fingerprint(2, 200)),
+            (185_000, noisy),
+            (200_000, song),
+        ];
+
+        assert_eq!(group_recordings(&songs), [vec![0, 2]]);
+    }
+
+    #[test]
+    fn a_sine_has_a_stable_fingerprint() {
+        let samples = tones(&[440.0], 10, SAMPLE_RATE);
+        let fingerprint = fingerprint_samples(&samples);
+        let frames = (samples.len() - FRAME_SIZE) / FRAME_STEP + 1;
+        assert_eq!(fingerprint.len(), frames - (CHROMA_FILTER.len() - 1) - (MAX_CLASSIFIER_WIDTH - 1));
+        assert_eq!(fingerprint_samples(&samples), fingerprint);
+
+        // The same tone decoded at another sample rate gives the same fingerprint once resampled.
+        let resampled = fingerprint_samples(&resample(&tones(&[440.0], 10, 44_100), 44_100));
+        assert!(similarity(&fingerprint, &resampled) > 0.95);
+
+        let other = fingerprint_samples(&tones(&[261.63, 329.63, 392.0], 10, SAMPLE_RATE));
+        assert!(similarity(&fingerprint, &other) < SAME_RECORDING_SIMILARITY);
+    }
+}
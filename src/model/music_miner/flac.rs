@@ -0,0 +1,471 @@
+extern crate id3;
+use crate::model::music_miner::musicbrainz;
+use id3::frame::{Comment, Content, Lyrics, Picture, PictureType, Timestamp};
+use id3::{Frame, Tag};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Metadata block type of STREAMINFO.
+const BLOCK_STREAMINFO: u8 = 0;
+/// Metadata block type of VORBIS_COMMENT.
+const BLOCK_VORBIS_COMMENT: u8 = 4;
+/// Metadata block type of PICTURE.
+const BLOCK_PICTURE: u8 = 6;
+
+/// Largest metadata block, whose length is stored in 24 bits.
+const MAX_BLOCK_LENGTH: usize = 0xFF_FFFF;
+
+/// Vendor string of VORBIS_COMMENT blocks created by `write_vorbis_comments()`.
+const VENDOR: &str = "MusicManager";
+
+/// The fields of the STREAMINFO block needed to describe the audio stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+    pub total_samples: u64,
+}
+
+/// A PICTURE block, with the picture type numbered as in ID3 APIC frames.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlacPicture {
+    pub picture_type: u32,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// The metadata read from the start of a FLAC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlacMetadata {
+    pub stream_info: StreamInfo,
+    /// The vendor string of the VORBIS_COMMENT block, naming the encoder.
+    pub vendor: Option<String>,
+    /// The Vorbis comments as `(FIELD, value)` pairs, with field names in upper case.
+    pub comments: Vec<(String, String)>,
+    pub pictures: Vec<FlacPicture>,
+    /// Offset of the first audio frame, right after the last metadata block.
+    pub audio_offset: u64,
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Parses the 34-byte STREAMINFO block.
+fn parse_stream_info(data: &[u8]) -> io::Result<StreamInfo> {
+    if data.len() < 18 {
+        return Err(invalid_data("STREAMINFO block is too short"));
+    }
+
+    // Bytes 10..18 hold: sample rate (20 bits), channels - 1 (3 bits),
+    // bits per sample - 1 (5 bits) and total samples (36 bits).
+    let packed = u64::from_be_bytes([data[10], data[11], data[12], data[13], data[14], data[15], data[16], data[17]]);
+    Ok(StreamInfo {
+        sample_rate: (packed >> 44) as u32,
+        channels: ((packed >> 41) & 0x7) as u8 + 1,
+        bits_per_sample: ((packed >> 36) & 0x1F) as u8 + 1,
+        total_samples: packed & 0xF_FFFF_FFFF,
+    })
+}
+
+/// Parses a VORBIS_COMMENT block into its vendor string and `(FIELD, value)` pairs.
+fn parse_vorbis_comment(data: &[u8]) -> Option<(String, Vec<(String, String)>)> {
+    let vendor_length = read_u32_le(data, 0)? as usize;
+    let vendor = String::from_utf8_lossy(data.get(4..4 + vendor_length)?).to_string();
+
+    let mut offset = 4 + vendor_length;
+    let count = read_u32_le(data, offset)?;
+    offset += 4;
+
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let length = read_u32_le(data, offset)? as usize;
+        let comment = String::from_utf8_lossy(data.get(offset + 4..offset + 4 + length)?).to_string();
+        offset += 4 + length;
+        if let Some((field, value)) = comment.split_once('=') {
+            comments.push((field.to_uppercase(), value.to_string()));
+        }
+    }
+
+    Some((vendor, comments))
+}
+
+/// Parses a PICTURE block.
+fn parse_picture(data: &[u8]) -> Option<FlacPicture> {
+    let picture_type = read_u32_be(data, 0)?;
+    let mime_length = read_u32_be(data, 4)? as usize;
+    let mime_type = String::from_utf8_lossy(data.get(8..8 + mime_length)?).to_string();
+
+    let mut offset = 8 + mime_length;
+    let description_length = read_u32_be(data, offset)? as usize;
+    // Skip the description, width, height, colour depth and number of colours.
+    offset += 4 + description_length + 16;
+
+    let data_length = read_u32_be(data, offset)? as usize;
+    let picture_data = data.get(offset + 4..offset + 4 + data_length)?.to_vec();
+
+    Some(FlacPicture { picture_type, mime_type, data: picture_data })
+}
+
+/// # Read FLAC Metadata function
+///
+/// Reads the metadata blocks at the start of a FLAC file: STREAMINFO, VORBIS_COMMENT and
+/// PICTURE. Other blocks (padding, seek tables, ...) are skipped.
+///
+/// # Arguments
+/// * `path` - The path of the FLAC file.
+///
+/// # Returns
+/// * `Ok(FlacMetadata)` - The metadata of the file.
+/// * `Err(io::Error)` - If the file cannot be read or is not a valid FLAC file.
+pub fn read_flac_metadata(path: &Path) -> io::Result<FlacMetadata> {
+    let mut file = File::open(path)?;
+
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    if &magic[..3] == b"ID3" {
+        // Some tools prepend an ID3v2 tag; skip it using its synchsafe size.
+        let mut header = [0u8; 6];
+        file.read_exact(&mut header)?;
+        let size = header[2..6].iter().fold(0u64, |size, byte| (size << 7) | u64::from(byte & 0x7F));
+        file.seek(SeekFrom::Start(10 + size))?;
+        file.read_exact(&mut magic)?;
+    }
+    if &magic != b"fLaC" {
+        return Err(invalid_data("Not a FLAC file"));
+    }
+
+    let mut stream_info = None;
+    let mut vendor = None;
+    let mut comments = Vec::new();
+    let mut pictures = Vec::new();
+
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        match block_type {
+            BLOCK_STREAMINFO | BLOCK_VORBIS_COMMENT | BLOCK_PICTURE => {
+                let mut data = vec![0u8; length];
+                file.read_exact(&mut data)?;
+                match block_type {
+                    BLOCK_STREAMINFO => stream_info = Some(parse_stream_info(&data)?),
+                    BLOCK_VORBIS_COMMENT => {
+                        if let Some((block_vendor, block_comments)) = parse_vorbis_comment(&data) {
+                            vendor = Some(block_vendor);
+                            comments.extend(block_comments);
+                        }
+                    }
+                    _ => pictures.extend(parse_picture(&data)),
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+
+        if is_last {
+            break;
+        }
+    }
+
+    let stream_info = stream_info.ok_or_else(|| invalid_data("FLAC file has no STREAMINFO block"))?;
+    let audio_offset = file.stream_position()?;
+
+    Ok(FlacMetadata { stream_info, vendor, comments, pictures, audio_offset })
+}
+
+/// Encodes a VORBIS_COMMENT block.
+fn encode_vorbis_comment(vendor: &str, comments: &[(String, String)]) -> Vec<u8> {
+    let mut data = (vendor.len() as u32).to_le_bytes().to_vec();
+    data.extend(vendor.as_bytes());
+    data.extend((comments.len() as u32).to_le_bytes());
+    for (field, value) in comments {
+        let comment = format!("{}={}", field, value);
+        data.extend((comment.len() as u32).to_le_bytes());
+        data.extend(comment.as_bytes());
+    }
+    data
+}
+
+/// # Write Vorbis Comments function
+///
+/// Replaces fields of the VORBIS_COMMENT block of a FLAC file, adding the block after
+/// STREAMINFO if the file has none. Other blocks and the audio are copied unchanged. The file
+/// is written to a temporary file next to it and renamed over it, so a failed write leaves
+/// the song intact.
+///
+/// # Arguments
+/// * `path` - The path of the FLAC file.
+/// * `fields` - The fields to replace (compared ignoring case) with their new value, or
+///   `None` to remove them.
+///
+/// # Returns
+/// * `Ok(())` - If the file was rewritten.
+/// * `Err(io::Error)` - If the file cannot be read or written, or is not a valid FLAC file.
+pub fn write_vorbis_comments(path: &Path, fields: &[(&str, Option<String>)]) -> io::Result<()> {
+    let data = fs::read(path)?;
+
+    let mut start = 0;
+    if data.starts_with(b"ID3") && data.len() >= 10 {
+        start = 10 + data[6..10].iter().fold(0usize, |size, byte| (size << 7) | usize::from(byte & 0x7F));
+    }
+    if data.get(start..start + 4) != Some(b"fLaC".as_slice()) {
+        return Err(invalid_data("Not a FLAC file"));
+    }
+
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    let mut offset = start + 4;
+    loop {
+        let header = data.get(offset..offset + 4).ok_or_else(|| invalid_data("Truncated FLAC metadata"))?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let block = data.get(offset + 4..offset + 4 + length).ok_or_else(|| invalid_data("Truncated FLAC metadata"))?;
+        blocks.push((block_type, block.to_vec()));
+        offset += 4 + length;
+        if is_last {
+            break;
+        }
+    }
+    if blocks.first().map(|(block_type, _)| *block_type) != Some(BLOCK_STREAMINFO) {
+        return Err(invalid_data("FLAC file has no STREAMINFO block"));
+    }
+
+    let position = blocks.iter().position(|(block_type, _)| *block_type == BLOCK_VORBIS_COMMENT);
+    let (vendor, mut comments) = position
+        .and_then(|position| parse_vorbis_comment(&blocks[position].1))
+        .unwrap_or_else(|| (VENDOR.to_string(), Vec::new()));
+    comments.retain(|(field, _)| !fields.iter().any(|(name, _)| field.eq_ignore_ascii_case(name)));
+    for (name, value) in fields {
+        if let Some(value) = value {
+            comments.push((name.to_uppercase(), value.clone()));
+        }
+    }
+
+    let block = encode_vorbis_comment(&vendor, &comments);
+    if block.len() > MAX_BLOCK_LENGTH {
+        return Err(invalid_data("Vorbis comments are too long for a FLAC metadata block"));
+    }
+    blocks.retain(|(block_type, _)| *block_type != BLOCK_VORBIS_COMMENT);
+    blocks.insert(position.unwrap_or(1).min(blocks.len()), (BLOCK_VORBIS_COMMENT, block));
+
+    let mut contents = data[..start].to_vec();
+    contents.extend(b"fLaC");
+    let count = blocks.len();
+    for (index, (block_type, block)) in blocks.into_iter().enumerate() {
+        let last_flag = if index + 1 == count { 0x80 } else { 0 };
+        contents.push(block_type | last_flag);
+        contents.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        contents.extend(block);
+    }
+    contents.extend(&data[offset..]);
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".part");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+/// Returns every value of a Vorbis comment field, in file order.
+fn values<'a>(metadata: &'a FlacMetadata, field: &str) -> Vec<&'a str> {
+    metadata.comments.iter()
+        .filter(|(name, _)| name == field)
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+/// Parses the number before an optional `/total`, as in `3/12`.
+fn leading_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// # To ID3 Tag function
+///
+/// Converts the Vorbis comments and pictures of a FLAC file into an ID3 tag, so FLAC files
+/// go through the same `assign_tag()` pipeline as MP3 files. REPLAYGAIN_* comments become
+/// TXXX frames of the same name, FMPS_RATING a `FMPS_Rating` TXXX frame, MUSICBRAINZ_ALBUMID a
+/// `MusicBrainz Album Id` TXXX frame and MUSICBRAINZ_TRACKID (the recording ID) a UFID frame. Fields with several values
+/// (e.g. two `ARTIST` comments) become multi-valued frames separated by null bytes.
+///
+/// # Arguments
+/// * `metadata` - The metadata read with `read_flac_metadata()`.
+///
+/// # Returns
+/// An ID3 tag with the equivalent frames.
+pub fn to_id3_tag(metadata: &FlacMetadata) -> Tag {
+    let mut tag = Tag::new();
+
+    let text_frames = [
+        ("ARTIST", "TPE1"), ("TITLE", "TIT2"), ("ALBUM", "TALB"), ("GENRE", "TCON"),
+        ("ALBUMARTIST", "TPE2"), ("COMPOSER", "TCOM"), ("CONDUCTOR", "TPE3"),
+        ("REMIXER", "TPE4"), ("BPM", "TBPM"), ("KEY", "TKEY"), ("INITIALKEY", "TKEY"),
+    ];
+    for (field, frame_id) in text_frames {
+        let field_values = values(metadata, field);
+        if !field_values.is_empty() && tag.get(frame_id).is_none() {
+            tag.set_text(frame_id, field_values.join("\0"));
+        }
+    }
+
+    if let Some(date) = values(metadata, "DATE").first() {
+        if let Some(year) = date.get(..4).and_then(|year| year.parse().ok()) {
+            tag.set_year(year);
+        }
+        if let Ok(timestamp) = date.trim().parse::<Timestamp>() {
+            tag.set_date_recorded(timestamp);
+        }
+    }
+    if let Some(track) = values(metadata, "TRACKNUMBER").first().and_then(|value| leading_number(value)) {
+        tag.set_track(track);
+    }
+    let track_total = values(metadata, "TRACKTOTAL").iter().chain(&values(metadata, "TOTALTRACKS"))
+        .find_map(|value| leading_number(value))
+        .or_else(|| values(metadata, "TRACKNUMBER").first()?.split_once('/')?.1.trim().parse().ok());
+    if let Some(total) = track_total {
+        tag.set_total_tracks(total);
+    }
+    if let Some(disc) = values(metadata, "DISCNUMBER").first().and_then(|value| leading_number(value)) {
+        tag.set_disc(disc);
+    }
+    if let Some(total) = values(metadata, "DISCTOTAL").iter().chain(&values(metadata, "TOTALDISCS")).find_map(|value| leading_number(value)) {
+        tag.set_total_discs(total);
+    }
+    if values(metadata, "COMPILATION").first().map(|value| value.trim()) == Some("1") {
+        tag.set_text("TCMP", "1");
+    }
+
+    if let Some(comment) = values(metadata, "COMMENT").iter().chain(&values(metadata, "DESCRIPTION")).next() {
+        tag.add_comment(Comment { lang: "eng".to_string(), description: String::new(), text: comment.to_string() });
+    }
+    if let Some(lyrics) = values(metadata, "LYRICS").iter().chain(&values(metadata, "UNSYNCEDLYRICS")).next() {
+        tag.add_lyrics(Lyrics { lang: "eng".to_string(), description: String::new(), text: lyrics.to_string() });
+    }
+
+    for (field, value) in &metadata.comments {
+        if field.starts_with("REPLAYGAIN_") {
+            tag.add_extended_text(field.as_str(), value.as_str());
+        } else if field == "FMPS_RATING" {
+            tag.add_extended_text("FMPS_Rating", value.as_str());
+        } else if field == "MUSICBRAINZ_ALBUMID" {
+            tag.add_extended_text(musicbrainz::ALBUM_ID_DESCRIPTION, value.as_str());
+        } else if field == "MUSICBRAINZ_TRACKID" {
+            let mut data = musicbrainz::UFID_OWNER.as_bytes().to_vec();
+            data.push(0);
+            data.extend(value.as_bytes());
+            tag.add_frame(Frame::with_content("UFID", Content::Unknown(data)));
+        }
+    }
+
+    for picture in &metadata.pictures {
+        let picture_type = if picture.picture_type == 3 { PictureType::CoverFront } else { PictureType::Other };
+        tag.add_picture(Picture {
+            mime_type: picture.mime_type.clone(),
+            picture_type,
+            description: String::new(),
+            data: picture.data.clone(),
+        });
+    }
+
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    /// Writes a FLAC file made of a STREAMINFO block, the given blocks and a few audio bytes.
+    fn write_fixture(name: &str, blocks: &[(u8, Vec<u8>)]) -> TempFile {
+        let mut stream_info = vec![0u8; 34];
+        let packed = (44_100u64 << 44) | (1 << 41) | (15 << 36) | 88_200;
+        stream_info[10..18].copy_from_slice(&packed.to_be_bytes());
+
+        let mut contents = b"fLaC".to_vec();
+        let all: Vec<(u8, Vec<u8>)> = std::iter::once((BLOCK_STREAMINFO, stream_info)).chain(blocks.iter().cloned()).collect();
+        for (index, (block_type, block)) in all.iter().enumerate() {
+            contents.push(block_type | if index + 1 == all.len() { 0x80 } else { 0 });
+            contents.extend(&(block.len() as u32).to_be_bytes()[1..]);
+            contents.extend(block);
+        }
+        contents.extend([0xFF, 0xF8, 0x69, 0x08]);
+
+        TempFile::new(name, contents)
+    }
+
+    #[test]
+    fn vorbis_comments_are_replaced_and_kept() {
+        let comments = encode_vorbis_comment("reference libFLAC 1.4.3", &[
+            ("TITLE".to_string(), "Intro".to_string()),
+            ("replaygain_track_gain".to_string(), "+1.00 dB".to_string()),
+        ]);
+        let file = write_fixture("replace.flac", &[(BLOCK_VORBIS_COMMENT, comments), (1, vec![0; 16])]);
+
+        write_vorbis_comments(file.path(), &[
+            ("REPLAYGAIN_TRACK_GAIN", Some("-6.54 dB".to_string())),
+            ("FMPS_RATING", Some("0.8".to_string())),
+        ]).unwrap();
+        let metadata = read_flac_metadata(file.path()).unwrap();
+        let contents = fs::read(file.path()).unwrap();
+
+        assert_eq!(metadata.vendor.as_deref(), Some("reference libFLAC 1.4.3"));
+        assert_eq!(metadata.comments, vec![
+            ("TITLE".to_string(), "Intro".to_string()),
+            ("REPLAYGAIN_TRACK_GAIN".to_string(), "-6.54 dB".to_string()),
+            ("FMPS_RATING".to_string(), "0.8".to_string()),
+        ]);
+        assert_eq!(metadata.stream_info.total_samples, 88_200);
+        assert!(contents.ends_with(&[0xFF, 0xF8, 0x69, 0x08]));
+    }
+
+    #[test]
+    fn a_comment_block_is_added_after_streaminfo() {
+        let file = write_fixture("add.flac", &[(1, vec![0; 8])]);
+
+        write_vorbis_comments(file.path(), &[("FMPS_RATING", Some("0.4".to_string()))]).unwrap();
+        let metadata = read_flac_metadata(file.path()).unwrap();
+        let contents = fs::read(file.path()).unwrap();
+
+        assert_eq!(metadata.vendor.as_deref(), Some(VENDOR));
+        assert_eq!(metadata.comments, vec![("FMPS_RATING".to_string(), "0.4".to_string())]);
+        assert_eq!(contents[4 + 4 + 34] & 0x7F, BLOCK_VORBIS_COMMENT);
+        assert_eq!(contents[4 + 4 + 34] & 0x80, 0);
+    }
+
+    #[test]
+    fn removed_comments_are_dropped() {
+        let comments = encode_vorbis_comment(VENDOR, &[("FMPS_RATING".to_string(), "1.0".to_string())]);
+        let file = write_fixture("remove.flac", &[(BLOCK_VORBIS_COMMENT, comments)]);
+
+        write_vorbis_comments(file.path(), &[("fmps_rating", None)]).unwrap();
+        let metadata = read_flac_metadata(file.path()).unwrap();
+
+        assert!(metadata.comments.is_empty());
+    }
+
+    #[test]
+    fn other_files_are_not_rewritten() {
+        let file = TempFile::new("not-flac.flac", b"RIFF0000WAVE");
+
+        let result = write_vorbis_comments(file.path(), &[("FMPS_RATING", Some("1.0".to_string()))]);
+        let contents = fs::read(file.path()).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(contents, b"RIFF0000WAVE");
+    }
+}
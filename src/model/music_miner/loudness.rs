@@ -0,0 +1,418 @@
+extern crate id3;
+use crate::model::music_miner::decoder::AudioDecoder;
+use crate::model::music_miner::flac;
+use id3::{ErrorKind, Tag, Version};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+
+/// Target loudness of ReplayGain 2.0, in LUFS.
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Blocks quieter than this (in LUFS) are ignored by the absolute gate of EBU R128.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks more than this many LU below the ungated loudness are ignored by the relative gate.
+const RELATIVE_GATE: f64 = 10.0;
+
+/// Number of 100 ms steps in a 400 ms gating block (blocks overlap by 75%).
+const STEPS_PER_BLOCK: usize = 4;
+
+/// The TXXX descriptions of the ReplayGain frames, paired with their tag map keys.
+pub const REPLAYGAIN_FRAMES: [(&str, &str); 4] = [
+    ("REPLAYGAIN_TRACK_GAIN", "Track Gain"),
+    ("REPLAYGAIN_TRACK_PEAK", "Track Peak"),
+    ("REPLAYGAIN_ALBUM_GAIN", "Album Gain"),
+    ("REPLAYGAIN_ALBUM_PEAK", "Album Peak"),
+];
+
+/// A second-order IIR filter in direct form I.
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0] - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// Builds the two stages of the K-weighting filter of ITU-R BS.1770 (a high shelf modelling
+/// the head, then a high-pass) for any sample rate.
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let rate = f64::from(sample_rate);
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    (shelf, high_pass)
+}
+
+/// The weight of each channel in the loudness sum: 1.0 for front channels and 1.41 for the
+/// surround channels of 5.0 and 5.1 layouts, whose LFE channel is ignored.
+fn channel_weights(channels: usize) -> Vec<f64> {
+    match channels {
+        5 => vec![1.0, 1.0, 1.0, 1.41, 1.41],
+        6 => vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41],
+        _ => vec![1.0; channels],
+    }
+}
+
+/// Converts the weighted mean square of a block into LUFS.
+fn to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// The loudness measurements of one track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackLoudness {
+    /// Weighted mean square of every 400 ms gating block, kept so albums can be gated as a whole.
+    pub blocks: Vec<f64>,
+    /// Highest absolute sample value, 1.0 being full scale.
+    pub peak: f64,
+}
+
+/// Measures the loudness of a stream of interleaved samples as described by EBU R128.
+pub struct LoudnessAnalyser {
+    filters: Vec<(Biquad, Biquad)>,
+    weights: Vec<f64>,
+    step_length: usize,
+    step_energy: f64,
+    step_samples: usize,
+    steps: Vec<f64>,
+    peak: f64,
+}
+
+impl LoudnessAnalyser {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        LoudnessAnalyser {
+            filters: vec![k_weighting(sample_rate); channels],
+            weights: channel_weights(channels),
+            step_length: (sample_rate / 10).max(1) as usize,
+            step_energy: 0.0,
+            step_samples: 0,
+            steps: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    /// Feeds interleaved samples to the analyser.
+    pub fn process(&mut self, samples: &[f32]) {
+        let channels = self.filters.len();
+        if channels == 0 {
+            return;
+        }
+
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let sample = f64::from(*sample);
+                self.peak = self.peak.max(sample.abs());
+
+                let (shelf, high_pass) = &mut self.filters[channel];
+                let filtered = high_pass.process(shelf.process(sample));
+                self.step_energy += self.weights[channel] * filtered * filtered;
+            }
+
+            self.step_samples += 1;
+            if self.step_samples == self.step_length {
+                self.steps.push(self.step_energy / self.step_length as f64);
+                self.step_energy = 0.0;
+                self.step_samples = 0;
+            }
+        }
+    }
+
+    /// Finishes the analysis, turning the 100 ms steps into overlapping 400 ms blocks.
+    /// A trailing partial step is dropped, as are blocks that would extend past the end.
+    pub fn finish(self) -> TrackLoudness {
+        let blocks = self.steps.windows(STEPS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+            .collect();
+        TrackLoudness { blocks, peak: self.peak }
+    }
+}
+
+/// # Integrated Loudness function
+///
+/// Computes the gated loudness of a set of blocks: blocks under -70 LUFS are dropped, then
+/// blocks more than 10 LU below the loudness of the remaining ones.
+///
+/// # Returns
+/// * `Some(f64)` - The integrated loudness in LUFS.
+/// * `None` - If every block is gated out (silence or a track shorter than 400 ms).
+pub fn integrated_loudness(blocks: &[f64]) -> Option<f64> {
+    let absolute: Vec<f64> = blocks.iter().copied().filter(|energy| to_lufs(*energy) > ABSOLUTE_GATE).collect();
+    if absolute.is_empty() {
+        return None;
+    }
+
+    let threshold = to_lufs(absolute.iter().sum::<f64>() / absolute.len() as f64) - RELATIVE_GATE;
+    let relative: Vec<f64> = absolute.into_iter().filter(|energy| to_lufs(*energy) > threshold).collect();
+    if relative.is_empty() {
+        return None;
+    }
+
+    Some(to_lufs(relative.iter().sum::<f64>() / relative.len() as f64))
+}
+
+/// Returns the ReplayGain 2.0 gain, in dB, that brings a loudness to the -18 LUFS reference.
+pub fn replaygain(loudness: f64) -> f64 {
+    REFERENCE_LOUDNESS - loudness
+}
+
+/// # Analyse Track function
+///
+/// Decodes a song and measures its loudness. Encoder delay and padding are trimmed, so only
+/// the actual audio is measured.
+///
+/// # Arguments
+/// * `path` - The path of the MP3 or FLAC file.
+///
+/// # Returns
+/// * `Ok(TrackLoudness)` - The gating blocks and sample peak of the song.
+/// * `Err(symphonia::core::errors::Error)` - If the song cannot be decoded.
+pub fn analyse_track(path: &Path) -> symphonia::core::errors::Result<TrackLoudness> {
+    let mut decoder = AudioDecoder::open(path, true)?;
+    let mut analyser = LoudnessAnalyser::new(decoder.sample_rate, decoder.channels);
+
+    while let Some(samples) = decoder.next_samples() {
+        analyser.process(&samples);
+    }
+
+    Ok(analyser.finish())
+}
+
+/// The ReplayGain values of a track and of the album it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: f64,
+    pub track_peak: f64,
+    pub album_gain: f64,
+    pub album_peak: f64,
+}
+
+impl ReplayGain {
+    /// Formats the values as they are written in REPLAYGAIN_* frames and comments, in
+    /// `REPLAYGAIN_FRAMES` order.
+    fn frame_values(&self) -> [String; 4] {
+        [
+            format!("{:+.2} dB", self.track_gain),
+            format!("{:.6}", self.track_peak),
+            format!("{:+.2} dB", self.album_gain),
+            format!("{:.6}", self.album_peak),
+        ]
+    }
+}
+
+/// # Analyse Album function
+///
+/// Measures every track of an album and computes its ReplayGain values. The album gain is
+/// computed from the gating blocks of all tracks together, as if they were played back to back,
+/// and the album peak is the highest track peak.
+///
+/// # Arguments
+/// * `paths` - The paths of the tracks of the album.
+///
+/// # Returns
+/// A vector with the values of each track, or the error that prevented decoding it. Tracks
+/// that are silent get a gain of 0 dB.
+pub fn analyse_album(paths: &[String]) -> Vec<(String, Result<ReplayGain, String>)> {
+    let measurements: Vec<(String, Result<TrackLoudness, String>)> = paths.iter()
+        .map(|path| (path.clone(), analyse_track(Path::new(path)).map_err(|e| e.to_string())))
+        .collect();
+
+    let album_blocks: Vec<f64> = measurements.iter()
+        .filter_map(|(_, measurement)| measurement.as_ref().ok())
+        .flat_map(|measurement| measurement.blocks.iter().copied())
+        .collect();
+    let album_gain = integrated_loudness(&album_blocks).map_or(0.0, replaygain);
+    let album_peak = measurements.iter()
+        .filter_map(|(_, measurement)| measurement.as_ref().ok())
+        .fold(0.0, |peak: f64, measurement| peak.max(measurement.peak));
+
+    measurements.into_iter()
+        .map(|(path, measurement)| {
+            let gain = measurement.map(|measurement| ReplayGain {
+                track_gain: integrated_loudness(&measurement.blocks).map_or(0.0, replaygain),
+                track_peak: measurement.peak,
+                album_gain,
+                album_peak,
+            });
+            (path, gain)
+        })
+        .collect()
+}
+
+/// Parses the number at the start of a ReplayGain value such as `-6.54 dB`.
+fn parse_value(value: &str) -> Option<f64> {
+    value.trim().trim_end_matches("dB").trim().parse().ok()
+}
+
+/// # Read ReplayGain function
+///
+/// Stores the values of the REPLAYGAIN_* TXXX frames of a tag in the tag map, under
+/// `Track Gain`, `Track Peak`, `Album Gain` and `Album Peak`. Descriptions are compared
+/// ignoring case, since some taggers write them in lower case.
+pub fn read_replaygain(tag: &Tag, map: &mut HashMap<String, String>) {
+    for extended_text in tag.extended_texts() {
+        let frame = REPLAYGAIN_FRAMES.iter()
+            .find(|(description, _)| extended_text.description.eq_ignore_ascii_case(description));
+        if let (Some((_, key)), Some(value)) = (frame, parse_value(&extended_text.value)) {
+            map.insert(key.to_string(), value.to_string());
+        }
+    }
+}
+
+/// # Write ReplayGain function
+///
+/// Replaces the ReplayGain values stored in a song: the REPLAYGAIN_* TXXX frames of an MP3
+/// file, written back as ID3v2.4, or the REPLAYGAIN_* Vorbis comments of a FLAC file. Other
+/// formats return an `UnsupportedFeature` error.
+pub fn write_replaygain(song_path: &Path, gain: &ReplayGain) -> id3::Result<()> {
+    let extension = song_path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    if extension == "flac" {
+        let fields: Vec<(&str, Option<String>)> = REPLAYGAIN_FRAMES.iter()
+            .zip(gain.frame_values())
+            .map(|((description, _), value)| (*description, Some(value)))
+            .collect();
+        return Ok(flac::write_vorbis_comments(song_path, &fields)?);
+    }
+    if extension != "mp3" {
+        return Err(id3::Error::new(ErrorKind::UnsupportedFeature, "ReplayGain values can only be written to MP3 and FLAC files"));
+    }
+
+    let mut tag = match Tag::read_from_path(song_path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(e),
+    };
+
+    for ((description, _), value) in REPLAYGAIN_FRAMES.iter().zip(gain.frame_values()) {
+        let existing: Vec<String> = tag.extended_texts()
+            .filter(|extended_text| extended_text.description.eq_ignore_ascii_case(description))
+            .map(|extended_text| extended_text.description.clone())
+            .collect();
+        for existing in existing {
+            tag.remove_extended_text(Some(&existing), None);
+        }
+        tag.add_extended_text(*description, value);
+    }
+
+    tag.write_to_path(song_path, Version::Id3v24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+
+    /// Measures a mono signal made of sine passages at 997 Hz, each given as
+    /// `(amplitude, seconds)`.
+    fn measure(sample_rate: u32, passages: &[(f32, usize)]) -> TrackLoudness {
+        let mut analyser = LoudnessAnalyser::new(sample_rate, 1);
+        for (amplitude, seconds) in passages {
+            let samples: Vec<f32> = (0..sample_rate as usize * seconds)
+                .map(|n| amplitude * (2.0 * PI * 997.0 * n as f64 / f64::from(sample_rate)).sin() as f32)
+                .collect();
+            analyser.process(&samples);
+        }
+        analyser.finish()
+    }
+
+    #[test]
+    fn a_sine_wave_has_the_loudness_given_by_bs1770() {
+        // A full-scale 997 Hz sine in one channel measures -3.01 LUFS, so a sine
+        // 20 dB lower measures -23.01 LUFS, at any sample rate.
+        for sample_rate in [44_100, 48_000, 96_000] {
+            let measurement = measure(sample_rate, &[(0.1, 10)]);
+            let loudness = integrated_loudness(&measurement.blocks).unwrap();
+
+            assert!((loudness + 23.01).abs() < 0.05, "{} Hz: {} LUFS", sample_rate, loudness);
+            assert!((measurement.peak - 0.1).abs() < 1e-3);
+            assert!((replaygain(loudness) - 5.01).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn quiet_passages_and_silence_are_gated_out() {
+        // The -63 LUFS passage is above the absolute gate but far below the relative one.
+        let measurement = measure(48_000, &[(0.1, 10), (0.001, 10), (0.0, 10)]);
+        let loudness = integrated_loudness(&measurement.blocks).unwrap();
+
+        assert!((loudness + 23.01).abs() < 0.1, "{} LUFS", loudness);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        assert_eq!(integrated_loudness(&measure(48_000, &[(0.0, 2)]).blocks), None);
+        assert_eq!(integrated_loudness(&measure(48_000, &[(0.1, 0)]).blocks), None);
+    }
+
+    #[test]
+    fn a_partial_block_at_the_end_is_dropped() {
+        let mut analyser = LoudnessAnalyser::new(48_000, 2);
+        analyser.process(&vec![0.5; 48_000 * 2 / 2 + 100]);
+
+        // 5 complete steps of 100 ms make 2 overlapping blocks of 4 steps.
+        assert_eq!(analyser.finish().blocks.len(), 2);
+    }
+
+    #[test]
+    fn replaygain_values_are_written_to_flac_comments() {
+        let mut contents = b"fLaC".to_vec();
+        contents.extend([0x80, 0, 0, 34]);
+        let mut stream_info = [0u8; 34];
+        stream_info[10..18].copy_from_slice(&((44_100u64 << 44) | (1 << 41) | (15 << 36)).to_be_bytes());
+        contents.extend(stream_info);
+        let file = TempFile::new("replaygain.flac", contents);
+
+        let gain = ReplayGain { track_gain: -6.54, track_peak: 0.988, album_gain: -7.1, album_peak: 1.0 };
+        write_replaygain(file.path(), &gain).unwrap();
+        let metadata = flac::read_flac_metadata(file.path()).unwrap();
+
+        let mut map = HashMap::new();
+        read_replaygain(&flac::to_id3_tag(&metadata), &mut map);
+        assert_eq!(map.get("Track Gain").map(String::as_str), Some("-6.54"));
+        assert_eq!(map.get("Track Peak").map(String::as_str), Some("0.988"));
+        assert_eq!(map.get("Album Gain").map(String::as_str), Some("-7.1"));
+        assert_eq!(map.get("Album Peak").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn other_formats_are_not_written() {
+        let gain = ReplayGain { track_gain: 0.0, track_peak: 0.0, album_gain: 0.0, album_peak: 0.0 };
+        let error = write_replaygain(Path::new("song.ogg"), &gain).unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::UnsupportedFeature));
+    }
+}
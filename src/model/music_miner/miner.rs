@@ -1,140 +1,306 @@
-use crate::model::music_miner::song_settings; 
+use crate::model::music_miner::{song_settings, path_tags, cover_art, artist_credits, synced_lyrics, flac, audio_properties, loudness, rating, musicbrainz};
+use crate::model::music_miner::artist_credits::ArtistSeparators;
+use crate::model::database_config::config::{self, LibraryRoot};
+use crate::model::error::{LibraryError, LibraryResult};
 extern crate id3;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use std::collections::HashMap;
-use id3::Tag;
+use id3::{ErrorKind, Tag};
 use std::fs;
 use std::path::Path;
 
 /// # Extract function
 ///
-/// Recursively traverses the specified directory and processes each file found in it and its subdirectories.
-/// For each MP3 file, it calls the `process_song()` function to extract and handle the file's metadata.
-/// The function collects the metadata for all valid MP3 files in the directory and returns it as a vector of hash maps.
-/// Each hash map contains information like the file path and album path. If an error occurs while reading a file or directory, a message is printed, and the process continues.
+/// Recursively traverses a library root and processes each file found in it and its subdirectories.
+/// For each MP3 or FLAC file, it calls the `process_song()` function to extract and handle the file's metadata.
+/// The function collects the metadata for all valid MP3 and FLAC files in the directory and returns it as a vector of hash maps.
+/// Each hash map contains information like the file path, album path and root. If a file or directory cannot be read, or the tags
+/// of a song cannot be parsed, the error is collected with its path and the process continues.
+/// Files and directories whose path relative to the root matches one of the root's exclude
+/// patterns are skipped; an invalid pattern is reported as a failure and ignored.
+/// Fields missing from a file's tag are inferred from its path using the patterns returned by
+/// `config::load_path_patterns()`, and the artist frames are split into individual performers
+/// using the separators returned by `config::load_artist_separators()`. The `[scan]` table of
+/// `Config.TOML` can turn off the inference and skip hidden files and directories.
 ///
 /// # Arguments
-/// * `mp3_dir_path` - A string slice that holds the path to the directory containing MP3 files.
+/// * `root` - The library root to scan, with its exclude patterns. Whether it is enabled is
+///   up to the caller.
 ///
 /// # Returns
-/// * `Vec<HashMap<String, String>>` - A vector of hash maps where each map contains metadata about an MP3 file.
-///
-/// # Panics
-/// Panics if the directory cannot be read.
-pub fn extract(mp3_dir_path: &str) -> Vec<HashMap<String, String>> {
+/// * `(Vec<HashMap<String, String>>, Vec<LibraryError>)` - The metadata of every song read, and the
+///   files and directories that could not be.
+pub fn extract(root: &LibraryRoot) -> (Vec<HashMap<String, String>>, Vec<LibraryError>) {
     let mut extracted_data = Vec::new();
-    
-    visit_dirs(Path::new(mp3_dir_path), &mut extracted_data);
+    let mut failures = Vec::new();
+    let mut options = load_scan_options();
+
+    let root_path = Path::new(&root.path);
+    if !root_path.is_dir() {
+        failures.push(LibraryError::validation(Some(root_path), "Not a directory"));
+        return (extracted_data, failures);
+    }
+    options.excludes = build_excludes(root, &mut failures);
+    visit_dirs(root_path, root_path, &options, &mut extracted_data, &mut failures);
 
-    extracted_data
+    (extracted_data, failures)
+}
+
+/// Builds the set of the exclude patterns of a root, adding the invalid ones to `failures`.
+fn build_excludes(root: &LibraryRoot, failures: &mut Vec<LibraryError>) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &root.excludes {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => failures.push(LibraryError::validation(
+                Some(Path::new(&root.path)),
+                format!("Invalid exclude pattern: {}", e),
+            )),
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        failures.push(LibraryError::validation(Some(Path::new(&root.path)), e.to_string()));
+        GlobSet::empty()
+    })
+}
+
+/// # Extract Song function
+///
+/// Reads the metadata of a single song the same way `extract()` does while scanning `root`,
+/// e.g. to update the database after its tags were rewritten.
+///
+/// # Arguments
+/// * `path` - The path of the MP3 or FLAC file.
+/// * `root` - The scanned directory containing the song, used to infer missing tags from the path.
+///
+/// # Returns
+/// * `Ok(HashMap<String, String>)` - The metadata of the song.
+/// * `Err(LibraryError)` - If the file is not an MP3 or FLAC file, or if it or its tags cannot be read.
+pub fn extract_song(path: &Path, root: &Path) -> LibraryResult<HashMap<String, String>> {
+    process_entry(path, root, &load_scan_options())?
+        .ok_or_else(|| LibraryError::validation(Some(path), "Not an MP3 or FLAC file"))
+}
+
+/// What a scan needs besides the directory: the path patterns used to fill fields missing
+/// from the tags (none if `infer_tags_from_paths` is off), the separators used to split
+/// artist frames into performers, whether hidden files are skipped, and the exclude
+/// patterns of the root.
+struct ScanOptions {
+    patterns: Vec<String>,
+    separators: ArtistSeparators,
+    skip_hidden: bool,
+    excludes: GlobSet,
+}
+
+/// Loads the scan options of `Config.TOML`, the path patterns and the artist separators,
+/// falling back to the defaults when the configuration cannot be read.
+fn load_scan_options() -> ScanOptions {
+    let settings = config::load_settings().unwrap_or_else(|e| {
+        log::warn!("Could not load the scan settings, using the defaults: {}", e);
+        config::Settings::default()
+    });
+
+    let patterns = if settings.scan.infer_tags_from_paths {
+        config::load_path_patterns().unwrap_or_else(|e| {
+            log::warn!("Could not load the path patterns, using the defaults: {}", e);
+            path_tags::DEFAULT_PATTERNS.iter().map(|pattern| pattern.to_string()).collect()
+        })
+    } else {
+        Vec::new()
+    };
+
+    let separators = config::load_artist_separators().unwrap_or_else(|e| {
+        log::warn!("Could not load the artist separators, using the defaults: {}", e);
+        ArtistSeparators::default()
+    });
+
+    ScanOptions { patterns, separators, skip_hidden: settings.scan.skip_hidden, excludes: GlobSet::empty() }
 }
 
 /// # Visit Directories function
 ///
 /// Recursively traverses the directory structure starting from the provided `dir` path.
 /// For each subdirectory, the function calls itself to continue traversing.
-/// For each file, the function checks if it's an MP3 file by calling the `process_song()` function.
-/// If the file is an MP3, it extracts metadata and adds it to the `extracted_data` vector.
+/// For each file, the function checks if it's an MP3 or FLAC file by calling `process_entry()`.
+/// If it is, its metadata is added to the `extracted_data` vector; files of other types are skipped.
+/// Entries matching the exclude patterns of `options` are skipped.
 /// The metadata includes the file path and the album path (the parent directory).
-/// If a file is not a valid MP3 file, a warning is printed.
+/// Directories that cannot be read and songs that cannot be parsed are added to `failures`.
 ///
 /// # Arguments
 /// * `dir` - A reference to a `Path` that represents the directory to be traversed.
-/// * `extracted_data` - A mutable reference to a vector of hash maps, which stores metadata for each MP3 file found.
-///
-/// # Panics
-/// Panics if the directory cannot be read.
-fn visit_dirs(dir: &Path, extracted_data: &mut Vec<HashMap<String, String>>) {
-    if dir.is_dir() {
-        match fs::read_dir(dir) {
-            Ok(music) => {
-                for song in music {
-                    match song {
-                        Ok(entry) => {
-                            let path = entry.path();
-
-                            if path.is_dir() {
-                                visit_dirs(&path, extracted_data);
-                            } else if path.is_file() {
-                                if let Some(mut tag_map) = process_song(&path) {
-                                    if let Some(album_path) = path.parent() {
-                                        if let Some(path_str) = path.to_str() {
-                                            tag_map.insert("Path".to_string(), path_str.to_string());
-                                        } else {
-                                            println!("Could not convert path to string for {:?}", path);
-                                            tag_map.insert("Path".to_string(), "Unknown".to_string());
-                                        }
-
-                                        if let Some(album_path_str) = album_path.to_str() {
-                                            tag_map.insert("AlbumPath".to_string(), album_path_str.to_string());
-                                        } else {
-                                            println!("Could not convert album path to string for {:?}", album_path);
-                                            tag_map.insert("AlbumPath".to_string(), "Unknown".to_string());
-                                        }
-                                    } else {
-                                        println!("The file {:?} does not have a valid parent directory.", path);
-                                        if let Some(path_str) = path.to_str() {
-                                            tag_map.insert("Path".to_string(), path_str.to_string());
-                                        } else {
-                                            println!("Could not convert path to string for {:?}", path);
-                                            tag_map.insert("Path".to_string(), "Unknown".to_string());
-                                        }
-                                        tag_map.insert("AlbumPath".to_string(), "Unknown".to_string());
-                                    }
-                                    extracted_data.push(tag_map);
-                                } else {
-                                    println!("{:?} is not a valid MP3 file", path);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("Could not read the entry: {}", e);
-                        }
-                    }
-                }
-            }
+/// * `root` - The directory the scan started from, used to compute paths for tag inference.
+/// * `options` - The path patterns, artist separators, exclude patterns and whether hidden files are skipped.
+/// * `extracted_data` - A mutable reference to a vector of hash maps, which stores metadata for each song found.
+/// * `failures` - Collects the errors met, with the path they concern.
+fn visit_dirs(dir: &Path, root: &Path, options: &ScanOptions,
+    extracted_data: &mut Vec<HashMap<String, String>>, failures: &mut Vec<LibraryError>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            failures.push(LibraryError::io(dir, e));
+            return;
+        }
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
             Err(e) => {
-                println!("Could not read the directory: {}", e);
+                failures.push(LibraryError::io(dir, e));
+                continue;
             }
+        };
+
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if options.skip_hidden && hidden {
+            continue;
+        }
+        if options.excludes.is_match(path.strip_prefix(root).unwrap_or(&path)) {
+            log::debug!(path = path.to_string_lossy().as_ref(); "Excluded from the scan");
+            continue;
+        }
+
+        if path.is_dir() {
+            visit_dirs(&path, root, options, extracted_data, failures);
+        } else if path.is_file() {
+            match process_entry(&path, root, options) {
+                Ok(Some(tag_map)) => extracted_data.push(tag_map),
+                Ok(None) => {}
+                Err(e) => failures.push(e),
+            }
+        }
+    }
+}
+
+/// # Process Entry function
+///
+/// Reads the metadata of a song found while scanning `root` with `process_song()`, fills the
+/// fields missing from its tag from its path, splits its artist frames into performers, and
+/// adds its `Path`, `AlbumPath` and `Root`.
+///
+/// # Returns
+/// * `Ok(Some(HashMap<String, String>))` - The metadata of the song.
+/// * `Ok(None)` - If the file is not an MP3 or FLAC file.
+/// * `Err(LibraryError)` - If the file or its tags cannot be read.
+fn process_entry(path: &Path, root: &Path, options: &ScanOptions) -> LibraryResult<Option<HashMap<String, String>>> {
+    let Some(mut tag_map) = process_song(path)? else {
+        return Ok(None);
+    };
+    let relative_path = path.strip_prefix(root).unwrap_or(path);
+    path_tags::fill_missing_tags(&mut tag_map, relative_path, &options.patterns);
+
+    let credits = artist_credits::parse_credits(
+        &tag_map["Artist"],
+        &tag_map["Title"],
+        tag_map.get("Remixer").map(String::as_str),
+        tag_map.get("Composer").map(String::as_str),
+        &options.separators,
+    );
+    tag_map.insert("Credits".to_string(), artist_credits::encode_credits(&credits));
+    tag_map.insert("Root".to_string(), root.to_string_lossy().into_owned());
+
+    if let Some(album_path) = path.parent() {
+        if let Some(path_str) = path.to_str() {
+            tag_map.insert("Path".to_string(), path_str.to_string());
+        } else {
+            log::warn!(path = path.to_string_lossy().as_ref(); "The path is not valid UTF-8");
+            tag_map.insert("Path".to_string(), "Unknown".to_string());
+        }
+
+        if let Some(album_path_str) = album_path.to_str() {
+            tag_map.insert("AlbumPath".to_string(), album_path_str.to_string());
+        } else {
+            log::warn!(path = album_path.to_string_lossy().as_ref(); "The album path is not valid UTF-8");
+            tag_map.insert("AlbumPath".to_string(), "Unknown".to_string());
+        }
+    } else {
+        log::warn!(path = path.to_string_lossy().as_ref(); "The file does not have a valid parent directory");
+        if let Some(path_str) = path.to_str() {
+            tag_map.insert("Path".to_string(), path_str.to_string());
+        } else {
+            log::warn!(path = path.to_string_lossy().as_ref(); "The path is not valid UTF-8");
+            tag_map.insert("Path".to_string(), "Unknown".to_string());
         }
+        tag_map.insert("AlbumPath".to_string(), "Unknown".to_string());
     }
+    Ok(Some(tag_map))
 }
 
 /// # Process Song function
 ///
-/// Processes the metadata of a given MP3 file, checking if it has an ID3v2.4 tag.
-/// If the tag is found, it extracts metadata such as artist, title, album, year, genre,
-/// and track number by calling `assign_tag()`. 
+/// Processes the metadata of a given MP3 or FLAC file. For MP3 files, it reads the ID3v2 tag;
+/// for FLAC files, it converts the Vorbis comments and pictures into an equivalent ID3 tag
+/// with `flac::to_id3_tag()`. Metadata such as artist, title, album, year, genre,
+/// and track number is then extracted by calling `assign_tag()`. Files without any tag are processed with
+/// an empty tag so their metadata can still be inferred from the path. The cover art is
+/// looked up with `cover_art::find_cover()` and its thumbnail path stored under `Cover`,
+/// synchronised lyrics from an `.lrc` sidecar or SYLT frame are stored under `Synced Lyrics`,
+/// and the duration, bitrate, sample rate, channels and encoder of the audio stream are read
+/// with `audio_properties::read_audio_properties()`. ReplayGain values from REPLAYGAIN_* frames
+/// are stored with `loudness::read_replaygain()`, the star rating with `rating::read_rating()`,
+/// and the MusicBrainz IDs and release date with `musicbrainz::read_musicbrainz()`.
 ///
 /// # Arguments
-/// * `path` - The path to the MP3 file to be processed.
+/// * `path` - The path to the MP3 or FLAC file to be processed.
 ///
 /// # Returns
-/// * `Ok(())` if the file was successfully processed.
-/// * `Err(())` if the file is not a valid MP3 file or if it has no readable tags.
-fn process_song(path: &std::path::Path) -> Option<HashMap<String, String>> {
-    if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("mp3") {
-        return None;
+/// * `Ok(Some(HashMap<String, String>))` - The metadata of the file.
+/// * `Ok(None)` - If the file is not an MP3 or FLAC file.
+/// * `Err(LibraryError)` - If the file cannot be read or its tags cannot be parsed.
+fn process_song(path: &std::path::Path) -> LibraryResult<Option<HashMap<String, String>>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    if !path.is_file() || !matches!(extension.as_deref(), Some("mp3") | Some("flac")) {
+        return Ok(None);
     }
 
-    println!("Processing file {:?}", path);
+    let path_str = path.to_string_lossy();
+    log::debug!(path = path_str.as_ref(); "Processing file");
 
-    match Tag::read_from_path(path) {
-        Ok(tag) => {
-                println!("Tag found in: {:?} \n", path);
-                let tag_map = song_settings::assign_tag(&tag);
-                print_tag_info(&tag_map);
-                return Some(tag_map);
+    let tag = if extension.as_deref() == Some("flac") {
+        match flac::read_flac_metadata(path) {
+            Ok(metadata) => flac::to_id3_tag(&metadata),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(LibraryError::TagParse { path: path.to_path_buf(), message: e.to_string() });
+            }
+            Err(e) => return Err(LibraryError::io(path, e)),
         }
-        Err(e) => {
-            println!("Failed to read the tags in {:?}: {:?} \n", path, e);
+    } else {
+        match Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, ErrorKind::NoTag) => {
+                log::info!(path = path_str.as_ref(); "No tag found, the fields are inferred from the path");
+                Tag::new()
+            }
+            Err(e) => {
+                return Err(LibraryError::TagParse { path: path.to_path_buf(), message: e.to_string() });
+            }
         }
-    }
+    };
 
-    None
+    let mut tag_map = song_settings::assign_tag(&tag);
+    loudness::read_replaygain(&tag, &mut tag_map);
+    rating::read_rating(&tag, &mut tag_map);
+    musicbrainz::read_musicbrainz(&tag, &mut tag_map);
+    if let Some(cover) = cover_art::find_cover(&tag, path) {
+        tag_map.insert("Cover".to_string(), cover);
+    }
+    if let Some(lyrics) = synced_lyrics::load_synced_lyrics(&tag, path) {
+        tag_map.insert("Synced Lyrics".to_string(), lyrics);
+    }
+    match audio_properties::read_audio_properties(path) {
+        Ok(properties) => audio_properties::insert_properties(&mut tag_map, &properties),
+        Err(e) => log::warn!(path = path_str.as_ref(); "Could not read the audio stream: {}", e),
+    }
+    log_tag_info(&path_str, &tag_map);
+    Ok(Some(tag_map))
 }
 
-fn print_tag_info(map: &HashMap<String, String>) {
+/// Logs the fields read from a song, at the trace level.
+fn log_tag_info(path: &str, map: &HashMap<String, String>) {
     for (tag, tag_content) in map.iter() {
-        println!("{tag}: {tag_content} \n"); 
+        log::trace!(path = path; "{tag}: {tag_content}");
     }
 }
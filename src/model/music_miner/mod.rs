@@ -1,12 +1,38 @@
 /// # `music_miner` Module
 ///
 /// The `music_miner` module contains the logic for processing and analyzing music files.
-/// It extracts metadata from MP3 files and handles related song settings.
+/// It extracts metadata from MP3 and FLAC files and handles related song settings.
 ///
 /// ## Submodules
 ///
 /// - `miner`: Implements the logic for traversing directories and processing MP3 files.
 /// - `song_settings`: Provides functions to assign and store metadata extracted from MP3 files.
+/// - `path_tags`: Infers missing metadata from file names and folder structure.
+/// - `cover_art`: Extracts album covers and stores their thumbnails.
+/// - `artist_credits`: Splits artist frames into individual performers with their roles.
+/// - `synced_lyrics`: Reads and writes time-coded lyrics (`.lrc` sidecars and SYLT frames).
+/// - `flac`: Reads the metadata blocks of FLAC files.
+/// - `audio_properties`: Reads the duration, bitrate and format of the audio stream.
+/// - `decoder`: Decodes MP3 and FLAC files into samples.
+/// - `loudness`: Measures EBU R128 loudness and reads and writes ReplayGain frames.
+/// - `rating`: Reads and writes star ratings (POPM and FMPS_Rating frames).
+/// - `duplicates`: Finds copies of the same song and moves the unwanted ones to the trash or a quarantine folder.
+/// - `fingerprint`: Computes Chromaprint-compatible acoustic fingerprints and compares them.
+/// - `tag_writer`: Writes the common text fields of MP3 tags and FLAC comments.
+/// - `musicbrainz`: Matches albums against releases of a local MusicBrainz dump.
 
 pub mod miner; 
 pub mod song_settings; 
+pub mod path_tags;
+pub mod cover_art;
+pub mod artist_credits;
+pub mod synced_lyrics;
+pub mod flac;
+pub mod audio_properties;
+pub mod decoder;
+pub mod loudness;
+pub mod rating;
+pub mod duplicates;
+pub mod fingerprint;
+pub mod tag_writer;
+pub mod musicbrainz;
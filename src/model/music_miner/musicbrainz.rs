@@ -0,0 +1,415 @@
+extern crate id3;
+use crate::model::music_miner::duplicates::normalise;
+use id3::frame::Content;
+use id3::Tag;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Owner of the UFID frame holding the MusicBrainz recording ID, as written by Picard.
+pub const UFID_OWNER: &str = "http://musicbrainz.org";
+
+/// Description of the TXXX frame (and name of the Vorbis comment, in upper case without
+/// spaces) holding the MusicBrainz release ID.
+pub const ALBUM_ID_DESCRIPTION: &str = "MusicBrainz Album Id";
+
+/// Smallest `match_score()` for a release to be proposed for an album.
+const MIN_MATCH_SCORE: f64 = 0.7;
+
+/// Largest difference between the length of a song and of a track for them to be the same.
+const DURATION_TOLERANCE_MS: i64 = 3000;
+
+/// A release (a particular edition of an album) read from a MusicBrainz dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub mbid: String,
+    pub title: String,
+    /// The artist credit of the release, e.g. `Simon & Garfunkel`.
+    pub artist: String,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub date: Option<String>,
+    /// The tracks of every medium, in order.
+    pub tracks: Vec<ReleaseTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseTrack {
+    pub recording_mbid: Option<String>,
+    pub title: String,
+    pub disc: u32,
+    pub position: u32,
+    pub length_ms: Option<i64>,
+}
+
+/// An album of the library, with the songs compared with the tracks of a release.
+#[derive(Debug, Clone)]
+pub struct LocalAlbum {
+    pub id_album: i64,
+    pub name: String,
+    pub artist: String,
+    pub year: Option<i32>,
+    pub release_date: Option<String>,
+    pub mbid: Option<String>,
+    pub tracks: Vec<LocalTrack>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalTrack {
+    pub path: String,
+    pub title: String,
+    pub disc: Option<i32>,
+    pub disc_total: Option<i32>,
+    pub track: i32,
+    pub track_total: Option<i32>,
+    pub duration_ms: Option<i64>,
+    pub mbid: Option<String>,
+}
+
+/// A field of a song that differs from the release it was matched to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// The tag map key of the field, e.g. `Title` or `MusicBrainz Album Id`.
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// The changes proposed for one song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackProposal {
+    pub path: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Deserialize)]
+struct JsonRelease {
+    id: String,
+    title: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<JsonArtistCredit>,
+    #[serde(default)]
+    media: Vec<JsonMedium>,
+}
+
+#[derive(Deserialize)]
+struct JsonArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct JsonMedium {
+    #[serde(default)]
+    position: Option<u32>,
+    #[serde(default)]
+    tracks: Vec<JsonTrack>,
+}
+
+#[derive(Deserialize)]
+struct JsonTrack {
+    title: String,
+    #[serde(default)]
+    position: Option<u32>,
+    #[serde(default)]
+    length: Option<i64>,
+    #[serde(default)]
+    recording: Option<JsonRecording>,
+}
+
+#[derive(Deserialize)]
+struct JsonRecording {
+    id: String,
+}
+
+/// The forms a JSON dump may take: a list of releases, or a search result.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonDocument {
+    Releases(Vec<JsonRelease>),
+    SearchResult { releases: Vec<JsonRelease> },
+}
+
+impl From<JsonRelease> for Release {
+    fn from(release: JsonRelease) -> Release {
+        let artist = release.artist_credit.iter()
+            .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+            .collect::<String>();
+        let tracks = release.media.into_iter().enumerate()
+            .flat_map(|(medium_index, medium)| {
+                let disc = medium.position.unwrap_or(medium_index as u32 + 1);
+                medium.tracks.into_iter().enumerate().map(move |(track_index, track)| ReleaseTrack {
+                    recording_mbid: track.recording.map(|recording| recording.id),
+                    title: track.title,
+                    disc,
+                    position: track.position.unwrap_or(track_index as u32 + 1),
+                    length_ms: track.length,
+                })
+            })
+            .collect();
+
+        Release {
+            mbid: release.id,
+            title: release.title,
+            artist,
+            date: release.date.filter(|date| !date.trim().is_empty()),
+            tracks,
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the flat TSV format, one track per line after a header line:
+/// `release_mbid  release_title  artist  date  disc  position  track_title  length_ms  recording_mbid`.
+/// Consecutive lines with the same `release_mbid` make up one release.
+fn read_tsv(reader: impl BufRead, keep: &mut impl FnMut(&Release) -> bool) -> io::Result<Vec<Release>> {
+    let mut releases = Vec::new();
+    let mut current: Option<Release> = None;
+
+    for (number, line) in reader.lines().enumerate().skip(1) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 9 {
+            return Err(invalid_data(format!("Line {} has {} columns instead of 9", number + 1, columns.len())));
+        }
+        let optional = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty() && value != "\\N");
+        let number_column = |value: &str| value.trim().parse::<u32>()
+            .map_err(|_| invalid_data(format!("Line {}: `{}` is not a number", number + 1, value)));
+
+        if current.as_ref().is_some_and(|release| release.mbid != columns[0]) {
+            releases.extend(current.take().filter(|release| keep(release)));
+        }
+        let release = current.get_or_insert_with(|| Release {
+            mbid: columns[0].to_string(),
+            title: columns[1].to_string(),
+            artist: columns[2].to_string(),
+            date: optional(columns[3]),
+            tracks: Vec::new(),
+        });
+        release.tracks.push(ReleaseTrack {
+            recording_mbid: optional(columns[8]),
+            title: columns[6].to_string(),
+            disc: number_column(columns[4])?,
+            position: number_column(columns[5])?,
+            length_ms: columns[7].trim().parse().ok(),
+        });
+    }
+    releases.extend(current.filter(|release| keep(release)));
+    Ok(releases)
+}
+
+/// # Load Releases function
+///
+/// Reads the releases of a local MusicBrainz dump. The format is chosen by the extension:
+/// - `.json`: a list of release objects as returned by the MusicBrainz web service (with
+///   `media` and `tracks`), or a search result with a `releases` list;
+/// - `.tsv`: the flat format of `read_tsv()`, one track per line;
+/// - anything else (e.g. the `release` file of the JSON data dumps): one release object per line.
+///
+/// # Arguments
+/// * `path` - The path of the dump.
+/// * `keep` - Decides which releases are kept, so large dumps need not be held in memory.
+///
+/// # Returns
+/// * `Ok(Vec<Release>)` - The releases kept.
+/// * `Err(io::Error)` - If the dump cannot be read or is malformed.
+pub fn load_releases(path: &Path, mut keep: impl FnMut(&Release) -> bool) -> io::Result<Vec<Release>> {
+    let reader = BufReader::new(File::open(path)?);
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+
+    match extension.as_deref() {
+        Some("tsv") => read_tsv(reader, &mut keep),
+        Some("json") => {
+            let document: JsonDocument = serde_json::from_reader(reader).map_err(|e| invalid_data(e.to_string()))?;
+            let releases = match document {
+                JsonDocument::Releases(releases) | JsonDocument::SearchResult { releases } => releases,
+            };
+            Ok(releases.into_iter().map(Release::from).filter(|release| keep(release)).collect())
+        }
+        _ => {
+            let mut releases = Vec::new();
+            for (number, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let release: JsonRelease = serde_json::from_str(&line)
+                    .map_err(|e| invalid_data(format!("Line {}: {}", number + 1, e)))?;
+                let release = Release::from(release);
+                if keep(&release) {
+                    releases.push(release);
+                }
+            }
+            Ok(releases)
+        }
+    }
+}
+
+/// # Text Similarity function
+///
+/// Compares two names once normalised (see `normalise()`), with the Levenshtein distance.
+///
+/// # Returns
+/// A value from 0.0 (nothing in common) to 1.0 (equal).
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    let (a, b): (Vec<char>, Vec<char>) = (normalise(a).chars().collect(), normalise(b).chars().collect());
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+/// Pairs the songs of an album with the tracks of a release, both in disc and track order
+/// (songs without track numbers in path order).
+fn align<'a>(album: &'a LocalAlbum, release: &'a Release) -> Vec<(&'a LocalTrack, &'a ReleaseTrack)> {
+    let mut songs: Vec<&LocalTrack> = album.tracks.iter().collect();
+    songs.sort_by(|a, b| (a.disc.unwrap_or(1), a.track, &a.path).cmp(&(b.disc.unwrap_or(1), b.track, &b.path)));
+    let mut tracks: Vec<&ReleaseTrack> = release.tracks.iter().collect();
+    tracks.sort_by_key(|track| (track.disc, track.position));
+    songs.into_iter().zip(tracks).collect()
+}
+
+/// # Match Score function
+///
+/// Scores how well a release fits an album: it must have as many tracks as the album has
+/// songs, and the score weighs the agreement of the track durations, track titles, album
+/// title and artist. An album already tagged with the release's MBID scores 1.0.
+///
+/// # Returns
+/// The score from 0.0 to 1.0, or `None` if the track counts differ.
+pub fn match_score(album: &LocalAlbum, release: &Release) -> Option<f64> {
+    if album.tracks.len() != release.tracks.len() || album.tracks.is_empty() {
+        return None;
+    }
+    if album.mbid.as_deref() == Some(release.mbid.as_str()) {
+        return Some(1.0);
+    }
+
+    let pairs = align(album, release);
+    let count = pairs.len() as f64;
+    let durations = pairs.iter()
+        .map(|(song, track)| match (song.duration_ms, track.length_ms) {
+            (Some(song), Some(track)) => if (song - track).abs() <= DURATION_TOLERANCE_MS { 1.0 } else { 0.0 },
+            _ => 0.5,
+        })
+        .sum::<f64>() / count;
+    let titles = pairs.iter().map(|(song, track)| text_similarity(&song.title, &track.title)).sum::<f64>() / count;
+
+    Some(0.35 * durations + 0.25 * titles + 0.25 * text_similarity(&album.name, &release.title)
+        + 0.15 * text_similarity(&album.artist, &release.artist))
+}
+
+/// # Best Release function
+///
+/// Finds the release that fits an album best, if any scores at least `MIN_MATCH_SCORE`.
+///
+/// # Returns
+/// The release and its score.
+pub fn best_release<'a>(album: &LocalAlbum, releases: &'a [Release]) -> Option<(&'a Release, f64)> {
+    releases.iter()
+        .filter_map(|release| Some((release, match_score(album, release)?)))
+        .filter(|(_, score)| *score >= MIN_MATCH_SCORE)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// # Propose Corrections function
+///
+/// Lists, for every song of an album, the fields that differ from the release it matched:
+/// the title, album, track and disc numbers and totals, release date and MusicBrainz IDs.
+/// Disc numbers are only proposed for releases with several discs, and fields the release
+/// does not know (e.g. its date) are left alone.
+///
+/// # Returns
+/// The proposals of the songs with at least one change.
+pub fn propose_corrections(album: &LocalAlbum, release: &Release) -> Vec<TrackProposal> {
+    let discs = release.tracks.iter().map(|track| track.disc).max().unwrap_or(1);
+    let old_date = album.release_date.clone().or_else(|| album.year.map(|year| year.to_string())).unwrap_or_default();
+
+    align(album, release).into_iter()
+        .map(|(song, track)| {
+            let track_total = release.tracks.iter().filter(|other| other.disc == track.disc).count();
+            let mut fields = vec![
+                ("Title", song.title.clone(), track.title.clone()),
+                ("Album", album.name.clone(), release.title.clone()),
+                ("Track Number", song.track.to_string(), track.position.to_string()),
+                ("Track Total", optional_number(song.track_total), track_total.to_string()),
+                ("Release Date", old_date.clone(), release.date.clone().unwrap_or_default()),
+                (ALBUM_ID_DESCRIPTION, album.mbid.clone().unwrap_or_default(), release.mbid.clone()),
+                ("MusicBrainz Recording Id", song.mbid.clone().unwrap_or_default(), track.recording_mbid.clone().unwrap_or_default()),
+            ];
+            if discs > 1 {
+                fields.push(("Disc Number", optional_number(song.disc), track.disc.to_string()));
+                fields.push(("Disc Total", optional_number(song.disc_total), discs.to_string()));
+            }
+
+            let changes = fields.into_iter()
+                .filter(|(_, old, new)| !new.is_empty() && old.trim() != new.trim())
+                .map(|(field, old, new)| FieldChange { field: field.to_string(), old, new })
+                .collect::<Vec<_>>();
+            TrackProposal { path: song.path.clone(), changes }
+        })
+        .filter(|proposal| !proposal.changes.is_empty())
+        .collect()
+}
+
+fn optional_number(number: Option<i32>) -> String {
+    number.map(|number| number.to_string()).unwrap_or_default()
+}
+
+/// Returns the data of the UFID frame of `owner`: the owner (a null-terminated string)
+/// followed by the identifier.
+fn ufid_identifier(data: &[u8], owner: &str) -> Option<String> {
+    let end = data.iter().position(|byte| *byte == 0)?;
+    if &data[..end] != owner.as_bytes() {
+        return None;
+    }
+    String::from_utf8(data[end + 1..].to_vec()).ok()
+}
+
+/// # Read MusicBrainz function
+///
+/// Stores the MusicBrainz IDs of a tag in the tag map, under `MusicBrainz Album Id` (from
+/// the TXXX frame of that name) and `MusicBrainz Recording Id` (from the UFID frame of
+/// MusicBrainz), and the full recording date (TDRC) under `Release Date` when it has at
+/// least a month. FLAC files get these frames from their MUSICBRAINZ_ALBUMID,
+/// MUSICBRAINZ_TRACKID and DATE comments in `flac::to_id3_tag()`.
+pub fn read_musicbrainz(tag: &Tag, map: &mut HashMap<String, String>) {
+    if let Some(album_id) = tag.extended_texts().find(|extended_text| extended_text.description == ALBUM_ID_DESCRIPTION) {
+        map.insert(ALBUM_ID_DESCRIPTION.to_string(), album_id.value.trim().to_string());
+    }
+
+    let recording_id = tag.frames()
+        .filter(|frame| frame.id() == "UFID")
+        .find_map(|frame| match frame.content() {
+            Content::Unknown(data) => ufid_identifier(data, UFID_OWNER),
+            _ => None,
+        });
+    if let Some(recording_id) = recording_id {
+        map.insert("MusicBrainz Recording Id".to_string(), recording_id);
+    }
+
+    if let Some(date) = tag.date_recorded().filter(|date| date.month.is_some()) {
+        map.insert("Release Date".to_string(), date.to_string());
+    }
+}
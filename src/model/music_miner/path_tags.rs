@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Patterns used when the user has not defined any in `PathPatterns.txt`.
+pub const DEFAULT_PATTERNS: [&str; 4] = [
+    "%artist%/%album%/%track% - %title%",
+    "%track% - %title%",
+    "%artist% - %title%",
+    "%title%",
+];
+
+/// A single piece of a path pattern: either literal text or a placeholder such as `%artist%`.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(String),
+}
+
+/// Maps a placeholder name to the key used in the tag map produced by `assign_tag`.
+fn field_key(field: &str) -> Option<&'static str> {
+    match field {
+        "artist" => Some("Artist"),
+        "title" => Some("Title"),
+        "album" => Some("Album"),
+        "year" => Some("Year"),
+        "genre" => Some("Genre"),
+        "track" => Some("Track Number"),
+        "ignore" => Some(""),
+        _ => None,
+    }
+}
+
+/// # Tokenize function
+///
+/// Splits one path component of a pattern into literal text and placeholders.
+/// Unknown placeholders (e.g. `%foo%`) are kept as literal text.
+fn tokenize(component: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = component;
+
+    while let Some(start) = rest.find('%') {
+        let after = &rest[start + 1..];
+        match after.find('%') {
+            Some(end) if field_key(&after[..end]).is_some() => {
+                if start > 0 {
+                    tokens.push(Token::Literal(rest[..start].to_string()));
+                }
+                tokens.push(Token::Field(after[..end].to_string()));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                tokens.push(Token::Literal(rest[..start + 1].to_string()));
+                rest = after;
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Literal(rest.to_string()));
+    }
+
+    tokens
+}
+
+/// Checks whether a captured value is acceptable for the given placeholder.
+/// Track numbers and years must be numeric, every other field must be non-empty.
+fn value_fits(field: &str, value: &str) -> bool {
+    match field {
+        "track" => !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()),
+        "year" => value.len() == 4 && value.chars().all(|c| c.is_ascii_digit()),
+        _ => !value.trim().is_empty(),
+    }
+}
+
+/// # Match Tokens function
+///
+/// Matches `text` against `tokens`, storing captured placeholder values in `captures`.
+/// Placeholders are matched lazily, so the shortest capture that lets the rest of the
+/// component match wins.
+fn match_tokens(tokens: &[Token], text: &str, captures: &mut HashMap<String, String>) -> bool {
+    match tokens.split_first() {
+        None => text.is_empty(),
+        Some((Token::Literal(literal), rest)) => {
+            text.starts_with(literal.as_str()) && match_tokens(rest, &text[literal.len()..], captures)
+        }
+        Some((Token::Field(field), rest)) => {
+            for (end, _) in text.char_indices().skip(1).chain(std::iter::once((text.len(), ' '))) {
+                let value = &text[..end];
+                if !value_fits(field, value) {
+                    continue;
+                }
+                if match_tokens(rest, &text[end..], captures) {
+                    captures.insert(field.clone(), value.trim().to_string());
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+/// # Match Pattern function
+///
+/// Matches a pattern such as `%artist%/%album%/%track% - %title%` against a path relative
+/// to the scanned directory. The pattern is aligned with the end of the path, so a pattern
+/// with three components is compared with the last two directories and the file name
+/// (without its extension).
+///
+/// # Arguments
+/// * `pattern` - The user-defined pattern, using `/` to separate directories.
+/// * `relative_path` - The path of the song relative to the scanned directory.
+///
+/// # Returns
+/// * `Some(HashMap<String, String>)` - The inferred values keyed like the map from `assign_tag`.
+/// * `None` - If the path does not follow the pattern.
+pub fn match_pattern(pattern: &str, relative_path: &Path) -> Option<HashMap<String, String>> {
+    let mut components: Vec<String> = relative_path
+        .iter()
+        .map(|component| component.to_string_lossy().to_string())
+        .collect();
+
+    if let Some(stem) = relative_path.file_stem() {
+        let last = components.len() - 1;
+        components[last] = stem.to_string_lossy().to_string();
+    }
+
+    let pattern_components: Vec<&str> = pattern.trim().trim_matches('/').split('/').collect();
+    if pattern_components.len() > components.len() {
+        return None;
+    }
+
+    let mut captures = HashMap::new();
+    let offset = components.len() - pattern_components.len();
+    for (pattern_component, component) in pattern_components.iter().zip(&components[offset..]) {
+        if !match_tokens(&tokenize(pattern_component), component, &mut captures) {
+            return None;
+        }
+    }
+
+    let mut inferred = HashMap::new();
+    for (field, value) in captures {
+        if let Some(key) = field_key(&field).filter(|key| !key.is_empty()) {
+            inferred.insert(key.to_string(), value);
+        }
+    }
+    Some(inferred)
+}
+
+/// # Fill Missing Tags function
+///
+/// Replaces every field of `tag_map` whose source is not `Tag` with the value inferred from
+/// the first pattern that matches the song's path, and marks that field's source as `Path`.
+/// Fields that were read from the ID3 tag are never overwritten.
+///
+/// # Arguments
+/// * `tag_map` - The metadata produced by `assign_tag`, including the `<Field> Source` keys.
+/// * `relative_path` - The path of the song relative to the scanned directory.
+/// * `patterns` - The patterns to try, in order of preference.
+pub fn fill_missing_tags(tag_map: &mut HashMap<String, String>, relative_path: &Path, patterns: &[String]) {
+    let all_tagged = tag_map
+        .iter()
+        .filter(|(key, _)| key.ends_with(" Source"))
+        .all(|(_, source)| source == "Tag");
+    if all_tagged {
+        return;
+    }
+
+    let inferred = match patterns.iter().find_map(|pattern| match_pattern(pattern, relative_path)) {
+        Some(inferred) => inferred,
+        None => return,
+    };
+
+    for (key, value) in inferred {
+        let source_key = format!("{} Source", key);
+        if tag_map.get(&source_key).map(String::as_str) != Some("Tag") {
+            tag_map.insert(key, value);
+            tag_map.insert(source_key, "Path".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns_match_the_end_of_the_path() {
+        let inferred = match_pattern(
+            "%artist%/%album%/%track% - %title%",
+            Path::new("Rock/Queen/A Night at the Opera/11 - Bohemian Rhapsody.mp3"),
+        ).unwrap();
+        assert_eq!(inferred["Artist"], "Queen");
+        assert_eq!(inferred["Album"], "A Night at the Opera");
+        assert_eq!(inferred["Track Number"], "11");
+        assert_eq!(inferred["Title"], "Bohemian Rhapsody");
+        assert_eq!(inferred.len(), 4);
+    }
+
+    #[test]
+    fn paths_not_following_the_pattern_do_not_match() {
+        let pattern = "%artist%/%album%/%track% - %title%";
+        assert!(match_pattern(pattern, Path::new("Queen/11 - Bohemian Rhapsody.mp3")).is_none());
+        assert!(match_pattern(pattern, Path::new("Queen/Opera/Bohemian Rhapsody.mp3")).is_none());
+        assert!(match_pattern(pattern, Path::new("Queen/Opera/eleven - Bohemian Rhapsody.mp3")).is_none());
+        assert!(match_pattern("(%year%) %album%", Path::new("(75) Opera.mp3")).is_none());
+    }
+
+    #[test]
+    fn literal_separators_split_the_fields() {
+        let inferred = match_pattern("%artist% - %title%", Path::new("AC-DC - Back in Black - Live.mp3")).unwrap();
+        assert_eq!(inferred["Artist"], "AC-DC");
+        assert_eq!(inferred["Title"], "Back in Black - Live");
+
+        let inferred = match_pattern("(%year%) %album% [%ignore%]", Path::new("(1975) Opera [FLAC].flac")).unwrap();
+        assert_eq!(inferred["Year"], "1975");
+        assert_eq!(inferred["Album"], "Opera");
+        assert!(!inferred.contains_key(""));
+
+        let inferred = match_pattern("%track%. %title%", Path::new("07. Intro. Part 1.mp3")).unwrap();
+        assert_eq!(inferred["Track Number"], "07");
+        assert_eq!(inferred["Title"], "Intro. Part 1");
+        assert_eq!(tokenize("100%foo%"), [Token::Literal("100%".to_string()), Token::Literal("foo%".to_string())]);
+    }
+
+    #[test]
+    fn fields_read_from_the_tag_are_kept() {
+        let mut tag_map = HashMap::from([
+            ("Artist".to_string(), "Queen".to_string()),
+            ("Artist Source".to_string(), "Tag".to_string()),
+            ("Title".to_string(), "Unknown".to_string()),
+            ("Title Source".to_string(), "Default".to_string()),
+        ]);
+        let path = Path::new("Freddie/Opera/11 - Bohemian Rhapsody.mp3");
+        fill_missing_tags(&mut tag_map, path, &patterns(&["%year%/%title%", "%artist%/%album%/%track% - %title%"]));
+
+        assert_eq!(tag_map["Artist"], "Queen");
+        assert_eq!(tag_map["Artist Source"], "Tag");
+        assert_eq!(tag_map["Title"], "Bohemian Rhapsody");
+        assert_eq!(tag_map["Title Source"], "Path");
+        assert_eq!(tag_map["Album Source"], "Path");
+    }
+
+    #[test]
+    fn fully_tagged_songs_and_unmatched_paths_are_left_alone() {
+        let tagged = HashMap::from([
+            ("Title".to_string(), "Speed of Life".to_string()),
+            ("Title Source".to_string(), "Tag".to_string()),
+        ]);
+        let mut tag_map = tagged.clone();
+        fill_missing_tags(&mut tag_map, Path::new("Bowie/Low/01 - Something Else.mp3"), &patterns(&DEFAULT_PATTERNS));
+        assert_eq!(tag_map, tagged);
+
+        let untagged = HashMap::from([("Title Source".to_string(), "Default".to_string())]);
+        let mut tag_map = untagged.clone();
+        fill_missing_tags(&mut tag_map, Path::new("Bowie/Low/speed.mp3"), &patterns(&["%track% - %title%"]));
+        assert_eq!(tag_map, untagged);
+    }
+}
@@ -0,0 +1,121 @@
+extern crate id3;
+use id3::frame::Content;
+use id3::{ErrorKind, Frame, Tag, Version};
+use crate::model::music_miner::flac;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Highest star rating of a song; `0` means the song is not rated.
+pub const MAX_RATING: i32 = 5;
+
+/// The owner written in POPM frames. Most players only read the rating of this owner (or of
+/// any owner), so using the name of Windows Media Player keeps ratings portable.
+const POPM_OWNER: &str = "Windows Media Player 9 Series";
+
+/// Description of the TXXX frame (and name of the Vorbis comment) of the FMPS rating, a
+/// number between 0.0 and 1.0.
+const FMPS_RATING: &str = "FMPS_Rating";
+
+/// Converts a POPM rating byte (0–255) into stars, using the ranges of Windows Media Player
+/// so ratings written by other players read back as the same number of stars.
+fn popm_to_stars(rating: u8) -> i32 {
+    match rating {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    }
+}
+
+/// Converts stars into the POPM rating byte written by Windows Media Player.
+fn stars_to_popm(stars: i32) -> u8 {
+    match stars {
+        i32::MIN..=0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+/// Returns the rating byte of a POPM frame: the owner (a null-terminated string), followed by
+/// the rating and an optional play counter.
+fn popm_rating(data: &[u8]) -> Option<u8> {
+    let end = data.iter().position(|byte| *byte == 0)?;
+    data.get(end + 1).copied()
+}
+
+/// # Read Rating function
+///
+/// Stores the star rating of a tag in the tag map under `Rating`, from its POPM frame or,
+/// failing that, from an FMPS_Rating TXXX frame (FLAC files get one from their FMPS_RATING
+/// comment in `flac::to_id3_tag()`). Tags without a rating leave the map unchanged.
+pub fn read_rating(tag: &Tag, map: &mut HashMap<String, String>) {
+    let popm = tag.frames()
+        .filter(|frame| frame.id() == "POPM")
+        .find_map(|frame| match frame.content() {
+            Content::Unknown(data) => popm_rating(data),
+            _ => None,
+        })
+        .map(popm_to_stars);
+
+    let fmps = || tag.extended_texts()
+        .find(|extended_text| extended_text.description.eq_ignore_ascii_case(FMPS_RATING))
+        .and_then(|extended_text| extended_text.value.trim().parse::<f64>().ok())
+        .map(|value| (value.clamp(0.0, 1.0) * f64::from(MAX_RATING)).round() as i32);
+
+    if let Some(stars) = popm.or_else(fmps) {
+        map.insert("Rating".to_string(), stars.to_string());
+    }
+}
+
+/// # Write Rating function
+///
+/// Replaces the rating of a song's tag with a POPM frame and an FMPS_Rating TXXX frame, and
+/// writes it back as ID3v2.4. FLAC files get an FMPS_RATING comment instead. A rating of `0`
+/// removes them. Other formats return an `UnsupportedFeature` error.
+///
+/// # Arguments
+/// * `song_path` - The path of the MP3 or FLAC file.
+/// * `stars` - The rating, from 0 to `MAX_RATING`.
+pub fn write_rating(song_path: &Path, stars: i32) -> id3::Result<()> {
+    let stars = stars.clamp(0, MAX_RATING);
+    let fmps = (stars > 0).then(|| format!("{:.1}", f64::from(stars) / f64::from(MAX_RATING)));
+
+    let extension = song_path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    if extension == "flac" {
+        return Ok(flac::write_vorbis_comments(song_path, &[(FMPS_RATING, fmps)])?);
+    }
+    if extension != "mp3" {
+        return Err(id3::Error::new(ErrorKind::UnsupportedFeature, "Ratings can only be written to MP3 and FLAC files"));
+    }
+
+    let mut tag = match Tag::read_from_path(song_path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(e),
+    };
+
+    tag.remove("POPM");
+    let existing: Vec<String> = tag.extended_texts()
+        .filter(|extended_text| extended_text.description.eq_ignore_ascii_case(FMPS_RATING))
+        .map(|extended_text| extended_text.description.clone())
+        .collect();
+    for existing in existing {
+        tag.remove_extended_text(Some(&existing), None);
+    }
+
+    if let Some(fmps) = fmps {
+        let mut data = POPM_OWNER.as_bytes().to_vec();
+        data.push(0);
+        data.push(stars_to_popm(stars));
+        data.extend([0; 4]);
+        tag.add_frame(Frame::with_content("POPM", Content::Unknown(data)));
+        tag.add_extended_text(FMPS_RATING, fmps);
+    }
+
+    tag.write_to_path(song_path, Version::Id3v24)
+}
@@ -9,6 +9,14 @@ use chrono::Datelike;
 /// If a specific tag is missing, it assigns "Unknown" as the default value.
 /// If the year is missing, it assigns the current year.
 /// If the track number is missing, it assigns `0`.
+/// For every field it also stores a `<Field> Source` entry set to `Tag` when the value was
+/// read from the tag, or `Default` when a fallback was used.
+/// The album artist (TPE2) is stored under `Album Artist` when present, and the iTunes
+/// compilation flag (TCMP) under `Compilation` as `1` or `0`. The remixer (TPE4) and
+/// composer (TCOM) are stored under `Remixer` and `Composer` when present, as are the disc
+/// number and total (`Disc Number`, `Disc Total`), the track total (`Track Total`), the
+/// conductor (TPE3), BPM (TBPM), key (TKEY), the first comment (COMM) and the first
+/// unsynchronised lyrics (USLT).
 ///
 /// # Arguments
 /// * `tag` - A reference to the tag from which the metadata is extracted.
@@ -18,24 +26,58 @@ use chrono::Datelike;
 pub fn assign_tag(tag: &Tag) -> HashMap<String, String> {
     let mut map: HashMap<String, String> = HashMap::new();
     
-    let artist = tag.artist().unwrap_or("Unknown");
-    map.insert("Artist".to_string(), artist.to_string());
+    insert_field(&mut map, "Artist", tag.artist().map(str::to_string), "Unknown".to_string());
+    insert_field(&mut map, "Title", tag.title().map(str::to_string), "Unknown".to_string());
+    insert_field(&mut map, "Album", tag.album().map(str::to_string), "Unknown".to_string());
 
-    let title = tag.title().unwrap_or("Unknown");
-    map.insert("Title".to_string(), title.to_string());
+    let current_year = chrono::Utc::now().year();
+    insert_field(&mut map, "Year", tag.year().map(|year| year.to_string()), current_year.to_string());
 
-    let album = tag.album().unwrap_or("Unknown");
-    map.insert("Album".to_string(), album.to_string());
+    insert_field(&mut map, "Genre", tag.genre().map(str::to_string), "Unknown".to_string());
+    insert_field(&mut map, "Track Number", tag.track().map(|track| track.to_string()), "0".to_string());
 
-    let current_year = chrono::Utc::now().year();
-    let year = tag.year().unwrap_or(current_year);
-    map.insert("Year".to_string(), year.to_string());
+    let numbers = [("Disc Number", tag.disc()), ("Disc Total", tag.total_discs()), ("Track Total", tag.total_tracks())];
+    for (key, number) in numbers {
+        if let Some(number) = number {
+            map.insert(key.to_string(), number.to_string());
+        }
+    }
+
+    if let Some(comment) = tag.comments().find(|comment| !comment.text.trim().is_empty()) {
+        map.insert("Comment".to_string(), comment.text.clone());
+    }
+
+    if let Some(lyrics) = tag.lyrics().find(|lyrics| !lyrics.text.trim().is_empty()) {
+        map.insert("Lyrics".to_string(), lyrics.text.clone());
+    }
 
-    let genre = tag.genre().unwrap_or("Unknown");
-    map.insert("Genre".to_string(), genre.to_string());
+    let text_frames = [("Remixer", "TPE4"), ("Composer", "TCOM"), ("Conductor", "TPE3"), ("BPM", "TBPM"), ("Key", "TKEY")];
+    for (key, frame_id) in text_frames {
+        if let Some(text) = tag.get(frame_id).and_then(|frame| frame.content().text()) {
+            map.insert(key.to_string(), text.to_string());
+        }
+    }
 
-    let track_number = tag.track().unwrap_or(0);
-    map.insert("Track Number".to_string(), track_number.to_string());
+    if let Some(album_artist) = tag.album_artist() {
+        map.insert("Album Artist".to_string(), album_artist.to_string());
+    }
+
+    let compilation = tag.get("TCMP")
+        .and_then(|frame| frame.content().text())
+        .map(|flag| flag.trim() == "1")
+        .unwrap_or(false);
+    map.insert("Compilation".to_string(), if compilation { "1" } else { "0" }.to_string());
 
     map
 }
+
+/// Inserts a field and its `<Field> Source` entry into the tag map,
+/// falling back to `default` when the tag does not contain the field.
+fn insert_field(map: &mut HashMap<String, String>, key: &str, value: Option<String>, default: String) {
+    let (value, source) = match value {
+        Some(value) => (value, "Tag"),
+        None => (default, "Default"),
+    };
+    map.insert(key.to_string(), value);
+    map.insert(format!("{} Source", key), source.to_string());
+}
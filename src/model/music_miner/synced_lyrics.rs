@@ -0,0 +1,255 @@
+extern crate id3;
+use id3::frame::{SynchronisedLyrics, SynchronisedLyricsType, TimestampFormat};
+use id3::{ErrorKind, Tag, Version};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One time-coded line of synchronised lyrics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricLine {
+    pub time_ms: u32,
+    pub text: String,
+}
+
+/// # Parse Timestamp function
+///
+/// Parses an LRC timestamp such as `01:23.45`, `01:23.456` or `01:23` into milliseconds.
+///
+/// # Returns
+/// * `Some(u32)` - The timestamp in milliseconds.
+/// * `None` - If the text is not a valid timestamp, or is too large to be held in milliseconds.
+pub fn parse_timestamp(text: &str) -> Option<u32> {
+    let (minutes, seconds) = text.trim().split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let seconds: u32 = seconds.parse().ok()?;
+    if seconds >= 60 || fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let fraction_ms = match fraction.len() {
+        1 => fraction.parse::<u32>().ok()? * 100,
+        2 => fraction.parse::<u32>().ok()? * 10,
+        _ => fraction[..3].parse::<u32>().ok()?,
+    };
+
+    minutes.checked_mul(60_000)?.checked_add(seconds * 1_000 + fraction_ms)
+}
+
+/// Formats milliseconds as an LRC timestamp (`mm:ss.xx`).
+pub fn format_timestamp(time_ms: u32) -> String {
+    format!("{:02}:{:02}.{:02}", time_ms / 60_000, (time_ms / 1_000) % 60, (time_ms % 1_000) / 10)
+}
+
+/// # Parse LRC function
+///
+/// Parses the contents of an `.lrc` file. Lines may carry several timestamps
+/// (`[00:12.00][01:15.30]Chorus`), and the `[offset:+/-ms]` header is applied to every line.
+/// Other header tags (`[ar:...]`, `[ti:...]`) and lines without timestamps are ignored.
+///
+/// # Returns
+/// The lyric lines sorted by time.
+pub fn parse_lrc(contents: &str) -> Vec<LyricLine> {
+    let mut lines = Vec::new();
+    let mut offset: i64 = 0;
+
+    for raw_line in contents.lines() {
+        let mut rest = raw_line.trim();
+        let mut times = Vec::new();
+
+        while let Some(tag_end) = rest.strip_prefix('[').and_then(|tag| tag.find(']')) {
+            let tag = &rest[1..tag_end + 1];
+            if let Some(time_ms) = parse_timestamp(tag) {
+                times.push(time_ms);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            }
+            rest = &rest[tag_end + 2..];
+        }
+
+        for time_ms in times {
+            // A positive offset makes the lyrics appear sooner.
+            let time_ms = (i64::from(time_ms) - offset).max(0) as u32;
+            lines.push(LyricLine { time_ms, text: rest.trim().to_string() });
+        }
+    }
+
+    lines.sort_by_key(|line| line.time_ms);
+    lines
+}
+
+/// # Current Line function
+///
+/// Finds the line being sung at a position of the song: the last line whose timestamp is not
+/// after the position.
+///
+/// # Arguments
+/// * `lines` - The lyric lines, sorted by time.
+/// * `position_ms` - The playback position, in milliseconds.
+///
+/// # Returns
+/// The index of the line, or `None` before the first line.
+pub fn current_line(lines: &[LyricLine], position_ms: u64) -> Option<usize> {
+    lines.partition_point(|line| u64::from(line.time_ms) <= position_ms).checked_sub(1)
+}
+
+/// Serialises lyric lines in LRC format, one timestamp per line.
+pub fn to_lrc(lines: &[LyricLine]) -> String {
+    lines.iter()
+        .map(|line| format!("[{}]{}\n", format_timestamp(line.time_ms), line.text))
+        .collect()
+}
+
+/// Returns the path of the `.lrc` sidecar file of a song (same name, `.lrc` extension).
+pub fn sidecar_path(song_path: &Path) -> PathBuf {
+    song_path.with_extension("lrc")
+}
+
+/// # From SYLT function
+///
+/// Returns the lines of the first SYLT frame of a tag that uses millisecond timestamps.
+/// SYLT frames timed in MPEG frames are ignored, since their duration depends on the stream.
+pub fn from_sylt(tag: &Tag) -> Option<Vec<LyricLine>> {
+    let lyrics = tag.synchronised_lyrics()
+        .find(|lyrics| matches!(lyrics.timestamp_format, TimestampFormat::MS))?;
+
+    let mut lines: Vec<LyricLine> = lyrics.content.iter()
+        .map(|(time_ms, text)| LyricLine { time_ms: *time_ms, text: text.trim_end_matches('\n').to_string() })
+        .collect();
+    lines.sort_by_key(|line| line.time_ms);
+    Some(lines)
+}
+
+/// # Load Synced Lyrics function
+///
+/// Loads the synchronised lyrics of a song, preferring an `.lrc` sidecar file next to the
+/// song over a SYLT frame in its tag.
+///
+/// # Returns
+/// * `Some(String)` - The lyrics in LRC format.
+/// * `None` - If the song has no synchronised lyrics.
+pub fn load_synced_lyrics(tag: &Tag, song_path: &Path) -> Option<String> {
+    let lines = match fs::read_to_string(sidecar_path(song_path)) {
+        Ok(contents) => parse_lrc(&contents),
+        Err(_) => from_sylt(tag)?,
+    };
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(to_lrc(&lines))
+    }
+}
+
+/// Writes lyric lines to the `.lrc` sidecar file of a song, replacing it if it exists.
+pub fn save_lrc_sidecar(song_path: &Path, lines: &[LyricLine]) -> io::Result<PathBuf> {
+    let path = sidecar_path(song_path);
+    fs::write(&path, to_lrc(lines))?;
+    Ok(path)
+}
+
+/// # Save SYLT function
+///
+/// Replaces the SYLT frames of a song's tag with the given lines, using millisecond
+/// timestamps, and writes the tag back as ID3v2.4. Songs without a tag get a new one.
+/// Only MP3 files can be written; other formats return an `UnsupportedFeature` error.
+pub fn save_sylt(song_path: &Path, lines: &[LyricLine]) -> id3::Result<()> {
+    if !song_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp3")) {
+        return Err(id3::Error::new(ErrorKind::UnsupportedFeature, "SYLT frames can only be written to MP3 files"));
+    }
+
+    let mut tag = match Tag::read_from_path(song_path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(e),
+    };
+
+    tag.remove_all_synchronised_lyrics();
+    tag.add_synchronised_lyrics(SynchronisedLyrics {
+        lang: "eng".to_string(),
+        timestamp_format: TimestampFormat::MS,
+        content_type: SynchronisedLyricsType::Lyrics,
+        content: lines.iter().map(|line| (line.time_ms, line.text.clone())).collect(),
+    });
+
+    tag.write_to_path(song_path, Version::Id3v24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(time_ms: u32, text: &str) -> LyricLine {
+        LyricLine { time_ms, text: text.to_string() }
+    }
+
+    #[test]
+    fn timestamps_are_parsed_and_formatted() {
+        assert_eq!(parse_timestamp("01:23.45"), Some(83_450));
+        assert_eq!(parse_timestamp("01:23.456"), Some(83_456));
+        assert_eq!(parse_timestamp("01:23.4"), Some(83_400));
+        assert_eq!(parse_timestamp(" 00:05 "), Some(5_000));
+        assert_eq!(parse_timestamp("00:60.00"), None);
+        assert_eq!(parse_timestamp("00:12."), None);
+        assert_eq!(parse_timestamp("ar:Queen"), None);
+        assert_eq!(parse_timestamp("4294967295:00.00"), None);
+        assert_eq!(parse_timestamp("71582:59.99"), None);
+        assert_eq!(format_timestamp(83_456), "01:23.45");
+        assert_eq!(format_timestamp(0), "00:00.00");
+    }
+
+    #[test]
+    fn lrc_lines_are_parsed_with_their_offset() {
+        let contents = "[ar:Queen]\n[ti:Bohemian Rhapsody]\n[offset:+500]\n\
+            [00:12.00][01:15.30]Is this the real life?\n\
+            no timestamp\n\
+            [00:05.00] Is this just fantasy? \n";
+        assert_eq!(parse_lrc(contents), [
+            line(4_500, "Is this just fantasy?"),
+            line(11_500, "Is this the real life?"),
+            line(74_800, "Is this the real life?"),
+        ]);
+        assert_eq!(parse_lrc("[offset:-250]\n[00:01.00]Late"), [line(1_250, "Late")]);
+        assert_eq!(parse_lrc("[offset:2000]\n[00:01.00]Early"), [line(0, "Early")]);
+    }
+
+    #[test]
+    fn lrc_round_trips() {
+        let lines = vec![line(0, "Intro"), line(83_450, "Verse"), line(3_600_000, "")];
+        let contents = to_lrc(&lines);
+        assert_eq!(contents, "[00:00.00]Intro\n[01:23.45]Verse\n[60:00.00]\n");
+        assert_eq!(parse_lrc(&contents), lines);
+    }
+
+    #[test]
+    fn sylt_frames_in_milliseconds_are_read() {
+        let mut tag = Tag::new();
+        tag.add_synchronised_lyrics(SynchronisedLyrics {
+            lang: "eng".to_string(),
+            timestamp_format: TimestampFormat::MS,
+            content_type: SynchronisedLyricsType::Lyrics,
+            content: vec![(2_000, "Second\n".to_string()), (1_000, "First".to_string())],
+        });
+        assert_eq!(from_sylt(&tag), Some(vec![line(1_000, "First"), line(2_000, "Second")]));
+
+        let mut frames_tag = Tag::new();
+        frames_tag.add_synchronised_lyrics(SynchronisedLyrics {
+            lang: "eng".to_string(),
+            timestamp_format: TimestampFormat::MPEG,
+            content_type: SynchronisedLyricsType::Lyrics,
+            content: vec![(40, "Timed in frames".to_string())],
+        });
+        assert_eq!(from_sylt(&frames_tag), None);
+    }
+
+    #[test]
+    fn the_current_line_follows_the_position() {
+        let lines = [line(1_000, "One"), line(5_000, "Two"), line(9_000, "Three")];
+        assert_eq!(current_line(&lines, 0), None);
+        assert_eq!(current_line(&lines, 1_000), Some(0));
+        assert_eq!(current_line(&lines, 8_999), Some(1));
+        assert_eq!(current_line(&lines, 60_000), Some(2));
+        assert_eq!(current_line(&[], 60_000), None);
+    }
+}
@@ -0,0 +1,260 @@
+extern crate id3;
+use crate::model::music_miner::flac;
+use crate::model::music_miner::musicbrainz::{ALBUM_ID_DESCRIPTION, UFID_OWNER};
+use id3::frame::{Content, Timestamp};
+use id3::{ErrorKind, Frame, Tag, Version};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The keys of the tag map that `write_fields()` can write, in the order they are shown.
+pub const WRITABLE_FIELDS: [&str; 11] = [
+    "Title", "Artist", "Album", "Album Artist", "Year", "Genre",
+    "Track Number", "Track Total", "Disc Number", "Disc Total", "Composer",
+];
+
+/// The Vorbis comments written for each key in FLAC files, with the names MusicBrainz Picard
+/// uses. The value goes into the first comment; the others are alternative names of the same
+/// field, which are removed.
+const VORBIS_FIELDS: [(&str, &[&str]); 14] = [
+    ("Title", &["TITLE"]),
+    ("Artist", &["ARTIST"]),
+    ("Album", &["ALBUM"]),
+    ("Album Artist", &["ALBUMARTIST"]),
+    ("Year", &["DATE"]),
+    ("Release Date", &["DATE"]),
+    ("Genre", &["GENRE"]),
+    ("Track Number", &["TRACKNUMBER"]),
+    ("Track Total", &["TRACKTOTAL", "TOTALTRACKS"]),
+    ("Disc Number", &["DISCNUMBER"]),
+    ("Disc Total", &["DISCTOTAL", "TOTALDISCS"]),
+    ("Composer", &["COMPOSER"]),
+    (ALBUM_ID_DESCRIPTION, &["MUSICBRAINZ_ALBUMID"]),
+    ("MusicBrainz Recording Id", &["MUSICBRAINZ_TRACKID"]),
+];
+
+/// Replaces the UFID frame of MusicBrainz with one holding `recording_id`, or removes it if
+/// the ID is empty. UFID frames of other owners are kept.
+fn set_recording_id(tag: &mut Tag, recording_id: &str) {
+    let others: Vec<Frame> = tag.frames()
+        .filter(|frame| frame.id() == "UFID")
+        .filter(|frame| !matches!(frame.content(), Content::Unknown(data) if data.starts_with(UFID_OWNER.as_bytes())))
+        .cloned()
+        .collect();
+    tag.remove("UFID");
+    for frame in others {
+        tag.add_frame(frame);
+    }
+
+    if !recording_id.is_empty() {
+        let mut data = UFID_OWNER.as_bytes().to_vec();
+        data.push(0);
+        data.extend(recording_id.as_bytes());
+        tag.add_frame(Frame::with_content("UFID", Content::Unknown(data)));
+    }
+}
+
+/// Parses the value of a number field (track, disc, year).
+fn parse_number(value: &str) -> id3::Result<u32> {
+    value.trim().parse().map_err(|_| id3::Error::new(ErrorKind::InvalidInput, "Track, disc and year fields must be numbers"))
+}
+
+/// Parses a release date (`YYYY`, `YYYY-MM` or `YYYY-MM-DD`).
+fn parse_date(value: &str) -> id3::Result<Timestamp> {
+    value.parse().map_err(|_| id3::Error::new(ErrorKind::InvalidInput, "Release dates must look like YYYY-MM-DD"))
+}
+
+/// Writes fields of a FLAC file's Vorbis comments (see `write_fields()`). `Year` and
+/// `Release Date` both go into DATE, the release date winning when both are given.
+fn write_vorbis_fields(song_path: &Path, fields: &HashMap<String, String>) -> id3::Result<()> {
+    let mut comments: Vec<(&str, Option<String>)> = Vec::new();
+    for (key, value) in fields {
+        let value = value.trim();
+        if key == "Year" && fields.contains_key("Release Date") {
+            continue;
+        }
+        let names = VORBIS_FIELDS.iter()
+            .find(|(field, _)| field == key)
+            .map(|(_, names)| *names)
+            .ok_or_else(|| id3::Error::new(ErrorKind::InvalidInput, "Unknown tag field"))?;
+
+        if !value.is_empty() {
+            match key.as_str() {
+                "Year" | "Track Number" | "Track Total" | "Disc Number" | "Disc Total" => {
+                    parse_number(value)?;
+                }
+                "Release Date" => {
+                    parse_date(value)?;
+                }
+                _ => {}
+            }
+        }
+        comments.push((names[0], (!value.is_empty()).then(|| value.to_string())));
+        comments.extend(names[1..].iter().map(|name| (*name, None)));
+    }
+
+    Ok(flac::write_vorbis_comments(song_path, &comments)?)
+}
+
+/// # Write Fields function
+///
+/// Writes fields of a song's tag, using the keys of the tag map built by `assign_tag()`
+/// (`Title`, `Artist`, `Track Number`…, see `WRITABLE_FIELDS`), and saves it as ID3v2.4.
+/// `Release Date` (`YYYY`, `YYYY-MM` or `YYYY-MM-DD`) is written to TDRC and its year to
+/// TYER, `MusicBrainz Album Id` to a TXXX frame and `MusicBrainz Recording Id` to the UFID
+/// frame of MusicBrainz, as MusicBrainz Picard does. FLAC files get the Vorbis comments of
+/// `VORBIS_FIELDS` instead. An empty value removes the field; fields not in `fields` are left
+/// untouched. Other formats return an `UnsupportedFeature` error.
+///
+/// # Arguments
+/// * `song_path` - The path of the MP3 or FLAC file.
+/// * `fields` - The values to write, by tag map key.
+pub fn write_fields(song_path: &Path, fields: &HashMap<String, String>) -> id3::Result<()> {
+    let extension = song_path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    if extension == "flac" {
+        return write_vorbis_fields(song_path, fields);
+    }
+    if extension != "mp3" {
+        return Err(id3::Error::new(ErrorKind::UnsupportedFeature, "Tags can only be written to MP3 and FLAC files"));
+    }
+
+    let mut tag = match Tag::read_from_path(song_path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(e),
+    };
+
+    for (key, value) in fields {
+        let value = value.trim();
+        match key.as_str() {
+            "Release Date" if value.is_empty() => {
+                tag.remove_date_recorded();
+                continue;
+            }
+            "Release Date" => {
+                let date = parse_date(value)?;
+                tag.set_year(date.year);
+                tag.set_date_recorded(date);
+                continue;
+            }
+            ALBUM_ID_DESCRIPTION => {
+                tag.remove_extended_text(Some(ALBUM_ID_DESCRIPTION), None);
+                if !value.is_empty() {
+                    tag.add_extended_text(ALBUM_ID_DESCRIPTION, value);
+                }
+                continue;
+            }
+            "MusicBrainz Recording Id" => {
+                set_recording_id(&mut tag, value);
+                continue;
+            }
+            _ => {}
+        }
+
+        if value.is_empty() {
+            match key.as_str() {
+                "Title" => tag.remove_title(),
+                "Artist" => tag.remove_artist(),
+                "Album" => tag.remove_album(),
+                "Album Artist" => tag.remove_album_artist(),
+                "Year" => tag.remove_year(),
+                "Genre" => tag.remove_genre(),
+                "Track Number" => tag.remove_track(),
+                "Track Total" => tag.remove_total_tracks(),
+                "Disc Number" => tag.remove_disc(),
+                "Disc Total" => tag.remove_total_discs(),
+                "Composer" => {
+                    tag.remove("TCOM");
+                }
+                _ => return Err(id3::Error::new(ErrorKind::InvalidInput, "Unknown tag field")),
+            }
+            continue;
+        }
+
+        match key.as_str() {
+            "Title" => tag.set_title(value),
+            "Artist" => tag.set_artist(value),
+            "Album" => tag.set_album(value),
+            "Album Artist" => tag.set_album_artist(value),
+            "Year" => tag.set_year(parse_number(value)? as i32),
+            "Genre" => tag.set_genre(value),
+            "Track Number" => tag.set_track(parse_number(value)?),
+            "Track Total" => tag.set_total_tracks(parse_number(value)?),
+            "Disc Number" => tag.set_disc(parse_number(value)?),
+            "Disc Total" => tag.set_total_discs(parse_number(value)?),
+            "Composer" => tag.set_text("TCOM", value),
+            _ => return Err(id3::Error::new(ErrorKind::InvalidInput, "Unknown tag field")),
+        }
+    }
+
+    tag.write_to_path(song_path, Version::Id3v24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_support::TempFile;
+    use std::fs;
+
+    /// Writes a FLAC file made of a STREAMINFO block, a VORBIS_COMMENT block holding
+    /// `comments` and a few audio bytes.
+    fn write_flac(name: &str, comments: &[&str]) -> TempFile {
+        let mut vorbis_comment = 4u32.to_le_bytes().to_vec();
+        vorbis_comment.extend(b"test");
+        vorbis_comment.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            vorbis_comment.extend((comment.len() as u32).to_le_bytes());
+            vorbis_comment.extend(comment.as_bytes());
+        }
+
+        let mut contents = b"fLaC\x00\x00\x00\x22".to_vec();
+        contents.extend([0; 34]);
+        contents.push(0x84);
+        contents.extend(&(vorbis_comment.len() as u32).to_be_bytes()[1..]);
+        contents.extend(vorbis_comment);
+        contents.extend([0xFF, 0xF8, 0x69, 0x08]);
+
+        TempFile::new(name, contents)
+    }
+
+    #[test]
+    fn flac_files_get_vorbis_comments() {
+        let file = write_flac("fields.flac", &["TITLE=Intro", "TOTALTRACKS=9", "DATE=1975", "COMMENT=kept"]);
+        let fields = HashMap::from([
+            ("Title".to_string(), "Death on Two Legs".to_string()),
+            ("Track Number".to_string(), "1".to_string()),
+            ("Track Total".to_string(), "12".to_string()),
+            ("Year".to_string(), "1975".to_string()),
+            ("Release Date".to_string(), "1975-11-21".to_string()),
+            ("Composer".to_string(), String::new()),
+            (ALBUM_ID_DESCRIPTION.to_string(), "b3b7e934-445b-4c68-a097-730c6a6d47e6".to_string()),
+            ("MusicBrainz Recording Id".to_string(), "0c7d3ab6-5e8a-4bba-9b8d-0b2ee1c8e2f0".to_string()),
+        ]);
+
+        write_fields(file.path(), &fields).unwrap();
+        let mut comments = flac::read_flac_metadata(file.path()).unwrap().comments;
+
+        comments.sort();
+        let expected: Vec<(String, String)> = [
+            ("COMMENT", "kept"),
+            ("DATE", "1975-11-21"),
+            ("MUSICBRAINZ_ALBUMID", "b3b7e934-445b-4c68-a097-730c6a6d47e6"),
+            ("MUSICBRAINZ_TRACKID", "0c7d3ab6-5e8a-4bba-9b8d-0b2ee1c8e2f0"),
+            ("TITLE", "Death on Two Legs"),
+            ("TRACKNUMBER", "1"),
+            ("TRACKTOTAL", "12"),
+        ].iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        assert_eq!(comments, expected);
+    }
+
+    #[test]
+    fn invalid_values_leave_flac_files_untouched() {
+        let file = write_flac("invalid.flac", &["TITLE=Intro"]);
+        let before = fs::read(file.path()).unwrap();
+
+        let result = write_fields(file.path(), &HashMap::from([("Track Number".to_string(), "one".to_string())]));
+        let after = fs::read(file.path()).unwrap();
+
+        assert!(matches!(result.unwrap_err().kind, ErrorKind::InvalidInput));
+        assert_eq!(before, after);
+    }
+}
@@ -0,0 +1,11 @@
+//! # `playback` Module
+//!
+//! The `playback` module plays songs through an audio output.
+//!
+//! ## Submodules
+//!
+//! - `sink`: Audio outputs: the default sound device, and a null sink for headless runs.
+//! - `player`: The playback engine, which decodes a queue of songs on its own thread.
+
+pub mod sink;
+pub mod player;
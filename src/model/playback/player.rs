@@ -0,0 +1,707 @@
+use crate::model::music_miner::decoder::AudioDecoder;
+use crate::model::playback::sink::{self, AudioSink};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::f64::consts::FRAC_PI_2;
+use std::mem;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Going to the previous song restarts the current one instead when it has played for longer than this.
+const RESTART_THRESHOLD_MS: u64 = 3_000;
+
+/// The next song is opened this long before the crossfade starts, so the fade can begin in the
+/// middle of a packet of the current song.
+const CROSSFADE_LOOKAHEAD_MS: u64 = 1_000;
+
+/// Builds the audio output on the player thread (some outputs cannot be moved between threads).
+pub type SinkFactory = Box<dyn FnOnce() -> Result<Box<dyn AudioSink>, Box<dyn Error>> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// A snapshot of what the player is doing, published by the player thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub state: PlaybackState,
+    /// Index in the queue of the current song.
+    pub queue_index: Option<usize>,
+    pub path: Option<String>,
+    /// Position of the sample being heard, in milliseconds.
+    pub position_ms: u64,
+    pub duration_ms: Option<u64>,
+    /// The last error met, e.g. a song that could not be decoded.
+    pub error: Option<String>,
+}
+
+impl Default for PlayerStatus {
+    fn default() -> Self {
+        PlayerStatus {
+            state: PlaybackState::Stopped,
+            queue_index: None,
+            path: None,
+            position_ms: 0,
+            duration_ms: None,
+            error: None,
+        }
+    }
+}
+
+/// A song the player has moved away from, to be recorded in the listening history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayRecord {
+    pub path: String,
+    /// Fraction of the song sent to the audio output, between 0 and 1.
+    pub fraction: f64,
+}
+
+/// How consecutive songs of the queue are joined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackSettings {
+    /// Trims the encoder delay and padding of MP3 files (from their LAME tag), so albums meant
+    /// to be heard without pauses, like live recordings, play without gaps.
+    pub gapless: bool,
+    /// Length of the crossfade between songs, in milliseconds; `0` disables it.
+    pub crossfade_ms: u32,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        PlaybackSettings { gapless: true, crossfade_ms: 0 }
+    }
+}
+
+/// The next song, started while the end of the current one fades out.
+struct Crossfade {
+    decoder: AudioDecoder,
+    index: usize,
+    /// Samples decoded from the next song but not mixed yet.
+    pending: VecDeque<f32>,
+    /// Frame of the current song where the fade starts.
+    start_frame: u64,
+    length_frames: u64,
+}
+
+enum Command {
+    PlayQueue(Vec<String>, usize),
+    Settings(PlaybackSettings),
+    TogglePause,
+    Seek(u64),
+    Next,
+    Previous,
+    Stop,
+    Shutdown,
+}
+
+/// Mixes the start of the next song into a packet of the current one with an equal-power
+/// fade, so the loudness stays constant through the transition.
+///
+/// # Arguments
+/// * `samples` - The interleaved samples of the current song, mixed in place.
+/// * `channels` - The number of channels of both songs.
+/// * `first_frame` - The position of the packet in the current song, in frames.
+/// * `crossfade` - The next song, consumed as the fade progresses.
+fn mix_crossfade(samples: &mut [f32], channels: usize, first_frame: u64, crossfade: &mut Crossfade) {
+    for (offset, frame) in samples.chunks_exact_mut(channels).enumerate() {
+        let position = first_frame + offset as u64;
+        if position < crossfade.start_frame {
+            continue;
+        }
+
+        while crossfade.pending.len() < channels {
+            match crossfade.decoder.next_samples() {
+                Some(next) => crossfade.pending.extend(next),
+                None => break,
+            }
+        }
+
+        let progress = ((position - crossfade.start_frame) as f64 / crossfade.length_frames as f64).min(1.0);
+        let fade_out = (progress * FRAC_PI_2).cos() as f32;
+        let fade_in = (progress * FRAC_PI_2).sin() as f32;
+        for sample in frame.iter_mut() {
+            let next = crossfade.pending.pop_front().unwrap_or(0.0);
+            *sample = *sample * fade_out + next * fade_in;
+        }
+    }
+}
+
+/// The state owned by the player thread.
+struct Engine {
+    sink_factory: Option<SinkFactory>,
+    sink: Option<Box<dyn AudioSink>>,
+    queue: Vec<String>,
+    index: usize,
+    decoder: Option<AudioDecoder>,
+    settings: PlaybackSettings,
+    crossfade: Option<Crossfade>,
+    /// Index of a song that could not be crossfaded into (it could not be opened or has another
+    /// format), so it is not opened again on every packet.
+    crossfade_failed: Option<usize>,
+    /// Samples of the current song to play before decoding more of it: the part of the song
+    /// decoded during a crossfade that outlasted the previous song.
+    carry: Vec<f32>,
+    /// Frames of the current song sent to the sink so far.
+    listened_frames: u64,
+    state: PlaybackState,
+    error: Option<String>,
+    status: Arc<Mutex<PlayerStatus>>,
+    plays: Arc<Mutex<Vec<PlayRecord>>>,
+}
+
+impl Engine {
+    /// Returns the sink, creating it on first use.
+    fn sink(&mut self) -> Result<&mut Box<dyn AudioSink>, Box<dyn Error>> {
+        if self.sink.is_none() {
+            let factory = self.sink_factory.take().ok_or("The audio output could not be opened")?;
+            self.sink = Some(factory()?);
+        }
+        Ok(self.sink.as_mut().unwrap())
+    }
+
+    /// Starts playing the song at `index` in the queue. Songs that cannot be opened are
+    /// skipped; playback stops if none of the remaining songs can be played.
+    ///
+    /// When `interrupt` is set (the user changed song) the samples still queued in the sink are
+    /// dropped; otherwise the song follows them without a gap.
+    fn load(&mut self, mut index: usize, interrupt: bool) {
+        self.record_play();
+        self.crossfade = None;
+        self.crossfade_failed = None;
+        self.carry.clear();
+        if interrupt {
+            if let Some(sink) = self.sink.as_mut() {
+                sink.clear();
+            }
+        }
+
+        while index < self.queue.len() {
+            match AudioDecoder::open(Path::new(&self.queue[index]), self.settings.gapless) {
+                Ok(decoder) => {
+                    let format = (decoder.sample_rate, decoder.channels);
+                    if let Err(e) = self.sink().and_then(|sink| sink.configure(format.0, format.1)) {
+                        self.error = Some(format!("Could not open the audio output: {}", e));
+                        self.stop();
+                        return;
+                    }
+                    if self.state == PlaybackState::Paused {
+                        if let Some(sink) = self.sink.as_mut() {
+                            sink.resume();
+                        }
+                    }
+                    self.index = index;
+                    self.decoder = Some(decoder);
+                    self.state = PlaybackState::Playing;
+                    return;
+                }
+                Err(e) => {
+                    self.error = Some(format!("Could not play {}: {}", self.queue[index], e));
+                    index += 1;
+                }
+            }
+        }
+
+        self.finish();
+    }
+
+    fn stop(&mut self) {
+        self.finish();
+        if let Some(sink) = self.sink.as_mut() {
+            sink.clear();
+        }
+    }
+
+    /// Stops decoding, letting the sink play the samples it still holds.
+    fn finish(&mut self) {
+        self.record_play();
+        self.decoder = None;
+        self.crossfade = None;
+        self.carry.clear();
+        self.state = PlaybackState::Stopped;
+    }
+
+    /// Reports how much of the current song was played, if any of it was.
+    fn record_play(&mut self) {
+        let listened_frames = mem::take(&mut self.listened_frames);
+        let (Some(decoder), Some(path)) = (self.decoder.as_ref(), self.queue.get(self.index)) else {
+            return;
+        };
+        if listened_frames == 0 {
+            return;
+        }
+
+        let fraction = match decoder.total_frames {
+            Some(total_frames) if total_frames > 0 => (listened_frames as f64 / total_frames as f64).min(1.0),
+            _ => 1.0,
+        };
+        self.plays.lock().unwrap().push(PlayRecord { path: path.clone(), fraction });
+    }
+
+    /// Moves on at the end of the current song: to the song already fading in, or else to the
+    /// next song of the queue.
+    fn advance(&mut self) {
+        if let Some(crossfade) = self.crossfade.take() {
+            self.record_play();
+            self.carry = crossfade.pending.into();
+            self.index = crossfade.index;
+            self.decoder = Some(crossfade.decoder);
+        } else if self.index + 1 < self.queue.len() {
+            self.load(self.index + 1, false);
+        } else {
+            self.finish();
+        }
+    }
+
+    /// Opens the next song when the current one gets close to the crossfade. Songs whose length
+    /// is unknown, or followed by a song of another format, are not crossfaded.
+    fn prepare_crossfade(&mut self) {
+        if self.settings.crossfade_ms == 0 || self.crossfade.is_some() || self.index + 1 >= self.queue.len()
+            || self.crossfade_failed == Some(self.index + 1) {
+            return;
+        }
+        let Some(decoder) = self.decoder.as_ref() else {
+            return;
+        };
+        let Some(total_frames) = decoder.total_frames else {
+            return;
+        };
+
+        let rate = u64::from(decoder.sample_rate);
+        let length_frames = (u64::from(self.settings.crossfade_ms) * rate / 1000).min(total_frames);
+        let start_frame = total_frames - length_frames;
+        if length_frames == 0 || decoder.position_frames() + CROSSFADE_LOOKAHEAD_MS * rate / 1000 < start_frame {
+            return;
+        }
+
+        // A song that cannot be opened is opened again by `load()`, which reports the error.
+        let format = (decoder.sample_rate, decoder.channels);
+        let index = self.index + 1;
+        match AudioDecoder::open(Path::new(&self.queue[index]), self.settings.gapless) {
+            Ok(next) if (next.sample_rate, next.channels) == format => {
+                self.crossfade = Some(Crossfade { decoder: next, index, pending: VecDeque::new(), start_frame, length_frames });
+            }
+            _ => self.crossfade_failed = Some(index),
+        }
+    }
+
+    /// Position of the sample being heard: the decoder position minus what is still buffered.
+    fn position_ms(&self) -> u64 {
+        match (&self.decoder, &self.sink) {
+            (Some(decoder), Some(sink)) => {
+                let queued_frames = sink.queued_frames() + self.carry.len() / decoder.channels.max(1);
+                let queued_ms = queued_frames as u64 * 1000 / u64::from(decoder.sample_rate);
+                decoder.position_ms().saturating_sub(queued_ms)
+            }
+            (Some(decoder), None) => decoder.position_ms(),
+            _ => 0,
+        }
+    }
+
+    /// Handles a command, returning `false` when the thread must exit.
+    fn handle(&mut self, command: Command) -> bool {
+        match command {
+            Command::PlayQueue(queue, start) => {
+                self.queue = queue;
+                self.error = None;
+                self.load(start, true);
+            }
+            Command::Settings(settings) => self.settings = settings,
+            Command::TogglePause => match self.state {
+                PlaybackState::Playing => {
+                    self.state = PlaybackState::Paused;
+                    if let Some(sink) = self.sink.as_mut() {
+                        sink.pause();
+                    }
+                }
+                PlaybackState::Paused => {
+                    self.state = PlaybackState::Playing;
+                    if let Some(sink) = self.sink.as_mut() {
+                        sink.resume();
+                    }
+                }
+                PlaybackState::Stopped => {
+                    if !self.queue.is_empty() {
+                        self.load(self.index, true);
+                    }
+                }
+            },
+            Command::Seek(position_ms) => {
+                if let Some(decoder) = self.decoder.as_mut() {
+                    if let Err(e) = decoder.seek(position_ms) {
+                        self.error = Some(format!("Could not seek: {}", e));
+                    } else {
+                        self.crossfade = None;
+                        self.carry.clear();
+                        if let Some(sink) = self.sink.as_mut() {
+                            sink.clear();
+                        }
+                    }
+                }
+            }
+            Command::Next => {
+                if self.index + 1 < self.queue.len() {
+                    self.load(self.index + 1, true);
+                } else {
+                    self.stop();
+                }
+            }
+            Command::Previous => {
+                if self.decoder.is_some() && (self.position_ms() > RESTART_THRESHOLD_MS || self.index == 0) {
+                    self.load(self.index, true);
+                } else if self.index > 0 {
+                    self.load(self.index - 1, true);
+                }
+            }
+            Command::Stop => self.stop(),
+            Command::Shutdown => return false,
+        }
+        true
+    }
+
+    /// Decodes the next packet of the current song and sends it to the sink, mixing in the next
+    /// song during a crossfade, and moving on to the next song at the end of the current one.
+    fn play_packet(&mut self) {
+        if !self.carry.is_empty() {
+            let carry = mem::take(&mut self.carry);
+            if let Some(decoder) = self.decoder.as_ref() {
+                self.listened_frames += (carry.len() / decoder.channels.max(1)) as u64;
+            }
+            self.write_to_sink(&carry);
+            return;
+        }
+
+        self.prepare_crossfade();
+        let Some(decoder) = self.decoder.as_mut() else {
+            self.advance();
+            return;
+        };
+        match decoder.next_samples() {
+            Some(mut samples) => {
+                let channels = decoder.channels.max(1);
+                self.listened_frames += (samples.len() / channels) as u64;
+                if let Some(crossfade) = self.crossfade.as_mut() {
+                    let first_frame = decoder.position_frames() - (samples.len() / channels) as u64;
+                    mix_crossfade(&mut samples, channels, first_frame, crossfade);
+                }
+                self.write_to_sink(&samples);
+            }
+            None => self.advance(),
+        }
+    }
+
+    /// Sends samples to the sink, stopping playback with the error if the sink fails.
+    fn write_to_sink(&mut self, samples: &[f32]) {
+        if let Some(sink) = self.sink.as_mut() {
+            if let Err(e) = sink.write(samples) {
+                self.error = Some(format!("The audio output stopped: {}", e));
+                self.stop();
+            }
+        }
+    }
+
+    fn publish(&self) {
+        let playing = self.decoder.is_some();
+        let mut status = self.status.lock().unwrap();
+        *status = PlayerStatus {
+            state: self.state,
+            queue_index: if playing { Some(self.index) } else { None },
+            path: if playing { self.queue.get(self.index).cloned() } else { None },
+            position_ms: self.position_ms(),
+            duration_ms: self.decoder.as_ref().and_then(|decoder| decoder.duration_ms),
+            error: self.error.clone(),
+        };
+    }
+
+    /// Runs the engine until the `Shutdown` command is received or the `Player` is dropped.
+    /// While playing, commands are polled between packets; otherwise the thread waits for one.
+    fn run(mut self, commands: Receiver<Command>) {
+        loop {
+            let command = if self.state == PlaybackState::Playing {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            };
+
+            match command {
+                Some(command) => {
+                    if !self.handle(command) {
+                        return;
+                    }
+                }
+                None => self.play_packet(),
+            }
+            self.publish();
+        }
+    }
+}
+
+/// Plays a queue of songs on a background thread. Every method returns immediately; the
+/// effect can be observed through `status()`.
+pub struct Player {
+    commands: Sender<Command>,
+    status: Arc<Mutex<PlayerStatus>>,
+    plays: Arc<Mutex<Vec<PlayRecord>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Player {
+    /// Starts a player whose audio output is built by `sink_factory` when the first song is played.
+    pub fn new(sink_factory: SinkFactory, settings: PlaybackSettings) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(PlayerStatus::default()));
+
+        // The engine is built on its own thread, since the sink it creates may not be `Send`.
+        let plays = Arc::new(Mutex::new(Vec::new()));
+
+        let engine_status = Arc::clone(&status);
+        let engine_plays = Arc::clone(&plays);
+        let thread = thread::spawn(move || {
+            let engine = Engine {
+                sink_factory: Some(sink_factory),
+                sink: None,
+                queue: Vec::new(),
+                index: 0,
+                decoder: None,
+                settings,
+                crossfade: None,
+                crossfade_failed: None,
+                carry: Vec::new(),
+                listened_frames: 0,
+                state: PlaybackState::Stopped,
+                error: None,
+                status: engine_status,
+                plays: engine_plays,
+            };
+            engine.run(receiver);
+        });
+
+        Player { commands, status, plays, thread: Some(thread) }
+    }
+
+    /// Starts a player using `sink::default_sink()`.
+    pub fn with_default_sink(settings: PlaybackSettings) -> Self {
+        Player::new(Box::new(sink::default_sink), settings)
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            log::error!("The player thread has stopped");
+        }
+    }
+
+    /// Replaces the queue with `paths` and starts playing the song at `start`.
+    pub fn play_queue(&self, paths: Vec<String>, start: usize) {
+        self.send(Command::PlayQueue(paths, start));
+    }
+
+    /// Changes how songs are joined. The gapless option applies from the next song opened.
+    pub fn set_settings(&self, settings: PlaybackSettings) {
+        self.send(Command::Settings(settings));
+    }
+
+    /// Pauses or resumes playback; when stopped, plays the current song of the queue again.
+    pub fn toggle_pause(&self) {
+        self.send(Command::TogglePause);
+    }
+
+    pub fn seek(&self, position_ms: u64) {
+        self.send(Command::Seek(position_ms));
+    }
+
+    pub fn next(&self) {
+        self.send(Command::Next);
+    }
+
+    /// Goes to the previous song, or restarts the current one if it has played for more than three seconds.
+    pub fn previous(&self) {
+        self.send(Command::Previous);
+    }
+
+    pub fn stop(&self) {
+        self.send(Command::Stop);
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Returns the songs the player has moved away from since the last call: songs played to
+    /// the end, skipped or stopped.
+    pub fn take_plays(&self) -> Vec<PlayRecord> {
+        mem::take(&mut *self.plays.lock().unwrap())
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::playback::sink::NullSink;
+    use crate::model::test_support::TempFile;
+    use std::time::{Duration, Instant};
+
+    const SAMPLE_RATE: u32 = 44_100;
+    const CHANNELS: usize = 2;
+
+    fn crc8(data: &[u8]) -> u8 {
+        data.iter().fold(0u8, |crc, byte| {
+            (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+        })
+    }
+
+    fn crc16(data: &[u8]) -> u16 {
+        data.iter().fold(0u16, |crc, byte| {
+            (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 })
+        })
+    }
+
+    /// Writes interleaved 16-bit stereo samples as a FLAC file of uncompressed (verbatim) frames.
+    fn write_flac(name: &str, samples: &[i16]) -> TempFile {
+        const BLOCK: usize = 4096;
+        let frames = samples.len() / CHANNELS;
+
+        let mut data = b"fLaC".to_vec();
+        data.extend([0x80, 0, 0, 34]);
+        data.extend((BLOCK as u16).to_be_bytes());
+        data.extend((BLOCK as u16).to_be_bytes());
+        data.extend([0; 6]);
+        let info = (u64::from(SAMPLE_RATE) << 44) | (((CHANNELS as u64) - 1) << 41) | (15 << 36) | frames as u64;
+        data.extend(info.to_be_bytes());
+        data.extend([0; 16]);
+
+        for (number, block) in samples.chunks(BLOCK * CHANNELS).enumerate() {
+            let block_frames = block.len() / CHANNELS;
+            // Frame numbers below 128 fit in one byte of the UTF-8-like coding.
+            assert!(number < 128);
+            let mut frame = vec![0xFF, 0xF8, 0x79, 0x18, number as u8];
+            frame.extend(((block_frames - 1) as u16).to_be_bytes());
+            frame.push(crc8(&frame));
+            for channel in 0..CHANNELS {
+                frame.push(0x02);
+                for sample in block.iter().skip(channel).step_by(CHANNELS) {
+                    frame.extend(sample.to_be_bytes());
+                }
+            }
+            frame.extend(crc16(&frame).to_be_bytes());
+            data.extend(frame);
+        }
+
+        TempFile::new(&format!("{}.flac", name), data)
+    }
+
+    fn sine(frames: usize) -> Vec<i16> {
+        (0..frames)
+            .flat_map(|frame| {
+                let value = ((frame as f64 * 440.0 * 2.0 * std::f64::consts::PI / f64::from(SAMPLE_RATE)).sin() * 16_000.0) as i16;
+                [value; CHANNELS]
+            })
+            .collect()
+    }
+
+    /// Plays the songs through a capturing null sink and returns every sample written to it.
+    fn render(files: &[TempFile], settings: PlaybackSettings) -> Vec<f32> {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink_samples = Arc::clone(&captured);
+        let player = Player::new(Box::new(move || Ok(Box::new(NullSink::capturing(sink_samples)) as Box<dyn AudioSink>)), settings);
+        player.play_queue(files.iter().map(|file| file.path().to_string_lossy().into_owned()).collect(), 0);
+
+        let started = Instant::now();
+        while captured.lock().unwrap().is_empty() || player.status().state != PlaybackState::Stopped {
+            assert!(started.elapsed() < Duration::from_secs(10), "playback did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(player);
+
+        Arc::try_unwrap(captured).unwrap().into_inner().unwrap()
+    }
+
+    #[test]
+    fn consecutive_songs_join_without_gaps() {
+        let signal = sine(30_000);
+        let first = write_flac("gapless-1", &signal[..12_345 * CHANNELS]);
+        let second = write_flac("gapless-2", &signal[12_345 * CHANNELS..]);
+
+        let output = render(&[first, second], PlaybackSettings { gapless: true, crossfade_ms: 0 });
+
+        let expected: Vec<f32> = signal.iter().map(|sample| f32::from(*sample) / 32_768.0).collect();
+        assert_eq!(output.len(), expected.len());
+        for (index, (played, original)) in output.iter().zip(&expected).enumerate() {
+            assert!((played - original).abs() < 1e-6, "sample {} differs: {} != {}", index, played, original);
+        }
+    }
+
+    #[test]
+    fn crossfade_overlaps_the_end_and_the_start_of_songs() {
+        let first = write_flac("crossfade-1", &vec![16_384; 20_000 * CHANNELS]);
+        let second = write_flac("crossfade-2", &vec![8_192; 20_000 * CHANNELS]);
+
+        let output = render(&[first, second], PlaybackSettings { gapless: true, crossfade_ms: 100 });
+
+        // The last 100 ms (4410 frames) of the first song are mixed with the start of the second.
+        assert_eq!(output.len(), (40_000 - 4_410) * CHANNELS);
+        assert_eq!(output[0], 0.5);
+        assert_eq!(output[output.len() - 1], 0.25);
+        for pair in output.windows(2) {
+            assert!((pair[0] - pair[1]).abs() < 0.001, "discontinuity: {} then {}", pair[0], pair[1]);
+        }
+    }
+
+    /// A sink whose device is gone: every write fails.
+    struct FailingSink;
+
+    impl AudioSink for FailingSink {
+        fn configure(&mut self, _sample_rate: u32, _channels: usize) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn write(&mut self, _samples: &[f32]) -> Result<(), Box<dyn Error>> {
+            Err("The device was unplugged".into())
+        }
+
+        fn pause(&mut self) {}
+
+        fn resume(&mut self) {}
+
+        fn clear(&mut self) {}
+
+        fn queued_frames(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn sink_failures_stop_playback_with_an_error() {
+        let song = write_flac("unplugged", &sine(10_000));
+        let player = Player::new(Box::new(|| Ok(Box::new(FailingSink) as Box<dyn AudioSink>)), PlaybackSettings::default());
+        player.play_queue(vec![song.path().to_string_lossy().into_owned()], 0);
+
+        let started = Instant::now();
+        while player.status().error.is_none() {
+            assert!(started.elapsed() < Duration::from_secs(10), "the failure was not reported");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let status = player.status();
+        assert_eq!(status.error.as_deref(), Some("The audio output stopped: The device was unplugged"));
+        assert_eq!(status.state, PlaybackState::Stopped);
+    }
+}
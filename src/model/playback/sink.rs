@@ -0,0 +1,462 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Environment variable selecting the audio output; set it to `null` to play without a sound
+/// device, or to `file:<path>` to render the output to a file.
+pub const SINK_VARIABLE: &str = "MUSICMANAGER_AUDIO_SINK";
+
+/// Amount of audio, in milliseconds, buffered ahead of the sound device.
+const BUFFER_MS: usize = 250;
+
+/// An audio output that receives interleaved `f32` samples from the player.
+pub trait AudioSink {
+    /// Prepares the sink for samples of the given format. Called before the first song and
+    /// whenever the format changes between songs.
+    fn configure(&mut self, sample_rate: u32, channels: usize) -> Result<(), Box<dyn Error>>;
+
+    /// Queues samples for playback, blocking while the buffer of the sink is full. Fails if
+    /// the output stopped working, e.g. when the sound device was unplugged.
+    fn write(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>>;
+
+    /// Stops consuming samples, keeping the ones already queued.
+    fn pause(&mut self);
+
+    /// Resumes consuming samples after `pause()`.
+    fn resume(&mut self);
+
+    /// Drops the samples queued but not played yet, e.g. when seeking.
+    fn clear(&mut self);
+
+    /// Number of frames (samples per channel) queued but not played yet.
+    fn queued_frames(&self) -> usize;
+}
+
+/// A sink that discards every sample. In real-time mode it sleeps for the duration of the
+/// samples it receives, so playback advances as it would on a sound device.
+pub struct NullSink {
+    realtime: bool,
+    sample_rate: u32,
+    channels: usize,
+    /// Receives a copy of every sample written, when rendering a queue for inspection.
+    captured: Option<Arc<Mutex<Vec<f32>>>>,
+}
+
+impl NullSink {
+    pub fn new(realtime: bool) -> Self {
+        NullSink { realtime, sample_rate: 44_100, channels: 2, captured: None }
+    }
+
+    /// Creates a sink that renders as fast as possible and appends every sample it receives to
+    /// `captured`, so the output of the player can be checked (e.g. for gaps between songs).
+    #[cfg(test)]
+    pub fn capturing(captured: Arc<Mutex<Vec<f32>>>) -> Self {
+        NullSink { realtime: false, sample_rate: 44_100, channels: 2, captured: Some(captured) }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn configure(&mut self, sample_rate: u32, channels: usize) -> Result<(), Box<dyn Error>> {
+        self.sample_rate = sample_rate;
+        self.channels = channels.max(1);
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        if let Some(captured) = &self.captured {
+            captured.lock().unwrap().extend_from_slice(samples);
+        }
+        if self.realtime {
+            let frames = (samples.len() / self.channels) as u64;
+            thread::sleep(Duration::from_micros(frames * 1_000_000 / u64::from(self.sample_rate.max(1))));
+        }
+        Ok(())
+    }
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn clear(&mut self) {}
+
+    fn queued_frames(&self) -> usize {
+        0
+    }
+}
+
+/// A sink that renders the output as fast as possible to a file of raw little-endian `f32`
+/// interleaved samples, which can be inspected (e.g. for gaps between songs) or imported in an
+/// audio editor.
+pub struct FileSink {
+    writer: BufWriter<File>,
+}
+
+impl FileSink {
+    pub fn create(path: &str) -> Result<FileSink, Box<dyn Error>> {
+        Ok(FileSink { writer: BufWriter::new(File::create(path)?) })
+    }
+}
+
+impl AudioSink for FileSink {
+    fn configure(&mut self, _sample_rate: u32, _channels: usize) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn clear(&mut self) {}
+
+    fn queued_frames(&self) -> usize {
+        0
+    }
+}
+
+/// Converts interleaved samples from the format of the songs to the format the sound device
+/// was opened with, when the device does not support the former: channels are mixed down or
+/// copied, and the sample rate is changed by linear interpolation.
+struct FormatConverter {
+    input_rate: u32,
+    input_channels: usize,
+    output_rate: u32,
+    output_channels: usize,
+    /// Position of the next output frame, in input frames from `previous`.
+    position: f64,
+    /// Last input frame of the previous write (with the output channels), interpolated with
+    /// the first frame of the next one.
+    previous: Vec<f32>,
+}
+
+impl FormatConverter {
+    fn new(input_rate: u32, input_channels: usize, output_rate: u32, output_channels: usize) -> Self {
+        let output_channels = output_channels.max(1);
+        FormatConverter {
+            input_rate,
+            input_channels: input_channels.max(1),
+            output_rate,
+            output_channels,
+            position: 1.0,
+            previous: vec![0.0; output_channels],
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        (self.input_rate, self.input_channels) == (self.output_rate, self.output_channels)
+    }
+
+    /// Forgets the end of the previous write, e.g. when seeking.
+    fn reset(&mut self) {
+        self.position = 1.0;
+        self.previous.fill(0.0);
+    }
+
+    /// Maps a frame of the songs to the channels of the device. Mono devices get the average
+    /// of every channel; mono songs are played on the first two channels of the device; other
+    /// songs keep the channels the device has and drop the rest.
+    fn remap(&self, frame: &[f32], output: &mut Vec<f32>) {
+        match (self.input_channels, self.output_channels) {
+            (_, 1) => output.push(frame.iter().sum::<f32>() / frame.len() as f32),
+            (1, _) => output.extend((0..self.output_channels).map(|channel| if channel < 2 { frame[0] } else { 0.0 })),
+            _ => output.extend((0..self.output_channels).map(|channel| frame.get(channel).copied().unwrap_or(0.0))),
+        }
+    }
+
+    /// Converts interleaved samples of the songs to samples of the device.
+    fn convert(&mut self, samples: &[f32]) -> Vec<f32> {
+        let channels = self.output_channels;
+        let mut frames = self.previous.clone();
+        for frame in samples.chunks_exact(self.input_channels) {
+            self.remap(frame, &mut frames);
+        }
+        let count = frames.len() / channels - 1;
+        if self.input_rate == self.output_rate {
+            self.previous.copy_from_slice(&frames[count * channels..]);
+            return frames.split_off(channels);
+        }
+
+        let step = f64::from(self.input_rate) / f64::from(self.output_rate);
+        let mut output = Vec::with_capacity(((count as f64 / step) as usize + 1) * channels);
+        while self.position < count as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            for channel in 0..channels {
+                let current = frames[index * channels + channel];
+                let next = frames[(index + 1) * channels + channel];
+                output.push(current + (next - current) * fraction);
+            }
+            self.position += step;
+        }
+        self.position -= count as f64;
+        self.previous.copy_from_slice(&frames[count * channels..]);
+        output
+    }
+}
+
+/// Samples shared between the player thread and the callbacks of the sound device.
+struct SharedBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    /// Signalled by the callback whenever it consumes samples, and when the stream fails.
+    consumed: Condvar,
+    /// The error reported by the stream, after which it consumes no more samples.
+    error: Mutex<Option<String>>,
+}
+
+/// A sink playing through the default output device of the system.
+pub struct CpalSink {
+    device: cpal::Device,
+    stream: Option<cpal::Stream>,
+    buffer: Arc<SharedBuffer>,
+    /// Converts from the format of the songs to the format of the stream, once configured.
+    converter: Option<FormatConverter>,
+}
+
+/// Builds an output stream that converts the shared `f32` samples to the device sample type.
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<SharedBuffer>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let error_buffer = Arc::clone(&buffer);
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut samples = buffer.samples.lock().unwrap();
+            for output in data.iter_mut() {
+                *output = T::from_sample(samples.pop_front().unwrap_or(0.0));
+            }
+            buffer.consumed.notify_all();
+        },
+        move |e| {
+            log::error!("Audio output error: {}", e);
+            *error_buffer.error.lock().unwrap() = Some(e.to_string());
+            error_buffer.consumed.notify_all();
+        },
+        None,
+    )
+}
+
+/// # Choose Config function
+///
+/// Picks the output format of the device for songs of the given format: the format of the
+/// songs if the device supports it, else the same sample rate with the closest number of
+/// channels, else the default format of the device. Only sample types `build_stream()` can
+/// produce are considered.
+fn choose_config(device: &cpal::Device, sample_rate: u32, channels: usize) -> Result<cpal::SupportedStreamConfig, Box<dyn Error>> {
+    let rate = cpal::SampleRate(sample_rate);
+    let supported: Vec<cpal::SupportedStreamConfigRange> = device.supported_output_configs()?
+        .filter(|range| matches!(range.sample_format(), SampleFormat::F32 | SampleFormat::I16 | SampleFormat::U16))
+        .filter(|range| range.min_sample_rate() <= rate && rate <= range.max_sample_rate())
+        .collect();
+
+    let closest = supported.into_iter()
+        .min_by_key(|range| (usize::from(range.channels()).abs_diff(channels), range.sample_format() != SampleFormat::F32));
+    match closest {
+        Some(range) => Ok(range.with_sample_rate(rate)),
+        None => Ok(device.default_output_config()?),
+    }
+}
+
+impl CpalSink {
+    /// Opens the default output device of the default audio host.
+    pub fn open_default() -> Result<CpalSink, Box<dyn Error>> {
+        let device = cpal::default_host().default_output_device().ok_or("No audio output device found")?;
+        Ok(CpalSink {
+            device,
+            stream: None,
+            buffer: Arc::new(SharedBuffer {
+                samples: Mutex::new(VecDeque::new()),
+                consumed: Condvar::new(),
+                error: Mutex::new(None),
+            }),
+            converter: None,
+        })
+    }
+
+    /// Waits until every queued sample has been played, giving up after a second.
+    fn drain(&self) {
+        let mut samples = self.buffer.samples.lock().unwrap();
+        for _ in 0..10 {
+            if samples.is_empty() || self.stream.is_none() {
+                break;
+            }
+            samples = self.buffer.consumed.wait_timeout(samples, Duration::from_millis(100)).unwrap().0;
+        }
+    }
+}
+
+impl AudioSink for CpalSink {
+    /// Opens a stream for songs of the given format. Devices that do not support the format
+    /// get the closest one they support (see `choose_config()`), and the samples are converted.
+    fn configure(&mut self, sample_rate: u32, channels: usize) -> Result<(), Box<dyn Error>> {
+        let format = (sample_rate, channels.max(1));
+        if self.converter.as_ref().is_some_and(|converter| (converter.input_rate, converter.input_channels) == format) {
+            return Ok(());
+        }
+
+        self.drain();
+        self.stream = None;
+        self.converter = None;
+        self.buffer.samples.lock().unwrap().clear();
+        *self.buffer.error.lock().unwrap() = None;
+
+        let supported = choose_config(&self.device, sample_rate, channels)?;
+        let config = cpal::StreamConfig { buffer_size: cpal::BufferSize::Default, ..supported.config() };
+        let output = (config.sample_rate.0, usize::from(config.channels));
+        if output != format {
+            log::info!("The audio output does not support {} Hz with {} channels, using {} Hz with {} channels",
+                sample_rate, channels, output.0, output.1);
+        }
+        let buffer = Arc::clone(&self.buffer);
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&self.device, &config, buffer)?,
+            SampleFormat::I16 => build_stream::<i16>(&self.device, &config, buffer)?,
+            SampleFormat::U16 => build_stream::<u16>(&self.device, &config, buffer)?,
+            format => return Err(format!("Unsupported sample format {}", format).into()),
+        };
+        stream.play()?;
+
+        self.stream = Some(stream);
+        self.converter = Some(FormatConverter::new(format.0, format.1, output.0, output.1));
+        Ok(())
+    }
+
+    /// Queues the samples, converted to the format of the stream. If the stream reports an
+    /// error while the buffer is full, the stream is closed, so the next `configure()` opens a
+    /// new one, and the error is returned.
+    fn write(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        let Some(converter) = self.converter.as_mut() else {
+            return Ok(());
+        };
+        let capacity = converter.output_rate as usize * converter.output_channels * BUFFER_MS / 1000;
+        let converted;
+        let samples = if converter.is_identity() {
+            samples
+        } else {
+            converted = converter.convert(samples);
+            &converted
+        };
+
+        let mut queued = self.buffer.samples.lock().unwrap();
+        for chunk in samples.chunks(capacity.max(1)) {
+            while queued.len() + chunk.len() > capacity {
+                if let Some(error) = self.buffer.error.lock().unwrap().take() {
+                    queued.clear();
+                    drop(queued);
+                    self.stream = None;
+                    self.converter = None;
+                    return Err(error.into());
+                }
+                queued = self.buffer.consumed.wait_timeout(queued, Duration::from_millis(100)).unwrap().0;
+            }
+            queued.extend(chunk);
+        }
+        Ok(())
+    }
+
+    fn pause(&mut self) {
+        if let Some(stream) = &self.stream {
+            if let Err(e) = stream.pause() {
+                log::warn!("Could not pause the audio output: {}", e);
+            }
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(stream) = &self.stream {
+            if let Err(e) = stream.play() {
+                log::warn!("Could not resume the audio output: {}", e);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buffer.samples.lock().unwrap().clear();
+        if let Some(converter) = self.converter.as_mut() {
+            converter.reset();
+        }
+    }
+
+    /// Frames queued, counted in frames of the songs.
+    fn queued_frames(&self) -> usize {
+        let Some(converter) = &self.converter else {
+            return 0;
+        };
+        let frames = self.buffer.samples.lock().unwrap().len() / converter.output_channels;
+        (frames as u64 * u64::from(converter.input_rate) / u64::from(converter.output_rate.max(1))) as usize
+    }
+}
+
+/// # Default Sink function
+///
+/// Opens the audio output used by the application: the default sound device, a real-time
+/// `NullSink` when the `MUSICMANAGER_AUDIO_SINK` environment variable is set to `null`
+/// (for running without a sound device, e.g. in headless tests), or a `FileSink` when it is
+/// set to `file:<path>`.
+pub fn default_sink() -> Result<Box<dyn AudioSink>, Box<dyn Error>> {
+    if let Ok(sink) = env::var(SINK_VARIABLE) {
+        if sink.eq_ignore_ascii_case("null") {
+            return Ok(Box::new(NullSink::new(true)));
+        }
+        if let Some(path) = sink.strip_prefix("file:") {
+            return Ok(Box::new(FileSink::create(path)?));
+        }
+    }
+    Ok(Box::new(CpalSink::open_default()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_are_mixed_down_and_copied() {
+        let mut to_mono = FormatConverter::new(44_100, 2, 44_100, 1);
+        assert_eq!(to_mono.convert(&[0.5, 0.25, -1.0, 1.0]), [0.375, 0.0]);
+
+        let mut to_surround = FormatConverter::new(44_100, 1, 44_100, 4);
+        assert_eq!(to_surround.convert(&[0.5, -0.5]), [0.5, 0.5, 0.0, 0.0, -0.5, -0.5, 0.0, 0.0]);
+
+        let mut to_stereo = FormatConverter::new(44_100, 3, 44_100, 2);
+        assert_eq!(to_stereo.convert(&[0.1, 0.2, 0.3]), [0.1, 0.2]);
+    }
+
+    #[test]
+    fn the_rate_is_changed_by_interpolation() {
+        let mut doubler = FormatConverter::new(22_050, 1, 44_100, 1);
+        assert_eq!(doubler.convert(&[0.0, 1.0, 0.0]), [0.0, 0.5, 1.0, 0.5]);
+        // The last sample is interpolated with the first one of the next packet.
+        assert_eq!(doubler.convert(&[1.0]), [0.0, 0.5]);
+
+        let mut halver = FormatConverter::new(96_000, 1, 48_000, 1);
+        assert_eq!(halver.convert(&[0.0, 0.1, 0.2, 0.3, 0.4, 0.5]), [0.0, 0.2, 0.4]);
+    }
+
+    #[test]
+    fn packets_join_without_discontinuities() {
+        let samples: Vec<f32> = (0..1_000).map(|index| (index as f32 * 0.01).sin()).collect();
+        let mut whole = FormatConverter::new(44_100, 1, 48_000, 2);
+        let expected = whole.convert(&samples);
+
+        let mut packets = FormatConverter::new(44_100, 1, 48_000, 2);
+        let converted: Vec<f32> = samples.chunks(97).flat_map(|packet| packets.convert(packet)).collect();
+        assert_eq!(converted.len(), expected.len());
+        assert!(converted.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-5));
+        assert!((expected.len() / 2).abs_diff(1_000 * 48_000 / 44_100) <= 1);
+    }
+}
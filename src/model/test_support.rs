@@ -0,0 +1,49 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the temporary files, so tests running at the same time never share one.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// # TempFile struct
+///
+/// A file or directory written by a test in the temporary directory. It is removed when
+/// dropped, so it is cleaned up even when an assertion of the test fails.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Writes `contents` to a new temporary file whose name ends with `name`, e.g. `intro.flac`.
+    pub fn new(name: &str, contents: impl AsRef<[u8]>) -> TempFile {
+        let file = TempFile::unique(name);
+        fs::write(&file.path, contents).unwrap();
+        file
+    }
+
+    /// Creates a new empty temporary directory whose name ends with `name`. It is removed
+    /// with its contents.
+    pub fn dir(name: &str) -> TempFile {
+        let directory = TempFile::unique(name);
+        fs::create_dir_all(&directory.path).unwrap();
+        directory
+    }
+
+    fn unique(name: &str) -> TempFile {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        TempFile { path: env::temp_dir().join(format!("musicmanager-{}-{}-{}", process::id(), id, name)) }
+    }
+
+    /// The path of the file or directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = if self.path.is_dir() { fs::remove_dir_all(&self.path) } else { fs::remove_file(&self.path) };
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use serde::Serialize;
+use std::path::Path;
+use crate::config::{load_settings, save_settings, LibraryRoot};
+use crate::controller::library::{reset_library, Library, RootSummary, SongDetails};
+use crate::model::music_miner::audio_properties::format_duration;
+use crate::model::music_miner::musicbrainz::ALBUM_ID_DESCRIPTION;
+use crate::model::music_miner::tag_writer::WRITABLE_FIELDS;
+
+const USAGE: &str = "Usage: MusicManager --cli [--json] <command>
+
+Commands:
+  scan [directory]                  Rebuild the library from the enabled music directories, adding <directory> first
+  roots                             List the music directories with their number of songs
+  roots add <directory> [glob]...   Add a music directory, leaving out the paths matching the globs
+  roots remove <directory>          Remove a music directory and its songs from the library
+  roots enable|disable <directory>  Choose whether a music directory is scanned
+  search <query>                    List the songs matching a search query (same syntax as the search entry)
+  show <path>                       Show every field of a song
+  export                            List every song of the library
+  stats                             Show figures about the library
+  tag set <path> <field>=<value>... Write fields into the tag of an MP3 or FLAC file (an empty value removes the field)
+
+Options:
+  --json                            Print JSON instead of text";
+
+/// The fields `tag set` accepts besides `WRITABLE_FIELDS`.
+const EXTRA_TAG_FIELDS: [&str; 3] = ["Release Date", ALBUM_ID_DESCRIPTION, "MusicBrainz Recording Id"];
+
+/// # CliError enum
+///
+/// Why a command did not succeed, which decides the exit code.
+#[derive(Debug)]
+enum CliError {
+    /// The command or its arguments are not in the usage.
+    Usage,
+    /// The command ran and failed.
+    Failed(Box<dyn Error>),
+}
+
+impl CliError {
+    /// The exit code of the command: 2 if it was invalid, 1 if it failed.
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage => 2,
+            CliError::Failed(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage => write!(f, "Invalid command.\n\n{}", USAGE),
+            CliError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// # Command enum
+///
+/// A command of the usage with its arguments, as read from the command line.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Help,
+    Scan(Option<String>),
+    ListRoots,
+    /// `roots add`, `roots remove`, `roots enable` or `roots disable`; only `add` takes excludes.
+    ChangeRoot { action: String, directory: String, excludes: Vec<String> },
+    Search(String),
+    Show(String),
+    Export,
+    Stats,
+    TagSet { path: String, assignments: Vec<String> },
+}
+
+/// # Parse Args function
+///
+/// Reads the command-line arguments following `--cli`. `--json` may appear anywhere.
+///
+/// # Returns
+/// * `Ok((Command, bool))` - The command, and whether `--json` was given.
+/// * `Err(CliError::Usage)` - If the command or its arguments are not in the usage.
+fn parse_args(args: &[String]) -> Result<(Command, bool), CliError> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--json").cloned().collect();
+    let Some((command, args)) = args.split_first() else {
+        return Err(CliError::Usage);
+    };
+
+    let command = match (command.as_str(), args) {
+        ("help" | "--help", []) => Command::Help,
+        ("scan", []) => Command::Scan(None),
+        ("scan", [directory]) => Command::Scan(Some(directory.clone())),
+        ("roots", []) => Command::ListRoots,
+        ("roots", [action, directory, excludes @ ..]) if action == "add" =>
+            Command::ChangeRoot { action: action.clone(), directory: directory.clone(), excludes: excludes.to_vec() },
+        ("roots", [action, directory]) if ["remove", "enable", "disable"].contains(&action.as_str()) =>
+            Command::ChangeRoot { action: action.clone(), directory: directory.clone(), excludes: Vec::new() },
+        ("search", [_, ..]) => Command::Search(args.join(" ")),
+        ("show", [path]) => Command::Show(path.clone()),
+        ("export", []) => Command::Export,
+        ("stats", []) => Command::Stats,
+        ("tag", [action, path, assignments @ ..]) if action == "set" =>
+            Command::TagSet { path: path.clone(), assignments: assignments.to_vec() },
+        _ => return Err(CliError::Usage),
+    };
+    Ok((command, json))
+}
+
+/// Prints a value as pretty JSON.
+fn print_json<T: Serialize>(out: &mut dyn Write, value: &T) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+/// Prints songs as JSON, or as tab-separated lines under a header.
+fn print_songs(out: &mut dyn Write, songs: &[SongDetails], json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(out, &songs);
+    }
+    writeln!(out, "Path\tTitle\tArtist\tAlbum\tTrack\tYear\tGenre\tDuration")?;
+    for song in songs {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            song.path, song.title, song.artist, song.album, song.track_number, song.year, song.genre,
+            song.duration_ms.map(format_duration).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// Prints the fields of a song, one per line, leaving out the empty ones.
+fn print_song(out: &mut dyn Write, song: &SongDetails) -> io::Result<()> {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    let number = |value: Option<i32>| value.map(|value| value.to_string()).unwrap_or_default();
+    let fields = [
+        ("Path", song.path.clone()),
+        ("Root", optional(&song.root)),
+        ("Title", song.title.clone()),
+        ("Artist", song.artist.clone()),
+        ("Album", song.album.clone()),
+        ("Album Artist", song.album_artist.clone()),
+        ("Year", song.year.to_string()),
+        ("Genre", song.genre.clone()),
+        ("Track Number", song.track_number.to_string()),
+        ("Track Total", number(song.track_total)),
+        ("Disc Number", number(song.disc)),
+        ("Disc Total", number(song.disc_total)),
+        ("Composer", optional(&song.composer)),
+        ("Conductor", optional(&song.conductor)),
+        ("Remixers", optional(&song.remixers)),
+        ("BPM", number(song.bpm)),
+        ("Key", optional(&song.key)),
+        ("Comment", optional(&song.comment)),
+        ("Duration", song.duration_ms.map(format_duration).unwrap_or_default()),
+        ("Bitrate", song.bitrate.map(|bitrate| format!("{} kbps", bitrate)).unwrap_or_default()),
+        ("Sample Rate", song.sample_rate.map(|rate| format!("{} Hz", rate)).unwrap_or_default()),
+        ("Channel Mode", optional(&song.channel_mode)),
+        ("Encoder", optional(&song.encoder)),
+        ("Rating", song.rating.to_string()),
+        ("Play Count", song.play_count.to_string()),
+        ("Last Played", optional(&song.last_played)),
+        ("Labels", song.labels.join(", ")),
+        ("Album Labels", song.album_labels.join(", ")),
+        ("Cover", optional(&song.cover)),
+    ];
+    for (field, value) in fields {
+        if !value.is_empty() {
+            writeln!(out, "{}: {}", field, value)?;
+        }
+    }
+    if let Some(lyrics) = &song.lyrics {
+        writeln!(out, "\nLyrics:\n{}", lyrics)?;
+    }
+    Ok(())
+}
+
+/// Finds the tag map key of a field typed on the command line, ignoring case and accepting
+/// `_` or `-` instead of spaces (e.g. `track_number` for `Track Number`).
+fn tag_field(name: &str) -> Option<&'static str> {
+    let name = name.trim().replace(['_', '-'], " ");
+    WRITABLE_FIELDS.iter().chain(EXTRA_TAG_FIELDS.iter())
+        .find(|field| field.eq_ignore_ascii_case(&name))
+        .copied()
+}
+
+/// Runs `scan [directory]`.
+fn scan(out: &mut dyn Write, library: &Library, directory: Option<&str>, json: bool) -> Result<(), Box<dyn Error>> {
+    let roots = reset_library(library, directory)?;
+    let report = library.scan(&roots, |inserted, total| {
+        if !json && total > 0 && (inserted % 100 == 0 || inserted == total) {
+            eprintln!("{}/{} songs", inserted, total);
+        }
+    })?;
+    let scanned: Vec<&str> = roots.iter().filter(|root| root.enabled).map(|root| root.path.as_str()).collect();
+
+    if json {
+        #[derive(Serialize)]
+        struct Failure {
+            kind: &'static str,
+            path: Option<String>,
+            message: String,
+        }
+        #[derive(Serialize)]
+        struct ScanResult<'a> {
+            roots: Vec<&'a str>,
+            inserted: usize,
+            failures: Vec<Failure>,
+        }
+        let failures = report.failures.iter()
+            .map(|failure| Failure {
+                kind: failure.kind(),
+                path: failure.path().map(|path| path.display().to_string()),
+                message: failure.message(),
+            })
+            .collect();
+        return print_json(out, &ScanResult { roots: scanned, inserted: report.added, failures });
+    }
+    writeln!(out, "Scanned {}: {} song(s) added, {} failure(s).", scanned.join(", "), report.added, report.failures.len())?;
+    for failure in &report.failures {
+        writeln!(out, "{}", failure)?;
+    }
+    Ok(())
+}
+
+/// Runs `roots`: the roots of `Config.TOML`, with the number of songs the library has for each.
+fn list_roots(out: &mut dyn Write, library: &Library, json: bool) -> Result<(), Box<dyn Error>> {
+    let scanned = library.roots()?;
+    let roots: Vec<RootSummary> = load_settings()?.roots.into_iter()
+        .map(|root| RootSummary {
+            songs: scanned.iter().find(|summary| summary.path == root.path).map_or(0, |summary| summary.songs),
+            path: root.path,
+            enabled: root.enabled,
+            excludes: root.excludes,
+        })
+        .collect();
+    if json {
+        return print_json(out, &roots);
+    }
+    writeln!(out, "Path\tEnabled\tSongs\tExcludes")?;
+    for root in &roots {
+        writeln!(out, "{}\t{}\t{}\t{}", root.path, if root.enabled { "yes" } else { "no" }, root.songs, root.excludes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Runs `roots add`, `roots remove`, `roots enable` and `roots disable`. The roots added or
+/// enabled are scanned by the next `scan`; a removed root loses its songs at once.
+fn change_root(out: &mut dyn Write, library: &Library, action: &str, directory: &str, excludes: &[String], json: bool)
+    -> Result<(), Box<dyn Error>> {
+    let mut settings = load_settings()?;
+    let position = settings.roots.iter().position(|root| root.path == directory);
+    let mut removed_songs = 0;
+    match (action, position) {
+        ("add", None) => {
+            if !Path::new(directory).is_dir() {
+                return Err(format!("{} is not a directory", directory).into());
+            }
+            settings.roots.push(LibraryRoot { excludes: excludes.to_vec(), ..LibraryRoot::new(directory) });
+        }
+        ("add", Some(_)) => return Err(format!("{} is already a music directory", directory).into()),
+        (_, None) => return Err(format!("{} is not a music directory", directory).into()),
+        ("remove", Some(position)) => {
+            settings.roots.remove(position);
+            removed_songs = library.remove_root(directory)?;
+        }
+        (_, Some(position)) => settings.roots[position].enabled = action == "enable",
+    }
+    save_settings(&settings)?;
+
+    let status = match action {
+        "add" => "added",
+        "remove" => "removed",
+        "enable" => "enabled",
+        _ => "disabled",
+    };
+    if json {
+        return print_json(out, &HashMap::from([
+            ("path", directory.to_string()),
+            ("status", status.to_string()),
+            ("removed_songs", removed_songs.to_string()),
+        ]));
+    }
+    if action == "remove" {
+        writeln!(out, "Removed {} and its {} song(s)", directory, removed_songs)?;
+    } else {
+        writeln!(out, "{} {}; run `scan` to update the library", directory, status)?;
+    }
+    Ok(())
+}
+
+/// Runs `stats`.
+fn stats(out: &mut dyn Write, library: &Library, json: bool) -> Result<(), Box<dyn Error>> {
+    let stats = library.stats()?;
+    if json {
+        return print_json(out, &stats);
+    }
+    writeln!(out, "Songs: {}", stats.songs)?;
+    writeln!(out, "Performers: {}", stats.performers)?;
+    writeln!(out, "Albums: {}", stats.albums)?;
+    writeln!(out, "Total duration: {}", format_duration(stats.total_duration_ms))?;
+    writeln!(out, "Total plays: {}", stats.total_plays)?;
+    writeln!(out, "Rated songs: {}", stats.rated_songs)?;
+    writeln!(out, "Genres:")?;
+    for (genre, count) in &stats.genres {
+        writeln!(out, "  {}: {}", genre, count)?;
+    }
+    Ok(())
+}
+
+/// Runs `tag set <path> <field>=<value>...`.
+fn tag_set(out: &mut dyn Write, library: &Library, path: &str, assignments: &[String], json: bool) -> Result<(), Box<dyn Error>> {
+    if assignments.is_empty() {
+        return Err("tag set needs at least one <field>=<value>".into());
+    }
+    let mut fields = HashMap::new();
+    for assignment in assignments {
+        let (name, value) = assignment.split_once('=')
+            .ok_or_else(|| format!("`{}` is not of the form <field>=<value>", assignment))?;
+        let field = tag_field(name).ok_or_else(|| format!(
+            "Unknown field `{}`; the fields are: {}",
+            name, WRITABLE_FIELDS.iter().chain(EXTRA_TAG_FIELDS.iter()).copied().collect::<Vec<_>>().join(", ")
+        ))?;
+        fields.insert(field.to_string(), value.to_string());
+    }
+
+    library.set_tags(path, &fields)?;
+    if json {
+        return print_json(out, &HashMap::from([("path", path.to_string()), ("status", "tagged".to_string())]));
+    }
+    writeln!(out, "Tagged {}", path)?;
+    Ok(())
+}
+
+/// Prints every field of the song at `path`.
+fn show(out: &mut dyn Write, library: &Library, path: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let song = library.song_by_path(path)?
+        .ok_or_else(|| format!("No song of the library has the path {}", path))?;
+    if json {
+        return print_json(out, &song);
+    }
+    print_song(out, &song)?;
+    Ok(())
+}
+
+/// Runs a command on the library, printing its output to `out`.
+fn execute(out: &mut dyn Write, library: &Library, command: &Command, json: bool) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Help => Ok(writeln!(out, "{}", USAGE)?),
+        Command::Scan(directory) => scan(out, library, directory.as_deref(), json),
+        Command::ListRoots => list_roots(out, library, json),
+        Command::ChangeRoot { action, directory, excludes } => change_root(out, library, action, directory, excludes, json),
+        Command::Search(query) => print_songs(out, &library.search(query)?, json),
+        Command::Show(path) => show(out, library, path, json),
+        Command::Export => print_songs(out, &library.songs()?, json),
+        Command::Stats => stats(out, library, json),
+        Command::TagSet { path, assignments } => tag_set(out, library, path, assignments, json),
+    }
+}
+
+/// # Run function
+///
+/// Runs Music Manager without its window, for scripts and servers: `scan`, `roots`, `search`,
+/// `show`, `export`, `stats` and `tag set` use the same controller functions as the interface and
+/// print text or, with `--json`, JSON. Progress and diagnostics go to the standard error so
+/// the output can be piped.
+///
+/// # Arguments
+/// * `args` - The command-line arguments following `--cli`.
+///
+/// # Returns
+/// The exit code: 0 on success, 1 if the command failed, 2 if it was invalid.
+pub fn run(args: &[String]) -> i32 {
+    let result = parse_args(args).and_then(|(command, json)| {
+        if command == Command::Help {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        let library = Library::open().map_err(|e| CliError::Failed(e.into()))?;
+        execute(&mut io::stdout().lock(), &library, &command, json).map_err(CliError::Failed)
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Turns the words of a command line into arguments.
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    /// A library with a song of "Low" and a song of "Heroes".
+    fn sample_library() -> Library {
+        let library = Library::open_in_memory().unwrap();
+        for (path, title, album) in [("/music/Bowie/Low/01.mp3", "Speed of Life", "Low"),
+            ("/music/Bowie/Heroes/03.mp3", "Heroes", "Heroes")] {
+            let album_path = Path::new(path).parent().unwrap().to_string_lossy().into_owned();
+            library.add_song(HashMap::from([
+                ("Path".to_string(), path.to_string()),
+                ("AlbumPath".to_string(), album_path),
+                ("Title".to_string(), title.to_string()),
+                ("Artist".to_string(), "David Bowie".to_string()),
+                ("Album".to_string(), album.to_string()),
+                ("Year".to_string(), "1977".to_string()),
+                ("Genre".to_string(), "Rock".to_string()),
+                ("Track Number".to_string(), "1".to_string()),
+            ])).unwrap();
+        }
+        library
+    }
+
+    /// Runs a command line on the library, as `run()` does, and returns the exit code and
+    /// the output.
+    fn run_on(library: &Library, words: &[&str]) -> (i32, String) {
+        let mut out = Vec::new();
+        let result = parse_args(&args(words))
+            .and_then(|(command, json)| execute(&mut out, library, &command, json).map_err(CliError::Failed));
+        let code = result.map_or_else(|e| e.exit_code(), |()| 0);
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn commands_are_parsed_with_json_anywhere() {
+        assert_eq!(parse_args(&args(&["search", "t:low", "--json", "&&", "y:1977"])).unwrap(),
+            (Command::Search("t:low && y:1977".to_string()), true));
+        assert_eq!(parse_args(&args(&["scan", "/music"])).unwrap(), (Command::Scan(Some("/music".to_string())), false));
+        assert_eq!(parse_args(&args(&["roots", "add", "/music", "**/Samples/**"])).unwrap().0, Command::ChangeRoot {
+            action: "add".to_string(),
+            directory: "/music".to_string(),
+            excludes: vec!["**/Samples/**".to_string()],
+        });
+        assert_eq!(parse_args(&args(&["--json", "tag", "set", "/a.flac", "title=One"])).unwrap(), (Command::TagSet {
+            path: "/a.flac".to_string(),
+            assignments: vec!["title=One".to_string()],
+        }, true));
+    }
+
+    #[test]
+    fn commands_outside_the_usage_are_rejected() {
+        for words in [&[][..], &["--json"], &["scan", "/a", "/b"], &["search"], &["roots", "rename", "/music"],
+            &["roots", "remove", "/music", "**/*.wav"], &["tag", "get", "/a.mp3"], &["play"]] {
+            assert!(matches!(parse_args(&args(words)), Err(CliError::Usage)), "{:?} was accepted", words);
+        }
+    }
+
+    #[test]
+    fn invalid_commands_exit_with_2_and_failures_with_1() {
+        let library = sample_library();
+        assert_eq!(run_on(&library, &["frobnicate"]).0, 2);
+        assert_eq!(run_on(&library, &["show", "/music/missing.mp3"]).0, 1);
+        assert_eq!(run_on(&library, &["tag", "set", "/music/Bowie/Low/01.mp3", "mood=calm"]).0, 1);
+        assert_eq!(run_on(&library, &["stats"]).0, 0);
+    }
+
+    #[test]
+    fn search_prints_the_matching_songs() {
+        let library = sample_library();
+
+        let (code, output) = run_on(&library, &["--json", "search", "t:heroes"]);
+        assert_eq!(code, 0);
+        let songs: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let songs = songs.as_array().unwrap();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[0]["title"], "Heroes");
+        assert_eq!(songs[0]["path"], "/music/Bowie/Heroes/03.mp3");
+
+        let (_, output) = run_on(&library, &["search", "a:low"]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("Path\tTitle"));
+        assert!(lines[1].starts_with("/music/Bowie/Low/01.mp3\tSpeed of Life"));
+    }
+
+    #[test]
+    fn stats_are_printed_as_json() {
+        let (code, output) = run_on(&sample_library(), &["stats", "--json"]);
+        assert_eq!(code, 0);
+        let stats: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(stats["songs"], 2);
+        assert_eq!(stats["albums"], 2);
+        assert_eq!(stats["performers"], 1);
+    }
+}
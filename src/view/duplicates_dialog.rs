@@ -0,0 +1,174 @@
+use gtk::prelude::*;
+use gtk::{Box as GtkBox, ButtonsType, ComboBoxText, Dialog, Frame, Label, MessageType, Orientation,
+    ProgressBar, RadioButton, ResponseType, ScrolledWindow, Window};
+use crate::controller::controller::{find_duplicates, remove_duplicate, DuplicateAction};
+use crate::controller::library::Library;
+use crate::model::music_miner::audio_properties::format_duration;
+use crate::model::music_miner::duplicates::{is_lossless, preferred_copy, DuplicateCandidate, KeepPreference};
+use crate::view::scan_report::{append_problems, run_dialog, run_in_background, show_error_dialog};
+use std::sync::{Arc, Mutex};
+
+/// The preferences of the "Keep" combo box, in order, with their labels.
+const KEEP_PREFERENCES: [(KeepPreference, &str); 3] = [
+    (KeepPreference::HighestBitrate, "Highest bitrate"),
+    (KeepPreference::Lossless, "Lossless format"),
+    (KeepPreference::ShortestPath, "Shortest path"),
+];
+
+const RESPONSE_TRASH: u16 = 1;
+const RESPONSE_QUARANTINE: u16 = 2;
+
+/// Describes a copy in the dialog: its path, bitrate, format and duration.
+fn describe_copy(candidate: &DuplicateCandidate) -> String {
+    let format = if is_lossless(&candidate.path) { "FLAC" } else { "MP3" };
+    format!("{}\n{} kbps, {}, {}", candidate.path, candidate.bitrate, format, format_duration(candidate.duration_ms))
+}
+
+/// Selects the copy to keep of every group according to `preference`.
+fn select_preferred(groups: &[Vec<DuplicateCandidate>], buttons: &[Vec<RadioButton>], preference: KeepPreference) {
+    for (group, group_buttons) in groups.iter().zip(buttons) {
+        let copies: Vec<&DuplicateCandidate> = group.iter().collect();
+        if let Some(button) = group_buttons.get(preferred_copy(&copies, preference)) {
+            button.set_active(true);
+        }
+    }
+}
+
+/// # Show Duplicates Dialog function
+///
+/// Looks for copies of the same song in the library, hashing the audio of the candidates on
+/// a worker thread while showing the progress in `progress_bar`, then lists each group of
+/// duplicates with a radio button per copy. The copy to keep is preselected by bitrate, format
+/// or path and can be changed; the other copies are moved to the trash or to the quarantine
+/// folder.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialog.
+/// * `library` - The library holding the songs.
+/// * `progress_bar` - The progress bar of the main window.
+///
+/// # Returns
+/// `true` if any file was moved, so the song list needs reloading.
+pub fn show_duplicates_dialog(window: &Window, library: &Library, progress_bar: &ProgressBar) -> bool {
+    let groups = library.duplicate_candidates().map_err(|e| e.to_string()).and_then(|candidates| {
+        let progress = Arc::new(Mutex::new((0, 0)));
+        let worker_progress = Arc::clone(&progress);
+        let work = move || find_duplicates(candidates, |hashed, total| *worker_progress.lock().unwrap() = (hashed, total));
+        run_in_background(work, || {
+            let (hashed, total) = *progress.lock().unwrap();
+            progress_bar.set_fraction(if total == 0 { 0.0 } else { hashed as f64 / total as f64 });
+            progress_bar.set_text(Some(&format!("Comparing songs ({}/{})", hashed, total)));
+        }).ok_or_else(|| "The comparison stopped unexpectedly".to_string())
+    });
+    let groups = match groups {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("Failed to look for duplicates: {}", e);
+            show_error_dialog(window, &format!("Failed to look for duplicates: {}", e));
+            return false;
+        }
+    };
+    progress_bar.set_fraction(1.0);
+    progress_bar.set_text(Some("Duplicate search complete"));
+
+    if groups.is_empty() {
+        run_dialog(window, MessageType::Info, ButtonsType::Ok, "No duplicates found.");
+        return false;
+    }
+
+    let dialog = Dialog::with_buttons(
+        Some(&format!("Duplicates ({} groups)", groups.len())),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[
+            ("Cancel", ResponseType::Cancel),
+            ("Move Others to Quarantine", ResponseType::Other(RESPONSE_QUARANTINE)),
+            ("Move Others to Trash", ResponseType::Other(RESPONSE_TRASH)),
+        ],
+    );
+    dialog.set_default_size(700, 500);
+
+    let content = dialog.content_area();
+    content.set_spacing(5);
+
+    let keep_box = GtkBox::new(Orientation::Horizontal, 5);
+    keep_box.pack_start(&Label::new(Some("Keep:")), false, false, 5);
+    let keep_combo = ComboBoxText::new();
+    for (_, label) in KEEP_PREFERENCES {
+        keep_combo.append_text(label);
+    }
+    keep_combo.set_active(Some(0));
+    keep_box.pack_start(&keep_combo, false, false, 0);
+    content.pack_start(&keep_box, false, false, 5);
+
+    let groups_box = GtkBox::new(Orientation::Vertical, 5);
+    let buttons: Vec<Vec<RadioButton>> = groups.iter()
+        .map(|group| {
+            let title = if group[0].title.is_empty() { "Untitled" } else { group[0].title.as_str() };
+            let frame = Frame::new(Some(title));
+            let copies_box = GtkBox::new(Orientation::Vertical, 0);
+            let mut group_buttons: Vec<RadioButton> = Vec::new();
+            for candidate in group {
+                let button = match group_buttons.first() {
+                    Some(first) => RadioButton::with_label_from_widget(first, &describe_copy(candidate)),
+                    None => RadioButton::with_label(&describe_copy(candidate)),
+                };
+                copies_box.pack_start(&button, false, false, 2);
+                group_buttons.push(button);
+            }
+            frame.add(&copies_box);
+            groups_box.pack_start(&frame, false, false, 0);
+            group_buttons
+        })
+        .collect();
+    select_preferred(&groups, &buttons, KeepPreference::HighestBitrate);
+
+    {
+        let groups = groups.clone();
+        let buttons = buttons.clone();
+        keep_combo.connect_changed(move |combo| {
+            let preference = combo.active()
+                .and_then(|index| KEEP_PREFERENCES.get(index as usize))
+                .map(|(preference, _)| *preference)
+                .unwrap_or(KeepPreference::HighestBitrate);
+            select_preferred(&groups, &buttons, preference);
+        });
+    }
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&groups_box);
+    content.pack_start(&scrolled_window, true, true, 5);
+
+    dialog.show_all();
+    let response = dialog.run();
+    dialog.close();
+
+    let (action, place) = match response {
+        ResponseType::Other(RESPONSE_TRASH) => (DuplicateAction::Trash, "the trash"),
+        ResponseType::Other(RESPONSE_QUARANTINE) => (DuplicateAction::Quarantine, "the quarantine folder"),
+        _ => return false,
+    };
+
+    let unwanted: Vec<&str> = groups.iter().zip(&buttons)
+        .flat_map(|(group, group_buttons)| group.iter().zip(group_buttons))
+        .filter(|(_, button)| !button.is_active())
+        .map(|(candidate, _)| candidate.path.as_str())
+        .collect();
+
+    let question = format!("Move {} file(s) to {}?", unwanted.len(), place);
+    if run_dialog(window, MessageType::Question, ButtonsType::YesNo, &question) != ResponseType::Yes {
+        return false;
+    }
+
+    let mut problems = Vec::new();
+    for path in &unwanted {
+        if let Err(e) = remove_duplicate(library, path, action) {
+            problems.push(format!("Could not move {}: {}", path, e));
+        }
+    }
+
+    let mut summary = format!("Moved {} file(s) to {}.", unwanted.len() - problems.len(), place);
+    append_problems(&mut summary, &problems);
+    run_dialog(window, MessageType::Info, ButtonsType::Ok, &summary);
+    problems.len() < unwanted.len()
+}
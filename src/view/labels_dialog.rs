@@ -0,0 +1,86 @@
+use gtk::prelude::*;
+use gtk::{Box as GtkBox, CheckButton, Dialog, Entry, Label, Orientation, ResponseType, ScrolledWindow, Window};
+use crate::controller::controller::LabelTarget;
+use crate::controller::library::{parse_labels, Library};
+use crate::view::scan_report::show_error_dialog;
+
+/// # Show Labels Dialog function
+///
+/// Opens a modal dialog to edit the labels of a song or album: every existing label can be
+/// ticked or unticked, and new labels can be typed separated by commas.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialog.
+/// * `library` - The library the labels are saved to.
+/// * `title` - The title of the dialog, naming the song or album.
+/// * `target` - The song or album whose labels are edited.
+///
+/// # Returns
+/// `true` if the labels were saved, `false` if the dialog was cancelled or saving failed.
+pub fn show_labels_dialog(window: &Window, library: &Library, title: &str, target: LabelTarget) -> bool {
+    let (all_labels, current) = match library.all_labels().and_then(|all| Ok((all, library.labels(&target)?))) {
+        Ok(labels) => labels,
+        Err(e) => {
+            show_error_dialog(window, &format!("Could not read the labels: {}", e));
+            return false;
+        }
+    };
+
+    let dialog = Dialog::with_buttons(
+        Some(title),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_default_size(300, 350);
+
+    let content = dialog.content_area();
+    content.set_spacing(5);
+
+    let toggles_box = GtkBox::new(Orientation::Vertical, 0);
+    let toggles: Vec<CheckButton> = all_labels.iter()
+        .map(|label| {
+            let toggle = CheckButton::with_label(label);
+            toggle.set_active(current.contains(label));
+            toggles_box.pack_start(&toggle, false, false, 0);
+            toggle
+        })
+        .collect();
+    if toggles.is_empty() {
+        toggles_box.pack_start(&Label::new(Some("No labels yet.")), false, false, 5);
+    }
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&toggles_box);
+    content.pack_start(&scrolled_window, true, true, 5);
+
+    let new_labels_entry = Entry::new();
+    new_labels_entry.set_placeholder_text(Some("New labels, separated by commas"));
+    new_labels_entry.set_activates_default(true);
+    content.pack_start(&new_labels_entry, false, false, 5);
+    dialog.set_default_response(ResponseType::Accept);
+
+    dialog.show_all();
+    let response = dialog.run();
+
+    let mut saved = false;
+    if response == ResponseType::Accept {
+        let mut labels: Vec<String> = all_labels.iter().zip(&toggles)
+            .filter(|(_, toggle)| toggle.is_active())
+            .map(|(label, _)| label.clone())
+            .collect();
+        for label in parse_labels(&new_labels_entry.text()) {
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+
+        match library.save_labels(&target, &labels) {
+            Ok(()) => saved = true,
+            Err(e) => show_error_dialog(window, &format!("Could not save the labels: {}", e)),
+        }
+    }
+
+    dialog.close();
+    saved
+}
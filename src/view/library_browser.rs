@@ -0,0 +1,182 @@
+use gtk::prelude::*;
+use gtk::{CellRendererText, ScrolledWindow, TreeIter, TreeStore, TreeView, TreeViewColumn};
+use crate::controller::library::Library;
+use crate::controller::controller::{get_performer_summaries,
+    get_performer_album_summaries, get_performer_album_songs};
+use std::rc::Rc;
+
+/// Column holding the text shown for a node.
+const COLUMN_NAME: u32 = 0;
+/// Column holding the song count shown next to performers and albums.
+const COLUMN_COUNT: u32 = 1;
+/// Column holding the kind of node (`NODE_PERFORMER`, `NODE_ALBUM`, ...).
+const COLUMN_KIND: u32 = 2;
+/// Column holding the `id_performer` of the node, or of its performer for albums and tracks.
+const COLUMN_PERFORMER_ID: u32 = 3;
+/// Column holding the `id_album` of album and track nodes.
+const COLUMN_ALBUM_ID: u32 = 4;
+/// Column holding the path of track nodes.
+const COLUMN_PATH: u32 = 5;
+
+const NODE_PERFORMER: i32 = 0;
+const NODE_ALBUM: i32 = 1;
+const NODE_TRACK: i32 = 2;
+/// Kind of the dummy child added under unexpanded nodes so GTK shows an expander.
+const NODE_PLACEHOLDER: i32 = 3;
+
+/// A `TreeStore`-based pane that groups the library as performer → album → track.
+///
+/// Only performers are loaded up front; the albums of a performer and the tracks of an
+/// album are fetched from the database the first time their node is expanded, so the pane
+/// stays responsive on large libraries.
+pub struct LibraryBrowser {
+    pub widget: ScrolledWindow,
+    tree_view: TreeView,
+    tree_store: TreeStore,
+    library: Rc<Library>,
+}
+
+impl LibraryBrowser {
+    /// Builds the browser pane. Call `reload()` to fill it from the library.
+    pub fn new(library: Rc<Library>) -> Self {
+        let tree_store = TreeStore::new(&[
+            glib::Type::STRING, glib::Type::STRING, glib::Type::I32,
+            glib::Type::I64, glib::Type::I64, glib::Type::STRING,
+        ]);
+        let tree_view = TreeView::with_model(&tree_store);
+
+        let renderer = CellRendererText::new();
+        let col_name = TreeViewColumn::new();
+        col_name.set_title("Library");
+        col_name.set_expand(true);
+        col_name.pack_start(&renderer, true);
+        col_name.add_attribute(&renderer, "text", COLUMN_NAME as i32);
+
+        let count_renderer = CellRendererText::new();
+        let col_count = TreeViewColumn::new();
+        col_count.set_title("Songs");
+        col_count.pack_start(&count_renderer, false);
+        col_count.add_attribute(&count_renderer, "text", COLUMN_COUNT as i32);
+
+        tree_view.append_column(&col_name);
+        tree_view.append_column(&col_count);
+
+        {
+            let tree_store = tree_store.clone();
+            let library = Rc::clone(&library);
+            tree_view.connect_test_expand_row(move |_, iter, _| {
+                load_children(&tree_store, &library, iter);
+                Inhibit(false)
+            });
+        }
+
+        let widget = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        widget.set_size_request(250, -1);
+        widget.add(&tree_view);
+
+        LibraryBrowser { widget, tree_view, tree_store, library }
+    }
+
+    /// Clears the browser and loads the performers from the library.
+    ///
+    /// # Errors
+    /// Prints an error to the console if performers cannot be retrieved.
+    pub fn reload(&self) {
+        self.tree_store.clear();
+
+        match get_performer_summaries(self.library.connection()) {
+            Ok(performers) => {
+                for performer in performers {
+                    let iter = self.tree_store.insert_with_values(None, None, &[
+                        (COLUMN_NAME, &performer.name),
+                        (COLUMN_COUNT, &performer.song_count.to_string()),
+                        (COLUMN_KIND, &NODE_PERFORMER),
+                        (COLUMN_PERFORMER_ID, &performer.id),
+                        (COLUMN_ALBUM_ID, &0i64),
+                    ]);
+                    append_placeholder(&self.tree_store, &iter);
+                }
+            },
+            Err(err) => {
+                eprintln!("Failed to retrieve performers from the database: {}", err);
+            }
+        }
+    }
+
+    /// Calls `callback` with the path of a track whenever a track node is selected.
+    pub fn connect_song_selected<F: Fn(&str) + 'static>(&self, callback: F) {
+        self.tree_view.connect_cursor_changed(move |tree_view| {
+            if let Some((model, iter)) = tree_view.selection().selected() {
+                let kind: i32 = model.value(&iter, COLUMN_KIND as i32).get().unwrap_or(NODE_PLACEHOLDER);
+                if kind == NODE_TRACK {
+                    let path: String = model.value(&iter, COLUMN_PATH as i32).get().unwrap_or_default();
+                    callback(&path);
+                }
+            }
+        });
+    }
+}
+
+/// Adds a dummy child under `parent` so the node can be expanded before its children are loaded.
+fn append_placeholder(tree_store: &TreeStore, parent: &TreeIter) {
+    tree_store.insert_with_values(Some(parent), None, &[
+        (COLUMN_NAME, &"Loading…"),
+        (COLUMN_KIND, &NODE_PLACEHOLDER),
+    ]);
+}
+
+/// Replaces the placeholder under a performer or album node with its real children.
+/// Nodes whose children were already loaded are left untouched.
+fn load_children(tree_store: &TreeStore, library: &Library, parent: &TreeIter) {
+    let placeholder = match tree_store.iter_children(Some(parent)) {
+        Some(child) => child,
+        None => return,
+    };
+    let child_kind: i32 = tree_store.value(&placeholder, COLUMN_KIND as i32).get().unwrap_or(NODE_TRACK);
+    if child_kind != NODE_PLACEHOLDER {
+        return;
+    }
+    tree_store.remove(&placeholder);
+    let connection = library.connection();
+
+    let kind: i32 = tree_store.value(parent, COLUMN_KIND as i32).get().unwrap_or(NODE_PLACEHOLDER);
+    let performer_id: i64 = tree_store.value(parent, COLUMN_PERFORMER_ID as i32).get().unwrap_or(0);
+
+    if kind == NODE_PERFORMER {
+        match get_performer_album_summaries(connection, performer_id) {
+            Ok(albums) => {
+                for album in albums {
+                    let iter = tree_store.insert_with_values(Some(parent), None, &[
+                        (COLUMN_NAME, &format!("{} ({})", album.name, album.year)),
+                        (COLUMN_COUNT, &album.song_count.to_string()),
+                        (COLUMN_KIND, &NODE_ALBUM),
+                        (COLUMN_PERFORMER_ID, &performer_id),
+                        (COLUMN_ALBUM_ID, &album.id),
+                    ]);
+                    append_placeholder(tree_store, &iter);
+                }
+            },
+            Err(err) => {
+                eprintln!("Failed to retrieve albums from the database: {}", err);
+            }
+        }
+    } else if kind == NODE_ALBUM {
+        let album_id: i64 = tree_store.value(parent, COLUMN_ALBUM_ID as i32).get().unwrap_or(0);
+        match get_performer_album_songs(connection, performer_id, album_id) {
+            Ok(songs) => {
+                for song in songs {
+                    tree_store.insert_with_values(Some(parent), None, &[
+                        (COLUMN_NAME, &format!("{:02}. {}", song.track_number, song.title)),
+                        (COLUMN_KIND, &NODE_TRACK),
+                        (COLUMN_PERFORMER_ID, &performer_id),
+                        (COLUMN_ALBUM_ID, &album_id),
+                        (COLUMN_PATH, &song.path),
+                    ]);
+                }
+            },
+            Err(err) => {
+                eprintln!("Failed to retrieve songs from the database: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use gtk::prelude::*;
+use gtk::{ButtonsType, MessageType, ProgressBar, ResponseType, Window};
+use crate::controller::controller::analyse_loudness;
+use crate::controller::library::Library;
+use crate::view::scan_report::{append_problems, run_dialog, run_in_background, show_error_dialog};
+
+/// Analyses and tags the songs of an album on a worker thread, handling the events of the
+/// window until it is done, then stores the gains in the library.
+///
+/// # Returns
+/// The problems met with individual songs, or the error that stopped the analysis.
+fn analyse_in_background(library: &Library, album_id: i64) -> Result<Vec<String>, String> {
+    let paths: Vec<String> = library.album_songs(album_id).map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|song| song.path)
+        .collect();
+    let (gains, problems) = run_in_background(move || analyse_loudness(&paths), || {})
+        .ok_or_else(|| "The analysis stopped unexpectedly".to_string())?;
+    library.store_loudness(album_id, &gains).map_err(|e| e.to_string())?;
+    Ok(problems)
+}
+
+/// # Run Loudness Scan function
+///
+/// Finds the albums that still lack loudness data and, after asking for confirmation,
+/// analyses them one by one on a worker thread, showing the progress in `progress_bar`. A
+/// summary with the problems met (songs that could not be decoded or tagged) is shown at the end.
+///
+/// # Arguments
+/// * `window` - The main window.
+/// * `library` - The library holding the albums.
+/// * `progress_bar` - The progress bar of the main window.
+pub fn run_loudness_scan(window: &Window, library: &Library, progress_bar: &ProgressBar) {
+    let albums = match library.albums_missing_loudness() {
+        Ok(albums) => albums,
+        Err(e) => {
+            eprintln!("Failed to find the albums without loudness data: {}", e);
+            show_error_dialog(window, &format!("Failed to find the albums without loudness data: {}", e));
+            return;
+        }
+    };
+
+    if albums.is_empty() {
+        run_dialog(window, MessageType::Info, ButtonsType::Ok, "Every album already has loudness data.");
+        return;
+    }
+
+    let question = format!("{} album(s) have no loudness data. Analyse them now?", albums.len());
+    if run_dialog(window, MessageType::Question, ButtonsType::YesNo, &question) != ResponseType::Yes {
+        return;
+    }
+
+    let mut problems = Vec::new();
+    for (index, (album_id, name)) in albums.iter().enumerate() {
+        progress_bar.set_text(Some(&format!("Analysing {}", name)));
+
+        match analyse_in_background(library, *album_id) {
+            Ok(album_problems) => problems.extend(album_problems),
+            Err(e) => problems.push(format!("Could not analyse {}: {}", name, e)),
+        }
+
+        progress_bar.set_fraction((index + 1) as f64 / albums.len() as f64);
+    }
+    progress_bar.set_text(Some("Loudness analysis complete"));
+
+    let mut summary = format!("Analysed {} album(s).", albums.len());
+    append_problems(&mut summary, &problems);
+    run_dialog(window, MessageType::Info, ButtonsType::Ok, &summary);
+}
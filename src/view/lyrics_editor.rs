@@ -0,0 +1,188 @@
+use gtk::prelude::*;
+use gtk::{Box as GtkBox, Button, ButtonsType, CellRendererText, ListStore, MessageDialog, MessageType,
+    Orientation, ScrolledWindow, TreeView, TreeViewColumn, Window, WindowType};
+use crate::controller::controller::LyricsTarget;
+use crate::controller::library::Library;
+use crate::model::music_miner::synced_lyrics::{format_timestamp, parse_lrc, parse_timestamp, LyricLine};
+use crate::view::scan_report::show_error_dialog;
+use std::rc::Rc;
+
+/// Column holding the timestamp of a line, formatted as `mm:ss.xx`.
+const COLUMN_TIME: u32 = 0;
+/// Column holding the text of a line.
+const COLUMN_TEXT: u32 = 1;
+
+/// Amount, in milliseconds, by which the shift buttons move the selected line.
+const SHIFT_STEP_MS: i64 = 100;
+
+/// Reads every line of the editor back into `LyricLine`s, sorted by time.
+/// Lines whose timestamp cannot be parsed are skipped.
+fn collect_lines(list_store: &ListStore) -> Vec<LyricLine> {
+    let mut lines = Vec::new();
+
+    if let Some(iter) = list_store.iter_first() {
+        loop {
+            let time: String = list_store.value(&iter, COLUMN_TIME as i32).get().unwrap_or_default();
+            let text: String = list_store.value(&iter, COLUMN_TEXT as i32).get().unwrap_or_default();
+            if let Some(time_ms) = parse_timestamp(&time) {
+                lines.push(LyricLine { time_ms, text });
+            }
+            if !list_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+
+    lines.sort_by_key(|line| line.time_ms);
+    lines
+}
+
+/// Adds an editable text column to the editor, writing edits back to `column` of the store.
+/// Edited timestamps are only accepted when they parse, and are normalised to `mm:ss.xx`.
+fn append_editable_column(tree_view: &TreeView, list_store: &ListStore, title: &str, column: u32) {
+    let renderer = CellRendererText::new();
+    renderer.set_editable(true);
+
+    let list_store = list_store.clone();
+    renderer.connect_edited(move |_, path, new_text| {
+        if let Some(iter) = list_store.iter(&path) {
+            if column == COLUMN_TIME {
+                if let Some(time_ms) = parse_timestamp(new_text) {
+                    list_store.set(&iter, &[(COLUMN_TIME, &format_timestamp(time_ms))]);
+                }
+            } else {
+                list_store.set(&iter, &[(column, &new_text)]);
+            }
+        }
+    });
+
+    let tree_column = TreeViewColumn::new();
+    tree_column.set_title(title);
+    tree_column.pack_start(&renderer, true);
+    tree_column.add_attribute(&renderer, "text", column as i32);
+    tree_view.append_column(&tree_column);
+}
+
+/// Shows an information dialog confirming where the lyrics were saved.
+fn show_saved_dialog(window: &Window, message: &str) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        MessageType::Info,
+        ButtonsType::Ok,
+        message,
+    );
+    dialog.run();
+    dialog.close();
+}
+
+/// # Show Lyrics Editor function
+///
+/// Opens a window listing the time-coded lines of a song's synchronised lyrics. Timestamps
+/// and text can be edited in place, lines can be added, removed or shifted in 0.1 s steps,
+/// and the result can be saved as an `.lrc` sidecar file or as a SYLT frame in the tag.
+///
+/// # Arguments
+/// * `parent` - The main window.
+/// * `library` - The library the lyrics are stored in.
+/// * `song_path` - The path of the song whose lyrics are edited.
+/// * `lrc` - The current lyrics in LRC format, if the song has any.
+pub fn show_lyrics_editor(parent: &Window, library: &Rc<Library>, song_path: &str, lrc: Option<&str>) {
+    let window = Window::new(WindowType::Toplevel);
+    window.set_title("Synchronised Lyrics");
+    window.set_transient_for(Some(parent));
+    window.set_default_size(500, 500);
+
+    let main_box = GtkBox::new(Orientation::Vertical, 5);
+    main_box.set_margin_top(10);
+    main_box.set_margin_bottom(10);
+    main_box.set_margin_start(10);
+    main_box.set_margin_end(10);
+
+    let list_store = ListStore::new(&[glib::Type::STRING, glib::Type::STRING]);
+    for line in parse_lrc(lrc.unwrap_or("")) {
+        let iter = list_store.append();
+        list_store.set(&iter, &[(COLUMN_TIME, &format_timestamp(line.time_ms)), (COLUMN_TEXT, &line.text)]);
+    }
+
+    let tree_view = TreeView::with_model(&list_store);
+    append_editable_column(&tree_view, &list_store, "Time", COLUMN_TIME);
+    append_editable_column(&tree_view, &list_store, "Line", COLUMN_TEXT);
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&tree_view);
+    main_box.pack_start(&scrolled_window, true, true, 0);
+
+    let edit_box = GtkBox::new(Orientation::Horizontal, 5);
+    let add_button = Button::with_label("Add line");
+    let remove_button = Button::with_label("Remove line");
+    let earlier_button = Button::with_label("−0.1 s");
+    let later_button = Button::with_label("+0.1 s");
+    edit_box.pack_start(&add_button, false, false, 0);
+    edit_box.pack_start(&remove_button, false, false, 0);
+    edit_box.pack_start(&earlier_button, false, false, 0);
+    edit_box.pack_start(&later_button, false, false, 0);
+    main_box.pack_start(&edit_box, false, false, 0);
+
+    let save_box = GtkBox::new(Orientation::Horizontal, 5);
+    save_box.set_halign(gtk::Align::End);
+    let save_lrc_button = Button::with_label("Save as .lrc");
+    let save_tag_button = Button::with_label("Save to tag (SYLT)");
+    save_box.pack_start(&save_lrc_button, false, false, 0);
+    save_box.pack_start(&save_tag_button, false, false, 0);
+    main_box.pack_start(&save_box, false, false, 0);
+
+    {
+        let list_store = list_store.clone();
+        let tree_view = tree_view.clone();
+        add_button.connect_clicked(move |_| {
+            let time_ms = collect_lines(&list_store).last().map_or(0, |line| line.time_ms + 1_000);
+            let iter = list_store.append();
+            list_store.set(&iter, &[(COLUMN_TIME, &format_timestamp(time_ms)), (COLUMN_TEXT, &"")]);
+            tree_view.selection().select_iter(&iter);
+        });
+    }
+
+    {
+        let tree_view = tree_view.clone();
+        remove_button.connect_clicked(move |_| {
+            if let Some((model, iter)) = tree_view.selection().selected() {
+                if let Some(list_store) = model.downcast_ref::<ListStore>() {
+                    list_store.remove(&iter);
+                }
+            }
+        });
+    }
+
+    for (button, step) in [(&earlier_button, -SHIFT_STEP_MS), (&later_button, SHIFT_STEP_MS)] {
+        let tree_view = tree_view.clone();
+        let list_store = list_store.clone();
+        button.connect_clicked(move |_| {
+            if let Some((_, iter)) = tree_view.selection().selected() {
+                let time: String = list_store.value(&iter, COLUMN_TIME as i32).get().unwrap_or_default();
+                if let Some(time_ms) = parse_timestamp(&time) {
+                    let shifted = (i64::from(time_ms) + step).max(0) as u32;
+                    list_store.set(&iter, &[(COLUMN_TIME, &format_timestamp(shifted))]);
+                }
+            }
+        });
+    }
+
+    for (button, target_is_tag) in [(&save_lrc_button, false), (&save_tag_button, true)] {
+        let window = window.clone();
+        let library = Rc::clone(library);
+        let list_store = list_store.clone();
+        let song_path = song_path.to_string();
+        button.connect_clicked(move |_| {
+            let lines = collect_lines(&list_store);
+            let target = if target_is_tag { LyricsTarget::Tag } else { LyricsTarget::Sidecar };
+            match library.save_synced_lyrics(&song_path, &lines, target) {
+                Ok(()) => show_saved_dialog(&window, "Synchronised lyrics saved."),
+                Err(e) => show_error_dialog(&window, &format!("Failed to save the lyrics: {}", e)),
+            }
+        });
+    }
+
+    window.add(&main_box);
+    window.show_all();
+}
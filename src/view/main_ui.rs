@@ -1,24 +1,477 @@
 use gtk::prelude::*;
 use gtk::{ProgressBar, Button, TreeView, TreeViewColumn, CellRendererText, Box as GtkBox, 
-    Orientation, Window, WindowType, Label, Entry, ScrolledWindow, ListStore, Settings, MenuButton, Popover, Frame, FileChooserAction, FileChooserDialog, ResponseType, Image};
-use crate::controller::controller::{populate_song_list, save_directory_to_config, 
-    create_database_connection, show_error_dialog, get_song_details, remove_database_file_if_exists, 
-    extract_songs_from_directory, insert_song_into_database, create_tables_if_not_exist};
+    Orientation, Window, WindowType, Label, Entry, ScrolledWindow, ListStore, Settings, MenuButton, Popover, Frame, FileChooserAction, FileChooserDialog, ResponseType, Image,
+    FlowBox, Stack, StackSwitcher, Expander, CheckButton, SpinButton, ComboBoxText, Paned, SortColumn, SortType};
+use crate::controller::controller::{remove_library_roots, get_song_details, AlbumDetails, LabelTarget, SongView};
+use crate::controller::library::{reset_library, Library, LibraryEvent};
+use crate::model::music_miner::rating::MAX_RATING;
 use gtk::traits::SettingsExt;
+use gtk::gdk_pixbuf::Pixbuf;
+use crate::view::library_browser::LibraryBrowser;
+use crate::view::lyrics_editor::show_lyrics_editor;
+use crate::view::synced_lyrics_pane::SyncedLyricsPane;
+use crate::view::labels_dialog::show_labels_dialog;
+use crate::view::loudness_scan::run_loudness_scan;
+use crate::view::duplicates_dialog::show_duplicates_dialog;
+use crate::view::recordings_dialog::show_recordings_dialog;
+use crate::view::release_matching::show_release_matching;
+use crate::view::scan_report::{show_error_dialog, show_scan_report};
+use crate::view::song_list::{format_rating, populate_album_song_list, populate_song_list,
+    populate_song_list_view, populate_song_list_with_query};
+use crate::view::scan_log::show_scan_log;
+use crate::view::preferences::show_preferences;
+use crate::view::now_playing::{NowPlayingBar, QueueEntry};
+use crate::model::playback::player::{PlaybackSettings, Player};
+use crate::config::{self, load_settings, save_settings, update_settings, ColumnSettings, Theme, WindowSettings};
+use crate::model::music_miner::audio_properties::format_duration;
+use std::path::Path;
+use std::cmp::Ordering;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+/// Tooltip of the search entry listing every prefix of the query language.
+const SEARCH_HELP: &str = "n: artist, t: title, a: album, aa: album artist, g: genre, y: year, tr: track, d: disc,\n\
+c: composer, cd: conductor, bpm: BPM, k: key, cm: comment, ly: lyrics,\n\
+len: duration in seconds, br: bitrate in kbps, pc: play count, lp: days since last played, r: rating, l: label.\n\
+Numbers accept 2000, >2000, <=120 or 1990-1999. Combine terms with &&.";
+
+/// The optional columns of the song list, hidden until enabled from the menu, with their
+/// index in the `ListStore` (filled by `append_song_row()` in the controller).
+const OPTIONAL_COLUMNS: [(&str, i32); 7] = [
+    ("Duration", 3),
+    ("Bitrate", 4),
+    ("Sample rate", 5),
+    ("Channels", 6),
+    ("Encoder", 7),
+    ("Plays", 9),
+    ("Last played", 10),
+];
+
+/// Index in the song list `ListStore` of the hidden column holding the path of each song.
+const PATH_COLUMN: i32 = 8;
+
+/// Index in the song list `ListStore` of the column showing the rating stars.
+const RATING_COLUMN: i32 = 11;
+
+/// Compares two cells of the song list, reading runs of digits as numbers so that `3:05`
+/// comes before `10:00` and `96 kbps` before `128 kbps`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut value: u64 = 0;
+                    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                        value = value.saturating_mul(10).saturating_add(u64::from(digit));
+                        chars.next();
+                    }
+                    value
+                };
+                let ordering = number(&mut a).cmp(&number(&mut b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Makes the columns of the song list resizable, reorderable and sortable by clicking their
+/// header, then restores the order, widths and sort column saved in `Config.TOML`.
+///
+/// # Arguments
+/// * `tree_view` - The song list.
+/// * `list_store` - Its model.
+/// * `columns` - The columns with the index of the model column they show.
+/// * `layout` - The saved layout.
+fn restore_column_layout(tree_view: &TreeView, list_store: &ListStore, columns: &[(TreeViewColumn, i32)], layout: &ColumnSettings) {
+    for (column, index) in columns {
+        column.set_resizable(true);
+        column.set_reorderable(true);
+        column.set_sort_column_id(*index);
+        let index = *index;
+        list_store.set_sort_func(SortColumn::Index(index as u32), move |model, a, b| {
+            let cell = |iter| model.value(iter, index).get::<String>().unwrap_or_default();
+            if index == RATING_COLUMN {
+                cell(a).matches('★').count().cmp(&cell(b).matches('★').count())
+            } else {
+                natural_cmp(&cell(a), &cell(b))
+            }
+        });
+    }
+
+    let column_titled = |title: &str| columns.iter().find(|(column, _)| column.title().as_deref() == Some(title));
+    let mut previous: Option<TreeViewColumn> = None;
+    for (column, _) in layout.order.iter().filter_map(|title| column_titled(title)) {
+        tree_view.move_column_after(column, previous.as_ref());
+        previous = Some(column.clone());
+    }
+    for (title, width) in &layout.widths {
+        if let Some((column, _)) = column_titled(title) {
+            column.set_fixed_width(*width);
+        }
+    }
+    if let Some((_, index)) = layout.sort_column.as_deref().and_then(column_titled) {
+        let order = if layout.sort_descending { SortType::Descending } else { SortType::Ascending };
+        list_store.set_sort_column_id(SortColumn::Index(*index as u32), order);
+    }
+}
+
+/// Reads the current layout of the song list: the order and widths of its columns and the
+/// column it is sorted by. The optional columns shown are kept from `visible`.
+fn column_layout(tree_view: &TreeView, list_store: &ListStore, columns: &[(TreeViewColumn, i32)], visible: Vec<String>) -> ColumnSettings {
+    let titles = tree_view.columns().iter().filter_map(|column| column.title().map(|title| title.to_string())).collect();
+    let widths = columns.iter()
+        .filter(|(column, _)| column.is_visible())
+        .filter_map(|(column, _)| column.title().map(|title| (title.to_string(), column.width())))
+        .collect();
+    let sort = match list_store.sort_column_id() {
+        Some((SortColumn::Index(index), order)) => columns.iter()
+            .find(|(_, column_index)| *column_index as u32 == index)
+            .and_then(|(column, _)| column.title())
+            .map(|title| (title.to_string(), order == SortType::Descending)),
+        _ => None,
+    };
+    ColumnSettings {
+        visible,
+        order: titles,
+        sort_column: sort.as_ref().map(|(title, _)| title.clone()),
+        sort_descending: sort.is_some_and(|(_, descending)| descending),
+        widths,
+    }
+}
+
+/// The views of the song list offered above it, with their id in the combo box.
+const SONG_VIEWS: [(&str, &str, SongView); 5] = [
+    ("all", "All songs", SongView::All),
+    ("most-played", "Most played", SongView::MostPlayed),
+    ("recently-played", "Recently played", SongView::RecentlyPlayed),
+    ("never-played", "Never played", SongView::NeverPlayed),
+    ("loved", "Loved", SongView::Loved),
+];
+
+/// Width and height, in pixels, of the cover shown in the details frame.
+const COVER_SIZE: i32 = 200;
+
+/// Width and height, in pixels, of the covers shown in the album grid.
+const TILE_COVER_SIZE: i32 = 128;
+
+/// Builds the tile shown in the album grid for one album: its cover, name, artist and year.
+fn build_album_tile(album: &AlbumDetails) -> GtkBox {
+    let tile = GtkBox::new(Orientation::Vertical, 5);
+    tile.set_margin_top(5);
+    tile.set_margin_bottom(5);
+    tile.set_margin_start(5);
+    tile.set_margin_end(5);
+
+    let cover = album.cover.as_deref()
+        .and_then(|path| Pixbuf::from_file_at_scale(path, TILE_COVER_SIZE, TILE_COVER_SIZE, true).ok());
+    let image = match cover {
+        Some(pixbuf) => Image::from_pixbuf(Some(&pixbuf)),
+        None => Image::from_icon_name(Some("media-optical"), gtk::IconSize::Dialog),
+    };
+    image.set_size_request(TILE_COVER_SIZE, TILE_COVER_SIZE);
+
+    let name_label = Label::new(Some(&album.name));
+    name_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    name_label.set_max_width_chars(18);
+    let artist_label = Label::new(Some(&album.artist));
+    artist_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    artist_label.set_max_width_chars(18);
+    let year_label = Label::new(Some(&album.year.to_string()));
+
+    tile.pack_start(&image, false, false, 0);
+    tile.pack_start(&name_label, false, false, 0);
+    tile.pack_start(&artist_label, false, false, 0);
+    tile.pack_start(&year_label, false, false, 0);
+    tile
+}
+
+/// Builds a play queue from the song list, in the order it is shown.
+/// Each entry takes the path, title and artist of a row.
+fn queue_from_list(list_store: &ListStore) -> Vec<QueueEntry> {
+    let mut entries = Vec::new();
+
+    if let Some(iter) = list_store.iter_first() {
+        loop {
+            entries.push(QueueEntry {
+                path: list_store.value(&iter, PATH_COLUMN).get().unwrap_or_default(),
+                title: list_store.value(&iter, 0).get().unwrap_or_default(),
+                artist: list_store.value(&iter, 1).get().unwrap_or_default(),
+            });
+            if !list_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+
+    entries
+}
+
+/// Fills the album grid with one tile per album in the database.
+/// `album_ids` keeps the `id_album` of each tile, in the same order as the tiles.
+///
+/// # Errors
+/// Prints an error to the console, leaving the grid empty, if the albums cannot be retrieved.
+fn populate_album_grid(flow_box: &FlowBox, album_ids: &Rc<RefCell<Vec<i64>>>, library: &Library) {
+    for child in flow_box.children() {
+        flow_box.remove(&child);
+    }
+
+    let albums = library.albums().unwrap_or_else(|err| {
+        eprintln!("Failed to retrieve albums from the database: {}", err);
+        Vec::new()
+    });
+    *album_ids.borrow_mut() = albums.iter().map(|album| album.id).collect();
+
+    for album in &albums {
+        flow_box.insert(&build_album_tile(album), -1);
+    }
+    flow_box.show_all();
+}
+
+/// The labels and cover shown in the "Song Details" frame.
+#[derive(Clone)]
+struct DetailsPanel {
+    cover: Image,
+    title: Label,
+    artist: Label,
+    album: Label,
+    credits: Label,
+    path: Label,
+    track: Label,
+    year: Label,
+    genre: Label,
+    extra: Label,
+    /// The labels of the song and of its album, shown as chips.
+    labels: FlowBox,
+    lyrics_expander: Expander,
+    lyrics: Label,
+    /// The time-coded lines, following the player.
+    synced_lyrics: SyncedLyricsPane,
+    synced_lyrics_button: Button,
+    /// Path and LRC lyrics of the song currently shown, used by the lyrics editor.
+    current_song: Rc<RefCell<Option<(String, Option<String>)>>>,
+    library: Rc<Library>,
+}
+
+impl DetailsPanel {
+    fn new(library: Rc<Library>) -> Self {
+        let cover = Image::from_icon_name(Some("audio-x-generic"), gtk::IconSize::Dialog);
+        cover.set_size_request(COVER_SIZE, COVER_SIZE);
+
+        DetailsPanel {
+            cover,
+            title: Label::new(Some("Title: ")),
+            artist: Label::new(Some("Artist: ")),
+            album: Label::new(Some("Album: ")),
+            credits: Label::new(None),
+            path: Label::new(Some("Path: ")),
+            track: Label::new(Some("Track number: ")),
+            year: Label::new(Some("Year: ")),
+            genre: Label::new(Some("Genre: ")),
+            extra: Label::new(None),
+            labels: FlowBox::new(),
+            lyrics_expander: Expander::new(Some("Lyrics")),
+            lyrics: Label::new(None),
+            synced_lyrics: SyncedLyricsPane::new(),
+            synced_lyrics_button: Button::with_label("Synchronised lyrics…"),
+            current_song: Rc::new(RefCell::new(None)),
+            library,
+        }
+    }
+
+    fn pack_into(&self, details_box: &GtkBox) {
+        details_box.pack_start(&self.cover, false, false, 5);
+        details_box.pack_start(&self.title, false, false, 5);
+        details_box.pack_start(&self.artist, false, false, 5);
+        details_box.pack_start(&self.album, false, false, 5);
+        details_box.pack_start(&self.credits, false, false, 5);
+        details_box.pack_start(&self.path, false, false, 5);
+        details_box.pack_start(&self.track, false, false, 5);
+        details_box.pack_start(&self.year, false, false, 5);
+        details_box.pack_start(&self.genre, false, false, 5);
+        details_box.pack_start(&self.extra, false, false, 5);
+        self.labels.set_selection_mode(gtk::SelectionMode::None);
+        details_box.pack_start(&self.labels, false, false, 5);
+
+        self.lyrics.set_line_wrap(true);
+        self.lyrics.set_selectable(true);
+        let lyrics_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        lyrics_window.set_min_content_height(150);
+        lyrics_window.add(&self.lyrics);
+        self.lyrics_expander.add(&lyrics_window);
+        details_box.pack_start(&self.lyrics_expander, true, true, 5);
+        self.synced_lyrics.widget.set_expanded(true);
+        details_box.pack_start(&self.synced_lyrics.widget, true, true, 5);
+
+        self.synced_lyrics_button.set_sensitive(false);
+        details_box.pack_start(&self.synced_lyrics_button, false, false, 5);
+    }
+
+    /// Opens the lyrics editor for the song currently shown when the lyrics button is clicked.
+    fn connect_synced_lyrics(&self, window: &Rc<Window>) {
+        let window = Rc::clone(window);
+        let current_song = Rc::clone(&self.current_song);
+        let library = Rc::clone(&self.library);
+        self.synced_lyrics_button.connect_clicked(move |_| {
+            if let Some((path, lrc)) = current_song.borrow().as_ref() {
+                show_lyrics_editor(&window, &library, path, lrc.as_deref());
+            }
+        });
+    }
+
+    /// Shows the details of the song with the given path.
+    fn show_song(&self, path: &str) {
+        if let Ok(song_details) = get_song_details(&self.library, path) {
+            self.title.set_text(&format!("Title: {}", song_details.title));
+            self.artist.set_text(&format!("Artist: {}", song_details.artist));
+            self.album.set_text(&format!("Album: {} ({})", song_details.album, song_details.album_artist));
+
+            let mut credits = Vec::new();
+            if let Some(remixers) = &song_details.remixers {
+                credits.push(format!("Remixed by: {}", remixers));
+            }
+            if let Some(composers) = &song_details.composers {
+                credits.push(format!("Composed by: {}", composers));
+            }
+            self.credits.set_text(&credits.join("\n"));
+            self.credits.set_visible(!credits.is_empty());
+            self.path.set_text(&format!("Path: {}", song_details.path));
+            let track = match song_details.track_total {
+                Some(total) => format!("Track number: {}/{}", song_details.track_number, total),
+                None => format!("Track number: {}", song_details.track_number),
+            };
+            self.track.set_text(&track);
+            self.year.set_text(&format!("Year: {}", song_details.year));
+            self.genre.set_text(&format!("Genre: {}", song_details.genre));
+            show_cover(&self.cover, song_details.cover.as_deref());
+
+            let mut extra = Vec::new();
+            if let Some(disc) = song_details.disc {
+                match song_details.disc_total {
+                    Some(total) => extra.push(format!("Disc: {}/{}", disc, total)),
+                    None => extra.push(format!("Disc: {}", disc)),
+                }
+            }
+            let optional_fields = [
+                ("Composer", song_details.composer.clone()),
+                ("Conductor", song_details.conductor.clone()),
+                ("BPM", song_details.bpm.map(|bpm| bpm.to_string())),
+                ("Key", song_details.key.clone()),
+                ("Comment", song_details.comment.clone()),
+            ];
+            for (name, value) in optional_fields {
+                if let Some(value) = value {
+                    extra.push(format!("{}: {}", name, value));
+                }
+            }
+
+            let audio: Vec<String> = [
+                song_details.duration_ms.map(format_duration),
+                song_details.bitrate.map(|bitrate| format!("{} kbps", bitrate)),
+                song_details.sample_rate.map(|rate| format!("{} Hz", rate)),
+                song_details.channel_mode.clone(),
+            ].into_iter().flatten().collect();
+            if !audio.is_empty() {
+                extra.push(format!("Audio: {}", audio.join(", ")));
+            }
+            if let Some(encoder) = &song_details.encoder {
+                extra.push(format!("Encoder: {}", encoder));
+            }
+            match &song_details.last_played {
+                Some(last_played) => extra.push(format!("Played {} times, last on {}", song_details.play_count, last_played)),
+                None => extra.push(String::from("Never played")),
+            }
+            self.extra.set_text(&extra.join("\n"));
+            self.extra.set_visible(!extra.is_empty());
+
+            for chip in self.labels.children() {
+                self.labels.remove(&chip);
+            }
+            for label in &song_details.labels {
+                self.labels.insert(&build_label_chip(label, false), -1);
+            }
+            for label in &song_details.album_labels {
+                self.labels.insert(&build_label_chip(label, true), -1);
+            }
+            self.labels.show_all();
+            self.labels.set_visible(!song_details.labels.is_empty() || !song_details.album_labels.is_empty());
+
+            self.lyrics.set_text(song_details.lyrics.as_deref().unwrap_or(""));
+            self.lyrics_expander.set_visible(song_details.lyrics.is_some());
+            self.synced_lyrics.show(&song_details.path, song_details.synced_lyrics.as_deref());
+
+            self.synced_lyrics_button.set_sensitive(true);
+            *self.current_song.borrow_mut() = Some((song_details.path, song_details.synced_lyrics));
+        }
+    }
+}
+
+/// Builds the chip showing a label in the details frame. Labels inherited from the album are
+/// shown in italics.
+fn build_label_chip(name: &str, from_album: bool) -> Frame {
+    let text = Label::new(None);
+    let escaped = glib::markup_escape_text(name);
+    if from_album {
+        text.set_markup(&format!("<i>{}</i>", escaped));
+        text.set_tooltip_text(Some("Album label"));
+    } else {
+        text.set_markup(&escaped);
+    }
+    text.set_margin_start(6);
+    text.set_margin_end(6);
+    text.set_margin_top(2);
+    text.set_margin_bottom(2);
+
+    let chip = Frame::new(None);
+    chip.add(&text);
+    chip
+}
+
+/// Shows the cover thumbnail at `cover` in `image`, or a generic icon when the song has no cover
+/// or the thumbnail cannot be loaded.
+fn show_cover(image: &Image, cover: Option<&str>) {
+    match cover.and_then(|path| Pixbuf::from_file_at_scale(path, COVER_SIZE, COVER_SIZE, true).ok()) {
+        Some(pixbuf) => image.set_from_pixbuf(Some(&pixbuf)),
+        None => image.set_from_icon_name(Some("audio-x-generic"), gtk::IconSize::Dialog),
+    }
+}
+
 pub fn build_ui() {
     gtk::init().expect("Failed to initialize GTK.");
 
     let settings = Settings::default().expect("Failed to get default settings");
 
-    let dark_mode_enabled = Rc::new(RefCell::new(true));
+    let app_settings = load_settings().unwrap_or_else(|e| {
+        eprintln!("Could not load the settings: {}", e);
+        config::Settings::default()
+    });
+
+    let dark_mode_enabled = Rc::new(RefCell::new(app_settings.theme == Theme::Dark));
     settings.set_gtk_application_prefer_dark_theme(*dark_mode_enabled.borrow());
 
     let window = Rc::new(Window::new(WindowType::Toplevel));
     window.set_title("Music Manager");
-    window.set_default_size(900, 600);
+    window.set_default_size(app_settings.window.width, app_settings.window.height);
+    if let (Some(x), Some(y)) = (app_settings.window.x, app_settings.window.y) {
+        window.move_(x, y);
+    }
+    if app_settings.window.maximized {
+        window.maximize();
+    }
 
     let main_box = GtkBox::new(Orientation::Vertical, 5);
     main_box.set_margin_top(10);
@@ -38,13 +491,56 @@ pub fn build_ui() {
     let toggle_theme_button = Button::with_label("Toggle Dark Mode");
 
     popover_box.pack_start(&toggle_theme_button, false, false, 5);
+    let preferences_button = Button::with_label("Preferences");
+    popover_box.pack_start(&preferences_button, false, false, 5);
+    let loudness_button = Button::with_label("Analyse Loudness");
+    popover_box.pack_start(&loudness_button, false, false, 5);
+    let duplicates_button = Button::with_label("Find Duplicates");
+    popover_box.pack_start(&duplicates_button, false, false, 5);
+    let recordings_button = Button::with_label("Match Recordings");
+    popover_box.pack_start(&recordings_button, false, false, 5);
+    let releases_button = Button::with_label("Match Releases");
+    popover_box.pack_start(&releases_button, false, false, 5);
+    let scan_log_button = Button::with_label("Scan Log");
+    popover_box.pack_start(&scan_log_button, false, false, 5);
+    popover_box.pack_start(&Label::new(Some("Columns")), false, false, 5);
+    let column_toggles: Vec<CheckButton> = OPTIONAL_COLUMNS.iter()
+        .map(|(title, _)| CheckButton::with_label(title))
+        .collect();
+    for toggle in &column_toggles {
+        popover_box.pack_start(toggle, false, false, 0);
+    }
+
+    let playback_settings = app_settings.playback();
+    popover_box.pack_start(&Label::new(Some("Playback")), false, false, 5);
+    let gapless_toggle = CheckButton::with_label("Gapless playback");
+    gapless_toggle.set_active(playback_settings.gapless);
+    popover_box.pack_start(&gapless_toggle, false, false, 0);
+    let crossfade_box = GtkBox::new(Orientation::Horizontal, 5);
+    let crossfade_spin = SpinButton::with_range(0.0, 12.0, 0.5);
+    crossfade_spin.set_digits(1);
+    crossfade_spin.set_value(f64::from(playback_settings.crossfade_ms) / 1000.0);
+    crossfade_box.pack_start(&Label::new(Some("Crossfade (s)")), false, false, 0);
+    crossfade_box.pack_start(&crossfade_spin, false, false, 0);
+    popover_box.pack_start(&crossfade_box, false, false, 0);
+
+    popover_box.pack_start(&Label::new(Some("Ratings")), false, false, 5);
+    let sync_ratings_toggle = CheckButton::with_label("Write ratings to tags (POPM, FMPS_RATING)");
+    sync_ratings_toggle.set_active(app_settings.sync_ratings);
+    sync_ratings_toggle.connect_toggled(|toggle| {
+        if let Err(e) = update_settings(|settings| settings.sync_ratings = toggle.is_active()) {
+            eprintln!("Could not save the rating settings: {}", e);
+        }
+    });
+    popover_box.pack_start(&sync_ratings_toggle, false, false, 0);
     popover.add(&popover_box);
     popover_box.show_all();
 
     menu_button.set_popover(Some(&popover));
     header_box.pack_start(&menu_button, false, false, 5);
 
-    let content_box = GtkBox::new(Orientation::Horizontal, 10);
+    let browser_paned = Paned::new(Orientation::Horizontal);
+    let details_paned = Paned::new(Orientation::Horizontal);
 
     let song_list_box = GtkBox::new(Orientation::Vertical, 5);
 
@@ -52,6 +548,7 @@ pub fn build_ui() {
     let directory_box = GtkBox::new(Orientation::Horizontal, 5);
     let directory_entry = Entry::new();
     directory_entry.set_placeholder_text(Some("Enter music directory here..."));
+    directory_entry.set_text(app_settings.music_directory().unwrap_or_default());
 
     let folder_button = Button::new();
     let folder_image = Image::from_icon_name(Some("folder"), gtk::IconSize::Button);
@@ -66,7 +563,7 @@ pub fn build_ui() {
 
     let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
     let tree_view = TreeView::new();
-    let list_store = Rc::new(RefCell::new(ListStore::new(&[glib::Type::STRING, glib::Type::STRING, glib::Type::STRING])));
+    let list_store = Rc::new(RefCell::new(ListStore::new(&[glib::Type::STRING; 12])));
     tree_view.set_model(Some(&*list_store.borrow()));
 
     let renderer = CellRendererText::new();
@@ -85,20 +582,104 @@ pub fn build_ui() {
     col_album.pack_start(&renderer, true);
     col_album.add_attribute(&renderer, "text", 2);
 
+    let col_rating = TreeViewColumn::new();
+    col_rating.set_title("Rating");
+    col_rating.pack_start(&renderer, true);
+    col_rating.add_attribute(&renderer, "text", RATING_COLUMN);
+
     tree_view.append_column(&col_title);
     tree_view.append_column(&col_artist);
     tree_view.append_column(&col_album);
+    tree_view.append_column(&col_rating);
+    let mut song_columns = vec![(col_title, 0), (col_artist, 1), (col_album, 2), (col_rating.clone(), RATING_COLUMN)];
+
+    for ((title, index), toggle) in OPTIONAL_COLUMNS.iter().zip(&column_toggles) {
+        let column = TreeViewColumn::new();
+        column.set_title(title);
+        column.pack_start(&renderer, true);
+        column.add_attribute(&renderer, "text", *index);
+        column.set_visible(false);
+        tree_view.append_column(&column);
+        song_columns.push((column.clone(), *index));
+
+        toggle.connect_toggled(move |toggle| column.set_visible(toggle.is_active()));
+        toggle.set_active(app_settings.columns.visible.iter().any(|visible| visible == title));
+    }
+
+    // The optional columns shown are saved to `Config.TOML` once restored.
+    for toggle in &column_toggles {
+        let column_toggles = column_toggles.clone();
+        toggle.connect_toggled(move |_| {
+            let visible: Vec<String> = OPTIONAL_COLUMNS.iter().zip(&column_toggles)
+                .filter(|(_, toggle)| toggle.is_active())
+                .map(|((title, _), _)| title.to_string())
+                .collect();
+            if let Err(e) = update_settings(|settings| settings.columns.visible = visible) {
+                eprintln!("Could not save the column layout: {}", e);
+            }
+        });
+    }
+
+    restore_column_layout(&tree_view, &list_store.borrow(), &song_columns, &app_settings.columns);
 
     scrolled_window.add(&tree_view);
-    song_list_box.pack_start(&scrolled_window, true, true, 5);
+
+    let album_scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    let album_flow_box = FlowBox::new();
+    album_flow_box.set_selection_mode(gtk::SelectionMode::Single);
+    album_flow_box.set_activate_on_single_click(false);
+    album_flow_box.set_homogeneous(true);
+    album_flow_box.set_valign(gtk::Align::Start);
+    album_scrolled_window.add(&album_flow_box);
+    let album_ids: Rc<RefCell<Vec<i64>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let view_stack = Stack::new();
+    view_stack.add_titled(&scrolled_window, "songs", "Songs");
+    view_stack.add_titled(&album_scrolled_window, "albums", "Albums");
+
+    let view_switcher = StackSwitcher::new();
+    view_switcher.set_stack(Some(&view_stack));
+
+    let song_view_combo = ComboBoxText::new();
+    for (id, label, _) in SONG_VIEWS {
+        song_view_combo.append(Some(id), label);
+    }
+    song_view_combo.set_active_id(Some("all"));
+
+    let view_bar = GtkBox::new(Orientation::Horizontal, 5);
+    view_bar.set_halign(gtk::Align::Center);
+    view_bar.pack_start(&view_switcher, false, false, 0);
+    view_bar.pack_start(&song_view_combo, false, false, 0);
+    song_list_box.pack_start(&view_bar, false, false, 0);
+    song_list_box.pack_start(&view_stack, true, true, 5);
 
     let progress_bar = ProgressBar::new();
     song_list_box.pack_start(&progress_bar, false, false, 5);
 
+    let mut library = match Library::open() {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("Failed to open the library: {}", e);
+            show_error_dialog(&window, &format!("Failed to open the library: {}", e));
+            std::process::exit(1);
+        }
+    };
+    {
+        let added_label = progress_bar.clone();
+        library.subscribe(move |event| {
+            if let LibraryEvent::SongAdded(path) = event {
+                let file_name = Path::new(path).file_name().unwrap_or_default().to_string_lossy();
+                added_label.set_text(Some(&format!("Added {}", file_name)));
+            }
+        });
+    }
+    let library = Rc::new(library);
+
     let right_box = GtkBox::new(Orientation::Vertical, 10);
 
     let search_entry = Entry::new();
-    search_entry.set_placeholder_text(Some("Search by artist (n:), title (t:), or album (a:). Use && to combine two or more fields."));
+    search_entry.set_placeholder_text(Some("Search by artist (n:), title (t:), album (a:), genre (g:), year (y:), composer (c:), lyrics (ly:)... Use && to combine two or more fields."));
+    search_entry.set_tooltip_text(Some(SEARCH_HELP));
     right_box.pack_start(&search_entry, false, false, 0);
 
     let details_frame = Frame::new(Some("Song Details"));
@@ -108,26 +689,55 @@ pub fn build_ui() {
     details_box.set_margin_start(10);
     details_box.set_margin_end(10);
 
-    let label_title = Label::new(Some("Title: "));
-    let label_path = Label::new(Some("Path: "));
-    let label_track = Label::new(Some("Track number: "));
-    let label_year = Label::new(Some("Year: "));
-    let label_genre = Label::new(Some("Genre: "));
-
-    details_box.pack_start(&label_title, false, false, 5);
-    details_box.pack_start(&label_path, false, false, 5);
-    details_box.pack_start(&label_track, false, false, 5);
-    details_box.pack_start(&label_year, false, false, 5);
-    details_box.pack_start(&label_genre, false, false, 5);
+    let details = DetailsPanel::new(Rc::clone(&library));
+    details.pack_into(&details_box);
+    details.connect_synced_lyrics(&window);
 
     details_frame.add(&details_box);
     right_box.pack_start(&details_frame, true, true, 0);
 
-    content_box.pack_start(&song_list_box, true, true, 0);
-    content_box.pack_start(&right_box, false, false, 0);
+    let library_browser = LibraryBrowser::new(Rc::clone(&library));
+    library_browser.reload();
+
+    details_paned.pack1(&song_list_box, true, false);
+    details_paned.pack2(&right_box, false, false);
+    browser_paned.pack1(&library_browser.widget, false, false);
+    browser_paned.pack2(&details_paned, true, false);
+    if let Some(position) = app_settings.window.browser_width {
+        browser_paned.set_position(position);
+    }
+    if let Some(position) = app_settings.window.details_position {
+        details_paned.set_position(position);
+    }
 
     main_box.pack_start(&header_box, false, false, 0);
-    main_box.pack_start(&content_box, true, true, 0);
+    main_box.pack_start(&browser_paned, true, true, 0);
+
+    let player = Rc::new(Player::with_default_sink(playback_settings));
+    let now_playing = NowPlayingBar::new(Rc::clone(&player), Rc::clone(&library));
+    details.synced_lyrics.follow(Rc::clone(&player));
+    main_box.pack_start(&now_playing.widget, false, false, 0);
+
+    // Both playback options are applied right away and saved to `Config.TOML`.
+    let apply_playback_settings = {
+        let gapless_toggle = gapless_toggle.clone();
+        let crossfade_spin = crossfade_spin.clone();
+        Rc::new(move || {
+            let settings = PlaybackSettings {
+                gapless: gapless_toggle.is_active(),
+                crossfade_ms: (crossfade_spin.value() * 1000.0).round() as u32,
+            };
+            player.set_settings(settings);
+            if let Err(e) = update_settings(|saved| saved.set_playback(&settings)) {
+                eprintln!("Could not save the playback settings: {}", e);
+            }
+        })
+    };
+    {
+        let apply = Rc::clone(&apply_playback_settings);
+        gapless_toggle.connect_toggled(move |_| apply());
+    }
+    crossfade_spin.connect_value_changed(move |_| apply_playback_settings());
 
     window.add(&main_box);
     window.show_all();
@@ -156,161 +766,324 @@ pub fn build_ui() {
 
     {
         let list_store = Rc::clone(&list_store);
+        let library = Rc::clone(&library);
         search_entry.connect_changed(move |search_entry| {
-            let query = search_entry.text().to_lowercase();
-            list_store.borrow().clear();
+            let query = search_entry.text().to_string();
+            populate_song_list_with_query(&list_store.borrow(), &library, &query);
+        });
+    }
 
-            populate_song_list(&list_store.borrow());
+    {
+        let list_store = Rc::clone(&list_store);
+        let view_stack = view_stack.clone();
+        let library = Rc::clone(&library);
+        song_view_combo.connect_changed(move |combo| {
+            let active_id = combo.active_id();
+            if let Some((_, _, view)) = SONG_VIEWS.iter().find(|(id, _, _)| active_id.as_deref() == Some(*id)) {
+                populate_song_list_view(&list_store.borrow(), &library, *view);
+                view_stack.set_visible_child_name("songs");
+            }
+        });
+    }
 
-            let mut artist_filter: Option<String> = None;
-            let mut title_filter: Option<String> = None;
-            let mut album_filter: Option<String> = None;
+    {
+        let album_flow_box = album_flow_box.clone();
+        let album_ids = Rc::clone(&album_ids);
+        let library = Rc::clone(&library);
+        view_stack.connect_visible_child_name_notify(move |stack| {
+            if stack.visible_child_name().as_deref() == Some("albums") {
+                populate_album_grid(&album_flow_box, &album_ids, &library);
+            }
+        });
+    }
 
-            let parts: Vec<&str> = query.split("&&").collect();
+    {
+        let list_store = Rc::clone(&list_store);
+        let album_ids = Rc::clone(&album_ids);
+        let view_stack = view_stack.clone();
+        let library = Rc::clone(&library);
+        album_flow_box.connect_child_activated(move |_, child| {
+            let album_id = usize::try_from(child.index()).ok()
+                .and_then(|index| album_ids.borrow().get(index).copied());
+            if let Some(album_id) = album_id {
+                populate_album_song_list(&list_store.borrow(), &library, album_id);
+                view_stack.set_visible_child_name("songs");
+            }
+        });
+    }
 
-            for part in parts {
-                if part.starts_with("n:") {
-                    artist_filter = Some(part[2..].trim().to_string());
-                } else if part.starts_with("t:") {
-                    title_filter = Some(part[2..].trim().to_string());
-                } else if part.starts_with("a:") {
-                    album_filter = Some(part[2..].trim().to_string());
-                }
+    {
+        let window = Rc::clone(&window);
+        let progress_bar = progress_bar.clone();
+        let library = Rc::clone(&library);
+        loudness_button.connect_clicked(move |_| run_loudness_scan(&window, &library, &progress_bar));
+    }
+
+    {
+        let window = Rc::clone(&window);
+        let progress_bar = progress_bar.clone();
+        let list_store = Rc::clone(&list_store);
+        let library = Rc::clone(&library);
+        duplicates_button.connect_clicked(move |_| {
+            if show_duplicates_dialog(&window, &library, &progress_bar) {
+                populate_song_list(&list_store.borrow(), &library);
             }
+        });
+    }
 
-            let mut to_remove = Vec::new();
+    {
+        let window = Rc::clone(&window);
+        let progress_bar = progress_bar.clone();
+        let list_store = Rc::clone(&list_store);
+        let library = Rc::clone(&library);
+        recordings_button.connect_clicked(move |_| {
+            if show_recordings_dialog(&window, &library, &progress_bar) {
+                populate_song_list(&list_store.borrow(), &library);
+            }
+        });
+    }
 
-            if let Some(iter) = list_store.borrow().iter_first() {
-                loop {
-                    let title: String = list_store.borrow().value(&iter, 0).get().unwrap();
-                    let artist: String = list_store.borrow().value(&iter, 1).get().unwrap();
-                    let album: String = list_store.borrow().value(&iter, 2).get().unwrap();
+    {
+        let window = Rc::clone(&window);
+        let progress_bar = progress_bar.clone();
+        let list_store = Rc::clone(&list_store);
+        let library = Rc::clone(&library);
+        releases_button.connect_clicked(move |_| {
+            if show_release_matching(&window, &library, &progress_bar) {
+                populate_song_list(&list_store.borrow(), &library);
+            }
+        });
+    }
 
-                    let mut matches = true;
+    {
+        let window = Rc::clone(&window);
+        scan_log_button.connect_clicked(move |_| show_scan_log(&window));
+    }
 
-                    if let Some(ref artist_filter) = artist_filter {
-                        if !artist.to_lowercase().contains(&artist_filter.to_lowercase()) {
-                            matches = false;
-                        }
-                    }
-                    if let Some(ref title_filter) = title_filter {
-                        if !title.to_lowercase().contains(&title_filter.to_lowercase()) {
-                            matches = false;
-                        }
-                    }
-                    if let Some(ref album_filter) = album_filter {
-                        if !album.to_lowercase().contains(&album_filter.to_lowercase()) {
-                            matches = false;
-                        }
-                    }
+    let window_clone = window.clone();
+    let library_browser = Rc::new(library_browser);
+    let library_browser_clone = Rc::clone(&library_browser);
+    {
+        let list_store = Rc::clone(&list_store);
+        let directory_entry = directory_entry.clone();
+        let library = Rc::clone(&library);
+        refresh_button.connect_clicked(move |_| {
+            let directory = directory_entry.text().trim().to_string();
+            let directory = (!directory.is_empty()).then_some(directory.as_str());
 
-                    if !matches {
-                        to_remove.push(iter.clone());
-                    }
+            let scan = reset_library(&library, directory).and_then(|roots| {
+                library.scan(&roots, |added, total| {
+                    progress_bar.set_fraction(if total == 0 { 1.0 } else { added as f64 / total as f64 });
 
-                    if !list_store.borrow().iter_next(&iter) {
-                        break;
+                    while gtk::events_pending() {
+                        gtk::main_iteration();
                     }
+                })
+            });
+            progress_bar.set_text(Some("100% Complete"));
+
+            match scan {
+                Ok(report) if report.added == 0 && report.failures.is_empty() => {
+                    eprintln!("No songs found in the music directories.");
+                    show_error_dialog(&window_clone, "No songs found in the music directories.");
+                }
+                Ok(report) if !report.failures.is_empty() => show_scan_report(&window_clone, &report),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Failed to scan the library: {}", e);
+                    show_error_dialog(&window_clone, &format!("Failed to scan the library: {}", e));
+                    return;
                 }
             }
 
-            for iter in to_remove {
-                list_store.borrow().remove(&iter);
+            populate_song_list(&list_store.borrow(), &library);
+            library_browser_clone.reload();
+        });
+    }
+
+    {
+        let details = details.clone();
+        tree_view.connect_cursor_changed(move |tree_view| {
+            if let Some((model, iter)) = tree_view.selection().selected() {
+                let path: String = model.value(&iter, PATH_COLUMN).get().unwrap();
+                details.show_song(&path);
             }
         });
     }
 
-    let window_clone = window.clone();
     {
+        // Clicking the n-th star of the rating column rates the song n stars; clicking the
+        // current rating again clears it.
         let list_store = Rc::clone(&list_store);
-        refresh_button.connect_clicked(move |_| {
-            if let Err(e) = remove_database_file_if_exists() {
-                eprintln!("Error: Could not remove existing database file: {}", e);
-                show_error_dialog(&window_clone, &format!("Error: {}", e));
-                return;
+        let window = Rc::clone(&window);
+        let sync_ratings_toggle = sync_ratings_toggle.clone();
+        let library = Rc::clone(&library);
+        tree_view.connect_button_press_event(move |tree_view, event| {
+            let (x, y) = event.position();
+            let Some((Some(path), Some(column), cell_x, _)) = tree_view.path_at_pos(x as i32, y as i32) else {
+                return Inhibit(false);
+            };
+            if column != col_rating || event.button() != 1 {
+                return Inhibit(false);
             }
 
-            let directory = directory_entry.text().to_string();
+            let list_store = list_store.borrow();
+            let Some(iter) = list_store.iter(&path) else {
+                return Inhibit(false);
+            };
+            let song_path: String = list_store.value(&iter, PATH_COLUMN).get().unwrap_or_default();
+            let current: String = list_store.value(&iter, RATING_COLUMN).get().unwrap_or_default();
+            let current = current.chars().filter(|star| *star == '★').count() as i32;
 
-            if directory.is_empty() {
-                eprintln!("Error: No directory provided.");
-                show_error_dialog(&window_clone, "No directory provided.");
-                return;
-            }
+            let star_width = tree_view.create_pango_layout(Some("★")).pixel_size().0.max(1);
+            let clicked = (cell_x / star_width + 1).min(MAX_RATING);
+            let rating = if clicked == current { 0 } else { clicked };
 
-            if let Err(e) = save_directory_to_config(&directory) {
-                eprintln!("Failed to save directory to config: {}", e);
-                show_error_dialog(&window_clone, &format!("Failed to save directory to config: {}", e));
-                return;
+            if let Err(e) = library.rate_song(&song_path, rating, sync_ratings_toggle.is_active()) {
+                show_error_dialog(&window, &format!("Could not save the rating of {}: {}", song_path, e));
+                return Inhibit(false);
             }
+            list_store.set_value(&iter, RATING_COLUMN as u32, &format_rating(rating).to_value());
+            Inhibit(false)
+        });
+    }
 
-            let extracted_data = extract_songs_from_directory(&directory);
-            let total_songs = extracted_data.len() as f64;
-
-            if total_songs == 0.0 {
-                eprintln!("No songs found in the specified directory.");
-                show_error_dialog(&window_clone, "No songs found in the specified directory.");
-                return;
+    {
+        // Right-clicking a song opens a menu to edit the labels of the song or of its album.
+        let window = Rc::clone(&window);
+        let details = details.clone();
+        let library = Rc::clone(&library);
+        tree_view.connect_button_press_event(move |tree_view, event| {
+            if event.button() != 3 {
+                return Inhibit(false);
             }
-            
-            let connection = match create_database_connection() {
-                Ok(conn) => conn,
-                Err(err) => {
-                    eprintln!("Failed to connect to the database: {}", err);
-                    show_error_dialog(&window_clone, &format!("Failed to connect to the database: {}", err));
-                    return;
-                }
+            let (x, y) = event.position();
+            let Some((Some(path), _, _, _)) = tree_view.path_at_pos(x as i32, y as i32) else {
+                return Inhibit(false);
             };
+            tree_view.selection().select_path(&path);
+            let Some(model) = tree_view.model() else {
+                return Inhibit(false);
+            };
+            let Some(iter) = model.iter(&path) else {
+                return Inhibit(false);
+            };
+            let title: String = model.value(&iter, 0).get().unwrap_or_default();
+            let album: String = model.value(&iter, 2).get().unwrap_or_default();
+            let song_path: String = model.value(&iter, PATH_COLUMN).get().unwrap_or_default();
 
-            if let Err(e) = create_tables_if_not_exist(&connection) {
-                eprintln!("Failed to create tables in the database: {}", e);
-                show_error_dialog(&window_clone, &format!("Failed to create tables in the database: {}", e));
-                return;
-            }
-
-            let mut processed_songs = 0.0;
-            for tag_map in extracted_data {
-                match insert_song_into_database(&connection, tag_map) {
-                    Ok(_) => {
-                        processed_songs += 1.0;
-
-                        let progress = processed_songs / total_songs;
-                        progress_bar.set_fraction(progress);
-                        progress_bar.set_text(Some(&format!("{:.0}% Complete", progress * 100.0)));
+            let menu = gtk::Menu::new();
+            let song_item = gtk::MenuItem::with_label("Edit song labels…");
+            let album_item = gtk::MenuItem::with_label("Edit album labels…");
+            menu.append(&song_item);
+            menu.append(&album_item);
 
-                        while gtk::events_pending() {
-                            gtk::main_iteration();
-                        }
+            {
+                let window = Rc::clone(&window);
+                let details = details.clone();
+                let library = Rc::clone(&library);
+                let song_path = song_path.clone();
+                song_item.connect_activate(move |_| {
+                    let dialog_title = format!("Labels of {}", title);
+                    if show_labels_dialog(&window, &library, &dialog_title, LabelTarget::Song(song_path.clone())) {
+                        details.show_song(&song_path);
                     }
-                    Err(e) => {
-                        eprintln!("Failed to insert song into database: {}", e);
-                        show_error_dialog(&window_clone, &format!("Failed to insert song into database: {}", e));
+                });
+            }
+            {
+                let window = Rc::clone(&window);
+                let details = details.clone();
+                let library = Rc::clone(&library);
+                album_item.connect_activate(move |_| {
+                    match library.album_path(&song_path) {
+                        Ok(Some(album_path)) => {
+                            let dialog_title = format!("Labels of {}", album);
+                            if show_labels_dialog(&window, &library, &dialog_title, LabelTarget::Album(album_path)) {
+                                details.show_song(&song_path);
+                            }
+                        }
+                        Ok(None) => show_error_dialog(&window, "The song has no album."),
+                        Err(e) => show_error_dialog(&window, &format!("Could not find the album: {}", e)),
                     }
-                }
+                });
             }
 
-            populate_song_list(&list_store.borrow());
+            menu.show_all();
+            menu.popup_at_pointer(Some(event));
+            Inhibit(true)
         });
     }
 
-    let label_title_clone = label_title.clone();
-    let label_path_clone = label_path.clone();
-    let label_track_clone = label_track.clone();
-    let label_year_clone = label_year.clone();
-    let label_genre_clone = label_genre.clone();
+    {
+        let list_store = Rc::clone(&list_store);
+        let now_playing_clone = now_playing.clone();
+        tree_view.connect_row_activated(move |_, path, _| {
+            let start = path.indices().first().copied().unwrap_or(0).max(0) as usize;
+            now_playing_clone.play(queue_from_list(&list_store.borrow()), start);
+        });
+    }
 
-    tree_view.connect_cursor_changed(move |tree_view| {
-        if let Some((model, iter)) = tree_view.selection().selected() {
-            let title: String = model.value(&iter, 0).get().unwrap();
-            if let Ok(song_details) = get_song_details(&title) {
-                label_title_clone.set_text(&format!("Title: {}", song_details.title));
-                label_path_clone.set_text(&format!("Path: {}", song_details.path));
-                label_track_clone.set_text(&format!("Track number: {}", song_details.track_number));
-                label_year_clone.set_text(&format!("Year: {}", song_details.year));
-                label_genre_clone.set_text(&format!("Genre: {}", song_details.genre));
+    {
+        let list_store = Rc::clone(&list_store);
+        let tree_view = tree_view.clone();
+        now_playing.set_queue_provider(move || {
+            let entries = queue_from_list(&list_store.borrow());
+            if entries.is_empty() {
+                return None;
             }
-        }
-    });
+            let start = tree_view.selection().selected()
+                .and_then(|(model, iter)| model.path(&iter))
+                .and_then(|path| path.indices().first().copied())
+                .map_or(0, |index| index.max(0) as usize);
+            Some((entries, start))
+        });
+    }
+
+    library_browser.connect_song_selected(move |path| details.show_song(path));
+
+    {
+        let window = Rc::clone(&window);
+        let settings = settings.clone();
+        let dark_mode_enabled = Rc::clone(&dark_mode_enabled);
+        let directory_entry = directory_entry.clone();
+        let list_store = Rc::clone(&list_store);
+        let library_browser = Rc::clone(&library_browser);
+        let library = Rc::clone(&library);
+        preferences_button.connect_clicked(move |_| {
+            let current = load_settings().unwrap_or_else(|e| {
+                eprintln!("Could not load the settings: {}", e);
+                config::Settings::default()
+            });
+            let Some(edited) = show_preferences(&window, &current) else {
+                return;
+            };
+            if let Err(e) = save_settings(&edited) {
+                eprintln!("Could not save the settings: {}", e);
+                show_error_dialog(&window, &format!("Could not save the settings: {}", e));
+                return;
+            }
+
+            let removed_roots: Vec<String> = current.roots.iter()
+                .filter(|root| edited.root(&root.path).is_none())
+                .map(|root| root.path.clone())
+                .collect();
+            if !removed_roots.is_empty() {
+                if let Err(e) = remove_library_roots(&library, &removed_roots) {
+                    eprintln!("Could not remove the songs of the removed directories: {}", e);
+                    show_error_dialog(&window, &format!("Could not remove the songs of the removed directories: {}", e));
+                }
+                populate_song_list(&list_store.borrow(), &library);
+                library_browser.reload();
+            }
+
+            *dark_mode_enabled.borrow_mut() = edited.theme == Theme::Dark;
+            settings.set_gtk_application_prefer_dark_theme(edited.theme == Theme::Dark);
+            directory_entry.set_text(edited.music_directory().unwrap_or_default());
+            gapless_toggle.set_active(edited.gapless);
+            crossfade_spin.set_value(f64::from(edited.crossfade_ms) / 1000.0);
+            sync_ratings_toggle.set_active(edited.sync_ratings);
+        });
+    }
 
     let dark_mode_enabled_clone = Rc::clone(&dark_mode_enabled);
     let settings_clone = settings.clone();
@@ -318,10 +1091,48 @@ pub fn build_ui() {
         let mut dark_mode = dark_mode_enabled_clone.borrow_mut();
         *dark_mode = !*dark_mode;
         settings_clone.set_gtk_application_prefer_dark_theme(*dark_mode);
+        let theme = if *dark_mode { Theme::Dark } else { Theme::Light };
+        if let Err(e) = update_settings(|settings| settings.theme = theme) {
+            eprintln!("Could not save the theme: {}", e);
+        }
     });
 
-    let _window_clone = window.clone();
+    // The size and position of the window are followed while it is not maximized, so the
+    // size restored is the one it had before being maximized.
+    let window_state = Rc::new(RefCell::new(app_settings.window.clone()));
+    {
+        let window_state = Rc::clone(&window_state);
+        window.connect_configure_event(move |window, _| {
+            let mut state = window_state.borrow_mut();
+            if !state.maximized {
+                (state.width, state.height) = window.size();
+                let (x, y) = window.position();
+                (state.x, state.y) = (Some(x), Some(y));
+            }
+            false
+        });
+    }
+    {
+        let window_state = Rc::clone(&window_state);
+        window.connect_window_state_event(move |_, event| {
+            window_state.borrow_mut().maximized = event.new_window_state().contains(gtk::gdk::WindowState::MAXIMIZED);
+            Inhibit(false)
+        });
+    }
+
     window.connect_delete_event(move |_, _| {
+        let window = WindowSettings {
+            browser_width: Some(browser_paned.position()),
+            details_position: Some(details_paned.position()),
+            ..window_state.borrow().clone()
+        };
+        let saved = update_settings(|settings| {
+            settings.columns = column_layout(&tree_view, &list_store.borrow(), &song_columns, std::mem::take(&mut settings.columns.visible));
+            settings.window = window;
+        });
+        if let Err(e) = saved {
+            eprintln!("Could not save the window layout: {}", e);
+        }
         gtk::main_quit();
         Inhibit(false)
     });
@@ -1 +1,15 @@
 pub mod main_ui;
+pub mod song_list;
+pub mod library_browser;
+pub mod lyrics_editor;
+pub mod synced_lyrics_pane;
+pub mod loudness_scan;
+pub mod now_playing;
+pub mod labels_dialog;
+pub mod duplicates_dialog;
+pub mod recordings_dialog;
+pub mod release_matching;
+pub mod scan_report;
+pub mod scan_log;
+pub mod preferences;
+pub mod cli;
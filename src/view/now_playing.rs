@@ -0,0 +1,180 @@
+use gtk::prelude::*;
+use gtk::{Adjustment, Box as GtkBox, Button, Image, Label, Orientation, Scale};
+use crate::controller::library::Library;
+use crate::model::music_miner::audio_properties::format_duration;
+use crate::model::playback::player::{PlaybackState, Player};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How often the bar refreshes the position and the current song, in milliseconds.
+const REFRESH_INTERVAL_MS: u64 = 250;
+
+/// A song of the play queue, with the title and artist shown while it plays.
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    pub path: String,
+    pub title: String,
+    pub artist: String,
+}
+
+/// Returns the songs to play, and the index to start from, when play is pressed with an empty queue.
+type QueueProvider = Rc<dyn Fn() -> Option<(Vec<QueueEntry>, usize)>>;
+
+/// The bar at the bottom of the main window with the playback controls, the current song and
+/// a seek slider.
+#[derive(Clone)]
+pub struct NowPlayingBar {
+    pub widget: GtkBox,
+    player: Rc<Player>,
+    /// The library the finished songs are recorded in.
+    library: Rc<Library>,
+    queue: Rc<RefCell<Vec<QueueEntry>>>,
+    queue_provider: Rc<RefCell<Option<QueueProvider>>>,
+    play_button: Button,
+    /// The state shown by the play button, so its icon is only replaced when the state changes.
+    shown_state: Rc<Cell<Option<PlaybackState>>>,
+    song_label: Label,
+    time_label: Label,
+    seek_scale: Scale,
+}
+
+impl NowPlayingBar {
+    pub fn new(player: Rc<Player>, library: Rc<Library>) -> Self {
+        let widget = GtkBox::new(Orientation::Horizontal, 5);
+
+        let previous_button = Button::new();
+        previous_button.set_image(Some(&Image::from_icon_name(Some("media-skip-backward"), gtk::IconSize::Button)));
+        let play_button = Button::new();
+        play_button.set_image(Some(&Image::from_icon_name(Some("media-playback-start"), gtk::IconSize::Button)));
+        let next_button = Button::new();
+        next_button.set_image(Some(&Image::from_icon_name(Some("media-skip-forward"), gtk::IconSize::Button)));
+        let stop_button = Button::new();
+        stop_button.set_image(Some(&Image::from_icon_name(Some("media-playback-stop"), gtk::IconSize::Button)));
+
+        let song_label = Label::new(Some("Not playing"));
+        song_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        song_label.set_width_chars(30);
+        song_label.set_xalign(0.0);
+
+        let seek_scale = Scale::new(Orientation::Horizontal, Some(&Adjustment::new(0.0, 0.0, 1.0, 1.0, 10.0, 0.0)));
+        seek_scale.set_draw_value(false);
+        seek_scale.set_sensitive(false);
+
+        let time_label = Label::new(Some("0:00 / 0:00"));
+
+        widget.pack_start(&previous_button, false, false, 0);
+        widget.pack_start(&play_button, false, false, 0);
+        widget.pack_start(&next_button, false, false, 0);
+        widget.pack_start(&stop_button, false, false, 0);
+        widget.pack_start(&song_label, false, false, 5);
+        widget.pack_start(&seek_scale, true, true, 5);
+        widget.pack_start(&time_label, false, false, 5);
+
+        let bar = NowPlayingBar {
+            widget,
+            player,
+            library,
+            queue: Rc::new(RefCell::new(Vec::new())),
+            queue_provider: Rc::new(RefCell::new(None)),
+            play_button,
+            shown_state: Rc::new(Cell::new(None)),
+            song_label,
+            time_label,
+            seek_scale,
+        };
+
+        {
+            let player = Rc::clone(&bar.player);
+            previous_button.connect_clicked(move |_| player.previous());
+        }
+        {
+            let player = Rc::clone(&bar.player);
+            next_button.connect_clicked(move |_| player.next());
+        }
+        {
+            let player = Rc::clone(&bar.player);
+            stop_button.connect_clicked(move |_| player.stop());
+        }
+        {
+            let bar_clone = bar.clone();
+            bar.play_button.connect_clicked(move |_| bar_clone.toggle_pause());
+        }
+        {
+            // `change-value` is only emitted for user interaction, not for `set_value()`.
+            let player = Rc::clone(&bar.player);
+            bar.seek_scale.connect_change_value(move |_, _, value| {
+                player.seek((value.max(0.0) * 1000.0) as u64);
+                Inhibit(false)
+            });
+        }
+        {
+            let bar_clone = bar.clone();
+            glib::timeout_add_local(Duration::from_millis(REFRESH_INTERVAL_MS), move || {
+                bar_clone.refresh();
+                glib::Continue(true)
+            });
+        }
+
+        bar
+    }
+
+    /// Sets the function called to build a queue when play is pressed and nothing has been queued yet.
+    pub fn set_queue_provider(&self, provider: impl Fn() -> Option<(Vec<QueueEntry>, usize)> + 'static) {
+        *self.queue_provider.borrow_mut() = Some(Rc::new(provider));
+    }
+
+    /// Replaces the play queue and starts playing the song at `start`.
+    pub fn play(&self, entries: Vec<QueueEntry>, start: usize) {
+        let paths = entries.iter().map(|entry| entry.path.clone()).collect();
+        *self.queue.borrow_mut() = entries;
+        self.player.play_queue(paths, start);
+    }
+
+    fn toggle_pause(&self) {
+        if self.queue.borrow().is_empty() {
+            let provider = self.queue_provider.borrow().clone();
+            if let Some((entries, start)) = provider.and_then(|provider| provider()) {
+                self.play(entries, start);
+            }
+        } else {
+            self.player.toggle_pause();
+        }
+    }
+
+    /// Shows the current song, position and state of the player, and records the songs it
+    /// has finished in the listening history.
+    fn refresh(&self) {
+        for play in self.player.take_plays() {
+            if let Err(e) = self.library.record_play(&play.path, play.fraction, "player") {
+                eprintln!("Could not record the playback of {}: {}", play.path, e);
+            }
+        }
+
+        let status = self.player.status();
+
+        if self.shown_state.replace(Some(status.state)) != Some(status.state) {
+            let icon = if status.state == PlaybackState::Playing { "media-playback-pause" } else { "media-playback-start" };
+            self.play_button.set_image(Some(&Image::from_icon_name(Some(icon), gtk::IconSize::Button)));
+        }
+
+        let entry = status.queue_index.and_then(|index| self.queue.borrow().get(index).cloned());
+        match entry {
+            Some(entry) => self.song_label.set_text(&format!("{} — {}", entry.title, entry.artist)),
+            None => self.song_label.set_text("Not playing"),
+        }
+        if let Some(error) = &status.error {
+            self.song_label.set_tooltip_text(Some(error));
+        }
+
+        let duration_ms = status.duration_ms.unwrap_or(0);
+        self.seek_scale.set_sensitive(status.state != PlaybackState::Stopped && duration_ms > 0);
+        self.seek_scale.set_range(0.0, (duration_ms as f64 / 1000.0).max(1.0));
+        self.seek_scale.set_value(status.position_ms as f64 / 1000.0);
+        self.time_label.set_text(&format!(
+            "{} / {}",
+            format_duration(status.position_ms as i64),
+            format_duration(duration_ms as i64)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use gtk::prelude::*;
+use gtk::{Button, CellRendererText, CellRendererToggle, CheckButton, ComboBoxText, Dialog, Entry, FileChooserAction,
+    FileChooserDialog, Frame, Grid, Label, ListStore, Orientation, ResponseType, ScrolledWindow, SpinButton, TreeView,
+    TreeViewColumn, Window, Box as GtkBox};
+use crate::config::{LibraryRoot, Settings, Theme};
+
+/// Columns of the roots list: whether the root is scanned, its path and its exclude patterns.
+const ROOT_ENABLED: u32 = 0;
+const ROOT_PATH: u32 = 1;
+const ROOT_EXCLUDES: u32 = 2;
+
+/// Separates the exclude patterns of a root in the roots list.
+const EXCLUDES_SEPARATOR: &str = "; ";
+
+/// Adds a labelled widget as a row of a grid.
+fn attach_row(grid: &Grid, row: i32, label: &str, widget: &impl IsA<gtk::Widget>) {
+    let label = Label::new(Some(label));
+    label.set_xalign(0.0);
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(widget, 1, row, 1, 1);
+}
+
+/// Builds a framed grid for a group of settings.
+fn section(content: &GtkBox, title: &str) -> Grid {
+    let frame = Frame::new(Some(title));
+    let grid = Grid::new();
+    grid.set_column_spacing(10);
+    grid.set_row_spacing(5);
+    grid.set_margin_top(5);
+    grid.set_margin_bottom(5);
+    grid.set_margin_start(5);
+    grid.set_margin_end(5);
+    frame.add(&grid);
+    content.pack_start(&frame, false, false, 5);
+    grid
+}
+
+/// Adds an editable text column to the roots list, writing edits back to `column` of the store.
+fn append_root_column(tree_view: &TreeView, store: &ListStore, title: &str, column: u32) {
+    let renderer = CellRendererText::new();
+    renderer.set_editable(true);
+    let store = store.clone();
+    renderer.connect_edited(move |_, path, new_text| {
+        if let Some(iter) = store.iter(&path) {
+            store.set(&iter, &[(column, &new_text.trim())]);
+        }
+    });
+
+    let tree_column = TreeViewColumn::new();
+    tree_column.set_title(title);
+    tree_column.set_resizable(true);
+    tree_column.set_expand(true);
+    tree_column.pack_start(&renderer, true);
+    tree_column.add_attribute(&renderer, "text", column as i32);
+    tree_view.append_column(&tree_column);
+}
+
+/// Builds the list of the library roots, with buttons to add and remove roots. Paths and
+/// exclude patterns are edited in place, the patterns being separated by `;`.
+fn roots_editor(window: &Window, roots: &[LibraryRoot]) -> (GtkBox, ListStore) {
+    let store = ListStore::new(&[bool::static_type(), String::static_type(), String::static_type()]);
+    for root in roots {
+        store.insert_with_values(None, &[
+            (ROOT_ENABLED, &root.enabled),
+            (ROOT_PATH, &root.path),
+            (ROOT_EXCLUDES, &root.excludes.join(EXCLUDES_SEPARATOR)),
+        ]);
+    }
+
+    let tree_view = TreeView::with_model(&store);
+    let toggle = CellRendererToggle::new();
+    {
+        let store = store.clone();
+        toggle.connect_toggled(move |_, path| {
+            if let Some(iter) = store.iter(&path) {
+                let enabled: bool = store.value(&iter, ROOT_ENABLED as i32).get().unwrap_or(true);
+                store.set(&iter, &[(ROOT_ENABLED, &!enabled)]);
+            }
+        });
+    }
+    let enabled_column = TreeViewColumn::new();
+    enabled_column.set_title("Scan");
+    enabled_column.pack_start(&toggle, false);
+    enabled_column.add_attribute(&toggle, "active", ROOT_ENABLED as i32);
+    tree_view.append_column(&enabled_column);
+    append_root_column(&tree_view, &store, "Directory", ROOT_PATH);
+    append_root_column(&tree_view, &store, "Exclude (e.g. **/Samples/**; *.tmp.mp3)", ROOT_EXCLUDES);
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.set_min_content_height(100);
+    scrolled_window.set_hexpand(true);
+    scrolled_window.add(&tree_view);
+
+    let add_button = Button::with_label("Add...");
+    {
+        let window = window.clone();
+        let store = store.clone();
+        add_button.connect_clicked(move |_| {
+            let dialog = FileChooserDialog::new(Some("Select Music Directory"), Some(&window), FileChooserAction::SelectFolder);
+            dialog.add_buttons(&[("Cancel", ResponseType::Cancel), ("Select", ResponseType::Accept)]);
+            if dialog.run() == ResponseType::Accept {
+                if let Some(folder) = dialog.filename() {
+                    store.insert_with_values(None, &[
+                        (ROOT_ENABLED, &true),
+                        (ROOT_PATH, &folder.to_string_lossy().into_owned()),
+                        (ROOT_EXCLUDES, &String::new()),
+                    ]);
+                }
+            }
+            dialog.close();
+        });
+    }
+    let remove_button = Button::with_label("Remove");
+    {
+        let tree_view = tree_view.clone();
+        let store = store.clone();
+        remove_button.connect_clicked(move |_| {
+            if let Some((_, iter)) = tree_view.selection().selected() {
+                store.remove(&iter);
+            }
+        });
+    }
+
+    let buttons = GtkBox::new(Orientation::Horizontal, 5);
+    buttons.pack_start(&add_button, false, false, 0);
+    buttons.pack_start(&remove_button, false, false, 0);
+    let editor = GtkBox::new(Orientation::Vertical, 5);
+    editor.pack_start(&scrolled_window, true, true, 0);
+    editor.pack_start(&buttons, false, false, 0);
+    (editor, store)
+}
+
+/// Reads the roots of the roots list, leaving out the rows without a path and repeated paths.
+fn read_roots(store: &ListStore) -> Vec<LibraryRoot> {
+    let mut roots = Vec::new();
+    let Some(iter) = store.iter_first() else {
+        return roots;
+    };
+    loop {
+        let path: String = store.value(&iter, ROOT_PATH as i32).get().unwrap_or_default();
+        let excludes: String = store.value(&iter, ROOT_EXCLUDES as i32).get().unwrap_or_default();
+        if !path.trim().is_empty() && !roots.iter().any(|root: &LibraryRoot| root.path == path.trim()) {
+            roots.push(LibraryRoot {
+                path: path.trim().to_string(),
+                enabled: store.value(&iter, ROOT_ENABLED as i32).get().unwrap_or(true),
+                excludes: excludes.split(';')
+                    .map(str::trim)
+                    .filter(|pattern| !pattern.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            });
+        }
+        if !store.iter_next(&iter) {
+            break;
+        }
+    }
+    roots
+}
+
+/// Returns the text of an entry, or `None` if it is empty.
+fn optional_text(entry: &Entry) -> Option<String> {
+    let text = entry.text().trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+/// # Show Preferences function
+///
+/// Shows the settings of `Config.TOML` in a dialog: the library roots (whether each is scanned
+/// by Refresh and its exclude patterns), the theme, the scan options, the playback options,
+/// whether ratings are written to tags, the quarantine folder and the MusicBrainz dump. The
+/// column layout is kept as it is.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialog.
+/// * `settings` - The current settings.
+///
+/// # Returns
+/// The edited settings if the dialog was accepted, `None` if it was cancelled.
+pub fn show_preferences(window: &Window, settings: &Settings) -> Option<Settings> {
+    let dialog = Dialog::with_buttons(
+        Some("Preferences"),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_default_size(650, 550);
+    let content = dialog.content_area();
+
+    let library = section(&content, "Library");
+    let (roots_box, roots_store) = roots_editor(window, &settings.roots);
+    attach_row(&library, 0, "Music directories", &roots_box);
+    let infer_toggle = CheckButton::with_label("Infer missing tags from paths");
+    infer_toggle.set_active(settings.scan.infer_tags_from_paths);
+    library.attach(&infer_toggle, 1, 1, 1, 1);
+    let hidden_toggle = CheckButton::with_label("Skip hidden files and directories");
+    hidden_toggle.set_active(settings.scan.skip_hidden);
+    library.attach(&hidden_toggle, 1, 2, 1, 1);
+    let quarantine_entry = Entry::new();
+    quarantine_entry.set_text(settings.quarantine_dir.as_deref().unwrap_or_default());
+    quarantine_entry.set_placeholder_text(Some("~/.local/share/musicmanager/Quarantine"));
+    attach_row(&library, 3, "Quarantine folder", &quarantine_entry);
+    let dump_entry = Entry::new();
+    dump_entry.set_text(settings.musicbrainz_dump.as_deref().unwrap_or_default());
+    attach_row(&library, 4, "MusicBrainz dump", &dump_entry);
+
+    let appearance = section(&content, "Appearance");
+    let theme_combo = ComboBoxText::new();
+    theme_combo.append(Some("dark"), "Dark");
+    theme_combo.append(Some("light"), "Light");
+    theme_combo.set_active_id(Some(if settings.theme == Theme::Dark { "dark" } else { "light" }));
+    attach_row(&appearance, 0, "Theme", &theme_combo);
+
+    let playback = section(&content, "Playback");
+    let gapless_toggle = CheckButton::with_label("Gapless playback");
+    gapless_toggle.set_active(settings.gapless);
+    playback.attach(&gapless_toggle, 1, 0, 1, 1);
+    let crossfade_spin = SpinButton::with_range(0.0, 12.0, 0.5);
+    crossfade_spin.set_digits(1);
+    crossfade_spin.set_value(f64::from(settings.crossfade_ms) / 1000.0);
+    attach_row(&playback, 1, "Crossfade (s)", &crossfade_spin);
+    let sync_ratings_toggle = CheckButton::with_label("Write ratings to tags (POPM)");
+    sync_ratings_toggle.set_active(settings.sync_ratings);
+    playback.attach(&sync_ratings_toggle, 1, 2, 1, 1);
+
+    dialog.show_all();
+    let response = dialog.run();
+
+    let edited = (response == ResponseType::Accept).then(|| {
+        let mut edited = settings.clone();
+        edited.roots = read_roots(&roots_store);
+        edited.scan.infer_tags_from_paths = infer_toggle.is_active();
+        edited.scan.skip_hidden = hidden_toggle.is_active();
+        edited.quarantine_dir = optional_text(&quarantine_entry);
+        edited.musicbrainz_dump = optional_text(&dump_entry);
+        edited.theme = if theme_combo.active_id().as_deref() == Some("light") { Theme::Light } else { Theme::Dark };
+        edited.gapless = gapless_toggle.is_active();
+        edited.crossfade_ms = (crossfade_spin.value() * 1000.0).round() as u32;
+        edited.sync_ratings = sync_ratings_toggle.is_active();
+        edited
+    });
+    dialog.close();
+    edited
+}
@@ -0,0 +1,155 @@
+use gtk::prelude::*;
+use gtk::{ButtonsType, CheckButton, Dialog, Frame, Grid, Label, MessageType, ProgressBar, RadioButton,
+    ResponseType, ScrolledWindow, Window, Box as GtkBox, Orientation};
+use crate::controller::controller::{compute_fingerprints, copy_song_metadata, RecordingCopy};
+use crate::controller::library::Library;
+use crate::view::scan_report::{append_problems, run_dialog, run_in_background, show_error_dialog};
+use std::sync::{Arc, Mutex};
+
+/// Describes a copy in the dialog: its path, its artist, title and album, and how many of
+/// its fields come from its tag.
+fn describe_copy(copy: &RecordingCopy) -> String {
+    format!("{}\n{} – {} ({}), {} tagged field(s)", copy.path, copy.artist, copy.title, copy.album, copy.tagged_fields)
+}
+
+/// The widgets of one copy: whether it is the source of the metadata, and whether it
+/// receives it.
+struct CopyRow {
+    source: RadioButton,
+    update: CheckButton,
+}
+
+/// # Show Recordings Dialog function
+///
+/// Fingerprints the songs of the library that have no up-to-date fingerprint on a worker
+/// thread, showing the progress in `progress_bar`, then lists the groups of songs that are
+/// the same recording. In each group the best tagged copy is preselected as the source, and
+/// the copies with fewer tagged fields are ticked to receive its metadata.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialog.
+/// * `library` - The library holding the songs.
+/// * `progress_bar` - The progress bar of the main window.
+///
+/// # Returns
+/// `true` if any song was retagged, so the song list needs reloading.
+pub fn show_recordings_dialog(window: &Window, library: &Library, progress_bar: &ProgressBar) -> bool {
+    let problems = library.fingerprint_stamps().map_err(|e| e.to_string()).and_then(|songs| {
+        let progress = Arc::new(Mutex::new((0, 0)));
+        let worker_progress = Arc::clone(&progress);
+        let work = move || compute_fingerprints(&songs, |checked, total| *worker_progress.lock().unwrap() = (checked, total));
+        let (fingerprints, problems) = run_in_background(work, || {
+            let (checked, total) = *progress.lock().unwrap();
+            progress_bar.set_fraction(if total == 0 { 0.0 } else { checked as f64 / total as f64 });
+            progress_bar.set_text(Some(&format!("Fingerprinting songs ({}/{})", checked, total)));
+        }).ok_or_else(|| "The fingerprinting stopped unexpectedly".to_string())?;
+        library.store_fingerprints(&fingerprints).map_err(|e| e.to_string())?;
+        Ok(problems)
+    });
+    let problems = match problems {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!("Failed to fingerprint the library: {}", e);
+            show_error_dialog(window, &format!("Failed to fingerprint the library: {}", e));
+            return false;
+        }
+    };
+    progress_bar.set_fraction(1.0);
+    progress_bar.set_text(Some("Fingerprinting complete"));
+
+    let groups = match library.same_recordings() {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("Failed to match the recordings: {}", e);
+            show_error_dialog(window, &format!("Failed to match the recordings: {}", e));
+            return false;
+        }
+    };
+
+    if groups.is_empty() {
+        let mut summary = String::from("No song was found twice in the library.");
+        append_problems(&mut summary, &problems);
+        run_dialog(window, MessageType::Info, ButtonsType::Ok, &summary);
+        return false;
+    }
+
+    let dialog = Dialog::with_buttons(
+        Some(&format!("Same Recordings ({} groups)", groups.len())),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Copy Metadata", ResponseType::Accept)],
+    );
+    dialog.set_default_size(750, 500);
+
+    let content = dialog.content_area();
+    content.set_spacing(5);
+    let hint = Label::new(Some("Choose the copy to copy the tags from, and tick the copies to tag. Only MP3 and FLAC files can be tagged."));
+    hint.set_line_wrap(true);
+    content.pack_start(&hint, false, false, 5);
+
+    let groups_box = GtkBox::new(Orientation::Vertical, 5);
+    let rows: Vec<Vec<CopyRow>> = groups.iter()
+        .map(|group| {
+            let frame = Frame::new(Some(&group[0].title));
+            let grid = Grid::new();
+            grid.set_column_spacing(10);
+            grid.attach(&Label::new(Some("Source")), 0, 0, 1, 1);
+            grid.attach(&Label::new(Some("Tag")), 1, 0, 1, 1);
+
+            let mut group_rows: Vec<CopyRow> = Vec::new();
+            for (index, copy) in group.iter().enumerate() {
+                let source = match group_rows.first() {
+                    Some(first) => RadioButton::from_widget(&first.source),
+                    None => RadioButton::new(),
+                };
+                let update = CheckButton::new();
+                update.set_active(index > 0 && copy.tagged_fields < group[0].tagged_fields);
+                let description = Label::new(Some(&describe_copy(copy)));
+                description.set_xalign(0.0);
+
+                let row = index as i32 + 1;
+                grid.attach(&source, 0, row, 1, 1);
+                grid.attach(&update, 1, row, 1, 1);
+                grid.attach(&description, 2, row, 1, 1);
+                group_rows.push(CopyRow { source, update });
+            }
+
+            frame.add(&grid);
+            groups_box.pack_start(&frame, false, false, 0);
+            group_rows
+        })
+        .collect();
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&groups_box);
+    content.pack_start(&scrolled_window, true, true, 5);
+
+    dialog.show_all();
+    let response = dialog.run();
+    dialog.close();
+    if response != ResponseType::Accept {
+        return false;
+    }
+
+    let mut copy_problems = Vec::new();
+    let mut tagged = 0;
+    for (group, group_rows) in groups.iter().zip(&rows) {
+        let Some(source) = group.iter().zip(group_rows).find(|(_, row)| row.source.is_active()).map(|(copy, _)| copy) else {
+            continue;
+        };
+        for (copy, row) in group.iter().zip(group_rows) {
+            if !row.update.is_active() || copy.path == source.path {
+                continue;
+            }
+            match copy_song_metadata(library, &source.path, &copy.path) {
+                Ok(()) => tagged += 1,
+                Err(e) => copy_problems.push(format!("Could not tag {}: {}", copy.path, e)),
+            }
+        }
+    }
+
+    let mut summary = format!("Tagged {} song(s).", tagged);
+    append_problems(&mut summary, &copy_problems);
+    run_dialog(window, MessageType::Info, ButtonsType::Ok, &summary);
+    tagged > 0
+}
@@ -0,0 +1,186 @@
+use gtk::prelude::*;
+use gtk::{Box as GtkBox, ButtonsType, CheckButton, Dialog, FileChooserAction, FileChooserDialog, FileFilter, Frame, Grid,
+    Label, MessageType, Orientation, ProgressBar, ResponseType, ScrolledWindow, Window};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::config::load_settings;
+use crate::controller::controller::{apply_album_match, match_albums, AlbumMatch};
+use crate::controller::library::Library;
+use crate::view::scan_report::{append_problems, run_dialog, run_in_background, show_error_dialog};
+
+/// Asks for the MusicBrainz dump, starting from the one used last time.
+fn choose_dump(window: &Window) -> Option<PathBuf> {
+    let dialog = FileChooserDialog::new(Some("Select MusicBrainz Dump"), Some(window), FileChooserAction::Open);
+    dialog.add_buttons(&[("Cancel", ResponseType::Cancel), ("Open", ResponseType::Accept)]);
+
+    let filter = FileFilter::new();
+    filter.set_name(Some("MusicBrainz dumps (JSON, JSON lines, TSV)"));
+    for pattern in ["*.json", "*.jsonl", "*.tsv", "release"] {
+        filter.add_pattern(pattern);
+    }
+    dialog.add_filter(&filter);
+    let all_files = FileFilter::new();
+    all_files.set_name(Some("All files"));
+    all_files.add_pattern("*");
+    dialog.add_filter(&all_files);
+
+    if let Some(previous) = load_settings().ok().and_then(|settings| settings.musicbrainz_dump) {
+        let previous = PathBuf::from(previous);
+        if previous.exists() {
+            dialog.set_filename(&previous);
+        }
+    }
+
+    let dump = if dialog.run() == ResponseType::Accept { dialog.filename() } else { None };
+    dialog.close();
+    dump
+}
+
+/// Builds the frame of one matched album: a check button to apply the match and a grid of
+/// the proposed changes, one line per field of each song.
+fn album_frame(album_match: &AlbumMatch) -> (Frame, CheckButton) {
+    let frame = Frame::new(None);
+    let frame_box = GtkBox::new(Orientation::Vertical, 2);
+    let apply = CheckButton::with_label(&format!(
+        "{} – {} → {} – {} ({:.0}% match)",
+        album_match.album.artist, album_match.album.name,
+        album_match.release.artist, album_match.release.title, album_match.score * 100.0,
+    ));
+    apply.set_active(true);
+    frame_box.pack_start(&apply, false, false, 2);
+
+    let grid = Grid::new();
+    grid.set_column_spacing(10);
+    grid.set_margin_start(25);
+    let mut row = 0;
+    for proposal in &album_match.proposals {
+        let file_name = Path::new(&proposal.path).file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| proposal.path.clone());
+        let song = Label::new(Some(&file_name));
+        song.set_xalign(0.0);
+        grid.attach(&song, 0, row, 3, 1);
+        row += 1;
+        for change in &proposal.changes {
+            let old = if change.old.is_empty() { "(empty)" } else { change.old.as_str() };
+            for (column, text) in [change.field.as_str(), old, &format!("→ {}", change.new)].into_iter().enumerate() {
+                let label = Label::new(Some(text));
+                label.set_xalign(0.0);
+                label.set_selectable(true);
+                grid.attach(&label, column as i32, row, 1, 1);
+            }
+            row += 1;
+        }
+    }
+    frame_box.pack_start(&grid, false, false, 2);
+    frame.add(&frame_box);
+    (frame, apply)
+}
+
+/// # Show Release Matching function
+///
+/// Asks for a local MusicBrainz dump (JSON, JSON lines or TSV), loads it and matches the
+/// albums of the library to its releases on a worker thread while showing the progress in
+/// `progress_bar`. The changes proposed for each matched album are then listed: titles, track
+/// and disc numbers, release dates and MBIDs. The ticked albums have the changes written
+/// into their tags and the database.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialogs.
+/// * `library` - The library holding the albums.
+/// * `progress_bar` - The progress bar of the main window.
+///
+/// # Returns
+/// `true` if any album was retagged, so the song list needs reloading.
+pub fn show_release_matching(window: &Window, library: &Library, progress_bar: &ProgressBar) -> bool {
+    let Some(dump) = choose_dump(window) else {
+        return false;
+    };
+    let albums = match library.albums_to_match() {
+        Ok(albums) => albums,
+        Err(e) => {
+            eprintln!("Failed to read the albums: {}", e);
+            show_error_dialog(window, &format!("Failed to read the albums: {}", e));
+            return false;
+        }
+    };
+
+    let progress = Arc::new(Mutex::new((0, 0)));
+    let worker_progress = Arc::clone(&progress);
+    let work = move || match_albums(albums, &dump, |compared, total| *worker_progress.lock().unwrap() = (compared, total))
+        .map_err(|e| e.to_string());
+    let matches = run_in_background(work, || match *progress.lock().unwrap() {
+        (_, 0) => {
+            progress_bar.pulse();
+            progress_bar.set_text(Some("Loading the MusicBrainz dump"));
+        }
+        (compared, total) => {
+            progress_bar.set_fraction(compared as f64 / total as f64);
+            progress_bar.set_text(Some(&format!("Matching albums ({}/{})", compared, total)));
+        }
+    });
+    let matches = match matches.unwrap_or_else(|| Err("The matching stopped unexpectedly".to_string())) {
+        Ok(matches) => matches,
+        Err(e) => {
+            eprintln!("Failed to match the albums: {}", e);
+            show_error_dialog(window, &format!("Failed to match the albums: {}", e));
+            return false;
+        }
+    };
+    progress_bar.set_fraction(1.0);
+    progress_bar.set_text(Some("Release matching complete"));
+
+    if matches.is_empty() {
+        run_dialog(window, MessageType::Info, ButtonsType::Ok, "No album needs correcting.");
+        return false;
+    }
+
+    let dialog = Dialog::with_buttons(
+        Some(&format!("Release Matches ({} albums)", matches.len())),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Apply Selected", ResponseType::Accept)],
+    );
+    dialog.set_default_size(800, 550);
+
+    let content = dialog.content_area();
+    content.set_spacing(5);
+    let hint = Label::new(Some("Tick the albums whose tags should be corrected. Only MP3 and FLAC files can be tagged."));
+    hint.set_line_wrap(true);
+    content.pack_start(&hint, false, false, 5);
+
+    let albums_box = GtkBox::new(Orientation::Vertical, 5);
+    let checks: Vec<CheckButton> = matches.iter()
+        .map(|album_match| {
+            let (frame, apply) = album_frame(album_match);
+            albums_box.pack_start(&frame, false, false, 0);
+            apply
+        })
+        .collect();
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&albums_box);
+    content.pack_start(&scrolled_window, true, true, 5);
+
+    dialog.show_all();
+    let response = dialog.run();
+    dialog.close();
+    if response != ResponseType::Accept {
+        return false;
+    }
+
+    let mut problems = Vec::new();
+    let mut applied = 0;
+    for (album_match, check) in matches.iter().zip(&checks) {
+        if !check.is_active() {
+            continue;
+        }
+        applied += 1;
+        problems.extend(apply_album_match(library, album_match));
+    }
+
+    let mut summary = format!("Corrected {} album(s).", applied);
+    append_problems(&mut summary, &problems);
+    run_dialog(window, MessageType::Info, ButtonsType::Ok, &summary);
+    applied > 0
+}
@@ -0,0 +1,124 @@
+use gtk::prelude::*;
+use gtk::{Box as GtkBox, Button, ComboBoxText, Entry, Label, ListStore, Orientation, ScrolledWindow, TreeView, Window,
+    WindowType};
+use log::LevelFilter;
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::config::get_local_dir;
+use crate::model::logging::{read_entries, LogEntry};
+use crate::view::scan_report::{append_text_column, show_error_dialog};
+
+/// The levels of the level filter, from the most to the least severe.
+const LEVELS: [(&str, LevelFilter); 5] = [
+    ("Errors", LevelFilter::Error),
+    ("Warnings", LevelFilter::Warn),
+    ("Info", LevelFilter::Info),
+    ("Debug", LevelFilter::Debug),
+    ("Everything", LevelFilter::Trace),
+];
+
+/// Reads the log of the local data directory, showing an error dialog if it cannot be read.
+fn load_entries(window: &Window) -> Vec<LogEntry> {
+    match get_local_dir().and_then(|directory| read_entries(&directory)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read the log: {}", e);
+            show_error_dialog(window, &format!("Failed to read the log: {}", e));
+            Vec::new()
+        }
+    }
+}
+
+/// Fills the list with the entries matching the filters, newest first, and shows their count.
+fn show_entries(store: &ListStore, count_label: &Label, entries: &[LogEntry], level: LevelFilter, path_filter: &str) {
+    store.clear();
+    let mut shown = 0;
+    for entry in entries.iter().rev().filter(|entry| entry.matches(level, path_filter)) {
+        let path = entry.path.clone().unwrap_or_default();
+        store.insert_with_values(None, &[(0, &entry.time), (1, &entry.level), (2, &path), (3, &entry.message)]);
+        shown += 1;
+    }
+    count_label.set_text(&format!("{} of {} entries", shown, entries.len()));
+}
+
+/// # Show Scan Log function
+///
+/// Opens a window listing the entries of the log written while scanning and tagging, newest
+/// first. The entries can be filtered by their minimum level and by a part of the file path
+/// they concern, and reloaded while a scan runs.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the log window.
+pub fn show_scan_log(window: &Window) {
+    let log_window = Window::new(WindowType::Toplevel);
+    log_window.set_title("Scan Log");
+    log_window.set_transient_for(Some(window));
+    log_window.set_default_size(900, 500);
+
+    let main_box = GtkBox::new(Orientation::Vertical, 5);
+    main_box.set_margin_top(10);
+    main_box.set_margin_bottom(10);
+    main_box.set_margin_start(10);
+    main_box.set_margin_end(10);
+
+    let filter_box = GtkBox::new(Orientation::Horizontal, 5);
+    let level_combo = ComboBoxText::new();
+    for (name, _) in LEVELS {
+        level_combo.append_text(name);
+    }
+    level_combo.set_active(Some(2));
+    let path_entry = Entry::new();
+    path_entry.set_placeholder_text(Some("Filter by path..."));
+    let reload_button = Button::with_label("Reload");
+    let count_label = Label::new(None);
+    filter_box.pack_start(&Label::new(Some("Level")), false, false, 0);
+    filter_box.pack_start(&level_combo, false, false, 0);
+    filter_box.pack_start(&path_entry, true, true, 0);
+    filter_box.pack_start(&reload_button, false, false, 0);
+    filter_box.pack_start(&count_label, false, false, 5);
+    main_box.pack_start(&filter_box, false, false, 0);
+
+    let store = ListStore::new(&[String::static_type(), String::static_type(), String::static_type(), String::static_type()]);
+    let tree_view = TreeView::with_model(&store);
+    append_text_column(&tree_view, "Time", 0);
+    append_text_column(&tree_view, "Level", 1);
+    append_text_column(&tree_view, "Path", 2);
+    append_text_column(&tree_view, "Message", 3);
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&tree_view);
+    main_box.pack_start(&scrolled_window, true, true, 0);
+    log_window.add(&main_box);
+
+    let entries = Rc::new(RefCell::new(load_entries(window)));
+    let refilter = {
+        let entries = Rc::clone(&entries);
+        let level_combo = level_combo.clone();
+        let path_entry = path_entry.clone();
+        Rc::new(move || {
+            let level = level_combo.active()
+                .and_then(|index| LEVELS.get(index as usize))
+                .map_or(LevelFilter::Info, |(_, level)| *level);
+            show_entries(&store, &count_label, &entries.borrow(), level, &path_entry.text());
+        })
+    };
+    refilter();
+
+    {
+        let refilter = Rc::clone(&refilter);
+        level_combo.connect_changed(move |_| refilter());
+    }
+    {
+        let refilter = Rc::clone(&refilter);
+        path_entry.connect_changed(move |_| refilter());
+    }
+    {
+        let window = window.clone();
+        reload_button.connect_clicked(move |_| {
+            *entries.borrow_mut() = load_entries(&window);
+            refilter();
+        });
+    }
+
+    log_window.show_all();
+}
@@ -0,0 +1,135 @@
+use gtk::prelude::*;
+use gtk::{ButtonsType, CellRendererText, Dialog, Label, ListStore, MessageDialog, MessageType, ResponseType, ScrolledWindow,
+    TreeView, TreeViewColumn, Window};
+use crate::controller::library::ScanReport;
+use std::thread;
+use std::time::Duration;
+
+/// Maximum number of problems listed in the summaries shown after a batch operation.
+const MAX_LISTED_PROBLEMS: usize = 10;
+
+/// Shows a modal message dialog and returns the response.
+pub fn run_dialog(window: &Window, message_type: MessageType, buttons: ButtonsType, message: &str) -> ResponseType {
+    let dialog = MessageDialog::new(Some(window), gtk::DialogFlags::MODAL, message_type, buttons, message);
+    let response = dialog.run();
+    dialog.close();
+    response
+}
+
+/// Displays an error dialog with a specific message.
+///
+/// # Parameters
+/// * `window`: The main window on which the dialog is shown.
+/// * `message`: The message that will be displayed in the dialog.
+///
+/// # Description
+/// Creates a modal pop-up window (`MessageDialog`) that displays an error message
+/// and an "OK" button to close the dialog. This dialog blocks interaction
+/// with the main window until it is closed.
+///
+/// # Example
+/// ```rust
+/// show_error_dialog(&window, "No directory provided.");
+/// ```
+pub fn show_error_dialog(window: &Window, message: &str) {
+    run_dialog(window, MessageType::Error, ButtonsType::Ok, message);
+}
+
+/// How long to wait between two checks of a worker thread, in milliseconds.
+const POLL_INTERVAL_MS: u64 = 20;
+
+/// # Run In Background function
+///
+/// Runs `work` on a worker thread, handling the events of the window until it is done so the
+/// window keeps redrawing during long operations.
+///
+/// # Arguments
+/// * `work` - The operation to run.
+/// * `on_poll` - Called on the main thread between two checks of the worker, e.g. to show its
+///   progress.
+///
+/// # Returns
+/// The result of `work`, or `None` if the worker thread panicked.
+pub fn run_in_background<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static, mut on_poll: impl FnMut()) -> Option<T> {
+    let worker = thread::spawn(work);
+    while !worker.is_finished() {
+        on_poll();
+        while gtk::events_pending() {
+            gtk::main_iteration();
+        }
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+    on_poll();
+    worker.join().ok()
+}
+
+/// Appends the number of problems and the first `MAX_LISTED_PROBLEMS` of them to the summary
+/// of a batch operation (loudness scan, duplicate removal, ...), and logs them all.
+pub fn append_problems(summary: &mut String, problems: &[String]) {
+    if problems.is_empty() {
+        return;
+    }
+    for problem in problems {
+        log::warn!("{}", problem);
+    }
+    summary.push_str(&format!("\n\n{} problem(s):\n", problems.len()));
+    summary.push_str(&problems.iter().take(MAX_LISTED_PROBLEMS).cloned().collect::<Vec<_>>().join("\n"));
+}
+
+/// Appends a text column showing the model column `index` to a tree view.
+pub fn append_text_column(tree_view: &TreeView, title: &str, index: i32) {
+    let column = TreeViewColumn::new();
+    column.set_title(title);
+    column.set_resizable(true);
+    let renderer = CellRendererText::new();
+    column.pack_start(&renderer, true);
+    column.add_attribute(&renderer, "text", index);
+    tree_view.append_column(&column);
+}
+
+/// # Show Scan Report function
+///
+/// Shows the outcome of a scan: how many songs were added and, in one list, every file that
+/// could not be read or added with the kind of problem and its description. The failures are
+/// also written to the scan log by `Library::scan()`.
+///
+/// # Arguments
+/// * `window` - The main window, parent of the dialog.
+/// * `report` - The report returned by `Library::scan()`.
+pub fn show_scan_report(window: &Window, report: &ScanReport) {
+    let dialog = Dialog::with_buttons(
+        Some("Scan Report"),
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        &[("Close", ResponseType::Close)],
+    );
+    dialog.set_default_size(750, 400);
+
+    let content = dialog.content_area();
+    content.set_spacing(5);
+    let summary = Label::new(Some(&format!(
+        "{} song(s) added, {} file(s) could not be read or added.",
+        report.added, report.failures.len()
+    )));
+    summary.set_xalign(0.0);
+    content.pack_start(&summary, false, false, 5);
+
+    let store = ListStore::new(&[String::static_type(), String::static_type(), String::static_type()]);
+    for failure in &report.failures {
+        let path = failure.path().map(|path| path.display().to_string()).unwrap_or_default();
+        store.insert_with_values(None, &[(0, &failure.kind()), (1, &path), (2, &failure.message())]);
+    }
+
+    let tree_view = TreeView::with_model(&store);
+    append_text_column(&tree_view, "Kind", 0);
+    append_text_column(&tree_view, "Path", 1);
+    append_text_column(&tree_view, "Problem", 2);
+
+    let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.add(&tree_view);
+    content.pack_start(&scrolled_window, true, true, 5);
+
+    dialog.show_all();
+    dialog.run();
+    dialog.close();
+}
@@ -0,0 +1,88 @@
+use gtk::prelude::*;
+use gtk::ListStore;
+use crate::controller::controller::SongView;
+use crate::controller::library::{Library, SongDetails};
+use crate::model::error::LibraryResult;
+use crate::model::music_miner::audio_properties::format_duration;
+use crate::model::music_miner::rating::MAX_RATING;
+
+/// Appends a song to the song list: title, artist and album, followed by the optional
+/// duration, bitrate, sample rate, channel mode and encoder columns (empty when unknown),
+/// the hidden path column used to queue songs for playback, the optional play count and
+/// last playback columns, and the rating stars.
+fn append_song_row(list_store: &ListStore, song: &SongDetails) {
+    let duration = song.duration_ms.map(format_duration).unwrap_or_default();
+    let bitrate = song.bitrate.map(|bitrate| format!("{} kbps", bitrate)).unwrap_or_default();
+    let sample_rate = song.sample_rate.map(|rate| format!("{:.1} kHz", f64::from(rate) / 1000.0)).unwrap_or_default();
+    let channel_mode = song.channel_mode.clone().unwrap_or_default();
+    let encoder = song.encoder.clone().unwrap_or_default();
+    let play_count = song.play_count.to_string();
+    let last_played = song.last_played.clone().unwrap_or_default();
+    let rating = format_rating(song.rating);
+
+    let iter = list_store.append();
+    list_store.set(&iter, &[
+        (0, &song.title),
+        (1, &song.artist),
+        (2, &song.album),
+        (3, &duration),
+        (4, &bitrate),
+        (5, &sample_rate),
+        (6, &channel_mode),
+        (7, &encoder),
+        (8, &song.path),
+        (9, &play_count),
+        (10, &last_played),
+        (11, &rating)
+    ]);
+}
+
+/// Formats a rating as filled and empty stars, e.g. `★★★☆☆` for three stars.
+pub fn format_rating(rating: i32) -> String {
+    let filled = rating.clamp(0, MAX_RATING) as usize;
+    "★".repeat(filled) + &"☆".repeat(MAX_RATING as usize - filled)
+}
+
+/// Replaces the rows of the song list with the songs read from the library.
+///
+/// # Errors
+/// Prints an error to the console, leaving the list untouched, if the songs cannot be retrieved.
+fn fill_song_list(list_store: &ListStore, songs: LibraryResult<Vec<SongDetails>>) {
+    match songs {
+        Ok(songs) => {
+            list_store.clear();
+
+            for song in songs {
+                append_song_row(list_store, &song);
+            }
+        },
+        Err(err) => {
+            eprintln!("Failed to retrieve songs from the database: {}", err);
+        }
+    };
+}
+
+/// Fills the given `ListStore` with every song of the library.
+pub fn populate_song_list(list_store: &ListStore, library: &Library) {
+    fill_song_list(list_store, library.songs());
+}
+
+/// Fills the given `ListStore` with the songs matching a search query.
+///
+/// # Arguments
+/// * `list_store` - A reference to the `ListStore` where the song data will be inserted.
+/// * `library` - The library to search.
+/// * `query` - The text typed in the search entry.
+pub fn populate_song_list_with_query(list_store: &ListStore, library: &Library, query: &str) {
+    fill_song_list(list_store, library.search(query));
+}
+
+/// Fills the given `ListStore` with the songs of a view of the library.
+pub fn populate_song_list_view(list_store: &ListStore, library: &Library, view: SongView) {
+    fill_song_list(list_store, library.songs_in_view(view));
+}
+
+/// Fills the given `ListStore` with the songs of a single album, ordered by track number.
+pub fn populate_album_song_list(list_store: &ListStore, library: &Library, album_id: i64) {
+    fill_song_list(list_store, library.album_songs(album_id));
+}
@@ -0,0 +1,116 @@
+use gtk::glib::translate::IntoGlib;
+use gtk::prelude::*;
+use gtk::{CellRendererText, Expander, ListStore, ScrolledWindow, TreePath, TreeView, TreeViewColumn};
+use crate::model::music_miner::synced_lyrics::{current_line, format_timestamp, parse_lrc, LyricLine};
+use crate::model::playback::player::Player;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How often the pane follows the position of the player, in milliseconds.
+const FOLLOW_INTERVAL_MS: u64 = 250;
+
+/// Columns of the lines list: the timestamp, the text and its font weight.
+const COLUMN_TIME: u32 = 0;
+const COLUMN_TEXT: u32 = 1;
+const COLUMN_WEIGHT: u32 = 2;
+
+/// The path and lines of the song shown in the pane.
+type ShownSong = Rc<RefCell<Option<(String, Vec<LyricLine>)>>>;
+
+/// # SyncedLyricsPane struct
+///
+/// The "Synchronised Lyrics" expander of the details frame: the time-coded lines of the song
+/// shown, with their timestamps. While that song plays, the line being sung is shown in bold
+/// and kept in view.
+#[derive(Clone)]
+pub struct SyncedLyricsPane {
+    pub widget: Expander,
+    store: ListStore,
+    tree_view: TreeView,
+    song: ShownSong,
+    /// The line shown in bold.
+    highlighted: Rc<Cell<Option<usize>>>,
+}
+
+impl SyncedLyricsPane {
+    pub fn new() -> Self {
+        let store = ListStore::new(&[String::static_type(), String::static_type(), i32::static_type()]);
+        let tree_view = TreeView::with_model(&store);
+        tree_view.set_headers_visible(false);
+        for column in [COLUMN_TIME, COLUMN_TEXT] {
+            let renderer = CellRendererText::new();
+            let tree_column = TreeViewColumn::new();
+            tree_column.pack_start(&renderer, true);
+            tree_column.add_attribute(&renderer, "text", column as i32);
+            tree_column.add_attribute(&renderer, "weight", COLUMN_WEIGHT as i32);
+            tree_view.append_column(&tree_column);
+        }
+
+        let scrolled_window = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.set_min_content_height(150);
+        scrolled_window.add(&tree_view);
+        let widget = Expander::new(Some("Synchronised Lyrics"));
+        widget.add(&scrolled_window);
+
+        SyncedLyricsPane {
+            widget,
+            store,
+            tree_view,
+            song: Rc::new(RefCell::new(None)),
+            highlighted: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Shows the time-coded lines of a song, hiding the pane if it has none.
+    pub fn show(&self, path: &str, lrc: Option<&str>) {
+        let lines = lrc.map(parse_lrc).unwrap_or_default();
+        self.store.clear();
+        for line in &lines {
+            self.store.insert_with_values(None, &[
+                (COLUMN_TIME, &format_timestamp(line.time_ms)),
+                (COLUMN_TEXT, &line.text),
+                (COLUMN_WEIGHT, &(gtk::pango::Weight::Normal.into_glib())),
+            ]);
+        }
+        self.widget.set_visible(!lines.is_empty());
+        self.highlighted.set(None);
+        *self.song.borrow_mut() = Some((path.to_string(), lines));
+    }
+
+    /// Highlights the line being sung whenever the player plays the song shown.
+    pub fn follow(&self, player: Rc<Player>) {
+        let pane = self.clone();
+        glib::timeout_add_local(Duration::from_millis(FOLLOW_INTERVAL_MS), move || {
+            let status = player.status();
+            let line = match pane.song.borrow().as_ref() {
+                Some((path, lines)) if status.path.as_deref() == Some(path.as_str()) => {
+                    current_line(lines, status.position_ms)
+                }
+                _ => None,
+            };
+            pane.highlight(line);
+            glib::Continue(true)
+        });
+    }
+
+    /// Shows one line in bold, and scrolls to it.
+    fn highlight(&self, line: Option<usize>) {
+        let previous = self.highlighted.replace(line);
+        if previous == line {
+            return;
+        }
+        for (index, weight) in [(previous, gtk::pango::Weight::Normal), (line, gtk::pango::Weight::Bold)] {
+            let Some(index) = index else {
+                continue;
+            };
+            if let Some(iter) = self.store.iter_nth_child(None, index as i32) {
+                self.store.set(&iter, &[(COLUMN_WEIGHT, &weight.into_glib())]);
+            }
+        }
+        if let Some(index) = line {
+            let path = TreePath::from_indicesv(&[index as i32]);
+            self.tree_view.scroll_to_cell(Some(&path), None::<&TreeViewColumn>, true, 0.5, 0.0);
+        }
+    }
+}